@@ -0,0 +1,71 @@
+//! Benchmarks of the hot paths of a run on synthetic image sets: packing a selection, evaluating
+//! it (packing plus fitness) and rendering the collage. Run with `cargo bench`.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use image_grid_optimizer::bench::{random_selections, synthetic_images};
+use image_grid_optimizer::collage::{create_collage, RenderOptions};
+use image_grid_optimizer::events;
+use image_grid_optimizer::ga::{evaluate_individual, ImageInfo};
+use image_grid_optimizer::layout::Layout;
+use image_grid_optimizer::packing::{pack_images, PackingOptions};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+/// Number of images per collage of the benchmarked selections.
+const SELECTION_SIZES: [usize; 3] = [10, 30, 60];
+
+/// Longer side of the synthetic images in pixels.
+const IMAGE_SIZE: u32 = 256;
+
+fn packing(c: &mut Criterion) {
+    let images = synthetic_images(200, IMAGE_SIZE, 0);
+    let options = PackingOptions::default();
+    let mut group = c.benchmark_group("pack_images");
+    for count in SELECTION_SIZES {
+        let selection = &random_selections(&images, count, count, 1, 0)[0];
+        group.bench_with_input(BenchmarkId::from_parameter(count), &selection.image_ids, |b, ids| {
+            b.iter(|| pack_images(ids, &images, &options))
+        });
+    }
+    group.finish();
+}
+
+fn evaluation(c: &mut Criterion) {
+    let images = synthetic_images(200, IMAGE_SIZE, 0);
+    let info = ImageInfo::default();
+    let options = PackingOptions::default();
+    let mut group = c.benchmark_group("evaluate_individual");
+    for count in SELECTION_SIZES {
+        let mut selection = random_selections(&images, count, count, 1, 0).remove(0);
+        group.bench_function(BenchmarkId::from_parameter(count), |b| {
+            b.iter(|| evaluate_individual(&mut selection, &images, &info, &options))
+        });
+    }
+    group.finish();
+}
+
+fn rendering(c: &mut Criterion) {
+    let images = synthetic_images(200, IMAGE_SIZE, 0);
+    let info = ImageInfo::default();
+    let options = PackingOptions::default();
+    // Rendering logs every placed image
+    events::set_silent(true);
+    let mut group = c.benchmark_group("create_collage");
+    group.sample_size(10);
+    for count in SELECTION_SIZES {
+        let mut selection = random_selections(&images, count, count, 1, 0).remove(0);
+        evaluate_individual(&mut selection, &images, &info, &options);
+        let Some(packed) = &selection.packed_layout else { continue };
+        let layout = Layout::build(packed, &selection.genes, &images, &HashMap::<u32, PathBuf>::new(), &options, &mut StdRng::seed_from_u64(0));
+        group.bench_with_input(BenchmarkId::from_parameter(count), &layout, |b, layout| {
+            b.iter(|| create_collage(&images, layout, &HashMap::new(), &RenderOptions::default()))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, packing, evaluation, rendering);
+criterion_main!(benches);
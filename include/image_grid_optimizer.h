@@ -0,0 +1,83 @@
+#ifndef IMAGE_GRID_OPTIMIZER_H
+#define IMAGE_GRID_OPTIMIZER_H
+
+/* Generated by cbindgen from src/ffi.rs; do not edit. */
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+// An optimizer with its images, parameters and last result. Create it with
+// `igo_optimizer_new` and release it with `igo_optimizer_free`.
+typedef struct IgoOptimizer IgoOptimizer;
+
+// Called after every generation of `igo_optimizer_run` with the generation (from 1), the number
+// of generations and the best fitness so far; returning false ends the run with the best collage
+// found so far.
+typedef bool (*IgoProgressCallback)(void *user_data, size_t generation, size_t generations, double best_fitness);
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Creates an optimizer without images. Images are scaled to `standard_width` pixels as they are
+// added, as with `--width`; 0 keeps their size.
+IgoOptimizer *igo_optimizer_new(uint32_t standard_width);
+
+// Releases an optimizer and everything it returned.
+//
+// # Safety
+// `optimizer` must come from `igo_optimizer_new` and not be used afterwards; null is ignored.
+void igo_optimizer_free(IgoOptimizer *optimizer);
+
+// Decodes an image from the `len` bytes of an encoded file (JPEG, PNG, ...) and adds it. Images
+// are numbered from 0 in the order they are added; these are the ids in the layout.
+//
+// # Safety
+// `optimizer` must be valid and `data` must point to `len` readable bytes.
+int32_t igo_optimizer_add_image(IgoOptimizer *optimizer, const uint8_t *data, size_t len);
+
+// Sets a parameter of the GA by the name of its command line option: `pop-size`, `gens`,
+// `min-images`, `max-images`, `mutation-rate`, `crossover-rate` or `seed`, e.g. `"gens"` to
+// `"300"`. Parameters not set keep the defaults of the command line tool.
+//
+// # Safety
+// `optimizer` must be valid and `name` and `value` must be null-terminated strings.
+int32_t igo_optimizer_set_param(IgoOptimizer *optimizer, const char *name, const char *value);
+
+// Runs the GA on the images added so far and keeps the layout of the best collage for
+// `igo_optimizer_layout_json` and `igo_optimizer_render`. `progress` may be null; `user_data` is
+// passed on to it.
+//
+// # Safety
+// `optimizer` must be valid.
+int32_t igo_optimizer_run(IgoOptimizer *optimizer, IgoProgressCallback progress, void *user_data);
+
+// The layout of the last run as JSON, as in the `layout.json` of the command line tool, or null
+// before the first successful run. The string belongs to the optimizer and stays valid until the
+// next run.
+//
+// # Safety
+// `optimizer` must be valid.
+const char *igo_optimizer_layout_json(const IgoOptimizer *optimizer);
+
+// Renders the collage of the last run with the default look and returns its pixels as RGBA, row
+// by row, with its size in `width` and `height`; null on failure. The pixels belong to the
+// optimizer and stay valid until the next run or render.
+//
+// # Safety
+// `optimizer` must be valid and `width` and `height` must be writable.
+const uint8_t *igo_optimizer_render(IgoOptimizer *optimizer, uint32_t *width, uint32_t *height);
+
+// Describes the last failure of a function of this optimizer, or null if there was none. The
+// string belongs to the optimizer and stays valid until the next failure.
+//
+// # Safety
+// `optimizer` must be valid.
+const char *igo_last_error(const IgoOptimizer *optimizer);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif // __cplusplus
+
+#endif // IMAGE_GRID_OPTIMIZER_H
@@ -0,0 +1,105 @@
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::{Component, Path, PathBuf};
+
+use flate2::read::GzDecoder;
+use zip::ZipArchive;
+
+/// Whether `path` is a ZIP or (optionally gzipped) TAR archive that can be used as input.
+pub fn is_archive(path: &Path) -> bool {
+    let name = path.file_name().map(|n| n.to_string_lossy().to_ascii_lowercase()).unwrap_or_default();
+    path.is_file() && [".zip", ".tar", ".tar.gz", ".tgz"].iter().any(|ext| name.ends_with(ext))
+}
+
+/// Calls `f` with the path and contents of every file in the archive, without extracting
+/// anything to disk. Paths are the archive path joined with the entry name, e.g.
+/// `photos.zip/summer/beach.jpg`, which `read_member` resolves again later. Entries with absolute
+/// names or `..` are skipped, as their paths would lead out of the archive to files on disk.
+pub fn for_each_entry(archive: &Path, mut f: impl FnMut(PathBuf, Vec<u8>)) -> Result<(), String> {
+    let error = |e: &dyn std::fmt::Display| format!("Error reading archive {}: {}", archive.display(), e);
+    let file = File::open(archive).map_err(|e| error(&e))?;
+
+    if is_zip(archive) {
+        let mut zip = ZipArchive::new(BufReader::new(file)).map_err(|e| error(&e))?;
+        for index in 0..zip.len() {
+            let mut entry = zip.by_index(index).map_err(|e| error(&e))?;
+            if !entry.is_file() || is_metadata_entry(entry.name()) {
+                continue;
+            }
+            let Some(name) = entry.enclosed_name() else {
+                continue;
+            };
+            let mut bytes = Vec::with_capacity(entry.size() as usize);
+            entry.read_to_end(&mut bytes).map_err(|e| error(&e))?;
+            f(archive.join(name), bytes);
+        }
+    } else {
+        let mut tar = tar::Archive::new(tar_reader(archive, file));
+        for entry in tar.entries().map_err(|e| error(&e))? {
+            let mut entry = entry.map_err(|e| error(&e))?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+            let name = entry.path().map_err(|e| error(&e))?.into_owned();
+            if !is_relative_inside(&name) || is_metadata_entry(&name.to_string_lossy()) {
+                continue;
+            }
+            let mut bytes = Vec::with_capacity(entry.size() as usize);
+            entry.read_to_end(&mut bytes).map_err(|e| error(&e))?;
+            f(archive.join(name), bytes);
+        }
+    }
+    Ok(())
+}
+
+/// Reads a file from inside an archive given a path produced by `for_each_entry`. Returns `None`
+/// when no parent of `path` is an archive.
+pub fn read_member(path: &Path) -> Option<Result<Vec<u8>, String>> {
+    let archive = path.ancestors().skip(1).find(|ancestor| is_archive(ancestor))?;
+    let member = path.strip_prefix(archive).ok()?.to_path_buf();
+    if is_zip(archive) {
+        return Some(read_zip_member(archive, &member));
+    }
+    let mut found = None;
+    let result = for_each_entry(archive, |entry, bytes| {
+        if found.is_none() && entry.strip_prefix(archive).is_ok_and(|name| name == member) {
+            found = Some(bytes);
+        }
+    });
+    Some(result.and_then(|_| found.ok_or_else(|| format!("{} not found in {}", member.display(), archive.display()))))
+}
+
+fn read_zip_member(archive: &Path, member: &Path) -> Result<Vec<u8>, String> {
+    let error = |e: &dyn std::fmt::Display| format!("Error reading {} from {}: {}", member.display(), archive.display(), e);
+    let file = File::open(archive).map_err(|e| error(&e))?;
+    let mut zip = ZipArchive::new(BufReader::new(file)).map_err(|e| error(&e))?;
+    // Zip entry names always use forward slashes
+    let name = member.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/");
+    let mut entry = zip.by_name(&name).map_err(|e| error(&e))?;
+    let mut bytes = Vec::with_capacity(entry.size() as usize);
+    entry.read_to_end(&mut bytes).map_err(|e| error(&e))?;
+    Ok(bytes)
+}
+
+/// Whether an entry name stays inside the archive: no root, drive prefix or `..`.
+fn is_relative_inside(name: &Path) -> bool {
+    name.components().all(|component| !matches!(component, Component::RootDir | Component::Prefix(_) | Component::ParentDir))
+}
+
+fn is_zip(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("zip"))
+}
+
+fn tar_reader(path: &Path, file: File) -> Box<dyn Read> {
+    let name = path.to_string_lossy().to_ascii_lowercase();
+    if name.ends_with(".gz") || name.ends_with(".tgz") {
+        Box::new(GzDecoder::new(BufReader::new(file)))
+    } else {
+        Box::new(BufReader::new(file))
+    }
+}
+
+/// Resource forks and Finder files that macOS adds to archives.
+fn is_metadata_entry(name: &str) -> bool {
+    name.starts_with("__MACOSX/") || name.rsplit('/').next().is_some_and(|file| file.starts_with("._") || file == ".DS_Store")
+}
@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use image::{DynamicImage, Rgb, RgbImage};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+
+use crate::cli::BenchArgs;
+use crate::collage::{create_collage, RenderOptions};
+use crate::dry_run::format_duration;
+use crate::events;
+use crate::ga::{create_random_individual, evaluate_individual, ImageInfo, Individual};
+use crate::layout::Layout;
+use crate::packing::PackingOptions;
+
+/// Aspect ratios of the synthetic images, as width by height: the usual photo formats in both
+/// orientations.
+pub const ASPECT_RATIOS: [(u32, u32); 7] = [(3, 2), (2, 3), (4, 3), (3, 4), (16, 9), (9, 16), (1, 1)];
+
+/// Selections evaluated over and over while timing, so that drawing them is not timed.
+const SAMPLE_SELECTIONS: usize = 256;
+
+/// Population sizes and generation counts of the table of run times.
+const POPULATIONS: [usize; 4] = [100, 500, 1000, 2000];
+const GENERATIONS: [usize; 3] = [1000, 3000, 10000];
+
+/// Run times the suggested settings aim for.
+const TARGET_RUNS: [Duration; 2] = [Duration::from_secs(60), Duration::from_secs(600)];
+
+/// Solid color images of mixed photo formats with `size` pixels on their longer side, the same for
+/// every `seed`. The packer only looks at their sizes; rendering copies their pixels.
+pub fn synthetic_images(count: usize, size: u32, seed: u64) -> HashMap<u32, DynamicImage> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    (0..count as u32)
+        .map(|id| {
+            let (w, h) = *ASPECT_RATIOS.choose(&mut rng).unwrap_or(&(1, 1));
+            let width = (size * w / w.max(h)).max(1);
+            let height = (size * h / w.max(h)).max(1);
+            let color = Rgb([rng.gen(), rng.gen(), rng.gen()]);
+            (id, DynamicImage::ImageRgb8(RgbImage::from_pixel(width, height, color)))
+        })
+        .collect()
+}
+
+/// Random selections of `min_images` to `max_images` of the images, as the first generation of a
+/// run draws them.
+pub fn random_selections(images: &HashMap<u32, DynamicImage>, min_images: usize, max_images: usize, count: usize, seed: u64) -> Vec<Individual> {
+    let mut all_images: Vec<(u32, DynamicImage)> = images.iter().map(|(id, img)| (*id, img.clone())).collect();
+    all_images.sort_by_key(|(id, _)| *id);
+    let mut rng = StdRng::seed_from_u64(seed);
+    let max_images = max_images.clamp(1, all_images.len().max(1));
+    let min_images = min_images.clamp(1, max_images);
+    (0..count)
+        .map(|_| create_random_individual(&all_images, &ImageInfo::default(), min_images, max_images, &mut rng))
+        .collect()
+}
+
+/// Times the fitness evaluation and the rendering on synthetic images and prints how long runs
+/// with different population sizes and generation counts would take on this machine.
+pub fn bench(args: &BenchArgs) {
+    let images = synthetic_images(args.images, args.size, 0);
+    let mut selections = random_selections(&images, args.min_images, args.max_images, SAMPLE_SELECTIONS, 0);
+    let info = ImageInfo::default();
+    let packing = PackingOptions::default();
+    let threads = rayon::current_num_threads().max(1);
+    info!(
+        "Benchmarking with {} synthetic images of {} px, {} to {} per collage, for about {}...",
+        args.images,
+        args.size,
+        args.min_images,
+        args.max_images,
+        format_duration(args.duration * 2)
+    );
+
+    // One thread at a time, as a single evaluation runs
+    let start = Instant::now();
+    let mut evaluations = 0;
+    while start.elapsed() < args.duration {
+        let selection = &mut selections[evaluations % SAMPLE_SELECTIONS];
+        evaluate_individual(selection, &images, &info, &packing);
+        evaluations += 1;
+    }
+    let single = evaluations as f64 / start.elapsed().as_secs_f64();
+
+    // The whole population at once on all threads, as every generation is evaluated
+    let start = Instant::now();
+    let mut evaluations = 0;
+    while start.elapsed() < args.duration {
+        selections.par_iter_mut().for_each(|selection| evaluate_individual(selection, &images, &info, &packing));
+        evaluations += SAMPLE_SELECTIONS;
+    }
+    let parallel = evaluations as f64 / start.elapsed().as_secs_f64();
+    info!("Evaluations: {:.0} per second on one thread, {:.0} per second on {} threads", single, parallel, threads);
+
+    let best = selections.iter().max_by(|a, b| a.fitness.total_cmp(&b.fitness));
+    if let Some((best, packed)) = best.and_then(|best| Some((best, best.packed_layout.as_ref()?))) {
+        let layout = Layout::build(packed, &best.genes, &images, &HashMap::<u32, PathBuf>::new(), &packing, &mut StdRng::seed_from_u64(0));
+        events::set_silent(true);
+        let start = Instant::now();
+        create_collage(&images, &layout, &HashMap::new(), &RenderOptions::default());
+        let elapsed = start.elapsed();
+        events::set_silent(false);
+        info!(
+            "Rendering: {:.0} ms for a collage of {} images on a {}x{} canvas",
+            elapsed.as_secs_f64() * 1000.0,
+            layout.images.len(),
+            layout.width,
+            layout.height
+        );
+    }
+
+    info!("\nEstimated run time (--pop-size down, --gens across):");
+    let header: Vec<String> = GENERATIONS.iter().map(|gens| format!("{:>12}", gens)).collect();
+    info!("{:>10}{}", "", header.join(""));
+    for population in POPULATIONS {
+        let row: Vec<String> = GENERATIONS
+            .iter()
+            .map(|&generations| format!("{:>12}", format_duration(run_time(population, generations, parallel))))
+            .collect();
+        info!("{:>10}{}", population, row.join(""));
+    }
+
+    // The defaults evaluate three generations per individual of the population; keep that ratio
+    for target in TARGET_RUNS {
+        let budget = parallel * target.as_secs_f64();
+        let population = (((budget / 3.0).sqrt() / 50.0).round() as usize * 50).clamp(50, 2000);
+        let generations = ((budget / population as f64 / 100.0).round() as usize * 100).max(100);
+        info!(
+            "For a run of about {}: --pop-size {} --gens {}",
+            format_duration(target),
+            population,
+            generations
+        );
+    }
+}
+
+/// Time of a run that evaluates `population` selections in each of `generations` generations.
+fn run_time(population: usize, generations: usize, evaluations_per_second: f64) -> Duration {
+    Duration::from_secs_f64(population as f64 * generations as f64 / evaluations_per_second.max(f64::MIN_POSITIVE))
+}
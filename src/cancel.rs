@@ -0,0 +1,39 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+#[cfg(feature = "cli")]
+use signal_hook::consts::SIGINT;
+
+/// Exit status of a run aborted by a second Ctrl+C, as for a shell job killed by SIGINT.
+#[cfg(feature = "cli")]
+const ABORTED: i32 = 130;
+
+/// Stops a running optimization from another thread, e.g. a GUI or the Ctrl+C handler. The GA
+/// checks it between generations and while evaluating one, and returns the best collage found so
+/// far. Clones share the same state.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Cancels `token` on the first Ctrl+C; a second one aborts the program at once.
+#[cfg(feature = "cli")]
+pub fn cancel_on_ctrl_c(token: &CancellationToken) -> Result<(), String> {
+    // The shutdown is registered first, so it only fires when the flag is already set
+    signal_hook::flag::register_conditional_shutdown(SIGINT, ABORTED, Arc::clone(&token.0))
+        .and_then(|_| signal_hook::flag::register(SIGINT, Arc::clone(&token.0)))
+        .map(|_| ())
+        .map_err(|e| format!("Error installing the Ctrl+C handler: {}", e))
+}
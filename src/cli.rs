@@ -1,89 +1,1459 @@
-use clap::{App, Arg};
-
-pub fn parse_args() -> (String, Option<String>, Option<u32>, usize, usize, usize, usize, f64, f64) {
-    let matches = App::new("ImageGridOptimizer GA")
-        .version("1.0")
-        .author("Senior Developer")
-        .about("Optimizes the arrangement of images using a Genetic Algorithm.")
-        .arg(
-            Arg::with_name("DIRECTORY")
-                .help("Directory containing the images.")
-                .required(true)
-                .index(1),
-        )
-        .arg(
-            Arg::with_name("filter")
-                .short("f")
-                .long("filter")
-                .value_name("FILTER")
-                .help("Filter for images (extension or part of filename).")
-                .takes_value(true),
-        )
-        .arg(
-            Arg::with_name("standard_width")
-                .short("w")
-                .long("width")
-                .value_name("WIDTH")
-                .help("Optional standard width for scaling images.")
-                .takes_value(true),
-        )
-        .arg(
-            Arg::with_name("population_size")
-                .long("pop-size")
-                .value_name("POP_SIZE")
-                .help("Population size for the genetic algorithm.")
-                .takes_value(true),
-        )
-        .arg(
-            Arg::with_name("generations")
-                .long("gens")
-                .value_name("GENS")
-                .help("Number of generations for the genetic algorithm.")
-                .takes_value(true),
-        )
-        .arg(
-            Arg::with_name("min_images")
-                .long("min-images")
-                .value_name("MIN_IMAGES")
-                .help("Minimum number of images per collage.")
-                .takes_value(true),
-        )
-        .arg(
-            Arg::with_name("max_images")
-                .long("max-images")
-                .value_name("MAX_IMAGES")
-                .help("Maximum number of images per collage.")
-                .takes_value(true),
-        )
-        .arg(
-            Arg::with_name("mutation_rate")
-                .long("mutation-rate")
-                .value_name("MUTATION_RATE")
-                .help("Mutation rate for the genetic algorithm.")
-                .takes_value(true),
-        )
-        .arg(
-            Arg::with_name("crossover_rate")
-                .long("crossover-rate")
-                .value_name("CROSSOVER_RATE")
-                .help("Crossover rate for the genetic algorithm.")
-                .takes_value(true),
-        )
-        .get_matches();
-
-    let dir = matches.value_of("DIRECTORY").unwrap().to_string();
-    let filter = matches.value_of("filter").map(|s| s.to_string());
-    let standard_width = matches
-        .value_of("standard_width")
-        .map(|w| w.parse::<u32>().expect("Invalid width"));
-
-    // Default large values to handle large number of trials
-    let population_size = matches.value_of("pop_size").unwrap_or("1000").parse::<usize>().expect("Invalid population size");
-    let generations = matches.value_of("gens").unwrap_or("3000").parse::<usize>().expect("Invalid number of generations");
-    let min_images = matches.value_of("min_images").unwrap_or("6").parse::<usize>().expect("Invalid min_images");
-    let max_images = matches.value_of("max_images").unwrap_or("60").parse::<usize>().expect("Invalid max_images");
-    let mutation_rate = matches.value_of("mutation_rate").unwrap_or("0.1").parse::<f64>().expect("Invalid mutation rate");
-    let crossover_rate = matches.value_of("crossover_rate").unwrap_or("0.7").parse::<f64>().expect("Invalid crossover rate");
-
-    (dir, filter, standard_width, population_size, generations, min_images, max_images, mutation_rate, crossover_rate)
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use clap::builder::RangedU64ValueParser;
+use clap::error::ErrorKind;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand, ValueHint};
+use clap_complete::{generate, Shell};
+use image::codecs::png::CompressionType;
+use image::{ImageFormat, Rgba};
+use rect_packer::Rect;
+use regex::Regex;
+
+use crate::cancel::CancellationToken;
+use crate::collage::{BackgroundColor, BackgroundImage, BackgroundMode, BannerPosition, FillMode, LabelOptions, LabelPosition, RenderOptions, ShadowOptions, TitleOptions, WatermarkOptions, WatermarkPosition};
+use crate::config::{config_args, dump_config};
+use crate::ga::{CrossoverKind, GaSettings, MutationOp, MutationWeights};
+use crate::icc;
+use crate::image_handling::{AnimatedFrame, LabelSource, LoadOptions, ScaleMode};
+use crate::output::{parse_paper, parse_dimensions, parse_physical_size, OutputOptions};
+use crate::packing::{LayoutKind, PackingOptions, PADDING_SIZE};
+use crate::remote::DownloadOptions;
+use crate::spiral::SpiralOptions;
+use crate::strip::{Direction, StripOptions};
+use crate::text::load_font;
+use crate::tone::StyleFilter;
+use crate::video::parse_interval;
+
+pub enum Command {
+    /// Run the genetic algorithm on a directory of images.
+    Optimize(Box<Args>),
+    /// Re-render a saved layout without optimizing.
+    Render(Box<RenderArgs>),
+    /// Lay the images out in a fixed grid of thumbnails with their file details.
+    ContactSheet(Box<ContactSheetArgs>),
+    /// Report the differences between two saved layouts.
+    Compare(CompareArgs),
+    /// Check a saved layout for overlaps, placements outside the canvas and missing files.
+    Verify(VerifyArgs),
+    /// Print statistics about the input directories without loading the images.
+    Inspect(InspectArgs),
+    /// Time the fitness evaluation on synthetic images.
+    Bench(BenchArgs),
+    /// Write synthetic test images.
+    GenerateSamples(SamplesArgs),
+    /// Search for good GA parameters on the input images.
+    Tune(TuneArgs),
+    /// Run optimizations submitted over HTTP.
+    Serve(ServeArgs),
+    /// Print a completion script for a shell.
+    Completions(Shell),
+}
+
+pub struct BenchArgs {
+    /// Number of synthetic images to choose from.
+    pub images: usize,
+    /// Longer side of the synthetic images in pixels.
+    pub size: u32,
+    pub min_images: usize,
+    pub max_images: usize,
+    /// How long the single-threaded and the parallel evaluation are each timed.
+    pub duration: Duration,
+}
+
+pub struct InspectArgs {
+    pub dirs: Vec<String>,
+    /// Estimate the memory use for images scaled to this width.
+    pub standard_width: Option<u32>,
+    pub json: bool,
+}
+
+pub struct VerifyArgs {
+    pub layout: PathBuf,
+    /// Smallest allowed space between images in pixels.
+    pub padding: i32,
+}
+
+pub struct TuneArgs {
+    pub dirs: Vec<String>,
+    /// Scale the images to this width, as with -w for a run.
+    pub standard_width: Option<u32>,
+    pub min_images: usize,
+    pub max_images: usize,
+    pub trials: usize,
+    /// Evaluations per run; the generations follow from the population size.
+    pub evaluations: usize,
+    /// Runs per trial with consecutive seeds.
+    pub repeats: usize,
+    pub seed: u64,
+    /// Save the best parameters as a config file.
+    pub write_config: Option<PathBuf>,
+}
+
+pub struct SamplesArgs {
+    pub count: usize,
+    /// Directory the images are written to.
+    pub output: PathBuf,
+    /// Largest longer side of the images in pixels.
+    pub size: u32,
+    pub seed: u64,
+}
+
+pub struct ServeArgs {
+    /// Address to listen on.
+    pub host: String,
+    pub port: u16,
+    /// Directory that holds the uploaded images and the results of every job.
+    pub jobs_dir: PathBuf,
+    /// Largest request body accepted, in bytes.
+    pub max_upload: u64,
+    /// Origin of web pages allowed to use the API from a browser.
+    pub allow_origin: Option<String>,
+    /// Directories on the server that jobs may read besides their uploads.
+    pub allow_dirs: Vec<PathBuf>,
+}
+
+pub struct CompareArgs {
+    pub layouts: [PathBuf; 2],
+    /// Side-by-side image of both collages.
+    pub output: PathBuf,
+}
+
+pub struct RenderArgs {
+    pub layout: PathBuf,
+    pub output: PathBuf,
+    pub scale: f64,
+    /// Only the settings for decoding individual files (animated frame, raster DPI, retargeting) are used.
+    pub load: LoadOptions,
+    pub label_source: Option<LabelSource>,
+    pub render: RenderOptions,
+    pub output_options: OutputOptions,
+}
+
+pub struct ContactSheetArgs {
+    pub dirs: Vec<String>,
+    /// Output image; pages after the first are numbered, a PDF gets one page each.
+    pub output: PathBuf,
+    pub columns: u32,
+    /// Rows of thumbnails per page; the images continue on further pages.
+    pub rows: u32,
+    /// Side of the square every thumbnail is fitted into, in pixels.
+    pub thumb_size: u32,
+    /// Heading printed at the top of every page.
+    pub title: Option<String>,
+    /// Only the filter and the settings for decoding individual files are used.
+    pub load: LoadOptions,
+    pub output_options: OutputOptions,
+}
+
+/// Where the images come from.
+pub enum Input {
+    /// Directories or archives that are scanned for images.
+    Directories(Vec<String>),
+    /// A `--from-list` file with one path per line, or `-` for stdin.
+    List(String),
+}
+
+/// How many collages the images are split across.
+#[derive(Clone, Copy)]
+pub enum PageCount {
+    Fixed(usize),
+    /// As many pages as needed to fit every image within `--max-images`.
+    Auto,
+}
+
+pub struct Args {
+    pub input: Input,
+    pub load: LoadOptions,
+    /// Memory budget in bytes; the images are loaded at a lower resolution to stay within it.
+    pub max_memory: Option<u64>,
+    pub download: DownloadOptions,
+    /// Strength of the color flow mode that arranges images as a color gradient.
+    pub color_flow: Option<f64>,
+    /// CSV file that maps file names to weights.
+    pub weights: Option<PathBuf>,
+    /// Read star ratings and raise the weight of rated images by this much per star.
+    pub rating_bonus: Option<f64>,
+    /// Images rated with at least this many stars are always included.
+    pub pin_rating: Option<u8>,
+    /// Images whose difference hashes differ in at most this many bits are collapsed into one.
+    pub dedup_threshold: Option<u32>,
+    pub ga: GaSettings,
+    pub seed: Option<u64>,
+    /// Layout of an earlier run whose images seed the first generation.
+    pub seed_layout: Option<PathBuf>,
+    pub pages: Option<PageCount>,
+    pub combined_pdf: Option<PathBuf>,
+    pub label_source: Option<LabelSource>,
+    pub gap_fill: Option<u32>,
+    pub export_svg: Option<PathBuf>,
+    pub svg_embed: bool,
+    pub export_ora: Option<PathBuf>,
+    /// CSV file with the fitness statistics of every generation.
+    pub stats_out: Option<PathBuf>,
+    /// Chart of the fitness over the generations.
+    pub plot: Option<PathBuf>,
+    /// HTML summary of the run for sharing.
+    pub report: Option<PathBuf>,
+    /// Render the best collage so far every this many generations.
+    pub save_every: Option<usize>,
+    /// File overwritten by every intermediate collage; `best_gen_<N>` files are written otherwise.
+    pub preview: Option<PathBuf>,
+    /// Animation of the best collage converging, as GIF or video.
+    pub evolution_gif: Option<PathBuf>,
+    /// Record a frame for the animation every this many generations.
+    pub evolution_every: usize,
+    pub output: PathBuf,
+    /// Write progress and results as JSON lines to stdout.
+    pub json: bool,
+    /// Save this many distinct layouts, best first, instead of one.
+    pub top_n: Option<usize>,
+    /// Independent runs of the GA, of which the best is kept.
+    pub restarts: usize,
+    /// Show the interactive dashboard while optimizing.
+    pub tui: bool,
+    /// Keep running and optimize again whenever the input directories change.
+    pub watch: bool,
+    /// In watch mode, seed each run with the images of the previous collage.
+    pub warm_start: bool,
+    /// Only report how many images pass the filters and estimate memory and run time.
+    pub dry_run: bool,
+    pub packing: PackingOptions,
+    pub render: RenderOptions,
+    pub output_options: OutputOptions,
+}
+
+/// Optimizes the arrangement of images using a Genetic Algorithm.
+///
+/// Without a subcommand the options of `optimize` are given directly.
+#[derive(Parser)]
+#[command(name = "ImageGridOptimizer", version = "1.0", author = "Senior Developer")]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Subcommands>,
+    #[command(flatten)]
+    optimize: OptimizeCli,
+}
+
+#[derive(Subcommand)]
+enum Subcommands {
+    /// Runs the genetic algorithm on the images, the same as giving the options without a subcommand.
+    Optimize(Box<OptimizeCli>),
+    /// Renders a saved layout.json again, e.g. at a higher resolution from the original files.
+    Render(Box<RenderCli>),
+    /// Prints the images as a contact sheet: a fixed grid of equally sized thumbnails in file name order with the file name, date and exposure under each, over as many numbered pages as needed.
+    ContactSheet(Box<ContactSheetCli>),
+    /// Prints statistics about the input files (extensions, resolutions, aspect ratios, megapixels, estimated memory) without running the optimization.
+    Inspect(InspectCli),
+    /// Checks a saved layout for overlapping images, placements outside the canvas, missing source files and too little padding. Exits with status 1 if any are found.
+    Verify(VerifyCli),
+    /// Compares two saved layouts: coverage, aspect ratio and shared images, plus a side-by-side image.
+    Compare(CompareCli),
+    /// Times the fitness evaluation and the rendering on synthetic images and prints how long runs with different --pop-size and --gens values take on this machine.
+    Bench(BenchCli),
+    /// Writes colored rectangles of mixed aspect ratios, labeled with their number and size, to try the optimizer without real photos.
+    GenerateSamples(SamplesCli),
+    /// Runs short optimizations with random combinations of --pop-size, --mutation-rate and --crossover-rate on the images and reports the best, optionally as a config file.
+    Tune(TuneCli),
+    /// Runs an HTTP server that takes optimization jobs (a directory on the server or uploaded images, plus options), reports their progress and serves the finished collages.
+    Serve(ServeCli),
+    /// Prints a completion script for bash, zsh, fish, elvish or powershell, e.g. `ImageGridOptimizer completions bash > /etc/bash_completion.d/ImageGridOptimizer`.
+    Completions {
+        /// Shell to complete in.
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+}
+
+#[derive(clap::Args)]
+struct InspectCli {
+    /// Directories or archives to inspect.
+    #[arg(value_name = "DIRECTORY", required = true, value_hint = ValueHint::DirPath)]
+    directories: Vec<String>,
+    /// Estimates the memory use for images scaled to this width, as with -w for a run.
+    #[arg(short = 'w', long = "width", value_name = "WIDTH", value_parser = positive::<u32>)]
+    standard_width: Option<u32>,
+    /// Writes the statistics as a JSON object to stdout.
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(clap::Args)]
+struct BenchCli {
+    /// Number of synthetic images to choose from.
+    #[arg(long, value_name = "N", default_value_t = 200, value_parser = positive::<usize>)]
+    images: usize,
+    /// Longer side of the synthetic images in pixels, e.g. the -w of your runs.
+    #[arg(long, value_name = "PX", default_value_t = 512, value_parser = positive::<u32>)]
+    size: u32,
+    /// Minimum number of images per collage.
+    #[arg(long, value_name = "MIN_IMAGES", default_value_t = 6, value_parser = positive::<usize>)]
+    min_images: usize,
+    /// Maximum number of images per collage.
+    #[arg(long, value_name = "MAX_IMAGES", default_value_t = 60, value_parser = positive::<usize>)]
+    max_images: usize,
+    /// Seconds to time the evaluation for, once on one thread and once on all threads.
+    #[arg(long, value_name = "SECONDS", default_value_t = 5.0, value_parser = positive::<f64>)]
+    seconds: f64,
+}
+
+#[derive(clap::Args)]
+struct TuneCli {
+    /// Directories or archives with the images to tune on.
+    #[arg(value_name = "DIRECTORY", required = true, value_hint = ValueHint::DirPath)]
+    directories: Vec<String>,
+    /// Scales all images to this width, as with -w for a run; use the same value as for the real run.
+    #[arg(short = 'w', long = "width", value_name = "WIDTH", value_parser = positive::<u32>)]
+    standard_width: Option<u32>,
+    /// Minimum number of images per collage.
+    #[arg(long, value_name = "MIN_IMAGES", default_value_t = 6, value_parser = positive::<usize>)]
+    min_images: usize,
+    /// Maximum number of images per collage.
+    #[arg(long, value_name = "MAX_IMAGES", default_value_t = 60, value_parser = positive::<usize>)]
+    max_images: usize,
+    /// Number of parameter combinations to try; the first one is always the defaults.
+    #[arg(long, value_name = "N", default_value_t = 12, value_parser = positive::<usize>)]
+    trials: usize,
+    /// Collages evaluated per run, so that large populations get fewer generations.
+    #[arg(long, value_name = "N", default_value_t = 20000, value_parser = positive::<usize>)]
+    evaluations: usize,
+    /// Runs per trial with different seeds; the trials are compared by their mean best fitness.
+    #[arg(long, value_name = "N", default_value_t = 3, value_parser = positive::<usize>)]
+    repeats: usize,
+    /// Seed for picking the combinations and the first seed of the runs of every trial.
+    #[arg(long, value_name = "SEED", default_value_t = 0)]
+    seed: u64,
+    /// Writes the best combination as a config file to use with --config.
+    #[arg(long, value_name = "FILE")]
+    write_config: Option<PathBuf>,
+}
+
+#[derive(clap::Args)]
+struct SamplesCli {
+    /// Number of images to write.
+    #[arg(long, value_name = "N", default_value_t = 200, value_parser = positive::<usize>)]
+    count: usize,
+    /// Directory to write the images to; it is created if needed.
+    #[arg(long = "out", value_name = "DIR", default_value = "samples", value_hint = ValueHint::DirPath)]
+    output: PathBuf,
+    /// Largest longer side of the images in pixels; the images vary between half and all of it.
+    #[arg(long, value_name = "PX", default_value_t = 1200, value_parser = positive::<u32>)]
+    size: u32,
+    /// Seed for the sizes and colors, the same seed writes the same images.
+    #[arg(long, value_name = "SEED", default_value_t = 0)]
+    seed: u64,
+}
+
+#[derive(clap::Args)]
+struct ServeCli {
+    /// Port to listen on.
+    #[arg(long, value_name = "PORT", default_value_t = 8080)]
+    port: u16,
+    /// Address to listen on; the default only accepts connections from this machine. The server has no authentication, so only listen on other addresses in a trusted network.
+    #[arg(long, value_name = "ADDRESS", default_value = "127.0.0.1")]
+    host: String,
+    /// Directory for the uploaded images and the results of the jobs (default: ImageGridOptimizer/jobs in the system temp directory).
+    #[arg(long, value_name = "DIR", value_hint = ValueHint::DirPath)]
+    jobs_dir: Option<PathBuf>,
+    /// Largest request accepted, in megabytes, e.g. for uploaded images.
+    #[arg(long, value_name = "MB", default_value_t = 1024.0, value_parser = positive::<f64>)]
+    max_upload: f64,
+    /// Lets web pages from this origin use the API from a browser, e.g. http://kiosk.local:3000. Without it, browsers keep pages of other origins from reading the responses.
+    #[arg(long, value_name = "ORIGIN")]
+    allow_origin: Option<String>,
+    /// Directory whose images and archives jobs may read through "dirs"; can be given several times. Without it, jobs only work on uploaded images.
+    #[arg(long = "allow-dir", value_name = "DIR", value_hint = ValueHint::DirPath)]
+    allow_dirs: Vec<PathBuf>,
+}
+
+#[derive(clap::Args)]
+struct VerifyCli {
+    /// Layout file to check.
+    #[arg(value_name = "LAYOUT")]
+    layout: PathBuf,
+    /// Smallest allowed space between images in pixels; lower it for layouts written with --max-output.
+    #[arg(long, value_name = "PX", default_value_t = PADDING_SIZE as i32)]
+    padding: i32,
+}
+
+#[derive(clap::Args)]
+struct CompareCli {
+    /// First layout file.
+    #[arg(value_name = "LAYOUT_A")]
+    layout_a: PathBuf,
+    /// Second layout file.
+    #[arg(value_name = "LAYOUT_B")]
+    layout_b: PathBuf,
+    /// Side-by-side image of both collages with shared images framed green and the others red.
+    #[arg(short, long, value_name = "FILE", default_value = "compare.png")]
+    output: PathBuf,
+}
+
+#[derive(clap::Args)]
+struct RenderCli {
+    /// Layout file written by a previous run.
+    #[arg(value_name = "LAYOUT")]
+    layout: PathBuf,
+    /// Output image; the format follows the extension (.jpg, .png, .webp, .avif, .pdf, ...).
+    #[arg(short, long, value_name = "FILE", default_value = "output.png")]
+    output: PathBuf,
+    /// Scales the whole layout, e.g. 4 for a print version of a thumbnail run.
+    #[arg(long, value_name = "FACTOR", default_value_t = 1.0, value_parser = positive::<f64>)]
+    scale: f64,
+    #[command(flatten)]
+    decode: DecodeCli,
+    #[command(flatten)]
+    draw: DrawCli,
+    #[command(flatten)]
+    file: FileCli,
+}
+
+#[derive(clap::Args)]
+struct ContactSheetCli {
+    /// Directories or archives with the images.
+    #[arg(value_name = "DIRECTORY", required = true, value_hint = ValueHint::DirPath)]
+    directories: Vec<String>,
+    /// Output image; with several pages they are saved as contact_sheet_01.jpg, contact_sheet_02.jpg, ..., and a .pdf gets one page each.
+    #[arg(short, long, value_name = "FILE", default_value = "contact_sheet.jpg")]
+    output: PathBuf,
+    /// Thumbnails per row.
+    #[arg(long, value_name = "N", default_value_t = 6, value_parser = positive::<u32>)]
+    columns: u32,
+    /// Rows of thumbnails per page.
+    #[arg(long, value_name = "N", default_value_t = 8, value_parser = positive::<u32>)]
+    rows: u32,
+    /// Side of the square every thumbnail is fitted into, in pixels.
+    #[arg(long, value_name = "PX", default_value_t = 300, value_parser = positive::<u32>)]
+    thumb_size: u32,
+    /// Heading at the top of every page, e.g. the name of the shoot.
+    #[arg(long, value_name = "TEXT")]
+    title: Option<String>,
+    /// Filter for images (extension or part of filename).
+    #[arg(short, long, value_name = "FILTER")]
+    filter: Option<String>,
+    #[command(flatten)]
+    decode: DecodeCli,
+    #[command(flatten)]
+    file: FileCli,
+}
+
+#[derive(clap::Args)]
+struct OptimizeCli {
+    /// Directory or .zip/.tar/.tar.gz archive containing the images. Can be given several times.
+    #[arg(value_name = "DIRECTORY", required_unless_present_any = ["from_list", "dump_config"], value_hint = ValueHint::DirPath)]
+    directories: Vec<String>,
+    /// Loads the files listed in FILE (or stdin with '-') instead of scanning a directory. Lines are CSV: path[,weight[,caption]]; paths may be http(s) URLs.
+    #[arg(long, value_name = "FILE", conflicts_with = "directories")]
+    from_list: Option<String>,
+    /// Reads the settings from a TOML file. Options given on the command line override the file.
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+    /// Writes the effective settings of the command line and --config to a TOML file and exits.
+    #[arg(long, value_name = "FILE")]
+    dump_config: Option<PathBuf>,
+    #[command(flatten)]
+    input: InputCli,
+    #[command(flatten)]
+    decode: DecodeCli,
+    #[command(flatten)]
+    fitness: FitnessCli,
+    #[command(flatten)]
+    ga: GaCli,
+    #[command(flatten)]
+    layout: LayoutCli,
+    #[command(flatten)]
+    draw: DrawCli,
+    /// Output image; the format follows the extension (.jpg, .png, .webp, .avif, .pdf, ...). layout.json is written next to it.
+    #[arg(short, long, value_name = "FILE", default_value = "output.jpg", help_heading = "Output")]
+    output: PathBuf,
+    #[command(flatten)]
+    export: ExportCli,
+    #[command(flatten)]
+    file: FileCli,
+    #[command(flatten)]
+    progress: ProgressCli,
+}
+
+#[derive(clap::Args)]
+#[command(next_help_heading = "Input")]
+struct InputCli {
+    /// Where images listed by URL are downloaded to and reused from (default: a directory in the system temp dir).
+    #[arg(long, value_name = "DIR")]
+    cache_dir: Option<PathBuf>,
+    /// Skips downloads larger than this many megabytes (default: 50).
+    #[arg(long, value_name = "MB", value_parser = positive::<f64>)]
+    max_download_size: Option<f64>,
+    /// Number of images downloaded at the same time (default: 8).
+    #[arg(long, value_name = "N", value_parser = positive::<usize>)]
+    download_jobs: Option<usize>,
+    /// Filter for images (extension or part of filename).
+    #[arg(short, long, value_name = "FILTER")]
+    filter: Option<String>,
+    /// Only loads files whose name matches the regular expression, e.g. '^IMG_\d{4}\.(jpe?g|png)$'.
+    #[arg(long, value_name = "REGEX", value_parser = Regex::new)]
+    filter_regex: Option<Regex>,
+    /// Also loads images that are symbolic links; they are skipped by default.
+    #[arg(long)]
+    follow_symlinks: bool,
+    /// Also loads hidden and system files (names starting with a dot, Thumbs.db, desktop.ini, files with the Windows hidden attribute); they are skipped by default.
+    #[arg(long)]
+    include_hidden: bool,
+    /// Aborts if more than PERCENT percent of the images could not be read or decoded, e.g. 0 to fail on any unreadable file.
+    #[arg(long, value_name = "PERCENT", value_parser = percentage_limit)]
+    strict: Option<f64>,
+    /// Optional standard width for scaling images.
+    #[arg(short = 'w', long = "width", value_name = "WIDTH", value_parser = positive::<u32>)]
+    standard_width: Option<u32>,
+    /// Scales images to the same width, the same height, or the same pixel area as a WIDTHxWIDTH square (default: width).
+    #[arg(long, value_name = "MODE", value_enum, requires = "standard_width")]
+    scale_mode: Option<ScaleMode>,
+    /// Keeps the estimated memory use under SIZE, e.g. 8G or 512M, by loading large images at a lower resolution.
+    #[arg(long, value_name = "SIZE", value_parser = memory_size)]
+    max_memory: Option<u64>,
+    /// Keeps the loaded (decoded and scaled) images in DIR and reuses them in later runs on the same files.
+    #[arg(long, value_name = "DIR", value_hint = ValueHint::DirPath)]
+    image_cache: Option<PathBuf>,
+    /// Skips images narrower than PX pixels, e.g. thumbnails and icons.
+    #[arg(long, value_name = "PX", default_value_t = 0)]
+    min_width: u32,
+    /// Skips images lower than PX pixels.
+    #[arg(long, value_name = "PX", default_value_t = 0)]
+    min_height: u32,
+    /// Skips files smaller than BYTES without decoding them.
+    #[arg(long, value_name = "BYTES", default_value_t = 0)]
+    min_bytes: u64,
+    /// Skips images whose width/height ratio is below RATIO, e.g. 0.5 or 1:2 to exclude tall screenshots.
+    #[arg(long, value_name = "RATIO", value_parser = aspect_ratio)]
+    ar_min: Option<f64>,
+    /// Skips images whose width/height ratio is above RATIO, e.g. 2.5 to exclude panoramas.
+    #[arg(long, value_name = "RATIO", value_parser = aspect_ratio)]
+    ar_max: Option<f64>,
+    /// Skips blurry images whose sharpness (variance of the Laplacian) is below VALUE, e.g. 100.
+    #[arg(long, value_name = "VALUE")]
+    min_sharpness: Option<f64>,
+    /// Only uses photos taken on or after DATE (YYYY-MM-DD) according to their EXIF data.
+    #[arg(long, value_name = "DATE", value_parser = date)]
+    since: Option<String>,
+    /// Only uses photos taken on or before DATE (YYYY-MM-DD) according to their EXIF data.
+    #[arg(long, value_name = "DATE", value_parser = date)]
+    until: Option<String>,
+    /// Collapses near-identical photos (burst shots, edited copies) and keeps the one with the highest resolution.
+    #[arg(long)]
+    dedup: bool,
+    /// How different two photos may be and still count as duplicates, 0-64 (default: 6). Implies --dedup.
+    #[arg(long, value_name = "BITS", value_parser = RangedU64ValueParser::<u32>::new().range(0..=64))]
+    dedup_threshold: Option<u32>,
+    /// Extracts a frame from every video file at this interval (e.g. 5s, 500ms, 1m) using ffmpeg.
+    #[arg(long, value_name = "INTERVAL", value_parser = interval)]
+    video_every: Option<f64>,
+}
+
+/// Options for decoding individual input files, shared by the optimizer and `render`.
+#[derive(clap::Args)]
+#[command(next_help_heading = "Input")]
+struct DecodeCli {
+    /// Frame used from animated GIF, WebP and PNG files; N counts from 0.
+    #[arg(long, value_name = "first|middle|N", default_value = "first", value_parser = animated_frame)]
+    animated_frame: AnimatedFrame,
+    /// Resolution at which SVG files and PDF pages (via pdftoppm) are rasterized.
+    #[arg(long, value_name = "DPI", default_value_t = 96.0, value_parser = positive::<f32>)]
+    raster_dpi: f32,
+    /// Adjusts images that are close to a standard aspect ratio (1:1, 4:3, 3:2, 16:9, ...) to exactly that ratio by removing low-energy seams, so they pack into even rows.
+    #[arg(long, value_name = "METHOD", value_parser = ["seam-carving"])]
+    retarget: Option<String>,
+    /// Largest share of the width or height that --retarget may remove (default: 10).
+    #[arg(long, value_name = "PERCENT", requires = "retarget", value_parser = percentage)]
+    retarget_max: Option<f64>,
+}
+
+#[derive(clap::Args)]
+#[command(next_help_heading = "Fitness")]
+struct FitnessCli {
+    /// Arranges the images by their dominant color and prefers selections with smooth color transitions between neighbours.
+    #[arg(long)]
+    color_flow: bool,
+    /// How much smooth color transitions raise the fitness (default: 1). Implies --color-flow.
+    #[arg(long, value_name = "FACTOR", value_parser = positive::<f64>)]
+    color_flow_strength: Option<f64>,
+    /// Weights images by a CSV file of 'name,weight' lines; heavier images are picked more often and count more in the fitness.
+    #[arg(long, value_name = "CSV")]
+    weights: Option<PathBuf>,
+    /// Prefers photos with a star rating from XMP sidecars or embedded XMP/EXIF metadata.
+    #[arg(long)]
+    ratings: bool,
+    /// Extra weight per star of a rated photo (default: 0.25, so 4 stars count twice). Implies --ratings.
+    #[arg(long, value_name = "FACTOR", value_parser = non_negative::<f64>)]
+    rating_bonus: Option<f64>,
+    /// Always includes photos rated with at least STARS stars, e.g. 5. Implies --ratings.
+    #[arg(long, value_name = "STARS", value_parser = RangedU64ValueParser::<u8>::new().range(1..=5))]
+    pin_rating: Option<u8>,
+}
+
+#[derive(clap::Args)]
+#[command(next_help_heading = "GA")]
+struct GaCli {
+    /// Population size for the genetic algorithm.
+    #[arg(long = "pop-size", value_name = "POP_SIZE", default_value_t = 1000, value_parser = positive::<usize>)]
+    population_size: usize,
+    /// Number of generations for the genetic algorithm.
+    #[arg(long = "gens", value_name = "GENS", default_value_t = 3000, value_parser = positive::<usize>)]
+    generations: usize,
+    /// Minimum number of images per collage.
+    #[arg(long, value_name = "MIN_IMAGES", default_value_t = 6, value_parser = positive::<usize>)]
+    min_images: usize,
+    /// Maximum number of images per collage.
+    #[arg(long, value_name = "MAX_IMAGES", default_value_t = 60, value_parser = positive::<usize>)]
+    max_images: usize,
+    /// Uses at least N images from every input directory.
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    per_dir_min: usize,
+    /// Uses at most N images from every input directory.
+    #[arg(long, value_name = "N")]
+    per_dir_max: Option<usize>,
+    /// Mutation rate for the genetic algorithm, between 0 and 1.
+    #[arg(long, value_name = "MUTATION_RATE", default_value_t = 0.1, value_parser = fraction::<f64>)]
+    mutation_rate: f64,
+    /// Relative probabilities of the mutation operators as OP=WEIGHT pairs: add, remove and replace change which images are used, swap and reorder change the order they are packed in, nudge shifts an image of a pile and moves it up or down, rescale makes an image of a packed layout larger or smaller and rotate turns it within --max-rotation. Operators left out are not used (default: add=1,remove=1,replace=1, swap=1 for layouts other than packed, nudge=1 for --layout pile, and rotate=1 for --layout packed with --scrapbook).
+    #[arg(long, value_name = "OP=WEIGHT,...", value_parser = mutation_weights)]
+    mutation_weights: Option<MutationWeights>,
+    /// Crossover rate for the genetic algorithm, between 0 and 1.
+    #[arg(long, value_name = "CROSSOVER_RATE", default_value_t = 0.7, value_parser = fraction::<f64>)]
+    crossover_rate: f64,
+    /// How parents are combined: 'cut' joins the start of one parent's image list with the end of the other's, 'layout' takes the images in a region of one parent's collage and those outside it from the other, keeping neighbouring images together.
+    #[arg(long, value_name = "KIND", value_enum, default_value_t = CrossoverKind::Cut)]
+    crossover: CrossoverKind,
+    /// Share of the first generation built greedily (largest images first, landscape and portrait images balanced) instead of at random; 0 starts purely at random.
+    #[arg(long, value_name = "FRACTION", default_value_t = 0.1, value_parser = fraction::<f64>)]
+    greedy_seeds: f64,
+    /// Restarts the population when the best fitness has not improved for GENS generations, keeping only the best --restart-keep collages, to escape local optima.
+    #[arg(long, value_name = "GENS", value_parser = positive::<usize>)]
+    restart_after: Option<usize>,
+    /// Number of the best collages that survive a restart.
+    #[arg(long, value_name = "N", default_value_t = 2, value_parser = positive::<usize>)]
+    restart_keep: usize,
+    /// Fitness sharing: collages whose images differ by less than this Jaccard distance (0 to 1, e.g. 0.3) share their fitness when parents are chosen, which keeps the population diverse.
+    #[arg(long, value_name = "DISTANCE", value_parser = fraction::<f64>)]
+    sharing_radius: Option<f64>,
+    /// Share of the generations, e.g. 0.3, that judge collages coarsely: the images are packed at 1/--coarse-factor of their size with less padding, then at half that factor and so on, before the final generations use the full sizes.
+    #[arg(long, value_name = "FRACTION", default_value_t = 0.0, value_parser = fraction::<f64>)]
+    coarse_to_fine: f64,
+    /// Factor the images are shrunk by in the first coarse generations of --coarse-to-fine.
+    #[arg(long, value_name = "N", default_value_t = 4, value_parser = positive::<u32>)]
+    coarse_factor: u32,
+    /// Seed for the random number generator to reproduce a run (default: random, printed and stored in the output).
+    #[arg(long, value_name = "SEED")]
+    seed: Option<u64>,
+    /// Starts from the collage of an earlier run: its images and variations of them join the first generation, so adding a few photos does not mean starting over.
+    #[arg(long, value_name = "LAYOUT")]
+    seed_layout: Option<PathBuf>,
+    /// Saves the N best layouts that differ in their images as output_1.jpg ... output_N.jpg with layout_1.json ... layout_N.json, to pick a favorite.
+    #[arg(long, value_name = "N", value_parser = positive::<usize>)]
+    top_n: Option<usize>,
+    /// Runs the whole optimization K times, each from a fresh random start, and keeps the best collage; guards against an unlucky start.
+    #[arg(long, value_name = "K", default_value_t = 1, value_parser = positive::<usize>)]
+    restarts: usize,
+}
+
+#[derive(clap::Args)]
+#[command(next_help_heading = "Layout")]
+struct LayoutCli {
+    /// Arrangement of the images: packed rectangles of their own sizes, a strip of images scaled to a common height (or width) in one or more rows, a honeycomb of hexagonal cells, a spiral around a hero image, a pile of overlapping prints, or cells split at the golden ratio with the images cropped to fill them.
+    #[arg(long, value_name = "MODE", value_enum, default_value_t = LayoutKind::Packed)]
+    layout: LayoutKind,
+    /// With --layout strip, whether the images run side by side (horizontal) or on top of each other (vertical).
+    #[arg(long, value_name = "DIRECTION", value_enum, default_value_t = Direction::Horizontal)]
+    direction: Direction,
+    /// With --layout strip, the common height (horizontal) or width (vertical) of the images in pixels (default: the median of the images).
+    #[arg(long, value_name = "PX", value_parser = positive::<u32>)]
+    strip_size: Option<u32>,
+    /// With --layout strip, the length in pixels every row should reach; the optimizer picks the images and their order to get close to it (default: 10 times the strip size).
+    #[arg(long, value_name = "PX", value_parser = positive::<u32>)]
+    strip_length: Option<u32>,
+    /// With --layout strip, wraps the images into this many rows (columns for a vertical strip) of --strip-length each.
+    #[arg(long, value_name = "N", default_value_t = 1, value_parser = positive::<u32>)]
+    strip_rows: u32,
+    /// With --layout hex, the width of the hexagonal cells in pixels (default: the median of the shorter sides of the images).
+    #[arg(long, value_name = "PX", value_parser = positive::<u32>)]
+    hex_size: Option<u32>,
+    /// With --layout spiral, how many times larger the hero image in the center is drawn than the other images of its weight.
+    #[arg(long, value_name = "FACTOR", default_value_t = 2.0, value_parser = positive::<f64>)]
+    spiral_hero: f64,
+    /// With --layout pile, how far in percent of its width and height every image may reach over its neighbours.
+    #[arg(long, value_name = "PERCENT", default_value_t = 20.0, value_parser = percentage)]
+    max_overlap: f64,
+    /// Splits the images across N collages, optimized one after another and saved as output_01.jpg, output_02.jpg, ...
+    #[arg(long, value_name = "N|auto", value_parser = page_count)]
+    pages: Option<PageCount>,
+    /// Rotates every image by a small random angle and draws it with a drop shadow.
+    #[arg(long)]
+    scrapbook: bool,
+    /// Largest rotation in scrapbook mode in degrees.
+    #[arg(long, value_name = "DEGREES", default_value_t = 5.0)]
+    max_rotation: f64,
+    /// Keeps this region of the collage free of images. Can be given multiple times.
+    #[arg(long, value_name = "X,Y,W,H", value_parser = region)]
+    reserve: Vec<Rect>,
+    /// Smallest size of the shorter side of any image in the final collage, e.g. 300px. Smaller images are skipped and collages that --max-output would shrink below it are penalized.
+    #[arg(long, value_name = "PX", default_value = "0", value_parser = tile_size)]
+    min_tile: u32,
+    /// Fills gaps left by the optimizer with scaled-down unused images no smaller than MIN_PX.
+    #[arg(long, value_name = "MIN_PX")]
+    gap_fill: Option<u32>,
+    /// Checks every packed layout for overlapping images, images outside the canvas and too little padding, and aborts on the first invalid one. Slows the optimization down; debug builds check the final layout anyway.
+    #[arg(long)]
+    validate_layout: bool,
+}
+
+/// Options that control how a layout is drawn, shared by the optimizer and `render`.
+#[derive(clap::Args)]
+#[command(next_help_heading = "Render")]
+struct DrawCli {
+    /// Render a soft drop shadow behind each image.
+    #[arg(long)]
+    shadow: bool,
+    /// Blur radius of the drop shadow in pixels.
+    #[arg(long, value_name = "RADIUS", default_value_t = 8.0, value_parser = non_negative::<f32>)]
+    shadow_blur: f32,
+    /// Offset of the drop shadow to the bottom right in pixels.
+    #[arg(long, value_name = "PX", default_value_t = 4, allow_negative_numbers = true)]
+    shadow_offset: i32,
+    /// Opacity of the drop shadow between 0 and 1.
+    #[arg(long, value_name = "OPACITY", default_value_t = 0.5, value_parser = fraction::<f32>)]
+    shadow_opacity: f32,
+    /// Rounds the corners of every image by the given radius in pixels.
+    #[arg(long, value_name = "PX", default_value_t = 0)]
+    corner_radius: u32,
+    /// Draws a caption for every image from its filename, EXIF date or a .txt/.json sidecar file.
+    #[arg(long, value_name = "SOURCE", value_enum)]
+    labels: Option<LabelSource>,
+    /// Draws captions over the bottom of each image or in a band below it.
+    #[arg(long, value_name = "POSITION", value_enum, default_value_t = LabelPosition::Over)]
+    label_position: LabelPosition,
+    /// Font size of the captions in pixels.
+    #[arg(long, value_name = "PX", default_value_t = 16.0, value_parser = positive::<f32>)]
+    label_size: f32,
+    /// Adds a banner with this title to the collage.
+    #[arg(long, value_name = "TITLE")]
+    title: Option<String>,
+    /// Smaller second line below the title.
+    #[arg(long, value_name = "SUBTITLE", requires = "title")]
+    subtitle: Option<String>,
+    /// Places the title banner at the top or bottom of the collage.
+    #[arg(long, value_name = "POSITION", value_enum, default_value_t = BannerPosition::Top)]
+    title_position: BannerPosition,
+    /// Font size of the title in pixels.
+    #[arg(long, value_name = "PX", default_value_t = 48.0, value_parser = positive::<f32>)]
+    title_size: f32,
+    /// TrueType/OpenType font used for titles and captions.
+    #[arg(long, value_name = "FONT")]
+    font: Option<PathBuf>,
+    /// Image (e.g. a logo) blended onto the finished collage.
+    #[arg(long, value_name = "IMAGE")]
+    watermark: Option<PathBuf>,
+    /// Position of the watermark.
+    #[arg(long, value_name = "POSITION", value_enum, default_value_t = WatermarkPosition::BottomRight)]
+    watermark_pos: WatermarkPosition,
+    /// Opacity of the watermark between 0 and 1.
+    #[arg(long, value_name = "OPACITY", default_value_t = 0.4, value_parser = fraction::<f32>)]
+    watermark_opacity: f32,
+    /// Background color: white, black, a hex color such as #1e2a3b, or auto for a muted complement of the colors of the selected images (default: white).
+    #[arg(long, value_name = "COLOR", value_parser = background_color)]
+    background: Option<BackgroundColor>,
+    /// Image drawn behind the collage instead of the background color.
+    #[arg(long, value_name = "IMAGE")]
+    background_image: Option<PathBuf>,
+    /// Repeats the background image or stretches it to the canvas.
+    #[arg(long, value_name = "MODE", value_enum, default_value_t = BackgroundMode::Tile)]
+    background_mode: BackgroundMode,
+    /// Fills the gaps between images with the background or blurred copies of the nearest image.
+    #[arg(long, value_name = "MODE", value_enum, default_value_t = FillMode::Background)]
+    fill: FillMode,
+    /// Applies grayscale, sepia or duotone:#DARK,#LIGHT (e.g. duotone:#1b2a49,#f4d35e) to every image.
+    #[arg(long, value_name = "FILTER", value_parser = style_filter)]
+    style_filter: Option<StyleFilter>,
+    /// Matches the luminance histogram of every image to the average of all images, so photos from different cameras look consistent.
+    #[arg(long)]
+    normalize_tones: bool,
+}
+
+/// Additional files written by the optimizer.
+#[derive(clap::Args)]
+#[command(next_help_heading = "Output")]
+struct ExportCli {
+    /// With --pages, also writes all collages into one PDF with a page each.
+    #[arg(long, value_name = "FILE", requires = "pages")]
+    combined_pdf: Option<PathBuf>,
+    /// Also writes the layout as an SVG file for editing in Inkscape or Illustrator.
+    #[arg(long, value_name = "FILE")]
+    export_svg: Option<PathBuf>,
+    /// Embeds the images into the SVG as base64 instead of linking the files.
+    #[arg(long, requires = "export_svg")]
+    svg_embed: bool,
+    /// Also writes a layered OpenRaster file with every image on its own layer for GIMP or Krita.
+    #[arg(long, value_name = "FILE")]
+    export_ora: Option<PathBuf>,
+}
+
+/// Options for the output file (PDF page, tiling, metadata), shared by the optimizer and `render`.
+#[derive(clap::Args)]
+#[command(next_help_heading = "Output")]
+struct FileCli {
+    /// Scales the collage down to fit within this many pixels, e.g. 8000x8000.
+    #[arg(long, value_name = "WxH", value_parser = dimensions)]
+    max_output: Option<(u32, u32)>,
+    /// Paper size of PDF output: A0-A6, letter, legal, tabloid or WxH in mm.
+    #[arg(long, value_name = "SIZE", default_value = "a4", value_parser = paper)]
+    paper: (f32, f32),
+    /// Resolution of the collage in PDF output and for --physical-size; when given, also recorded in the headers of JPEG, PNG and TIFF output [default: 300].
+    #[arg(long, value_name = "DPI", value_parser = positive::<f32>)]
+    dpi: Option<f32>,
+    /// Scales the collage to the pixels needed to print it at this size with --dpi, e.g. 60x40cm, 600x400mm or 24x16in.
+    #[arg(long, value_name = "WxH", value_parser = physical_size)]
+    physical_size: Option<(f32, f32)>,
+    /// With --physical-size, warns about images that print at fewer pixels of their source file per inch than this.
+    #[arg(long, value_name = "DPI", default_value_t = 150.0, value_parser = positive::<f32>, requires = "physical_size")]
+    min_dpi: f32,
+    /// Bleed added around the paper size of PDF output in millimeters.
+    #[arg(long, value_name = "MM", default_value_t = 3.0, value_parser = non_negative::<f32>)]
+    bleed: f32,
+    /// Splits the collage into overlapping tiles with crop marks for printing a poster in pieces, e.g. 2x2.
+    #[arg(long, value_name = "COLSxROWS", value_parser = dimensions)]
+    tile: Option<(u32, u32)>,
+    /// Overlap between neighbouring tiles in percent of the tile size.
+    #[arg(long, value_name = "PERCENT", default_value_t = 5.0, value_parser = non_negative::<f32>)]
+    tile_overlap: f32,
+    /// Does not embed the source filenames and parameters as XMP metadata into the output.
+    #[arg(long)]
+    no_metadata: bool,
+    /// Quality of JPEG, WebP and AVIF output and of images in PDF output.
+    #[arg(long, value_name = "1-100", default_value_t = 90, value_parser = RangedU64ValueParser::<u8>::new().range(1..=100))]
+    quality: u8,
+    /// Trades PNG encoding speed against file size.
+    #[arg(long, value_name = "LEVEL", default_value = "default", value_parser = ["fast", "default", "best"])]
+    png_compression: String,
+    /// Embeds an ICC profile into PNG and JPEG output: srgb, or an ICC file of an RGB color space such as Adobe RGB that the collage is converted to.
+    #[arg(long, value_name = "PROFILE", value_parser = icc::read_output_profile)]
+    output_profile: Option<::std::vec::Vec<u8>>,
+    /// Converts the collage to CMYK with the ICC profile of the print shop (e.g. ISOcoated_v2_eci.icc) and saves it as a TIFF with the profile and --dpi embedded; needs an output ending in .tif or .tiff.
+    #[arg(long, value_name = "ICC", value_parser = icc::read_print_profile, conflicts_with = "output_profile")]
+    print_profile: Option<::std::vec::Vec<u8>>,
+}
+
+#[derive(clap::Args)]
+#[command(next_help_heading = "Progress")]
+struct ProgressCli {
+    /// Hides the progress bar of the optimization.
+    #[arg(short, long)]
+    quiet: bool,
+    /// Shows a full-screen dashboard during the optimization with keys to pause (p), raise the mutation rate (m) or stop early and save (s).
+    #[arg(long, conflicts_with = "json")]
+    tui: bool,
+    /// Writes newline-delimited JSON events (images loaded, generations, layout, saved files) to stdout and all other messages to stderr.
+    #[arg(long)]
+    json: bool,
+    /// Writes the best, mean and worst fitness, free area and aspect deviation of every generation to a CSV file.
+    #[arg(long, value_name = "CSV")]
+    stats_out: Option<PathBuf>,
+    /// Draws the best, mean and worst fitness over the generations as a chart, e.g. fitness.png.
+    #[arg(long, value_name = "IMAGE")]
+    plot: Option<PathBuf>,
+    /// Writes a self-contained HTML report with a preview, the parameters, the fitness curve, used and unused images and timings.
+    #[arg(long, value_name = "HTML")]
+    report: Option<PathBuf>,
+    /// Every N generations, renders the best collage so far as best_gen_<N>.jpg next to the output.
+    #[arg(long, value_name = "N", value_parser = positive::<usize>)]
+    save_every: Option<usize>,
+    /// With --save-every, overwrites this file (e.g. preview.jpg) instead of writing a file per save.
+    #[arg(long, value_name = "FILE", requires = "save_every")]
+    preview: Option<PathBuf>,
+    /// Records the best collage while it converges as an animated GIF, e.g. evolution.gif, or as a video with ffmpeg for other extensions such as .mp4.
+    #[arg(long, value_name = "FILE")]
+    evolution_gif: Option<PathBuf>,
+    /// Records a frame for --evolution-gif every N generations (default: 25).
+    #[arg(long, value_name = "N", requires = "evolution_gif", value_parser = positive::<usize>)]
+    evolution_every: Option<usize>,
+    /// Keeps running and regenerates the collage whenever images are added to or removed from the input directories.
+    #[arg(long, conflicts_with = "from_list")]
+    watch: bool,
+    /// With --watch, starts every new run from the images of the previous collage.
+    #[arg(long, requires = "watch")]
+    warm_start: bool,
+    /// Reads the image headers only, reports how many images pass the filters, estimates the peak memory and the time per generation, and exits without optimizing.
+    #[arg(long, conflicts_with_all = ["watch", "tui"])]
+    dry_run: bool,
+}
+
+pub fn parse_args() -> Command {
+    let matches = matches();
+    if let Some((path, optimize_matches)) = optimize_matches(&matches).and_then(|m| Some((m.get_one::<PathBuf>("dump_config")?, m))) {
+        if let Err(e) = fs::write(path, dump_config(&Cli::command(), optimize_matches)) {
+            eprintln!("Error writing config file {}: {}", path.display(), e);
+            std::process::exit(1);
+        }
+        info!("Configuration written to {}", path.display());
+        std::process::exit(0);
+    }
+
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    match cli.command {
+        None => Command::Optimize(Box::new(optimize_args(cli.optimize).unwrap_or_else(|e| e.exit()))),
+        Some(Subcommands::Optimize(optimize)) => Command::Optimize(Box::new(optimize_args(*optimize).unwrap_or_else(|e| e.exit()))),
+        Some(Subcommands::Render(render)) => {
+            let RenderCli { layout, output, scale, decode, draw, file } = *render;
+            let (render_options, label_source) = draw.options(false).unwrap_or_else(|e| e.exit());
+            let output_options = file.options(&output).unwrap_or_else(|e| e.exit());
+            Command::Render(Box::new(RenderArgs {
+                layout,
+                output,
+                scale,
+                load: LoadOptions {
+                    animated_frame: decode.animated_frame,
+                    raster_dpi: decode.raster_dpi,
+                    retarget_max: decode.retarget_max(),
+                    ..Default::default()
+                },
+                label_source,
+                render: render_options,
+                output_options,
+            }))
+        }
+        Some(Subcommands::ContactSheet(sheet)) => {
+            let ContactSheetCli { directories, output, columns, rows, thumb_size, title, filter, decode, file } = *sheet;
+            let output_options = file.options(&output).unwrap_or_else(|e| e.exit());
+            Command::ContactSheet(Box::new(ContactSheetArgs {
+                dirs: directories,
+                output,
+                columns,
+                rows,
+                thumb_size,
+                title,
+                load: LoadOptions {
+                    filter,
+                    animated_frame: decode.animated_frame,
+                    raster_dpi: decode.raster_dpi,
+                    retarget_max: decode.retarget_max(),
+                    ..Default::default()
+                },
+                output_options,
+            }))
+        }
+        Some(Subcommands::Inspect(inspect)) => Command::Inspect(InspectArgs {
+            dirs: inspect.directories,
+            standard_width: inspect.standard_width,
+            json: inspect.json,
+        }),
+        Some(Subcommands::Verify(verify)) => Command::Verify(VerifyArgs { layout: verify.layout, padding: verify.padding }),
+        Some(Subcommands::Compare(compare)) => Command::Compare(CompareArgs {
+            layouts: [compare.layout_a, compare.layout_b],
+            output: compare.output,
+        }),
+        Some(Subcommands::Bench(bench)) => Command::Bench(BenchArgs {
+            images: bench.images,
+            size: bench.size,
+            min_images: bench.min_images,
+            max_images: bench.max_images,
+            duration: Duration::from_secs_f64(bench.seconds),
+        }),
+        Some(Subcommands::GenerateSamples(samples)) => Command::GenerateSamples(SamplesArgs {
+            count: samples.count,
+            output: samples.output,
+            size: samples.size,
+            seed: samples.seed,
+        }),
+        Some(Subcommands::Tune(tune)) => Command::Tune(TuneArgs {
+            dirs: tune.directories,
+            standard_width: tune.standard_width,
+            min_images: tune.min_images,
+            max_images: tune.max_images,
+            trials: tune.trials,
+            evaluations: tune.evaluations,
+            repeats: tune.repeats,
+            seed: tune.seed,
+            write_config: tune.write_config,
+        }),
+        Some(Subcommands::Serve(serve)) => Command::Serve(ServeArgs {
+            host: serve.host,
+            port: serve.port,
+            jobs_dir: serve.jobs_dir.unwrap_or_else(|| std::env::temp_dir().join("ImageGridOptimizer").join("jobs")),
+            max_upload: (serve.max_upload * 1024.0 * 1024.0) as u64,
+            allow_origin: serve.allow_origin,
+            allow_dirs: serve.allow_dirs,
+        }),
+        Some(Subcommands::Completions { shell }) => Command::Completions(shell),
+    }
+}
+
+/// Parses the options of an optimization given as command line arguments without the program
+/// name, e.g. for a job of the HTTP server. Unlike `parse_args`, errors are returned instead of
+/// ending the program, and `--config` files are not read.
+pub fn parse_optimize_args(args: &[String]) -> Result<Args, String> {
+    let argv = ["ImageGridOptimizer", "optimize"].into_iter().map(String::from).chain(args.iter().cloned());
+    let matches = Cli::command().try_get_matches_from(argv).map_err(|e| e.to_string())?;
+    let cli = Cli::from_arg_matches(&matches).map_err(|e| e.to_string())?;
+    match cli.command {
+        Some(Subcommands::Optimize(optimize)) => optimize_args(*optimize).map_err(|e| e.to_string()),
+        _ => Err("expected the options of an optimization".to_string()),
+    }
+}
+
+/// Writes the completion script for `shell` to stdout.
+pub fn print_completions(shell: Shell) {
+    let mut command = Cli::command();
+    let name = command.get_name().to_string();
+    generate(shell, &mut command, name, &mut std::io::stdout());
+}
+
+/// Parses the command line, filling in the settings of `--config` that it does not give.
+fn matches() -> ArgMatches {
+    let argv: Vec<OsString> = std::env::args_os().collect();
+    let matches = Cli::command().get_matches_from(&argv);
+    let Some((path, optimize_matches)) = optimize_matches(&matches).and_then(|m| Some((m.get_one::<PathBuf>("config")?, m))) else {
+        return matches;
+    };
+    match config_args(path, &Cli::command(), optimize_matches) {
+        // The settings are options of the optimizer, which ends the command line in both forms
+        Ok(settings) => Cli::command().get_matches_from(argv.into_iter().chain(settings)),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// The matches of the optimizer's options, given with or without the `optimize` subcommand.
+fn optimize_matches(matches: &ArgMatches) -> Option<&ArgMatches> {
+    match matches.subcommand() {
+        None => Some(matches),
+        Some(("optimize", optimize)) => Some(optimize),
+        Some(_) => None,
+    }
+}
+
+/// A usage error for values that can only be checked after parsing.
+fn invalid(message: String) -> clap::Error {
+    Cli::command().error(ErrorKind::ValueValidation, message)
+}
+
+fn optimize_args(cli: OptimizeCli) -> Result<Args, clap::Error> {
+    let OptimizeCli { directories, from_list, input, decode, fitness, ga, layout, draw, output, export, file, progress, .. } = cli;
+    if ga.min_images > ga.max_images {
+        return Err(invalid(format!("--min-images ({}) must not be larger than --max-images ({})", ga.min_images, ga.max_images)));
+    }
+    if let Some(max) = ga.per_dir_max.filter(|max| *max < ga.per_dir_min) {
+        return Err(invalid(format!("--per-dir-max ({}) must not be smaller than --per-dir-min ({})", max, ga.per_dir_min)));
+    }
+    if layout.layout != LayoutKind::Packed && !layout.reserve.is_empty() {
+        return Err(invalid("--reserve only works with --layout packed".to_string()));
+    }
+    let gene_ops = ga.mutation_weights.as_ref().is_some_and(|weights| weights.weight(MutationOp::Rescale) > 0.0 || weights.weight(MutationOp::Rotate) > 0.0);
+    if layout.layout != LayoutKind::Packed && gene_ops {
+        return Err(invalid("The rescale and rotate mutations only work with --layout packed".to_string()));
+    }
+    if matches!(layout.layout, LayoutKind::Hex | LayoutKind::Golden) {
+        // Cells cropped to their shape have no room for captions, rotation or thumbnails in between
+        let conflict = [("--labels", draw.labels.is_some()), ("--scrapbook", layout.scrapbook), ("--gap-fill", layout.gap_fill.is_some())]
+            .into_iter()
+            .find(|(_, given)| *given);
+        if let Some((option, _)) = conflict {
+            let mode = if layout.layout == LayoutKind::Hex { "hex" } else { "golden" };
+            return Err(invalid(format!("{} does not work with --layout {}", option, mode)));
+        }
+    }
+    if layout.layout == LayoutKind::Pile && layout.gap_fill.is_some() {
+        // The images of a pile cover the gaps themselves
+        return Err(invalid("--gap-fill does not work with --layout pile".to_string()));
+    }
+
+    let defaults = DownloadOptions::default();
+    let download = DownloadOptions {
+        cache_dir: input.cache_dir.unwrap_or(defaults.cache_dir),
+        max_bytes: input.max_download_size.map_or(defaults.max_bytes, |mb| (mb * 1024.0 * 1024.0) as u64),
+        jobs: input.download_jobs.unwrap_or(defaults.jobs),
+    };
+    let input_source = match from_list {
+        Some(list) => Input::List(list),
+        None => Input::Directories(directories),
+    };
+
+    let (render, label_source) = draw.options(layout.scrapbook)?;
+    let max_rotation = if layout.scrapbook { layout.max_rotation.abs() } else { 0.0 };
+    let caption_height = render.labels.as_ref().map_or(0, |l| l.band_height());
+    let output_options = file.options(&output)?;
+
+    Ok(Args {
+        input: input_source,
+        download,
+        // The images of a hexagonal grid are assigned to the cells by color
+        color_flow: fitness.color_flow_strength.or((fitness.color_flow || layout.layout == LayoutKind::Hex).then_some(1.0)),
+        weights: fitness.weights,
+        rating_bonus: fitness
+            .rating_bonus
+            .or((fitness.ratings || fitness.pin_rating.is_some()).then_some(0.25)),
+        pin_rating: fitness.pin_rating,
+        dedup_threshold: input.dedup_threshold.or(input.dedup.then_some(6)),
+        max_memory: input.max_memory,
+        load: LoadOptions {
+            filter: input.filter,
+            filter_regex: input.filter_regex,
+            follow_symlinks: input.follow_symlinks,
+            include_hidden: input.include_hidden,
+            standard_size: input.standard_width,
+            scale_mode: input.scale_mode.unwrap_or_default(),
+            max_pixels: None,
+            image_cache: input.image_cache,
+            animated_frame: decode.animated_frame,
+            video_every: input.video_every,
+            raster_dpi: decode.raster_dpi,
+            retarget_max: decode.retarget_max(),
+            min_width: input.min_width,
+            min_height: input.min_height,
+            min_bytes: input.min_bytes,
+            aspect_ratio_min: input.ar_min,
+            aspect_ratio_max: input.ar_max,
+            min_sharpness: input.min_sharpness,
+            since: input.since,
+            until: input.until,
+            max_failed_percent: input.strict,
+        },
+        ga: GaSettings {
+            population_size: ga.population_size,
+            generations: ga.generations,
+            min_images: ga.min_images,
+            max_images: ga.max_images,
+            mutation_rate: ga.mutation_rate,
+            // Outside the rectangle packer the order decides where every image goes, so it is mutated too
+            mutation_weights: ga.mutation_weights.unwrap_or_else(|| match layout.layout {
+                // Evolved angles need less room than any angle up to --max-rotation
+                LayoutKind::Packed if max_rotation > 0.0 => MutationWeights([1.0, 1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0]),
+                LayoutKind::Packed => MutationWeights::default(),
+                LayoutKind::Pile => MutationWeights([1.0, 1.0, 1.0, 1.0, 0.0, 1.0, 0.0, 0.0]),
+                _ => MutationWeights([1.0, 1.0, 1.0, 1.0, 0.0, 0.0, 0.0, 0.0]),
+            }),
+            crossover_rate: ga.crossover_rate,
+            crossover: ga.crossover,
+            per_dir_min: ga.per_dir_min,
+            per_dir_max: ga.per_dir_max,
+            quiet: progress.quiet,
+            initial: Vec::new(),
+            greedy_share: ga.greedy_seeds,
+            restart_after: ga.restart_after,
+            restart_keep: ga.restart_keep,
+            sharing_radius: ga.sharing_radius.filter(|radius| *radius > 0.0),
+            coarse_share: ga.coarse_to_fine,
+            coarse_factor: ga.coarse_factor,
+            cancel: CancellationToken::new(),
+        },
+        seed: ga.seed,
+        seed_layout: ga.seed_layout,
+        pages: layout.pages,
+        combined_pdf: export.combined_pdf,
+        label_source,
+        gap_fill: layout.gap_fill,
+        export_svg: export.export_svg,
+        svg_embed: export.svg_embed,
+        export_ora: export.export_ora,
+        stats_out: progress.stats_out,
+        plot: progress.plot,
+        report: progress.report,
+        save_every: progress.save_every,
+        preview: progress.preview,
+        evolution_gif: progress.evolution_gif,
+        evolution_every: progress.evolution_every.unwrap_or(25),
+        output,
+        json: progress.json,
+        tui: progress.tui,
+        top_n: ga.top_n,
+        restarts: ga.restarts,
+        watch: progress.watch,
+        warm_start: progress.warm_start,
+        dry_run: progress.dry_run,
+        packing: PackingOptions {
+            layout: layout.layout,
+            strip: StripOptions {
+                direction: layout.direction,
+                size: layout.strip_size,
+                length: layout.strip_length,
+                rows: layout.strip_rows,
+            },
+            hex_size: layout.hex_size,
+            spiral: SpiralOptions { hero_scale: layout.spiral_hero, ..Default::default() },
+            max_overlap: layout.max_overlap / 100.0,
+            max_rotation,
+            caption_height,
+            reserved: layout.reserve,
+            min_tile: layout.min_tile,
+            max_size: output_options.size_limit(),
+            validate: layout.validate_layout,
+            downscale: 1,
+        },
+        render,
+        output_options,
+    })
+}
+
+impl DecodeCli {
+    /// Maximum fraction of the width or height removed by `--retarget seam-carving`.
+    fn retarget_max(&self) -> Option<f64> {
+        self.retarget.as_ref()?;
+        Some(self.retarget_max.unwrap_or(10.0) / 100.0)
+    }
+}
+
+impl DrawCli {
+    /// Reads the drawing options and the files they name; `force_shadow` turns on the drop shadow
+    /// (used by scrapbook mode).
+    fn options(self, force_shadow: bool) -> Result<(RenderOptions, Option<LabelSource>), clap::Error> {
+        let shadow = (self.shadow || force_shadow).then_some(ShadowOptions {
+            blur_radius: self.shadow_blur,
+            offset: self.shadow_offset,
+            opacity: self.shadow_opacity,
+        });
+        let labels = self.labels.map(|_| LabelOptions { size: self.label_size, position: self.label_position });
+        let title_size = self.title_size;
+        let title_position = self.title_position;
+        let title = self.title.map(|title| TitleOptions { title, subtitle: self.subtitle, size: title_size, position: title_position });
+        let font = self.font.map(|path| load_font(&path).map_err(invalid)).transpose()?;
+        let watermark = match self.watermark {
+            Some(path) => Some(WatermarkOptions {
+                image: open_rgba(&path, "watermark")?,
+                position: self.watermark_pos,
+                opacity: self.watermark_opacity,
+            }),
+            None => None,
+        };
+        let background_image = match self.background_image {
+            Some(path) => Some(BackgroundImage { image: open_rgba(&path, "background image")?, mode: self.background_mode }),
+            None => None,
+        };
+
+        let render = RenderOptions {
+            shadow,
+            corner_radius: self.corner_radius,
+            labels,
+            title,
+            font,
+            watermark,
+            background: self.background.unwrap_or_default(),
+            background_image,
+            fill: self.fill,
+            normalize_tones: self.normalize_tones,
+            style_filter: self.style_filter,
+        };
+        Ok((render, self.labels))
+    }
+}
+
+fn open_rgba(path: &std::path::Path, what: &str) -> Result<image::RgbaImage, clap::Error> {
+    match image::open(path) {
+        Ok(img) => Ok(img.to_rgba8()),
+        Err(e) => Err(invalid(format!("Error opening {} {}: {}", what, path.display(), e))),
+    }
+}
+
+impl FileCli {
+    fn options(self, output: &Path) -> Result<OutputOptions, clap::Error> {
+        let is_tiff = matches!(ImageFormat::from_path(output), Ok(ImageFormat::Tiff));
+        if self.print_profile.is_some() && !is_tiff {
+            return Err(invalid(format!("--print-profile writes a CMYK TIFF, but the output {} is not a .tif file", output.display())));
+        }
+        Ok(OutputOptions {
+            paper: self.paper,
+            dpi: self.dpi.unwrap_or(300.0),
+            embed_dpi: self.dpi.is_some() || self.physical_size.is_some(),
+            physical_size: self.physical_size,
+            min_dpi: self.min_dpi,
+            bleed_mm: self.bleed,
+            tiles: self.tile,
+            tile_overlap: self.tile_overlap,
+            metadata: !self.no_metadata,
+            quality: self.quality,
+            max_size: self.max_output,
+            color_profile: self.output_profile,
+            print_profile: self.print_profile,
+            png_compression: match self.png_compression.as_str() {
+                "fast" => CompressionType::Fast,
+                "best" => CompressionType::Best,
+                _ => CompressionType::Default,
+            },
+        })
+    }
+}
+
+/// Parses a number greater than zero.
+fn positive<T: FromStr + PartialOrd + From<u8>>(value: &str) -> Result<T, String> {
+    value.parse::<T>().ok().filter(|v| *v > T::from(0)).ok_or_else(|| "expected a number greater than 0".to_string())
+}
+
+/// Parses a number of at least zero.
+fn non_negative<T: FromStr + PartialOrd + From<u8>>(value: &str) -> Result<T, String> {
+    value.parse::<T>().ok().filter(|v| *v >= T::from(0)).ok_or_else(|| "expected a number of at least 0".to_string())
+}
+
+/// Parses a number between 0 and 1.
+fn fraction<T: FromStr + PartialOrd + From<u8>>(value: &str) -> Result<T, String> {
+    value
+        .parse::<T>()
+        .ok()
+        .filter(|v| *v >= T::from(0) && *v <= T::from(1))
+        .ok_or_else(|| "expected a number between 0 and 1".to_string())
+}
+
+/// Parses a percentage above 0 and below 100.
+fn percentage(value: &str) -> Result<f64, String> {
+    value.parse::<f64>().ok().filter(|p| *p > 0.0 && *p < 100.0).ok_or_else(|| "expected a percentage between 0 and 100".to_string())
+}
+
+/// Parses a percentage from 0 to 100.
+fn percentage_limit(value: &str) -> Result<f64, String> {
+    value.parse::<f64>().ok().filter(|p| (0.0..=100.0).contains(p)).ok_or_else(|| "expected a percentage from 0 to 100".to_string())
+}
+
+/// Parses a size in bytes with an optional K, M, G or T suffix (powers of 1024), e.g. 8G or 1.5GB.
+fn memory_size(value: &str) -> Result<u64, String> {
+    let value = value.trim().to_ascii_uppercase();
+    let value = value.strip_suffix('B').unwrap_or(&value);
+    let (number, factor) = match value.char_indices().last() {
+        Some((i, 'K')) => (&value[..i], 1u64 << 10),
+        Some((i, 'M')) => (&value[..i], 1 << 20),
+        Some((i, 'G')) => (&value[..i], 1 << 30),
+        Some((i, 'T')) => (&value[..i], 1 << 40),
+        _ => (value, 1),
+    };
+    number
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|n| *n > 0.0)
+        .map(|n| (n * factor as f64) as u64)
+        .ok_or_else(|| "expected a size such as 8G, 512M or 2048K".to_string())
+}
+
+fn page_count(value: &str) -> Result<PageCount, String> {
+    match value {
+        "auto" => Ok(PageCount::Auto),
+        n => n.parse::<usize>().ok().filter(|&n| n > 0).map(PageCount::Fixed).ok_or_else(|| "expected a number of pages or 'auto'".to_string()),
+    }
+}
+
+fn animated_frame(value: &str) -> Result<AnimatedFrame, String> {
+    match value {
+        "first" => Ok(AnimatedFrame::First),
+        "middle" => Ok(AnimatedFrame::Middle),
+        index => index.parse::<usize>().map(AnimatedFrame::Index).map_err(|_| "expected first, middle or a frame number".to_string()),
+    }
+}
+
+/// Parses a size such as `300px` or `300`.
+fn tile_size(value: &str) -> Result<u32, String> {
+    value.trim_end_matches("px").parse::<u32>().map_err(|_| "expected a size in pixels, e.g. 300px".to_string())
+}
+
+fn interval(value: &str) -> Result<f64, String> {
+    parse_interval(value).ok_or_else(|| "expected an interval such as 5s, 500ms or 1m".to_string())
+}
+
+fn paper(value: &str) -> Result<(f32, f32), String> {
+    parse_paper(value).ok_or_else(|| "expected A0-A6, letter, legal, tabloid or WxH in mm".to_string())
+}
+
+fn physical_size(value: &str) -> Result<(f32, f32), String> {
+    parse_physical_size(value).ok_or_else(|| "expected a size with a unit such as 60x40cm, 600x400mm or 24x16in".to_string())
+}
+
+fn dimensions(value: &str) -> Result<(u32, u32), String> {
+    parse_dimensions(value).ok_or_else(|| "expected two sizes such as 8000x6000".to_string())
+}
+
+fn background_color(value: &str) -> Result<BackgroundColor, String> {
+    match value {
+        "auto" => Ok(BackgroundColor::Auto),
+        color => parse_color(color).map(BackgroundColor::Color).ok_or_else(|| "expected white, black, auto or a hex color such as #1e2a3b".to_string()),
+    }
+}
+
+fn style_filter(value: &str) -> Result<StyleFilter, String> {
+    parse_style_filter(value).ok_or_else(|| "expected grayscale, sepia or duotone:#DARK,#LIGHT".to_string())
+}
+
+fn aspect_ratio(value: &str) -> Result<f64, String> {
+    parse_aspect_ratio(value).ok_or_else(|| "expected a ratio such as 1.5 or 3:2".to_string())
+}
+
+fn date(value: &str) -> Result<String, String> {
+    parse_date(value).ok_or_else(|| "expected a date as YYYY-MM-DD".to_string())
+}
+
+fn mutation_weights(value: &str) -> Result<MutationWeights, String> {
+    parse_mutation_weights(value).ok_or_else(|| "expected OP=WEIGHT pairs such as add=1,remove=1,swap=0.5 with the operators add, remove, replace, swap, reorder, nudge, rescale and rotate".to_string())
+}
+
+fn region(value: &str) -> Result<Rect, String> {
+    parse_region(value).ok_or_else(|| "expected x,y,w,h in pixels with a positive width and height".to_string())
+}
+
+/// Parses `grayscale`, `sepia` or `duotone:COLOR,COLOR`.
+fn parse_style_filter(value: &str) -> Option<StyleFilter> {
+    match value.to_ascii_lowercase().as_str() {
+        "grayscale" | "greyscale" => Some(StyleFilter::Grayscale),
+        "sepia" => Some(StyleFilter::Sepia),
+        _ => {
+            let (dark, light) = value.strip_prefix("duotone:")?.split_once(',')?;
+            Some(StyleFilter::Duotone(parse_color(dark.trim())?, parse_color(light.trim())?))
+        }
+    }
+}
+
+/// Parses `white`, `black` or a hex color such as `#1e2a3b`.
+fn parse_color(value: &str) -> Option<Rgba<u8>> {
+    match value.to_ascii_lowercase().as_str() {
+        "white" => return Some(Rgba([255, 255, 255, 255])),
+        "black" => return Some(Rgba([0, 0, 0, 255])),
+        _ => {}
+    }
+    let hex = value.strip_prefix('#').unwrap_or(value);
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some(Rgba([channel(0)?, channel(2)?, channel(4)?, 255]))
+}
+
+/// Parses an aspect ratio given as a number (`1.5`) or as `W:H` (`3:2`).
+fn parse_aspect_ratio(value: &str) -> Option<f64> {
+    let ratio = match value.split_once(':') {
+        Some((w, h)) => w.trim().parse::<f64>().ok()? / h.trim().parse::<f64>().ok()?,
+        None => value.trim().parse::<f64>().ok()?,
+    };
+    (ratio.is_finite() && ratio > 0.0).then_some(ratio)
+}
+
+/// Parses a `YYYY-MM-DD` date (month and day may have one digit) into the zero-padded form.
+fn parse_date(value: &str) -> Option<String> {
+    let mut parts = value.trim().splitn(3, '-').map(|part| part.parse::<u32>().ok());
+    let (year, month, day) = (parts.next()??, parts.next()??, parts.next()??);
+    ((1..=12).contains(&month) && (1..=31).contains(&day)).then(|| format!("{:04}-{:02}-{:02}", year, month, day))
+}
+
+/// Parses `op=weight` pairs separated by commas, e.g. `add=2,remove=1`; at least one weight must be positive.
+fn parse_mutation_weights(value: &str) -> Option<MutationWeights> {
+    let mut weights = MutationWeights([0.0; MutationOp::ALL.len()]);
+    for pair in value.split(',') {
+        let (name, weight) = pair.split_once('=')?;
+        let op = MutationOp::ALL.into_iter().find(|op| op.name() == name.trim().to_ascii_lowercase())?;
+        weights.0[op as usize] = weight.trim().parse::<f64>().ok().filter(|w| w.is_finite() && *w >= 0.0)?;
+    }
+    weights.0.iter().any(|w| *w > 0.0).then_some(weights)
+}
+
+/// Parses a region given as `x,y,w,h` in pixels.
+fn parse_region(value: &str) -> Option<Rect> {
+    let parts: Vec<i32> = value.split(',').map(|p| p.trim().parse::<i32>().ok()).collect::<Option<_>>()?;
+    match parts[..] {
+        [x, y, w, h] if x >= 0 && y >= 0 && w > 0 && h > 0 => Some(Rect::new(x, y, w, h)),
+        _ => None,
+    }
 }
@@ -1,59 +1,769 @@
-use image::{DynamicImage, Rgba, GenericImage, GenericImageView};
-use rect_packer::Rect;
+use image::imageops::{self, blur};
+use ab_glyph::FontArc;
+use clap::ValueEnum;
+use image::{DynamicImage, GenericImageView, GrayImage, Rgb, Rgba, RgbaImage};
 use std::collections::HashMap;
 
+use crate::color::{complementary_background, palette};
+#[cfg(feature = "gpu")]
+use crate::gpu::GpuCanvas;
+use crate::layout::{Layout, PlacedImage, Shape};
+use crate::packing::rotated_bounds;
+use crate::text::{blend_pixel, default_font, draw_text, fit_text, line_height, text_width};
+use crate::tone::{apply_style_filter, apply_tone_curve, dither_to_rgba8, tone_curves, StyleFilter, ToneCurve};
+
+/// Number of colors extracted from the images for `--background auto`.
+const PALETTE_SIZE: usize = 6;
+
+#[derive(Clone, Default)]
+pub struct RenderOptions {
+    pub shadow: Option<ShadowOptions>,
+    pub corner_radius: u32,
+    pub labels: Option<LabelOptions>,
+    pub title: Option<TitleOptions>,
+    /// Font for captions and titles; the embedded DejaVu Sans is used when unset.
+    pub font: Option<FontArc>,
+    pub watermark: Option<WatermarkOptions>,
+    pub background: BackgroundColor,
+    pub background_image: Option<BackgroundImage>,
+    pub fill: FillMode,
+    /// Match the tones of all images before compositing.
+    pub normalize_tones: bool,
+    pub style_filter: Option<StyleFilter>,
+}
+
+/// Color of the canvas around the images.
+#[derive(Clone, Copy, PartialEq)]
+pub enum BackgroundColor {
+    Color(Rgba<u8>),
+    /// Picked from the palette of the images in the collage.
+    Auto,
+}
+
+impl Default for BackgroundColor {
+    fn default() -> Self {
+        BackgroundColor::Color(Rgba([255, 255, 255, 255]))
+    }
+}
+
+/// How the space between the images is filled.
+#[derive(Clone, Copy, Default, PartialEq, ValueEnum)]
+pub enum FillMode {
+    /// Leave the background (white or the background image) visible.
+    #[default]
+    Background,
+    /// Extend a blurred, enlarged copy of the nearest image into the gaps.
+    Blur,
+}
+
+#[derive(Clone)]
+pub struct BackgroundImage {
+    pub image: RgbaImage,
+    pub mode: BackgroundMode,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum BackgroundMode {
+    Tile,
+    Stretch,
+}
+
+#[derive(Clone)]
+pub struct WatermarkOptions {
+    pub image: RgbaImage,
+    pub position: WatermarkPosition,
+    pub opacity: f32,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum WatermarkPosition {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+    Center,
+}
+
+impl RenderOptions {
+    /// Scales every pixel size (shadows, corners, fonts) so a layout rendered at a different
+    /// resolution keeps its proportions.
+    pub fn scaled(&self, factor: f64) -> RenderOptions {
+        let factor_f32 = factor as f32;
+        let mut scaled = self.clone();
+        if let Some(shadow) = &mut scaled.shadow {
+            shadow.blur_radius *= factor_f32;
+            shadow.offset = (shadow.offset as f64 * factor).round() as i32;
+        }
+        scaled.corner_radius = (self.corner_radius as f64 * factor).round() as u32;
+        if let Some(labels) = &mut scaled.labels {
+            labels.size *= factor_f32;
+        }
+        if let Some(title) = &mut scaled.title {
+            title.size *= factor_f32;
+        }
+        scaled
+    }
+}
+
+#[derive(Clone)]
+pub struct TitleOptions {
+    pub title: String,
+    pub subtitle: Option<String>,
+    pub size: f32,
+    pub position: BannerPosition,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum BannerPosition {
+    Top,
+    Bottom,
+}
+
+impl TitleOptions {
+    fn subtitle_size(&self) -> f32 {
+        self.size * 0.55
+    }
+
+    /// Height of the banner that is added to the canvas on top of the packed area.
+    pub fn band_height(&self, font: &FontArc) -> u32 {
+        let mut height = self.size + line_height(font, self.size);
+        if self.subtitle.is_some() {
+            height += line_height(font, self.subtitle_size());
+        }
+        height.ceil() as u32
+    }
+}
+
+#[derive(Clone)]
+pub struct LabelOptions {
+    pub size: f32,
+    pub position: LabelPosition,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum LabelPosition {
+    /// Caption on a translucent strip across the bottom of the image.
+    Over,
+    /// Caption in a white band below the image; the band is reserved during packing.
+    Below,
+}
+
+impl LabelOptions {
+    /// Height of the band reserved below each image, or 0 when captions are drawn over it.
+    pub fn band_height(&self) -> u32 {
+        match self.position {
+            LabelPosition::Over => 0,
+            LabelPosition::Below => (self.size * 1.6).ceil() as u32,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ShadowOptions {
+    pub blur_radius: f32,
+    pub offset: i32,
+    pub opacity: f32,
+}
+
+/// A collage before it is flattened: the canvas with background, gap fill and shadows, every
+/// image as its own positioned tile, and a transparent overlay with the banner and watermark.
+pub struct CollageLayers {
+    pub background: RgbaImage,
+    /// Image id, rendered tile and its top-left position on the canvas, in drawing order.
+    pub tiles: Vec<(u32, RgbaImage, i64, i64)>,
+    pub overlay: RgbaImage,
+}
+
+impl CollageLayers {
+    pub fn flatten(&self) -> DynamicImage {
+        let mut collage = self.background.clone();
+        // Place images with offset
+        for (_, tile, x, y) in &self.tiles {
+            imageops::overlay(&mut collage, tile, *x, *y);
+        }
+        imageops::overlay(&mut collage, &self.overlay, 0, 0);
+        DynamicImage::ImageRgba8(collage)
+    }
+}
+
 pub fn create_collage(
     images: &HashMap<u32, DynamicImage>,
-    packed_locations: &[(u32, Rect)],
-    max_width: u32,
-    max_height: u32,
+    layout: &Layout,
+    captions: &HashMap<u32, String>,
+    options: &RenderOptions,
 ) -> DynamicImage {
-    println!("Creating collage...");
-    println!("Collage dimensions: Width = {}, Height = {}", max_width, max_height);
+    render_layers(images, layout, captions, options).flatten()
+}
+
+pub fn render_layers(
+    images: &HashMap<u32, DynamicImage>,
+    layout: &Layout,
+    captions: &HashMap<u32, String>,
+    options: &RenderOptions,
+) -> CollageLayers {
+    info!("Creating collage...");
+    info!("Collage dimensions: Width = {}, Height = {}", layout.width, layout.height);
 
-    let mut min_x = u32::MAX;
-    let mut min_y = u32::MAX;
-    let mut max_x = 0;
-    let mut max_y = 0;
+    let font = options.font.clone().unwrap_or_else(default_font);
+    let banner_height = options.title.as_ref().map_or(0, |t| t.band_height(&font));
+    let banner_on_top = options.title.as_ref().is_some_and(|t| t.position == BannerPosition::Top);
+    let offset_y = if banner_on_top { banner_height as i64 } else { 0 };
 
-    for (id, rect) in packed_locations {
-        let x_end = (rect.x + rect.width) as u32;
-        let y_end = (rect.y + rect.height) as u32;
+    let background_color = match options.background {
+        BackgroundColor::Color(color) => color,
+        BackgroundColor::Auto => {
+            let palette = palette(layout.images.iter().filter_map(|placed| images.get(&placed.id)), PALETTE_SIZE);
+            let Rgb([r, g, b]) = complementary_background(&palette);
+            info!("Background color: #{:02x}{:02x}{:02x}", r, g, b);
+            Rgba([r, g, b, 255])
+        }
+    };
+    let mut background = RgbaImage::from_pixel(layout.width, layout.height + banner_height, background_color);
+    if let Some(background_image) = &options.background_image {
+        draw_background(&mut background, background_image);
+    }
 
-        if (rect.x as u32) < min_x { min_x = rect.x as u32; }
-        if (rect.y as u32) < min_y { min_y = rect.y as u32; }
-        if x_end > max_x { max_x = x_end; }
-        if y_end > max_y { max_y = y_end; }
+    let tone_curves = if options.normalize_tones {
+        let selected: Vec<(u32, &DynamicImage)> =
+            layout.images.iter().filter_map(|placed| Some((placed.id, images.get(&placed.id)?))).collect();
+        tone_curves(&selected)
+    } else {
+        HashMap::new()
+    };
 
-        println!(
+    let mut ids = Vec::new();
+    let mut tiles = Vec::new();
+    for placed in &layout.images {
+        info!(
             "Image ID: {}, Position: ({}, {}), Size: {}x{}",
-            id, rect.x, rect.y, rect.width, rect.height
+            placed.id, placed.x, placed.y, placed.width, placed.height
         );
+        if let Some(img) = images.get(&placed.id) {
+            ids.push(placed.id);
+            tiles.push(render_tile(img, placed, offset_y, tone_curves.get(&placed.id), captions.get(&placed.id), options, &font));
+        }
+    }
+
+    if options.fill == FillMode::Blur {
+        draw_blur_fill(&mut background, &tiles);
+    }
+
+    if let Some(shadow) = &options.shadow {
+        draw_shadows(&mut background, &tiles, shadow);
     }
 
-    let bounding_width = max_x - min_x;
-    let bounding_height = max_y - min_y;
+    let mut overlay = RgbaImage::new(background.width(), background.height());
+    if let Some(title) = &options.title {
+        let banner_top = if banner_on_top { 0 } else { layout.height };
+        draw_banner(&mut overlay, title, banner_top, &font);
+    }
+
+    if let Some(watermark) = &options.watermark {
+        draw_watermark(&mut overlay, watermark);
+    }
+
+    let tiles = ids.into_iter().zip(tiles).map(|(id, (tile, x, y))| (id, tile, x, y)).collect();
+    CollageLayers { background, tiles, overlay }
+}
 
-    let offset_x = (max_width.saturating_sub(bounding_width)) / 2;
-    let offset_y = (max_height.saturating_sub(bounding_height)) / 2;
+/// Whether `create_collage_streamed` can draw the collage. An automatic background, tone
+/// normalization and the blurred gap fill look at all images at once, so they need them loaded
+/// together.
+pub fn can_stream(options: &RenderOptions) -> bool {
+    options.background != BackgroundColor::Auto && !options.normalize_tones && options.fill != FillMode::Blur
+}
+
+/// Draws the same collage as `create_collage`, but gets every image from `load` only when its
+/// tile is drawn and drops it right after, so besides the canvas only one source image is held in
+/// memory at a time. Meant for large final renders; check `can_stream` first.
+pub fn create_collage_streamed(
+    layout: &Layout,
+    captions: &HashMap<u32, String>,
+    options: &RenderOptions,
+    mut load: impl FnMut(&PlacedImage) -> Option<DynamicImage>,
+) -> DynamicImage {
+    info!("Creating collage one image at a time...");
+    info!("Collage dimensions: Width = {}, Height = {}", layout.width, layout.height);
 
-    let mut collage = DynamicImage::new_rgba8(max_width, max_height);
+    let font = options.font.clone().unwrap_or_else(default_font);
+    let banner_height = options.title.as_ref().map_or(0, |t| t.band_height(&font));
+    let banner_on_top = options.title.as_ref().is_some_and(|t| t.position == BannerPosition::Top);
+    let offset_y = if banner_on_top { banner_height as i64 } else { 0 };
+
+    let background_color = match options.background {
+        BackgroundColor::Color(color) => color,
+        BackgroundColor::Auto => Rgba([255, 255, 255, 255]),
+    };
+    let mut collage = RgbaImage::from_pixel(layout.width, layout.height + banner_height, background_color);
+    if let Some(background_image) = &options.background_image {
+        draw_background(&mut collage, background_image);
+    }
 
-    // Fill background with white
-    for y in 0..max_height {
-        for x in 0..max_width {
-            collage.put_pixel(x, y, Rgba([255, 255, 255, 255]));
+    // Shadows lie below all images. Their shape only depends on the size, caption band, corners
+    // and rotation of a tile, so it is taken from blank tiles before any image is loaded.
+    if let Some(shadow) = &options.shadow {
+        let mut mask = GrayImage::new(collage.width(), collage.height());
+        for placed in &layout.images {
+            let (width, height) = placed.drawn_size();
+            let blank = DynamicImage::ImageRgba8(RgbaImage::from_pixel(width.max(1), height.max(1), Rgba([0, 0, 0, 255])));
+            let (tile, x, y) = render_tile(&blank, placed, offset_y, None, captions.get(&placed.id), options, &font);
+            add_shadow(&mut mask, &tile, x, y, shadow);
         }
+        apply_shadow(&mut collage, mask, shadow);
     }
 
-    // Place images with offset
-    for (id, rect) in packed_locations {
-        if let Some(img) = images.get(id) {
-            let target_x = offset_x + (rect.x as u32 - min_x);
-            let target_y = offset_y + (rect.y as u32 - min_y);
-            collage.copy_from(img, target_x, target_y).unwrap();
+    // With the `gpu` feature the tiles are blended on the GPU while the canvas stays there
+    #[cfg(feature = "gpu")]
+    let canvas = GpuCanvas::new(&collage);
+    for placed in &layout.images {
+        info!(
+            "Image ID: {}, Position: ({}, {}), Size: {}x{}",
+            placed.id, placed.x, placed.y, placed.width, placed.height
+        );
+        if let Some(img) = load(placed) {
+            let (tile, x, y) = render_tile(&img, placed, offset_y, None, captions.get(&placed.id), options, &font);
+            #[cfg(feature = "gpu")]
+            if canvas.as_ref().is_some_and(|canvas| canvas.overlay(&tile, x, y)) {
+                continue;
+            }
+            imageops::overlay(&mut collage, &tile, x, y);
         }
     }
+    #[cfg(feature = "gpu")]
+    if let Some(canvas) = canvas {
+        match canvas.finish() {
+            Ok(finished) => collage = finished,
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+
+    if let Some(title) = &options.title {
+        let banner_top = if banner_on_top { 0 } else { layout.height };
+        draw_banner(&mut collage, title, banner_top, &font);
+    }
+    if let Some(watermark) = &options.watermark {
+        draw_watermark(&mut collage, watermark);
+    }
+    DynamicImage::ImageRgba8(collage)
+}
 
-    collage
+/// Turns an image into the tile drawn for `placed`, with its tone curve, style filter, caption,
+/// rounded corners and rotation, and returns it with its top-left position on the canvas.
+fn render_tile(
+    img: &DynamicImage,
+    placed: &PlacedImage,
+    offset_y: i64,
+    curve: Option<&ToneCurve>,
+    caption: Option<&String>,
+    options: &RenderOptions,
+    font: &FontArc,
+) -> (RgbaImage, i64, i64) {
+    let (drawn_width, drawn_height) = placed.drawn_size();
+    let deep = img.color().bytes_per_pixel() > img.color().channel_count();
+    let mut tile = if deep {
+        // Resized at full precision and only then reduced to the 8 bits of the canvas
+        let pixels = img.to_rgba16();
+        if img.dimensions() == (drawn_width, drawn_height) {
+            dither_to_rgba8(&pixels)
+        } else {
+            dither_to_rgba8(&imageops::resize(&pixels, drawn_width, drawn_height, imageops::FilterType::Lanczos3))
+        }
+    } else if img.dimensions() == (drawn_width, drawn_height) {
+        img.to_rgba8()
+    } else {
+        resize_rgba(&img.to_rgba8(), drawn_width, drawn_height)
+    };
+    if let Some(curve) = curve {
+        apply_tone_curve(&mut tile, curve);
+    }
+    if let Some(filter) = options.style_filter {
+        apply_style_filter(&mut tile, filter);
+    }
+    match placed.shape {
+        Shape::Rectangle => {
+            if let (Some(labels), Some(caption)) = (&options.labels, caption) {
+                tile = add_caption(&tile, caption, labels, font);
+            }
+            if options.corner_radius > 0 {
+                tile = round_corners(&tile, options.corner_radius);
+            }
+        }
+        // Captions and rounded corners have no room on a hexagon
+        Shape::Hexagon => tile = crop_to_hexagon(&tile, placed.crop_origin(), placed.width as u32, placed.height as u32),
+        Shape::Fill => {
+            tile = crop_at(&tile, placed.crop_origin(), placed.width as u32, placed.height as u32);
+            if options.corner_radius > 0 {
+                tile = round_corners(&tile, options.corner_radius);
+            }
+        }
+    }
+    if placed.rotation != 0.0 {
+        tile = rotate(&tile, placed.rotation.to_radians());
+    }
+    // Center the tile inside its packed footprint
+    let x = placed.x as i64 + (placed.width as i64 - tile.width() as i64) / 2;
+    let y = offset_y + placed.y as i64 + (placed.height as i64 - tile.height() as i64) / 2;
+    (tile, x, y)
+}
+
+/// Resizes with a Lanczos3 filter, or on the GPU when built with the `gpu` feature and one is
+/// available.
+pub fn resize_rgba(img: &RgbaImage, width: u32, height: u32) -> RgbaImage {
+    #[cfg(feature = "gpu")]
+    if let Some(resized) = crate::gpu::resize(img, width, height) {
+        return resized;
+    }
+    imageops::resize(img, width, height, imageops::FilterType::Lanczos3)
+}
+
+/// Covers the canvas with the background image, either repeated or scaled to the canvas size.
+fn draw_background(collage: &mut RgbaImage, background: &BackgroundImage) {
+    let (width, height) = collage.dimensions();
+    match background.mode {
+        BackgroundMode::Stretch => {
+            let stretched = imageops::resize(&background.image, width, height, imageops::FilterType::Triangle);
+            imageops::overlay(collage, &stretched, 0, 0);
+        }
+        BackgroundMode::Tile => {
+            let (tile_w, tile_h) = background.image.dimensions();
+            if tile_w == 0 || tile_h == 0 {
+                return;
+            }
+            for y in (0..height).step_by(tile_h as usize) {
+                for x in (0..width).step_by(tile_w as usize) {
+                    imageops::overlay(collage, &background.image, x as i64, y as i64);
+                }
+            }
+        }
+    }
+}
+
+/// Fills the canvas with heavily blurred copies of the tiles, each enlarged around its own
+/// position, so every gap takes the colors of the closest image.
+fn draw_blur_fill(collage: &mut RgbaImage, tiles: &[(RgbaImage, i64, i64)]) {
+    const ENLARGEMENT: f64 = 1.5;
+    const DOWNSCALE: u32 = 8;
+
+    let layers: Vec<(RgbaImage, i64, i64)> = tiles
+        .iter()
+        .map(|(tile, x, y)| {
+            let (w, h) = tile.dimensions();
+            let big_w = (w as f64 * ENLARGEMENT).ceil() as u32;
+            let big_h = (h as f64 * ENLARGEMENT).ceil() as u32;
+            // Blur at a fraction of the size; upscaling afterwards smooths it further for free
+            let small = imageops::resize(tile, (big_w / DOWNSCALE).max(1), (big_h / DOWNSCALE).max(1), imageops::FilterType::Triangle);
+            let small = blur(&small, 3.0);
+            let layer = imageops::resize(&small, big_w, big_h, imageops::FilterType::Triangle);
+            let layer_x = x - (big_w - w) as i64 / 2;
+            let layer_y = y - (big_h - h) as i64 / 2;
+            (layer, layer_x, layer_y)
+        })
+        .collect();
+
+    let nearest = nearest_tiles(collage.width(), collage.height(), tiles);
+    for ((px, py, pixel), nearest) in collage.enumerate_pixels_mut().zip(nearest) {
+        if let Some((layer, layer_x, layer_y)) = nearest.map(|index| &layers[index]) {
+            let sx = (px as i64 - layer_x).clamp(0, layer.width() as i64 - 1) as u32;
+            let sy = (py as i64 - layer_y).clamp(0, layer.height() as i64 - 1) as u32;
+            let sample = *layer.get_pixel(sx, sy);
+            blend_pixel(pixel, sample, 1.0);
+        }
+    }
+}
+
+/// Index of the tile closest to every pixel of a `width`x`height` canvas, row by row, or `None`
+/// without tiles on the canvas. An exact Euclidean distance transform (Felzenszwalb and
+/// Huttenlocher) seeded with the tiles carries the nearest tile along, so the cost grows with the
+/// canvas only; where tiles overlap, the first one wins.
+fn nearest_tiles(width: u32, height: u32, tiles: &[(RgbaImage, i64, i64)]) -> Vec<Option<usize>> {
+    let (width, height) = (width as usize, height as usize);
+    let mut seeds = vec![None; width * height];
+    for (index, (tile, x, y)) in tiles.iter().enumerate().rev() {
+        let clip = |start: i64, len: u32, max: usize| (start.clamp(0, max as i64) as usize, (start + len as i64).clamp(0, max as i64) as usize);
+        let ((left, right), (top, bottom)) = (clip(*x, tile.width(), width), clip(*y, tile.height(), height));
+        for row in top..bottom {
+            seeds[row * width + left..row * width + right].fill(Some(index));
+        }
+    }
+
+    // Nearest seed within every column: squared distance and tile
+    let mut columns: Vec<Option<(f64, usize)>> = vec![None; width * height];
+    for x in 0..width {
+        let mut last = None;
+        for y in 0..height {
+            last = seeds[y * width + x].map(|index| (y, index)).or(last);
+            columns[y * width + x] = last.map(|(seed_y, index)| (((y - seed_y) as f64).powi(2), index));
+        }
+        let mut last = None;
+        for y in (0..height).rev() {
+            last = seeds[y * width + x].map(|index| (y, index)).or(last);
+            if let Some((seed_y, index)) = last {
+                let distance = ((seed_y - y) as f64).powi(2);
+                if columns[y * width + x].is_none_or(|(above, _)| distance < above) {
+                    columns[y * width + x] = Some((distance, index));
+                }
+            }
+        }
+    }
+
+    // Along every row, the lower envelope of the parabolas rooted in the column minima
+    let mut nearest = vec![None; width * height];
+    let mut sites: Vec<(usize, f64, usize)> = Vec::with_capacity(width);
+    let mut bounds: Vec<f64> = Vec::with_capacity(width);
+    for y in 0..height {
+        let row = &columns[y * width..(y + 1) * width];
+        sites.clear();
+        bounds.clear();
+        for (q, column) in row.iter().enumerate() {
+            let Some((f_q, index)) = *column else { continue };
+            while let Some(&(v, f_v, _)) = sites.last() {
+                let crossing = ((f_q + (q * q) as f64) - (f_v + (v * v) as f64)) / (2.0 * (q - v) as f64);
+                if bounds.last().is_some_and(|bound| crossing <= *bound) {
+                    sites.pop();
+                    bounds.pop();
+                } else {
+                    bounds.push(crossing);
+                    break;
+                }
+            }
+            sites.push((q, f_q, index));
+        }
+        let mut k = 0;
+        for x in 0..width {
+            while k < bounds.len() && bounds[k] < x as f64 {
+                k += 1;
+            }
+            nearest[y * width + x] = sites.get(k).map(|(_, _, index)| *index);
+        }
+    }
+    nearest
+}
+
+/// Darkens the canvas underneath every tile with a blurred, offset copy of its silhouette.
+fn draw_shadows(collage: &mut RgbaImage, tiles: &[(RgbaImage, i64, i64)], shadow: &ShadowOptions) {
+    let mut mask = GrayImage::new(collage.width(), collage.height());
+    for (tile, x, y) in tiles {
+        add_shadow(&mut mask, tile, *x, *y, shadow);
+    }
+    apply_shadow(collage, mask, shadow);
+}
+
+/// Adds the shape of a tile, moved by the shadow offset, to the mask of the shadows.
+fn add_shadow(mask: &mut GrayImage, tile: &RgbaImage, x: i64, y: i64, shadow: &ShadowOptions) {
+    let (width, height) = mask.dimensions();
+    for (tile_x, tile_y, pixel) in tile.enumerate_pixels() {
+        let mask_x = x + tile_x as i64 + shadow.offset as i64;
+        let mask_y = y + tile_y as i64 + shadow.offset as i64;
+        if mask_x < 0 || mask_y < 0 || mask_x >= width as i64 || mask_y >= height as i64 {
+            continue;
+        }
+        let coverage = mask.get_pixel_mut(mask_x as u32, mask_y as u32);
+        coverage[0] = coverage[0].max(pixel[3]);
+    }
+}
+
+/// Darkens the canvas below the blurred mask of the shadows.
+fn apply_shadow(collage: &mut RgbaImage, mask: GrayImage, shadow: &ShadowOptions) {
+    let mask = if shadow.blur_radius > 0.0 { blur(&mask, shadow.blur_radius) } else { mask };
+    let opacity = shadow.opacity.clamp(0.0, 1.0);
+
+    for (pixel, coverage) in collage.pixels_mut().zip(mask.pixels()) {
+        let alpha = coverage[0] as f32 / 255.0 * opacity;
+        for channel in pixel.0.iter_mut().take(3) {
+            *channel = (*channel as f32 * (1.0 - alpha)).round() as u8;
+        }
+    }
+}
+
+/// Draws the centered title and optional subtitle into the banner starting at `top`.
+fn draw_banner(collage: &mut RgbaImage, title: &TitleOptions, top: u32, font: &FontArc) {
+    let width = collage.width() as f32;
+    let margin = title.size / 2.0;
+    let text = fit_text(font, title.size, &title.title, width - 2.0 * margin);
+    let x = (width - text_width(font, title.size, &text)) / 2.0;
+    let mut y = top as f32 + margin;
+    draw_text(collage, font, title.size, &text, x, y, Rgba([30, 30, 30, 255]));
+    y += line_height(font, title.size);
+
+    if let Some(subtitle) = &title.subtitle {
+        let size = title.subtitle_size();
+        let text = fit_text(font, size, subtitle, width - 2.0 * margin);
+        let x = (width - text_width(font, size, &text)) / 2.0;
+        draw_text(collage, font, size, &text, x, y, Rgba([90, 90, 90, 255]));
+    }
+}
+
+/// Blends the watermark into the requested corner, shrinking it to at most a quarter of the canvas width.
+fn draw_watermark(collage: &mut RgbaImage, watermark: &WatermarkOptions) {
+    let (width, height) = collage.dimensions();
+    let mut logo = watermark.image.clone();
+    let max_logo_width = (width / 4).max(1);
+    if logo.width() > max_logo_width {
+        let logo_height = ((logo.height() as f64 * max_logo_width as f64 / logo.width() as f64) as u32).max(1);
+        logo = imageops::resize(&logo, max_logo_width, logo_height, imageops::FilterType::Lanczos3);
+    }
+    let opacity = watermark.opacity.clamp(0.0, 1.0);
+    for pixel in logo.pixels_mut() {
+        pixel[3] = (pixel[3] as f32 * opacity).round() as u8;
+    }
+
+    let margin = (width.min(height) / 50) as i64;
+    let right = width as i64 - logo.width() as i64 - margin;
+    let bottom = height as i64 - logo.height() as i64 - margin;
+    let (x, y) = match watermark.position {
+        WatermarkPosition::TopLeft => (margin, margin),
+        WatermarkPosition::TopRight => (right, margin),
+        WatermarkPosition::BottomLeft => (margin, bottom),
+        WatermarkPosition::BottomRight => (right, bottom),
+        WatermarkPosition::Center => (
+            (width as i64 - logo.width() as i64) / 2,
+            (height as i64 - logo.height() as i64) / 2,
+        ),
+    };
+    imageops::overlay(collage, &logo, x, y);
+}
+
+/// Returns a copy of `img` with `caption` drawn over its bottom edge or in a band below it.
+fn add_caption(img: &RgbaImage, caption: &str, labels: &LabelOptions, font: &FontArc) -> RgbaImage {
+    let (width, height) = img.dimensions();
+    let margin = (labels.size / 2.0).ceil();
+    let text = fit_text(font, labels.size, caption, width as f32 - 2.0 * margin);
+    let text_x = ((width as f32 - text_width(font, labels.size, &text)) / 2.0).max(margin);
+
+    match labels.position {
+        LabelPosition::Over => {
+            let mut tile = img.clone();
+            let strip_height = (line_height(font, labels.size) + margin).ceil() as u32;
+            let strip_top = height.saturating_sub(strip_height);
+            for y in strip_top..height {
+                for x in 0..width {
+                    let pixel = tile.get_pixel_mut(x, y);
+                    for channel in pixel.0.iter_mut().take(3) {
+                        *channel = (*channel as f32 * 0.45).round() as u8;
+                    }
+                }
+            }
+            let text_y = strip_top as f32 + margin / 2.0;
+            draw_text(&mut tile, font, labels.size, &text, text_x, text_y, Rgba([255, 255, 255, 255]));
+            tile
+        }
+        LabelPosition::Below => {
+            let band_height = labels.band_height();
+            let mut tile = RgbaImage::from_pixel(width, height + band_height, Rgba([255, 255, 255, 255]));
+            imageops::replace(&mut tile, img, 0, 0);
+            let text_y = height as f32 + (band_height as f32 - line_height(font, labels.size)) / 2.0;
+            draw_text(&mut tile, font, labels.size, &text, text_x, text_y, Rgba([60, 60, 60, 255]));
+            tile
+        }
+    }
+}
+
+/// Cuts the `width`x`height` part at `(left, top)` out of `img`, moved back inside the image
+/// where it would reach over its edge.
+fn crop_at(img: &RgbaImage, (left, top): (u32, u32), width: u32, height: u32) -> RgbaImage {
+    let (width, height) = (width.clamp(1, img.width()), height.clamp(1, img.height()));
+    let (left, top) = (left.min(img.width() - width), top.min(img.height() - height));
+    imageops::crop_imm(img, left, top, width, height).to_image()
+}
+
+/// Cuts the `width`x`height` part at `origin` out of `img` and masks it with an anti-aliased
+/// hexagon with pointy top and bottom.
+fn crop_to_hexagon(img: &RgbaImage, origin: (u32, u32), width: u32, height: u32) -> RgbaImage {
+    let mut hexagon = crop_at(img, origin, width, height);
+    let (width, height) = hexagon.dimensions();
+    let (center_x, center_y) = (width as f32 / 2.0, height as f32 / 2.0);
+    let slope = width as f32 / height as f32;
+    for (x, y, pixel) in hexagon.enumerate_pixels_mut() {
+        let dx = (x as f32 + 0.5 - center_x).abs();
+        let dy = (y as f32 + 0.5 - center_y).abs();
+        // How far the pixel lies outside the vertical and the slanted edges
+        let outside = (dx - center_x).max(dx / 2.0 + dy * slope - center_x);
+        let coverage = (0.5 - outside).clamp(0.0, 1.0);
+        if coverage < 1.0 {
+            pixel[3] = (pixel[3] as f32 * coverage).round() as u8;
+        }
+    }
+    hexagon
+}
+
+/// Returns a copy of `img` whose corners are masked out with an anti-aliased rounded rectangle.
+fn round_corners(img: &RgbaImage, radius: u32) -> RgbaImage {
+    let (width, height) = img.dimensions();
+    let mut rounded = img.clone();
+    for (x, y, pixel) in rounded.enumerate_pixels_mut() {
+        let coverage = corner_coverage(x, y, width, height, radius);
+        if coverage < 1.0 {
+            pixel[3] = (pixel[3] as f32 * coverage).round() as u8;
+        }
+    }
+    rounded
+}
+
+/// Fraction of the pixel at (x, y) that lies inside a `width`x`height` rounded rectangle.
+fn corner_coverage(x: u32, y: u32, width: u32, height: u32, radius: u32) -> f32 {
+    let radius = radius.min(width / 2).min(height / 2) as f32;
+    if radius <= 0.0 {
+        return 1.0;
+    }
+    let px = x as f32 + 0.5;
+    let py = y as f32 + 0.5;
+    let cx = px.clamp(radius, width as f32 - radius);
+    let cy = py.clamp(radius, height as f32 - radius);
+    let distance = ((px - cx).powi(2) + (py - cy).powi(2)).sqrt();
+    (radius - distance + 0.5).clamp(0.0, 1.0)
+}
+
+/// Rotates `img` by `angle` radians around its center onto a transparent canvas that
+/// exactly fits the rotated image, sampling bilinearly so the edges stay smooth.
+fn rotate(img: &RgbaImage, angle: f64) -> RgbaImage {
+    let (width, height) = img.dimensions();
+    let (out_width, out_height) = rotated_bounds(width, height, angle);
+    let (sin, cos) = angle.sin_cos();
+    let (src_cx, src_cy) = (width as f64 / 2.0, height as f64 / 2.0);
+    let (dst_cx, dst_cy) = (out_width as f64 / 2.0, out_height as f64 / 2.0);
+
+    RgbaImage::from_fn(out_width, out_height, |x, y| {
+        let dx = x as f64 + 0.5 - dst_cx;
+        let dy = y as f64 + 0.5 - dst_cy;
+        let src_x = dx * cos + dy * sin + src_cx - 0.5;
+        let src_y = -dx * sin + dy * cos + src_cy - 0.5;
+        sample_bilinear(img, src_x, src_y)
+    })
+}
+
+fn sample_bilinear(img: &RgbaImage, x: f64, y: f64) -> Rgba<u8> {
+    let x0 = x.floor();
+    let y0 = y.floor();
+    let fx = x - x0;
+    let fy = y - y0;
+    let texel = |tx: f64, ty: f64| -> [f64; 4] {
+        if tx < 0.0 || ty < 0.0 || tx >= img.width() as f64 || ty >= img.height() as f64 {
+            return [0.0; 4];
+        }
+        let p = img.get_pixel(tx as u32, ty as u32);
+        let alpha = p[3] as f64;
+        // Premultiply so transparent neighbours don't bleed their color into the edge
+        [p[0] as f64 * alpha, p[1] as f64 * alpha, p[2] as f64 * alpha, alpha]
+    };
+    let corners = [
+        (texel(x0, y0), (1.0 - fx) * (1.0 - fy)),
+        (texel(x0 + 1.0, y0), fx * (1.0 - fy)),
+        (texel(x0, y0 + 1.0), (1.0 - fx) * fy),
+        (texel(x0 + 1.0, y0 + 1.0), fx * fy),
+    ];
+    let mut acc = [0.0; 4];
+    for (texel, weight) in corners {
+        for (sum, value) in acc.iter_mut().zip(texel) {
+            *sum += value * weight;
+        }
+    }
+    if acc[3] <= 0.0 {
+        return Rgba([0, 0, 0, 0]);
+    }
+    Rgba([
+        (acc[0] / acc[3]).round() as u8,
+        (acc[1] / acc[3]).round() as u8,
+        (acc[2] / acc[3]).round() as u8,
+        acc[3].round() as u8,
+    ])
 }
@@ -0,0 +1,163 @@
+use std::collections::HashMap;
+
+use image::imageops::FilterType;
+use image::{DynamicImage, Rgb};
+
+/// Colors below this saturation count as gray; their hue is meaningless.
+const GRAY_SATURATION: f64 = 0.15;
+
+const KMEANS_ITERATIONS: usize = 10;
+
+/// The most common color of an image: pixels of a 64x64 thumbnail are grouped into 4096 color
+/// bins and the average of the fullest bin is returned.
+pub fn dominant_color(img: &DynamicImage) -> Rgb<u8> {
+    let small = img.resize_exact(64, 64, FilterType::Triangle).to_rgb8();
+    let mut bins: HashMap<(u8, u8, u8), (u32, [u32; 3])> = HashMap::new();
+    for pixel in small.pixels() {
+        let [r, g, b] = pixel.0;
+        let (count, sum) = bins.entry((r >> 4, g >> 4, b >> 4)).or_default();
+        *count += 1;
+        for (total, value) in sum.iter_mut().zip(pixel.0) {
+            *total += value as u32;
+        }
+    }
+    let (count, sum) = bins.into_values().max_by_key(|(count, _)| *count).unwrap_or((1, [255; 3]));
+    Rgb(sum.map(|total| (total / count) as u8))
+}
+
+/// The `k` main colors of a set of images, found by k-means clustering of the pixels of 32x32
+/// thumbnails. Returns every color with the share of pixels it stands for, most common first.
+pub fn palette<'a>(images: impl IntoIterator<Item = &'a DynamicImage>, k: usize) -> Vec<(Rgb<u8>, f64)> {
+    let mut pixels: Vec<[f64; 3]> = images
+        .into_iter()
+        .flat_map(|img| img.resize_exact(32, 32, FilterType::Triangle).to_rgb8().pixels().map(|p| p.0.map(|c| c as f64)).collect::<Vec<_>>())
+        .collect();
+    if pixels.is_empty() || k == 0 {
+        return Vec::new();
+    }
+
+    // Start from colors spread evenly over the range from dark to light, which keeps the result
+    // deterministic
+    pixels.sort_by(|a, b| a.iter().sum::<f64>().total_cmp(&b.iter().sum::<f64>()));
+    let k = k.min(pixels.len());
+    let mut centers: Vec<[f64; 3]> = (0..k).map(|i| pixels[(2 * i + 1) * pixels.len() / (2 * k)]).collect();
+    let mut assignment = vec![0; pixels.len()];
+    for _ in 0..KMEANS_ITERATIONS {
+        for (pixel, cluster) in pixels.iter().zip(assignment.iter_mut()) {
+            *cluster = (0..k)
+                .min_by(|&a, &b| squared_distance(pixel, &centers[a]).total_cmp(&squared_distance(pixel, &centers[b])))
+                .unwrap_or(0);
+        }
+        let mut sums = vec![([0.0; 3], 0usize); k];
+        for (pixel, &cluster) in pixels.iter().zip(&assignment) {
+            let (sum, count) = &mut sums[cluster];
+            for (total, value) in sum.iter_mut().zip(pixel) {
+                *total += value;
+            }
+            *count += 1;
+        }
+        for (center, (sum, count)) in centers.iter_mut().zip(sums) {
+            // Empty clusters keep their center
+            if count > 0 {
+                *center = sum.map(|total| total / count as f64);
+            }
+        }
+    }
+
+    let mut counts = vec![0usize; k];
+    for &cluster in &assignment {
+        counts[cluster] += 1;
+    }
+    let mut palette: Vec<(Rgb<u8>, f64)> = centers
+        .iter()
+        .zip(counts)
+        .filter(|(_, count)| *count > 0)
+        .map(|(center, count)| (Rgb(center.map(|c| c.round() as u8)), count as f64 / pixels.len() as f64))
+        .collect();
+    palette.sort_by(|a, b| b.1.total_cmp(&a.1));
+    palette
+}
+
+fn squared_distance(a: &[f64; 3], b: &[f64; 3]) -> f64 {
+    a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum()
+}
+
+/// A background that sets the images off: a muted complement of the most common colorful
+/// palette color, light behind dark images and dark behind light ones. Mostly gray palettes get a
+/// neutral gray.
+pub fn complementary_background(palette: &[(Rgb<u8>, f64)]) -> Rgb<u8> {
+    let lightness: f64 = palette.iter().map(|(color, share)| hsl(*color).2 * share).sum();
+    let target_lightness = if lightness < 0.6 { 0.92 } else { 0.15 };
+    let main_hue = palette
+        .iter()
+        .filter(|(_, share)| *share >= 0.1)
+        .map(|(color, _)| hsl(*color))
+        .find(|(_, saturation, _)| *saturation >= GRAY_SATURATION);
+    match main_hue {
+        Some((hue, saturation, _)) => from_hsl((hue + 180.0) % 360.0, saturation.min(0.3), target_lightness),
+        None => from_hsl(0.0, 0.0, target_lightness),
+    }
+}
+
+/// Hue in degrees, saturation and lightness (HSL) of a color, all but the hue in 0..=1.
+pub fn hsl(color: Rgb<u8>) -> (f64, f64, f64) {
+    let [r, g, b] = color.0.map(|c| c as f64 / 255.0);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let lightness = (max + min) / 2.0;
+    let delta = max - min;
+    if delta == 0.0 {
+        return (0.0, 0.0, lightness);
+    }
+    let saturation = delta / (1.0 - (2.0 * lightness - 1.0).abs());
+    let hue = if max == r {
+        60.0 * ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+    (hue, saturation.min(1.0), lightness)
+}
+
+/// Inverse of `hsl`.
+pub fn from_hsl(hue: f64, saturation: f64, lightness: f64) -> Rgb<u8> {
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    let x = chroma * (1.0 - ((hue / 60.0).rem_euclid(2.0) - 1.0).abs());
+    let (r, g, b) = match (hue / 60.0) as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = lightness - chroma / 2.0;
+    Rgb([r, g, b].map(|c| ((c + m) * 255.0).round().clamp(0.0, 255.0) as u8))
+}
+
+/// Position of a color on the spectrum used by `--color-flow`: colorful images by hue, followed by
+/// gray ones from dark to light.
+pub fn spectrum_position(color: Rgb<u8>) -> f64 {
+    let (hue, saturation, lightness) = hsl(color);
+    if saturation < GRAY_SATURATION {
+        1.0 + lightness
+    } else {
+        hue / 360.0
+    }
+}
+
+/// How different two colors look on the spectrum, from 0 (same hue) to 1 (opposite hues, or a
+/// colorful next to a gray image).
+pub fn color_distance(a: Rgb<u8>, b: Rgb<u8>) -> f64 {
+    let (hue_a, saturation_a, lightness_a) = hsl(a);
+    let (hue_b, saturation_b, lightness_b) = hsl(b);
+    match (saturation_a < GRAY_SATURATION, saturation_b < GRAY_SATURATION) {
+        (true, true) => (lightness_a - lightness_b).abs(),
+        (false, false) => {
+            let difference = (hue_a - hue_b).abs();
+            difference.min(360.0 - difference) / 180.0
+        }
+        _ => 1.0,
+    }
+}
@@ -0,0 +1,97 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use image::{imageops, DynamicImage, Rgba, RgbaImage};
+
+use crate::collage::{create_collage, RenderOptions};
+use crate::layout::Layout;
+use crate::packing::DESIRED_ASPECT_RATIO;
+use crate::text::{default_font, draw_text};
+
+/// Both collages are drawn at this height in the diff image.
+const PANEL_HEIGHT: u32 = 600;
+const PANEL_GAP: u32 = 20;
+const HEADER_HEIGHT: u32 = 40;
+const OUTLINE_WIDTH: u32 = 4;
+const SHARED_COLOR: Rgba<u8> = Rgba([46, 160, 67, 255]);
+const UNIQUE_COLOR: Rgba<u8> = Rgba([214, 39, 40, 255]);
+
+/// How well a layout uses its canvas, measured like the fitness of the optimization does.
+pub struct LayoutMetrics {
+    pub images: usize,
+    /// Share of the canvas covered by image footprints, in percent.
+    pub coverage: f64,
+    pub aspect_ratio: f64,
+    /// Deviation of the canvas from the desired aspect ratio.
+    pub aspect_deviation: f64,
+}
+
+pub fn layout_metrics(layout: &Layout) -> LayoutMetrics {
+    let canvas = layout.width as f64 * layout.height as f64;
+    let covered: f64 = layout.images.iter().map(|placed| placed.width as f64 * placed.height as f64).sum();
+    let aspect_ratio = layout.width as f64 / layout.height.max(1) as f64;
+    LayoutMetrics {
+        images: layout.images.len(),
+        coverage: if canvas > 0.0 { (covered / canvas * 100.0).min(100.0) } else { 0.0 },
+        aspect_ratio,
+        aspect_deviation: (aspect_ratio - DESIRED_ASPECT_RATIO).abs(),
+    }
+}
+
+/// Source files placed in both layouts. Images are matched by path, as ids are only unique within
+/// one run.
+pub fn shared_images(a: &Layout, b: &Layout) -> HashSet<PathBuf> {
+    let in_b: HashSet<&PathBuf> = b.images.iter().map(|placed| &placed.path).collect();
+    a.images.iter().map(|placed| &placed.path).filter(|path| in_b.contains(path)).cloned().collect()
+}
+
+/// Draws both collages side by side at the same height, each under its file name. Images placed
+/// in both layouts are framed green, the others red. `images` holds the decoded source files.
+pub fn diff_image(layouts: [(&Path, &Layout); 2], images: &HashMap<PathBuf, DynamicImage>) -> RgbaImage {
+    let shared = shared_images(layouts[0].1, layouts[1].1);
+    let panels: Vec<(&Path, RgbaImage)> = layouts
+        .iter()
+        .map(|(name, layout)| (*name, draw_panel(layout, images, &shared)))
+        .collect();
+
+    let width = panels.iter().map(|(_, panel)| panel.width()).sum::<u32>() + PANEL_GAP * 3;
+    let mut diff = RgbaImage::from_pixel(width, HEADER_HEIGHT + PANEL_HEIGHT + PANEL_GAP, Rgba([255, 255, 255, 255]));
+    let font = default_font();
+    let mut x = PANEL_GAP;
+    for (name, panel) in &panels {
+        let label = name.file_name().map_or_else(|| name.display().to_string(), |n| n.to_string_lossy().into_owned());
+        draw_text(&mut diff, &font, 22.0, &label, x as f32, 8.0, Rgba([0, 0, 0, 255]));
+        imageops::overlay(&mut diff, panel, x as i64, HEADER_HEIGHT as i64);
+        x += panel.width() + PANEL_GAP;
+    }
+    diff
+}
+
+fn draw_panel(layout: &Layout, images: &HashMap<PathBuf, DynamicImage>, shared: &HashSet<PathBuf>) -> RgbaImage {
+    let factor = PANEL_HEIGHT as f64 / layout.height.max(1) as f64;
+    let layout = layout.scaled(factor);
+    let image_map: HashMap<u32, DynamicImage> = layout
+        .images
+        .iter()
+        .filter_map(|placed| Some((placed.id, images.get(&placed.path)?.clone())))
+        .collect();
+    let mut panel = create_collage(&image_map, &layout, &HashMap::new(), &RenderOptions::default()).to_rgba8();
+    for placed in &layout.images {
+        let color = if shared.contains(&placed.path) { SHARED_COLOR } else { UNIQUE_COLOR };
+        draw_outline(&mut panel, placed.x, placed.y, placed.width, placed.height, color);
+    }
+    panel
+}
+
+/// Draws a frame of `OUTLINE_WIDTH` pixels just inside the rectangle.
+fn draw_outline(img: &mut RgbaImage, x: i32, y: i32, width: i32, height: i32, color: Rgba<u8>) {
+    let border = OUTLINE_WIDTH as i32;
+    for py in y.max(0)..(y + height).min(img.height() as i32) {
+        for px in x.max(0)..(x + width).min(img.width() as i32) {
+            let inside = px >= x + border && px < x + width - border && py >= y + border && py < y + height - border;
+            if !inside {
+                img.put_pixel(px as u32, py as u32, color);
+            }
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use std::ffi::OsString;
+use std::fs;
+use std::path::Path;
+
+use clap::parser::ValueSource;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use toml::{Table, Value};
+
+/// The options a config file can hold with the section they are listed in. Keys are the long
+/// command line options and the sections their help headings, so the input directories (or
+/// `--from-list`) and `--config` itself stay on the command line.
+fn settings(command: &Command) -> impl Iterator<Item = (String, &Arg)> {
+    command.get_arguments().filter_map(|arg| {
+        let section = arg.get_help_heading()?.to_ascii_lowercase();
+        arg.get_long().is_some().then_some((section, arg))
+    })
+}
+
+/// Reads a config file and turns the settings that `matches` (the command line) does not give into
+/// command line arguments, so the command line wins over the file.
+pub fn config_args(path: &Path, command: &Command, matches: &ArgMatches) -> Result<Vec<OsString>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Error reading config file {}: {}", path.display(), e))?;
+    let table: Table = text.parse().map_err(|e| format!("Error parsing config file {}: {}", path.display(), e))?;
+
+    // Sections only group the settings, a key may also be given at the top level
+    let sections: Vec<String> = settings(command).map(|(section, _)| section).collect();
+    let mut entries = Vec::new();
+    for (key, value) in &table {
+        match value {
+            Value::Table(section) if sections.contains(key) => entries.extend(section),
+            _ => entries.push((key, value)),
+        }
+    }
+
+    let mut args = Vec::new();
+    for (key, value) in entries {
+        let arg = settings(command)
+            .map(|(_, arg)| arg)
+            .find(|arg| arg.get_long() == Some(key.as_str()))
+            .ok_or_else(|| format!("Error in config file {}: unknown setting '{}'", path.display(), key))?;
+        if matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine) {
+            continue;
+        }
+        let values = match value {
+            Value::Boolean(true) => {
+                args.push(OsString::from(format!("--{}", key)));
+                continue;
+            }
+            Value::Boolean(false) => continue,
+            Value::Array(values) => values.iter().collect(),
+            value => vec![value],
+        };
+        for value in values {
+            let value = match value {
+                Value::String(text) => text.clone(),
+                Value::Integer(_) | Value::Float(_) | Value::Datetime(_) => value.to_string(),
+                _ => return Err(format!("Error in config file {}: invalid value for '{}'", path.display(), key)),
+            };
+            args.push(OsString::from(format!("--{}={}", key, value)));
+        }
+    }
+    Ok(args)
+}
+
+/// The effective settings of the command line, the config file and the defaults, as a config
+/// file. Options without a value are listed as comments, so the file also shows what can be set.
+pub fn dump_config(command: &Command, matches: &ArgMatches) -> String {
+    let mut text = String::from(
+        "# ImageGridOptimizer configuration, use it with --config FILE.\n\
+         # Keys are the long command line options; commented keys are not set.\n",
+    );
+    let mut current = None;
+    for (section, arg) in settings(command) {
+        if current.as_ref() != Some(&section) {
+            text.push_str(&format!("\n[{}]\n", section));
+            current = Some(section);
+        }
+        let key = arg.get_long().unwrap_or_default();
+        let values: Vec<String> = matches
+            .get_raw(arg.get_id().as_str())
+            .map(|values| values.map(|value| value.to_string_lossy().into_owned()).collect())
+            .unwrap_or_default();
+        if !arg.get_action().takes_values() {
+            // Flags are stored as true or false
+            text.push_str(&format!("{} = {}\n", key, values.first().map_or("false", String::as_str)));
+            continue;
+        }
+        let values: Vec<String> = values.iter().map(|value| toml_value(value)).collect();
+        // Options such as `--reserve` that can be given several times are written as lists
+        let repeatable = matches!(arg.get_action(), ArgAction::Append);
+        match values.len() {
+            0 => text.push_str(&format!("# {} =\n", key)),
+            1 if !repeatable => text.push_str(&format!("{} = {}\n", key, values[0])),
+            _ => text.push_str(&format!("{} = [{}]\n", key, values.join(", "))),
+        }
+    }
+    text
+}
+
+/// Writes a command line value as a TOML number if it is one, and as a string otherwise.
+fn toml_value(value: &str) -> String {
+    let number = format!("value = {}", value)
+        .parse::<Table>()
+        .ok()
+        .and_then(|table| table.get("value").cloned())
+        .filter(|value| matches!(value, Value::Integer(_) | Value::Float(_)));
+    number.unwrap_or_else(|| Value::String(value.to_string())).to_string()
+}
@@ -0,0 +1,177 @@
+use ab_glyph::FontArc;
+use image::{DynamicImage, Rgba, RgbaImage};
+
+use crate::cli::ContactSheetArgs;
+use crate::collage::resize_rgba;
+use crate::error::Error;
+use crate::image_handling::{load_directories, read_caption, read_exposure, LabelSource, LoadOptions, LoadedImage, ScaleMode};
+use crate::metadata::Provenance;
+use crate::output::{page_path, save_collage, save_pdf};
+use crate::text::{default_font, draw_text, fit_text, line_height, text_width};
+
+const PAPER: Rgba<u8> = Rgba([255, 255, 255, 255]);
+const INK: Rgba<u8> = Rgba([40, 40, 40, 255]);
+/// Color of the file details under a thumbnail, lighter than its name.
+const DETAILS: Rgba<u8> = Rgba([110, 110, 110, 255]);
+/// Outline of every thumbnail cell, so that white photos stand out from the paper.
+const FRAME: Rgba<u8> = Rgba([215, 215, 215, 255]);
+
+/// Lines of text under every thumbnail: file name, date and exposure.
+const INFO_LINES: u32 = 3;
+
+/// Sizes of a contact sheet page, all derived from the thumbnail size.
+struct Sheet {
+    thumb: u32,
+    gap: u32,
+    font_size: f32,
+    caption_height: u32,
+    header_height: u32,
+    footer_height: u32,
+    columns: u32,
+    rows: u32,
+}
+
+impl Sheet {
+    fn new(thumb: u32, columns: u32, rows: u32, title: bool) -> Sheet {
+        let thumb = thumb.max(16);
+        let gap = (thumb / 10).max(4);
+        let font_size = (thumb as f32 / 14.0).max(8.0);
+        let font = default_font();
+        let line = line_height(&font, font_size).ceil() as u32;
+        Sheet {
+            thumb,
+            gap,
+            font_size,
+            caption_height: INFO_LINES * line + gap / 2,
+            header_height: if title { line_height(&font, font_size * 2.0).ceil() as u32 + gap } else { 0 },
+            footer_height: line + gap,
+            columns,
+            rows,
+        }
+    }
+
+    fn width(&self) -> u32 {
+        self.columns * self.thumb + (self.columns + 1) * self.gap
+    }
+
+    fn height(&self) -> u32 {
+        self.header_height + self.rows * (self.thumb + self.caption_height) + (self.rows + 1) * self.gap + self.footer_height
+    }
+
+    /// Top-left corner of the thumbnail cell at `index` on the page.
+    fn cell(&self, index: u32) -> (u32, u32) {
+        let (column, row) = (index % self.columns, index / self.columns);
+        let x = self.gap + column * (self.thumb + self.gap);
+        let y = self.header_height + self.gap + row * (self.thumb + self.caption_height + self.gap);
+        (x, y)
+    }
+}
+
+/// Lays the images out in a fixed grid of uniform thumbnails in file name order, with the file
+/// name, date and exposure of every photo under it and a page number on every page. Pages are
+/// saved as numbered files, or as the pages of one PDF for a `.pdf` output.
+pub fn contact_sheet(args: &ContactSheetArgs) -> Result<(), Error> {
+    // The physical size and the maximum output size scale the whole page, text included
+    let sheet = Sheet::new(args.thumb_size, args.columns, args.rows, args.title.is_some());
+    let factor = args.output_options.scale_for(sheet.width(), sheet.height());
+    let sheet = Sheet::new((args.thumb_size as f64 * factor).round() as u32, args.columns, args.rows, args.title.is_some());
+
+    // Images are loaded with the pixel area of a thumbnail, enough for any aspect ratio
+    let load = LoadOptions { standard_size: Some(sheet.thumb), scale_mode: ScaleMode::Area, ..args.load.clone() };
+    let (mut images, _) = load_directories(&args.dirs, &load)?;
+    if images.is_empty() {
+        return Err(Error::NoImages);
+    }
+    images.sort_by(|a, b| a.1.cmp(&b.1));
+
+    let per_page = (args.columns * args.rows) as usize;
+    let pages: Vec<&[LoadedImage]> = images.chunks(per_page).collect();
+    info!("Laying out {} images on {} pages of {}x{} pixels...", images.len(), pages.len(), sheet.width(), sheet.height());
+    let font = default_font();
+    let rendered: Vec<DynamicImage> = pages
+        .iter()
+        .enumerate()
+        .map(|(index, page)| DynamicImage::ImageRgba8(render_page(page, &sheet, &font, args.title.as_deref(), index + 1, pages.len())))
+        .collect();
+
+    let is_pdf = args.output.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("pdf"));
+    if is_pdf {
+        let provenance = Provenance { sources: images.iter().map(|(_, path, _)| path.clone()).collect(), ..Default::default() };
+        info!("Saving {} pages as '{}'...", rendered.len(), args.output.display());
+        return save_pdf(&rendered, &args.output, &args.output_options, Some(&provenance)).map_err(|e| {
+            eprintln!("Error saving contact sheet: {}", e);
+            Error::Save(1)
+        });
+    }
+    let mut failed = 0;
+    for (index, (page, images)) in rendered.iter().zip(&pages).enumerate() {
+        let path = if rendered.len() > 1 { page_path(&args.output, index + 1) } else { args.output.clone() };
+        let provenance = Provenance { sources: images.iter().map(|(_, path, _)| path.clone()).collect(), ..Default::default() };
+        info!("Saving page {} as '{}'...", index + 1, path.display());
+        if let Err(e) = save_collage(page, &path, &args.output_options, Some(&provenance)) {
+            eprintln!("Error saving contact sheet: {}", e);
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        return Err(Error::Save(failed));
+    }
+    Ok(())
+}
+
+fn render_page(images: &[LoadedImage], sheet: &Sheet, font: &FontArc, title: Option<&str>, page: usize, pages: usize) -> RgbaImage {
+    let (width, height) = (sheet.width(), sheet.height());
+    let mut canvas = RgbaImage::from_pixel(width, height, PAPER);
+    if let Some(title) = title {
+        let size = sheet.font_size * 2.0;
+        let title = fit_text(font, size, title, (width - 2 * sheet.gap) as f32);
+        draw_text(&mut canvas, font, size, &title, sheet.gap as f32, sheet.gap as f32, INK);
+    }
+
+    for (index, (_, path, img)) in images.iter().enumerate() {
+        let (x, y) = sheet.cell(index as u32);
+        draw_frame(&mut canvas, x, y, sheet.thumb);
+
+        // Fit the photo into the square cell, centered, whatever its aspect ratio
+        let fit = sheet.thumb as f64 / img.width().max(img.height()).max(1) as f64;
+        let thumb_w = ((img.width() as f64 * fit).round() as u32).clamp(1, sheet.thumb);
+        let thumb_h = ((img.height() as f64 * fit).round() as u32).clamp(1, sheet.thumb);
+        let thumb = resize_rgba(&img.to_rgba8(), thumb_w, thumb_h);
+        let left = (x + (sheet.thumb - thumb_w) / 2) as i64;
+        let top = (y + (sheet.thumb - thumb_h) / 2) as i64;
+        image::imageops::overlay(&mut canvas, &thumb, left, top);
+
+        let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        let lines = [
+            (Some(name), INK),
+            (read_caption(path, LabelSource::ExifDate), DETAILS),
+            (read_exposure(path), DETAILS),
+        ];
+        let line = line_height(font, sheet.font_size);
+        let mut text_y = (y + sheet.thumb + sheet.gap / 2) as f32;
+        for (text, color) in lines {
+            if let Some(text) = text {
+                let text = fit_text(font, sheet.font_size, &text, sheet.thumb as f32);
+                let text_x = x as f32 + (sheet.thumb as f32 - text_width(font, sheet.font_size, &text)) / 2.0;
+                draw_text(&mut canvas, font, sheet.font_size, &text, text_x, text_y, color);
+            }
+            text_y += line;
+        }
+    }
+
+    let number = format!("Page {} of {}", page, pages);
+    let number_x = (width as f32 - text_width(font, sheet.font_size, &number)) / 2.0;
+    let number_y = (height - sheet.footer_height) as f32;
+    draw_text(&mut canvas, font, sheet.font_size, &number, number_x, number_y, DETAILS);
+    canvas
+}
+
+/// Outlines the square thumbnail cell with its top-left corner at (x, y).
+fn draw_frame(canvas: &mut RgbaImage, x: u32, y: u32, size: u32) {
+    let (right, bottom) = (x + size - 1, y + size - 1);
+    for i in 0..size {
+        for (px, py) in [(x + i, y), (x + i, bottom), (x, y + i), (right, y + i)] {
+            canvas.put_pixel(px, py, FRAME);
+        }
+    }
+}
@@ -0,0 +1,242 @@
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Gauge, Paragraph, Sparkline};
+use ratatui::{DefaultTerminal, Frame};
+
+use crate::ga::{GaControl, GaSettings, GenerationStats};
+
+/// The screen is redrawn at most this often; generations can be much faster.
+const FRAME_INTERVAL: Duration = Duration::from_millis(100);
+
+/// `m` multiplies the mutation rate by this much.
+const MUTATION_BUMP: f64 = 1.5;
+
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+static LOG: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Whether the dashboard owns the terminal; messages go to `log` meanwhile.
+pub fn active() -> bool {
+    ACTIVE.load(Ordering::Relaxed)
+}
+
+/// Keeps a message for the log pane. The messages are printed when the dashboard closes.
+pub fn log(message: String) {
+    if let Ok(mut log) = LOG.lock() {
+        log.push(message);
+    }
+}
+
+/// Full-screen view of a running optimization for `--tui`, with keys to pause, raise the
+/// mutation rate or stop early.
+pub struct Dashboard {
+    terminal: DefaultTerminal,
+    title: String,
+    settings: GaSettings,
+    images: usize,
+    best: Vec<f64>,
+    latest: Option<GenerationStats>,
+    paused: bool,
+    started: Instant,
+    last_draw: Option<Instant>,
+}
+
+impl Dashboard {
+    /// Takes over the terminal until the dashboard is dropped. `images` is the number of images
+    /// being optimized.
+    pub fn start(title: String, settings: &GaSettings, images: usize) -> Result<Self, String> {
+        if !std::io::stdout().is_terminal() {
+            return Err("Error starting the dashboard: stdout is not a terminal".to_string());
+        }
+        let terminal = ratatui::try_init().map_err(|e| format!("Error starting the dashboard: {}", e))?;
+        ACTIVE.store(true, Ordering::Relaxed);
+        Ok(Dashboard {
+            terminal,
+            title,
+            settings: settings.clone(),
+            images,
+            best: Vec::with_capacity(settings.generations),
+            latest: None,
+            paused: false,
+            started: Instant::now(),
+            last_draw: None,
+        })
+    }
+
+    /// Shows a generation and handles the keys pressed since the previous one. Blocks while the
+    /// run is paused.
+    pub fn update(&mut self, stats: &GenerationStats, control: &mut GaControl) {
+        self.best.push(stats.best);
+        self.latest = Some(stats.clone());
+        self.handle_keys(control, Duration::ZERO);
+        while self.paused && !control.stop {
+            self.draw(control);
+            self.handle_keys(control, FRAME_INTERVAL);
+        }
+        let last_generation = stats.generation == self.settings.generations;
+        if control.stop || last_generation || self.last_draw.is_none_or(|drawn| drawn.elapsed() >= FRAME_INTERVAL) {
+            self.draw(control);
+        }
+    }
+
+    /// Handles all pending key presses, waiting up to `timeout` for the first one.
+    fn handle_keys(&mut self, control: &mut GaControl, timeout: Duration) {
+        let mut wait = timeout;
+        while event::poll(wait).unwrap_or(false) {
+            wait = Duration::ZERO;
+            let Ok(Event::Key(key)) = event::read() else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match key.code {
+                // The terminal is in raw mode, so Ctrl-C arrives as a key instead of a signal
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    ratatui::restore();
+                    std::process::exit(130);
+                }
+                KeyCode::Char('p') | KeyCode::Char(' ') => self.paused = !self.paused,
+                KeyCode::Char('m') => {
+                    control.mutation_rate = (control.mutation_rate * MUTATION_BUMP).min(1.0);
+                    log(format!("Mutation rate raised to {:.3}", control.mutation_rate));
+                }
+                KeyCode::Char('s') | KeyCode::Char('q') => {
+                    control.stop = true;
+                    log("Stopping after this generation and saving the best collage...".to_string());
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn draw(&mut self, control: &GaControl) {
+        let view = View {
+            title: &self.title,
+            settings: &self.settings,
+            images: self.images,
+            best: &self.best,
+            latest: self.latest.as_ref(),
+            paused: self.paused,
+            elapsed: self.started.elapsed(),
+            control,
+        };
+        if let Err(e) = self.terminal.draw(|frame| view.render(frame)) {
+            log(format!("Error drawing the dashboard: {}", e));
+        }
+        self.last_draw = Some(Instant::now());
+    }
+}
+
+impl Drop for Dashboard {
+    fn drop(&mut self) {
+        ratatui::restore();
+        ACTIVE.store(false, Ordering::Relaxed);
+        for message in LOG.lock().map(|mut log| std::mem::take(&mut *log)).unwrap_or_default() {
+            info!("{}", message);
+        }
+    }
+}
+
+/// What one frame of the dashboard shows.
+struct View<'a> {
+    title: &'a str,
+    settings: &'a GaSettings,
+    images: usize,
+    best: &'a [f64],
+    latest: Option<&'a GenerationStats>,
+    paused: bool,
+    elapsed: Duration,
+    control: &'a GaControl,
+}
+
+impl View<'_> {
+    fn render(&self, frame: &mut Frame) {
+        let [progress_area, chart_area, parameter_area, log_area, help_area] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Length(10),
+            Constraint::Length(6),
+            Constraint::Min(3),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        let generation = self.latest.map_or(0, |stats| stats.generation);
+        let generations = self.settings.generations.max(1);
+        let state = if self.control.stop {
+            "stopping"
+        } else if self.paused {
+            "paused"
+        } else {
+            "running"
+        };
+        let progress = Gauge::default()
+            .block(Block::bordered().title(format!(" ImageGridOptimizer {} ({}) ", self.title, state)))
+            .gauge_style(Style::default().fg(Color::Cyan))
+            .ratio((generation as f64 / generations as f64).min(1.0))
+            .label(format!("Generation {} / {}", generation, generations));
+        frame.render_widget(progress, progress_area);
+
+        // Only the most recent generations fit; the curve is stretched between their extremes
+        let width = chart_area.width.saturating_sub(2) as usize;
+        let recent = &self.best[self.best.len().saturating_sub(width)..];
+        let low = recent.iter().copied().fold(f64::INFINITY, f64::min);
+        let high = recent.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let heights: Vec<u64> = recent
+            .iter()
+            .map(|best| if high > low { (1.0 + (best - low) / (high - low) * 99.0) as u64 } else { 50 })
+            .collect();
+        let best = self.latest.map_or(0.0, |stats| stats.best);
+        let chart = Sparkline::default()
+            .block(Block::bordered().title(format!(" Best fitness {:.5} ", best)))
+            .style(Style::default().fg(Color::Green))
+            .max(100)
+            .data(&heights);
+        frame.render_widget(chart, chart_area);
+
+        let rate = generation as f64 / self.elapsed.as_secs_f64().max(1e-9);
+        let remaining = Duration::from_secs_f64(generations.saturating_sub(generation) as f64 / rate.max(1e-9));
+        let parameters = match self.latest {
+            Some(stats) => vec![
+                Line::from(format!(
+                    "Population {}   Images {} ({}-{} per collage)   Crossover rate {}   Mutation rate {:.3}",
+                    self.settings.population_size,
+                    self.images,
+                    self.settings.min_images,
+                    self.settings.max_images,
+                    self.settings.crossover_rate,
+                    self.control.mutation_rate
+                )),
+                Line::from(format!("Fitness: best {:.5}, mean {:.5}, worst {:.5}", stats.best, stats.mean, stats.worst)),
+                Line::from(format!(
+                    "Best collage: {:.1}% free area, aspect ratio deviation {:.3}",
+                    stats.free_area_percentage, stats.aspect_ratio_diff
+                )),
+                Line::from(format!(
+                    "Elapsed {}s, {:.1} generations/s, about {}s left",
+                    self.elapsed.as_secs(),
+                    rate,
+                    remaining.as_secs()
+                )),
+            ],
+            None => Vec::new(),
+        };
+        frame.render_widget(Paragraph::new(parameters).block(Block::bordered().title(" Parameters ")), parameter_area);
+
+        let lines = log_area.height.saturating_sub(2) as usize;
+        let messages: Vec<Line> = LOG
+            .lock()
+            .map(|log| log[log.len().saturating_sub(lines)..].iter().map(|message| Line::from(message.clone())).collect())
+            .unwrap_or_default();
+        frame.render_widget(Paragraph::new(messages).block(Block::bordered().title(" Messages ")), log_area);
+
+        let help = "p pause/resume   m raise mutation rate   s stop and save   Ctrl-C abort";
+        frame.render_widget(Paragraph::new(help).style(Style::default().fg(Color::DarkGray)), help_area);
+    }
+}
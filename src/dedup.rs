@@ -0,0 +1,71 @@
+use std::io::Cursor;
+use std::path::Path;
+
+use image::imageops::FilterType;
+use image::io::Reader;
+use image::{DynamicImage, GenericImageView};
+use rayon::prelude::*;
+
+use crate::image_handling::{read_source, LoadedImage};
+
+/// Collapses near-identical photos such as burst shots and edited copies: images whose
+/// difference hashes differ in at most `threshold` of 64 bits count as the same photo, and only
+/// the one with the highest resolution is kept. Prints what was dropped.
+pub fn drop_near_duplicates(images: Vec<LoadedImage>, threshold: u32) -> Vec<LoadedImage> {
+    let hashes: Vec<(u64, u64)> = images
+        .par_iter()
+        .map(|(_, path, img)| (dhash(img), resolution(path, img)))
+        .collect();
+
+    // Visit the largest images first so that every group keeps its best version
+    let mut order: Vec<usize> = (0..images.len()).collect();
+    order.sort_by_key(|&i| std::cmp::Reverse(hashes[i].1));
+    let mut kept: Vec<usize> = Vec::new();
+    let mut keep = vec![false; images.len()];
+    for i in order {
+        let duplicate_of = kept
+            .iter()
+            .map(|&k| (k, (hashes[i].0 ^ hashes[k].0).count_ones()))
+            .find(|&(_, distance)| distance <= threshold);
+        match duplicate_of {
+            Some((k, distance)) => info!(
+                "Dropping near-duplicate {} (keeping {}, distance {})",
+                images[i].1.display(),
+                images[k].1.display(),
+                distance
+            ),
+            None => {
+                kept.push(i);
+                keep[i] = true;
+            }
+        }
+    }
+
+    let total = images.len();
+    let images: Vec<LoadedImage> = images.into_iter().zip(keep).filter_map(|(image, keep)| keep.then_some(image)).collect();
+    info!("Removed {} near-duplicate images, {} remain.", total - images.len(), images.len());
+    images
+}
+
+/// 64-bit difference hash: each bit tells whether a pixel of a 9x8 grayscale thumbnail is
+/// brighter than its right neighbour. Robust against scaling, compression and small edits.
+fn dhash(img: &DynamicImage) -> u64 {
+    let small = img.resize_exact(9, 8, FilterType::Triangle).to_luma8();
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash = (hash << 1) | (small.get_pixel(x, y)[0] > small.get_pixel(x + 1, y)[0]) as u64;
+        }
+    }
+    hash
+}
+
+/// Pixel count of the source file; loaded images are already scaled to the standard size. Falls
+/// back to the loaded size for files whose header cannot be read (e.g. SVG).
+fn resolution(path: &Path, img: &DynamicImage) -> u64 {
+    let source = read_source(path)
+        .ok()
+        .and_then(|bytes| Reader::new(Cursor::new(bytes)).with_guessed_format().ok()?.into_dimensions().ok());
+    let (width, height) = source.unwrap_or_else(|| img.dimensions());
+    width as u64 * height as u64
+}
@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use image::DynamicImage;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+use crate::cli::{Args, Input};
+use crate::file_list::read_file_list;
+use crate::image_handling::{loaded_size, scan_headers};
+use crate::inspect::{fit_memory_budget, memory_estimate, BYTES_PER_PIXEL, RENDER_BUFFERS};
+use crate::packing::pack_images;
+use crate::remote::is_url;
+
+/// Number of random selections packed to time an evaluation.
+const SAMPLE_PACKS: usize = 20;
+
+/// The packer only looks at image sizes, so the sample packs use blank placeholders shrunk to at
+/// most this size on their longer side.
+const PLACEHOLDER_SIZE: u32 = 512;
+
+/// Reports what a run would do without decoding the images or optimizing: how many images pass
+/// the filters, the expected memory use and the time per generation from a few sample packs.
+pub fn dry_run(args: &Args) {
+    info!("Dry run: reading file headers only, nothing is optimized or saved.");
+    let (inputs, urls) = match &args.input {
+        Input::Directories(dirs) => (dirs.iter().map(PathBuf::from).collect(), 0),
+        Input::List(list) => match read_file_list(list) {
+            Ok(entries) => {
+                let (urls, files): (Vec<PathBuf>, Vec<PathBuf>) =
+                    entries.into_iter().map(|entry| entry.path).partition(|path| is_url(&path.to_string_lossy()));
+                (files, urls.len())
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                return;
+            }
+        },
+    };
+    let scan = scan_headers(&inputs, &args.load);
+    info!("Supported files: {}", scan.files);
+    info!("Images passing the filters: {}", scan.sizes.len());
+    if scan.unchecked > 0 {
+        info!("Not counted: {} videos, PDFs and SVGs, whose images are only known after extracting them", scan.unchecked);
+    }
+    if urls > 0 {
+        info!("Not counted: {} URLs, which are only downloaded by a real run", urls);
+    }
+    if args.load.min_sharpness.is_some() || args.dedup_threshold.is_some() {
+        info!("Sharpness and near-duplicate filters need the pixels and may remove further images.");
+    }
+    if scan.sizes.is_empty() {
+        info!("No images to optimize.");
+        return;
+    }
+
+    let load = match args.max_memory {
+        Some(budget) => fit_memory_budget(&scan.sizes, &args.load, args.ga.max_images, budget),
+        None => args.load.clone(),
+    };
+    let (loaded, decoding) = memory_estimate(&scan.sizes, &load);
+    let sizes: Vec<(u32, u32)> = scan.sizes.iter().map(|&(w, h)| loaded_size(w, h, &load)).collect();
+    let (pack_time, canvas) = sample_packs(&sizes, args);
+    let rendering = canvas * BYTES_PER_PIXEL * RENDER_BUFFERS / 1e6;
+    // Every worker thread decodes a file at the same time while loading
+    let threads = rayon::current_num_threads().max(1);
+    let decoding = decoding * threads as f64;
+    info!(
+        "Estimated memory: {:.0} MB for the loaded images, {:.0} MB for decoding the largest files on {} threads, {:.0} MB for rendering; peak about {:.0} MB",
+        loaded,
+        decoding,
+        threads,
+        rendering,
+        loaded + decoding.max(rendering)
+    );
+
+    // Every generation evaluates the whole population in parallel
+    let generation = pack_time.mul_f64(args.ga.population_size as f64 / threads as f64);
+    let total = generation.mul_f64(args.ga.generations as f64);
+    info!(
+        "Evaluation: {:.2} ms per collage on average, about {:.1} ms per generation of {} with {} worker threads, {} for {} generations",
+        pack_time.as_secs_f64() * 1000.0,
+        generation.as_secs_f64() * 1000.0,
+        args.ga.population_size,
+        threads,
+        format_duration(total),
+        args.ga.generations
+    );
+}
+
+/// Packs random selections of the images, sized like the selections of the run. Returns the
+/// average time per pack and the average canvas area in pixels.
+fn sample_packs(sizes: &[(u32, u32)], args: &Args) -> (Duration, f64) {
+    let longest = sizes.iter().map(|&(w, h)| w.max(h)).max().unwrap_or(1);
+    let shrink = (PLACEHOLDER_SIZE as f64 / longest as f64).min(1.0);
+    let placeholders: HashMap<u32, DynamicImage> = sizes
+        .iter()
+        .enumerate()
+        .map(|(id, &(w, h))| {
+            let w = ((w as f64 * shrink) as u32).max(1);
+            let h = ((h as f64 * shrink) as u32).max(1);
+            (id as u32, DynamicImage::new_luma8(w, h))
+        })
+        .collect();
+
+    let mut rng = StdRng::seed_from_u64(args.seed.unwrap_or_default());
+    let ids: Vec<u32> = (0..sizes.len() as u32).collect();
+    let min_images = args.ga.min_images.clamp(1, ids.len());
+    let max_images = args.ga.max_images.clamp(min_images, ids.len());
+    let mut elapsed = Duration::ZERO;
+    let mut canvas = 0.0;
+    for _ in 0..SAMPLE_PACKS {
+        let count = rng.gen_range(min_images..=max_images);
+        let selection: Vec<u32> = ids.choose_multiple(&mut rng, count).copied().collect();
+        let start = Instant::now();
+        let (_, w, h) = pack_images(&selection, &placeholders, &args.packing).unwrap_or_default();
+        elapsed += start.elapsed();
+        canvas += w as f64 * h as f64 / (shrink * shrink);
+    }
+    (elapsed / SAMPLE_PACKS as u32, canvas / SAMPLE_PACKS as f64)
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match seconds {
+        0..=59 => format!("{:.1} s", duration.as_secs_f64()),
+        60..=3599 => format!("{} min {} s", seconds / 60, seconds % 60),
+        _ => format!("{} h {} min", seconds / 3600, seconds % 3600 / 60),
+    }
+}
@@ -0,0 +1,63 @@
+use std::any::Any;
+use std::io;
+use std::path::PathBuf;
+
+use thiserror::Error;
+
+/// Failures that end a run. Each kind exits with its own code so that scripts can tell them apart.
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Error reading directory {}: {source}", path.display())]
+    ReadDirectory { path: PathBuf, source: io::Error },
+    /// An input that is not a directory, such as an archive or a file list, could not be read.
+    #[error("{0}")]
+    ReadInput(String),
+    #[error("No images loaded.")]
+    NoImages,
+    #[error("Image {0} was not loaded and cannot be packed.")]
+    UnknownImage(u32),
+    #[error("The {0} images do not fit on the canvas.")]
+    Packing(usize),
+    /// An image of the final collage is smaller than `--min-tile` on its shorter side.
+    #[error("{} would be {size} pixels on its shorter side, below --min-tile {min}; allow fewer images with --max-images or more room with --max-output.", path.display())]
+    TileTooSmall { path: PathBuf, size: u32, min: u32 },
+    /// Output files that could not be saved; the reasons are printed as they occur so that the
+    /// remaining files are still saved.
+    #[error("{0} output files could not be saved.")]
+    Save(usize),
+    /// A packed layout failed the checks of `--validate-layout`.
+    #[error("Packing produced an invalid layout: {0}")]
+    InvalidLayout(String),
+    /// Options given to the library that contradict each other, such as more `min_images` than
+    /// `max_images`; the command line tool rejects these while parsing.
+    #[error("{0}")]
+    InvalidOptions(String),
+    /// The HTTP server of `serve` could not start.
+    #[error("{0}")]
+    Serve(String),
+}
+
+impl Error {
+    /// Exit code of the process; 1 is left for other errors and 2 is used by clap for invalid
+    /// command lines, as for invalid options of the library.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::ReadDirectory { .. } | Error::ReadInput(_) => 3,
+            Error::InvalidOptions(_) => 2,
+            Error::NoImages => 4,
+            Error::UnknownImage(_) | Error::Packing(_) | Error::TileTooSmall { .. } => 5,
+            Error::Save(_) => 6,
+            Error::InvalidLayout(_) => 7,
+            Error::Serve(_) => 1,
+        }
+    }
+}
+
+/// The message of a caught panic, for reporting it like an error.
+pub fn panic_message(panic: &(dyn Any + Send)) -> String {
+    panic
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| panic.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string())
+}
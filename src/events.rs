@@ -0,0 +1,62 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use serde_json::Value;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static SILENT: AtomicBool = AtomicBool::new(false);
+/// Receives every event in addition to stdout, see `set_sink`.
+type Sink = Box<dyn Fn(&Value) + Send>;
+static SINK: Mutex<Option<Sink>> = Mutex::new(None);
+
+/// Switches to machine-readable output: events are written to stdout as JSON lines and progress
+/// messages move to stderr.
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Drops progress messages while set, e.g. the per-image lines of a collage rendered only to time it.
+pub fn set_silent(silent: bool) {
+    SILENT.store(silent, Ordering::Relaxed);
+}
+
+pub fn silent() -> bool {
+    SILENT.load(Ordering::Relaxed)
+}
+
+/// Prints a progress message: into the dashboard while it runs, to stderr with `--json` so that
+/// stdout only carries events, and to stdout otherwise.
+pub fn message(text: String) {
+    #[cfg(feature = "cli")]
+    if crate::dashboard::active() {
+        crate::dashboard::log(text);
+        return;
+    }
+    if enabled() {
+        eprintln!("{}", text);
+    } else {
+        println!("{}", text);
+    }
+}
+
+/// Hands every event to `sink` as well, whether or not they are written to stdout, e.g. to track
+/// the progress of a job of the HTTP server; `None` removes it.
+pub fn set_sink(sink: Option<Sink>) {
+    if let Ok(mut current) = SINK.lock() {
+        *current = sink;
+    }
+}
+
+/// Writes an event such as `{"event": "generation", ...}` as one line to stdout, if enabled.
+pub fn emit(event: Value) {
+    if let Some(sink) = SINK.lock().ok().as_ref().and_then(|sink| sink.as_ref()) {
+        sink(&event);
+    }
+    if enabled() {
+        println!("{}", event);
+    }
+}
@@ -0,0 +1,239 @@
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+
+use serde_json::{Map, Value};
+
+use crate::error::panic_message;
+use crate::ga::ProgressEvent;
+use crate::layout::Layout;
+use crate::wasm::{ImageSet, Options};
+
+// Functions that can fail return 0 on success and otherwise the exit code of the command line tool
+// for the same kind of failure. After changes here, regenerate `include/image_grid_optimizer.h`
+// with `cbindgen --config cbindgen.toml --output include/image_grid_optimizer.h`.
+
+/// Returned for a null pointer, a string that is not UTF-8 or a render before the first run.
+const INVALID_ARGUMENT: i32 = 1;
+/// Returned for an unknown parameter or a value it does not accept, as for a wrong command line.
+const INVALID_PARAMETER: i32 = 2;
+/// Returned for an image that cannot be decoded, as for an input that cannot be read.
+const UNREADABLE_IMAGE: i32 = 3;
+/// Returned for a panic, i.e. a bug, like the exit code of a Rust program that panics.
+const PANICKED: i32 = 101;
+
+/// Called after every generation of `igo_optimizer_run` with the generation (from 1), the number
+/// of generations and the best fitness so far; returning false ends the run with the best collage
+/// found so far.
+pub type IgoProgressCallback = Option<extern "C" fn(user_data: *mut c_void, generation: usize, generations: usize, best_fitness: f64) -> bool>;
+
+/// An optimizer with its images, parameters and last result. Create it with
+/// `igo_optimizer_new` and release it with `igo_optimizer_free`.
+pub struct IgoOptimizer {
+    images: ImageSet,
+    standard_width: Option<u32>,
+    params: Map<String, Value>,
+    layout: Option<(Layout, CString)>,
+    pixels: Vec<u8>,
+    error: Option<CString>,
+}
+
+impl IgoOptimizer {
+    fn fail(&mut self, code: i32, message: String) -> i32 {
+        self.error = CString::new(message).ok();
+        code
+    }
+}
+
+/// Creates an optimizer without images. Images are scaled to `standard_width` pixels as they are
+/// added, as with `--width`; 0 keeps their size.
+#[no_mangle]
+pub extern "C" fn igo_optimizer_new(standard_width: u32) -> *mut IgoOptimizer {
+    guarded(ptr::null_mut(), ptr::null_mut(), || {
+        let optimizer = IgoOptimizer {
+            images: ImageSet::default(),
+            standard_width: (standard_width > 0).then_some(standard_width),
+            params: Map::new(),
+            layout: None,
+            pixels: Vec::new(),
+            error: None,
+        };
+        Box::into_raw(Box::new(optimizer))
+    })
+}
+
+/// Releases an optimizer and everything it returned.
+///
+/// # Safety
+/// `optimizer` must come from `igo_optimizer_new` and not be used afterwards; null is ignored.
+#[no_mangle]
+pub unsafe extern "C" fn igo_optimizer_free(optimizer: *mut IgoOptimizer) {
+    guarded(ptr::null_mut(), (), || {
+        if !optimizer.is_null() {
+            drop(Box::from_raw(optimizer));
+        }
+    })
+}
+
+/// Decodes an image from the `len` bytes of an encoded file (JPEG, PNG, ...) and adds it. Images
+/// are numbered from 0 in the order they are added; these are the ids in the layout.
+///
+/// # Safety
+/// `optimizer` must be valid and `data` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn igo_optimizer_add_image(optimizer: *mut IgoOptimizer, data: *const u8, len: usize) -> i32 {
+    guarded(optimizer, PANICKED, || {
+        let Some(optimizer) = optimizer.as_mut() else {
+            return INVALID_ARGUMENT;
+        };
+        if data.is_null() {
+            return optimizer.fail(INVALID_ARGUMENT, "The image data is null".to_string());
+        }
+        match optimizer.images.push(slice::from_raw_parts(data, len), optimizer.standard_width) {
+            Ok(_) => 0,
+            Err(e) => optimizer.fail(UNREADABLE_IMAGE, e),
+        }
+    })
+}
+
+/// Sets a parameter of the GA by the name of its command line option: `pop-size`, `gens`,
+/// `min-images`, `max-images`, `mutation-rate`, `crossover-rate` or `seed`, e.g. `"gens"` to
+/// `"300"`. Parameters not set keep the defaults of the command line tool.
+///
+/// # Safety
+/// `optimizer` must be valid and `name` and `value` must be null-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn igo_optimizer_set_param(optimizer: *mut IgoOptimizer, name: *const c_char, value: *const c_char) -> i32 {
+    guarded(optimizer, PANICKED, || {
+        let Some(optimizer) = optimizer.as_mut() else {
+            return INVALID_ARGUMENT;
+        };
+        let (Some(name), Some(value)) = (utf8(name), utf8(value)) else {
+            return optimizer.fail(INVALID_ARGUMENT, "The parameter name or value is null or not UTF-8".to_string());
+        };
+        let Ok(parsed) = serde_json::from_str::<Value>(value) else {
+            return optimizer.fail(INVALID_PARAMETER, format!("Invalid value for {}: {}", name, value));
+        };
+        let mut params = optimizer.params.clone();
+        params.insert(name.to_string(), parsed);
+        if let Err(e) = serde_json::from_value::<Options>(Value::Object(params.clone())) {
+            return optimizer.fail(INVALID_PARAMETER, format!("Invalid parameter {}={}: {}", name, value, e));
+        }
+        optimizer.params = params;
+        0
+    })
+}
+
+/// Runs the GA on the images added so far and keeps the layout of the best collage for
+/// `igo_optimizer_layout_json` and `igo_optimizer_render`. `progress` may be null; `user_data` is
+/// passed on to it.
+///
+/// # Safety
+/// `optimizer` must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn igo_optimizer_run(optimizer: *mut IgoOptimizer, progress: IgoProgressCallback, user_data: *mut c_void) -> i32 {
+    guarded(optimizer, PANICKED, || {
+        let Some(optimizer) = optimizer.as_mut() else {
+            return INVALID_ARGUMENT;
+        };
+        let options: Options = match serde_json::from_value(Value::Object(optimizer.params.clone())) {
+            Ok(options) => options,
+            Err(e) => return optimizer.fail(INVALID_PARAMETER, e.to_string()),
+        };
+        let on_progress = |event: ProgressEvent| {
+            if let (ProgressEvent::Generation { stats, control, .. }, Some(progress)) = (event, progress) {
+                control.stop |= !progress(user_data, stats.generation, options.gens, stats.best);
+            }
+        };
+        match optimizer.images.optimize_with_progress(&options, on_progress) {
+            Ok(layout) => {
+                let json = serde_json::to_string(&layout).unwrap_or_default();
+                optimizer.layout = Some((layout, CString::new(json).unwrap_or_default()));
+                optimizer.pixels.clear();
+                0
+            }
+            Err(e) => optimizer.fail(e.exit_code(), e.to_string()),
+        }
+    })
+}
+
+/// The layout of the last run as JSON, as in the `layout.json` of the command line tool, or null
+/// before the first successful run. The string belongs to the optimizer and stays valid until the
+/// next run.
+///
+/// # Safety
+/// `optimizer` must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn igo_optimizer_layout_json(optimizer: *const IgoOptimizer) -> *const c_char {
+    guarded(ptr::null_mut(), ptr::null(), || {
+        optimizer.as_ref().and_then(|optimizer| optimizer.layout.as_ref()).map_or(ptr::null(), |(_, json)| json.as_ptr())
+    })
+}
+
+/// Renders the collage of the last run with the default look and returns its pixels as RGBA, row
+/// by row, with its size in `width` and `height`; null on failure. The pixels belong to the
+/// optimizer and stay valid until the next run or render.
+///
+/// # Safety
+/// `optimizer` must be valid and `width` and `height` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn igo_optimizer_render(optimizer: *mut IgoOptimizer, width: *mut u32, height: *mut u32) -> *const u8 {
+    guarded(optimizer, ptr::null(), || {
+        let Some(optimizer) = optimizer.as_mut() else {
+            return ptr::null();
+        };
+        if width.is_null() || height.is_null() {
+            optimizer.fail(INVALID_ARGUMENT, "The width or height pointer is null".to_string());
+            return ptr::null();
+        }
+        let Some((layout, _)) = &optimizer.layout else {
+            optimizer.fail(INVALID_ARGUMENT, "There is no layout to render; run the optimizer first".to_string());
+            return ptr::null();
+        };
+        match optimizer.images.render_rgba(layout) {
+            Ok(collage) => {
+                *width = collage.width();
+                *height = collage.height();
+                optimizer.pixels = collage.into_raw();
+                optimizer.pixels.as_ptr()
+            }
+            Err(e) => {
+                optimizer.fail(e.exit_code(), e.to_string());
+                ptr::null()
+            }
+        }
+    })
+}
+
+/// Describes the last failure of a function of this optimizer, or null if there was none. The
+/// string belongs to the optimizer and stays valid until the next failure.
+///
+/// # Safety
+/// `optimizer` must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn igo_last_error(optimizer: *const IgoOptimizer) -> *const c_char {
+    guarded(ptr::null_mut(), ptr::null(), || {
+        optimizer.as_ref().and_then(|optimizer| optimizer.error.as_ref()).map_or(ptr::null(), |error| error.as_ptr())
+    })
+}
+
+/// Runs the body of an exported function. A panic must not unwind into the host application, which
+/// would abort it, so it is caught and reported as the last error of `optimizer`, if there is one,
+/// and `on_panic` is returned.
+fn guarded<T>(optimizer: *mut IgoOptimizer, on_panic: T, body: impl FnOnce() -> T) -> T {
+    catch_unwind(AssertUnwindSafe(body)).unwrap_or_else(|panic| {
+        // SAFETY: the exported functions require `optimizer` to be valid or null
+        if let Some(optimizer) = unsafe { optimizer.as_mut() } {
+            optimizer.fail(PANICKED, format!("Internal error: {}", panic_message(&*panic)));
+        }
+        on_panic
+    })
+}
+
+unsafe fn utf8<'a>(text: *const c_char) -> Option<&'a str> {
+    if text.is_null() {
+        return None;
+    }
+    CStr::from_ptr(text).to_str().ok()
+}
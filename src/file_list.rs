@@ -0,0 +1,150 @@
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use crate::remote::{download_all, is_url, DownloadOptions};
+
+/// One line of a `--from-list` file: `path[,weight[,caption]]`.
+#[derive(Clone, Debug)]
+pub struct ListEntry {
+    pub path: PathBuf,
+    /// Relative importance in the fitness; images without a weight count as 1.
+    pub weight: Option<f64>,
+    pub caption: Option<String>,
+}
+
+/// Reads a file list from `source`, or from stdin when it is `-`. Relative paths are resolved
+/// against the directory of the list file; URLs are kept as they are for `download_entries`.
+pub fn read_file_list(source: &str) -> Result<Vec<ListEntry>, String> {
+    if source == "-" {
+        let mut text = String::new();
+        io::stdin()
+            .read_to_string(&mut text)
+            .map_err(|e| format!("Error reading file list from stdin: {}", e))?;
+        return parse_file_list(&text, Path::new("")).map_err(|e| format!("Error reading file list from stdin: {}", e));
+    }
+    let path = Path::new(source);
+    let text = fs::read_to_string(path).map_err(|e| format!("Error reading file list {}: {}", path.display(), e))?;
+    parse_file_list(&text, path.parent().unwrap_or(Path::new("")))
+        .map_err(|e| format!("Error reading file list {}: {}", path.display(), e))
+}
+
+/// Parses CSV lines of a path and optional weight and caption columns. Empty lines and lines
+/// starting with `#` are ignored, as is a `path,...` header line.
+fn parse_file_list(text: &str, base: &Path) -> Result<Vec<ListEntry>, String> {
+    let mut entries = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields = split_csv_line(line);
+        if index == 0 && ["path", "file", "filename"].contains(&fields[0].to_ascii_lowercase().as_str()) {
+            continue;
+        }
+
+        let weight = match fields.get(1).map(|w| w.trim()).filter(|w| !w.is_empty()) {
+            Some(weight) => Some(
+                weight
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|w| *w >= 0.0)
+                    .ok_or_else(|| format!("invalid weight '{}' on line {}", weight, index + 1))?,
+            ),
+            None => None,
+        };
+        let caption = fields.get(2).map(|c| c.trim().to_string()).filter(|c| !c.is_empty());
+        let file = fields[0].trim();
+        let path = if is_url(file) { PathBuf::from(file) } else { base.join(file) };
+        entries.push(ListEntry { path, weight, caption });
+    }
+    Ok(entries)
+}
+
+/// Writes `paths` as a file list for `--from-list`: one absolute path per line, quoted where a
+/// comma or quote would split it.
+pub fn write_file_list(paths: &[&Path], path: &Path) -> Result<(), String> {
+    let mut text = String::new();
+    for source in paths {
+        let source = fs::canonicalize(source).unwrap_or_else(|_| source.to_path_buf()).to_string_lossy().into_owned();
+        if source.contains([',', '"']) {
+            text.push_str(&format!("\"{}\"\n", source.replace('"', "\"\"")));
+        } else {
+            text.push_str(&source);
+            text.push('\n');
+        }
+    }
+    fs::write(path, text).map_err(|e| format!("Error writing {}: {}", path.display(), e))
+}
+
+/// Downloads the entries given by URL and points them to the downloaded files. Entries that fail
+/// to download are reported and dropped.
+pub fn download_entries(entries: Vec<ListEntry>, options: &DownloadOptions) -> Vec<ListEntry> {
+    let urls: Vec<String> = entries
+        .iter()
+        .map(|entry| entry.path.to_string_lossy().into_owned())
+        .filter(|path| is_url(path))
+        .collect();
+    if urls.is_empty() {
+        return entries;
+    }
+    info!("Downloading {} images...", urls.len());
+    let mut downloads = download_all(&urls, options).into_iter();
+
+    let mut resolved = Vec::with_capacity(entries.len());
+    for mut entry in entries {
+        if is_url(&entry.path.to_string_lossy()) {
+            match downloads.next() {
+                Some(Ok(path)) => entry.path = path,
+                Some(Err(e)) => {
+                    eprintln!("{}", e);
+                    continue;
+                }
+                None => continue,
+            }
+        }
+        resolved.push(entry);
+    }
+    resolved
+}
+
+/// Reads a `--weights` CSV file of `name,weight` lines. Names are file names or paths.
+pub fn read_weights(path: &Path) -> Result<Vec<(String, f64)>, String> {
+    let error = |e: &dyn std::fmt::Display| format!("Error reading weights {}: {}", path.display(), e);
+    let text = fs::read_to_string(path).map_err(|e| error(&e))?;
+    let mut weights = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields = split_csv_line(line);
+        let value = fields.get(1).map(|w| w.trim()).unwrap_or_default();
+        match value.parse::<f64>() {
+            Ok(weight) if weight >= 0.0 => weights.push((fields[0].trim().to_string(), weight)),
+            // A header line such as `file,weight`
+            _ if index == 0 => continue,
+            _ => return Err(error(&format!("invalid weight '{}' on line {}", value, index + 1))),
+        }
+    }
+    Ok(weights)
+}
+
+/// Splits a CSV line at commas. Fields may be quoted to contain commas, with `""` for a quote.
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                fields.last_mut().unwrap().push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(String::new()),
+            _ => fields.last_mut().unwrap().push(c),
+        }
+    }
+    fields
+}
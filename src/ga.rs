@@ -1,35 +1,343 @@
+use clap::ValueEnum;
 use rand::Rng;
 use rand::seq::SliceRandom;
-use std::collections::HashMap;
-use image::DynamicImage;
+use indicatif::{ProgressBar, ProgressStyle};
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+#[cfg(target_arch = "wasm32")]
+use web_time::Instant;
+use image::{DynamicImage, Rgb};
+use rect_packer::Rect;
+use serde_json::json;
 
-use crate::packing::{pack_images, DESIRED_ASPECT_RATIO};
+use crate::cancel::CancellationToken;
+use crate::color::{color_distance, spectrum_position};
+use crate::error::Error;
+use crate::events::emit;
+use crate::hex::{hex_area, hex_neighbours};
+use crate::golden::{kept_share, shown_share};
+use crate::pile::{covered_area, occlusion, pack_pile, PileGene};
+use crate::packing::{
+    fit_scale, neighbours, pack_genes, pack_images, reserved_area, ImageGene, LayoutKind, PackedLayout, PackingOptions, DESIRED_ASPECT_RATIO,
+    MAX_GENE_SCALE, MIN_GENE_SCALE,
+};
+
+/// Share of the first generation filled with mutated copies of `GaSettings::initial`, so the run
+/// explores around a known good selection without the population collapsing onto it.
+const INITIAL_VARIANT_SHARE: f64 = 0.1;
+
+/// Share of images two alternatives of `--top-n` must not have in common (Jaccard distance).
+const MIN_DISTINCTNESS: f64 = 0.25;
+
+/// How two parents are combined into a child.
+#[derive(Clone, Copy, Default, PartialEq, ValueEnum)]
+pub enum CrossoverKind {
+    /// Joins the start of one parent's image list with the end of the other's.
+    #[default]
+    Cut,
+    /// Takes the images in a region of one parent's layout and those outside the same region of
+    /// the other's, so neighbouring images stay together (`layout_crossover`).
+    Layout,
+}
+
+/// The ways `mutate` changes a selection.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MutationOp {
+    /// Adds an image that is not in the selection yet.
+    Add,
+    /// Drops an image.
+    Remove,
+    /// Exchanges an image for one that is not in the selection yet.
+    Replace,
+    /// Exchanges the places of two images in the packing order.
+    Swap,
+    /// Moves an image to another place in the packing order.
+    Reorder,
+    /// Shifts an image of a pile and moves it up or down in the pile.
+    Nudge,
+    /// Makes an image of a packed layout larger or smaller.
+    Rescale,
+    /// Turns an image of a packed layout within `--max-rotation`.
+    Rotate,
+}
+
+impl MutationOp {
+    pub const ALL: [MutationOp; 8] = [
+        MutationOp::Add,
+        MutationOp::Remove,
+        MutationOp::Replace,
+        MutationOp::Swap,
+        MutationOp::Reorder,
+        MutationOp::Nudge,
+        MutationOp::Rescale,
+        MutationOp::Rotate,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MutationOp::Add => "add",
+            MutationOp::Remove => "remove",
+            MutationOp::Replace => "replace",
+            MutationOp::Swap => "swap",
+            MutationOp::Reorder => "reorder",
+            MutationOp::Nudge => "nudge",
+            MutationOp::Rescale => "rescale",
+            MutationOp::Rotate => "rotate",
+        }
+    }
+}
+
+/// Relative probabilities of the mutation operators, in the order of `MutationOp::ALL`. By default
+/// images are added, removed and replaced equally often and the packing order is left alone.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MutationWeights(pub [f64; 8]);
+
+impl Default for MutationWeights {
+    fn default() -> Self {
+        MutationWeights([1.0, 1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0])
+    }
+}
+
+impl MutationWeights {
+    pub fn weight(&self, op: MutationOp) -> f64 {
+        self.0[op as usize]
+    }
+}
+
+/// Parameters of the genetic algorithm.
+#[derive(Clone)]
+pub struct GaSettings {
+    pub population_size: usize,
+    pub generations: usize,
+    pub min_images: usize,
+    pub max_images: usize,
+    pub mutation_rate: f64,
+    pub mutation_weights: MutationWeights,
+    pub crossover_rate: f64,
+    pub crossover: CrossoverKind,
+    /// Every input directory contributes at least this many images (if it has them).
+    pub per_dir_min: usize,
+    /// No input directory contributes more than this many images.
+    pub per_dir_max: Option<usize>,
+    /// Hide the progress of the generations.
+    pub quiet: bool,
+    /// Selections put into the first generation together with mutated variants of them (e.g. the
+    /// previous best in watch mode); ids that are not among the images being optimized are ignored.
+    pub initial: Vec<Vec<u32>>,
+    /// Share of the first generation built by the greedy heuristics of `greedy_individuals`
+    /// instead of at random.
+    pub greedy_share: f64,
+    /// Restarts the population when the best fitness has not improved for this many generations.
+    pub restart_after: Option<usize>,
+    /// Fittest individuals that survive a restart.
+    pub restart_keep: usize,
+    /// Fitness sharing: individuals closer than this Jaccard distance share their fitness when
+    /// the parents are chosen, so the population does not collapse into clones of one selection.
+    pub sharing_radius: Option<f64>,
+    /// Share of the generations that pack the images downscaled, starting at 1/`coarse_factor`
+    /// of their size and halving the factor stage by stage; 0 packs at full size throughout.
+    pub coarse_share: f64,
+    pub coarse_factor: u32,
+    /// Ends the run early with the best individual of the last fully evaluated generation.
+    pub cancel: CancellationToken,
+}
+
+/// Fitness of the population in one generation and the packing quality of its best individual.
+#[derive(Clone, Debug)]
+pub struct GenerationStats {
+    pub generation: usize,
+    pub best: f64,
+    pub mean: f64,
+    pub worst: f64,
+    /// Share of the best collage's canvas not covered by images, in percent.
+    pub free_area_percentage: f64,
+    /// Deviation of the best collage from the desired aspect ratio.
+    pub aspect_ratio_diff: f64,
+}
+
+/// Lets the `on_generation` callback of `run_ga` steer the rest of the run.
+#[derive(Clone, Debug)]
+pub struct GaControl {
+    /// Used from the next generation on; starts at `GaSettings::mutation_rate`.
+    pub mutation_rate: f64,
+    /// Ends the run after this generation, returning its best individual.
+    pub stop: bool,
+}
+
+/// Stages of a run of `run_ga`, reported through `ProgressEvent::Phase`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Phase {
+    /// Building and evaluating the first generation.
+    Seeding,
+    /// Evolving the population, generation by generation.
+    Evolving,
+    /// A stage of `--coarse-to-fine` begins, packing at 1/`downscale` of the size (1: full size).
+    Refining { downscale: u32 },
+    /// The run is over; the final population is returned next.
+    Finished,
+}
+
+/// What `run_ga` reports while it runs, e.g. for a front end that shows the progress.
+pub enum ProgressEvent<'a> {
+    Phase(Phase),
+    /// A generation has been evaluated. `best` is packed at full size; the callback may change the
+    /// mutation rate or stop the run through `control`.
+    Generation { stats: &'a GenerationStats, best: &'a Individual, control: &'a mut GaControl },
+    /// The best collage so far has improved; its layout can be rendered as a preview.
+    Preview { generation: usize, best: &'a Individual },
+    /// The population was restarted after the best fitness stagnated.
+    Restart { generation: usize },
+}
+
+/// What is known about the images beyond their pixels.
+#[derive(Clone, Default)]
+pub struct ImageInfo {
+    /// Relative importance in the fitness; images without a weight count as 1.
+    pub weights: HashMap<u32, f64>,
+    /// Index of the input directory every image came from, for the per-directory quotas.
+    pub groups: HashMap<u32, usize>,
+    /// Star ratings (0-5) of the rated images.
+    pub ratings: HashMap<u32, u8>,
+    /// Extra weight per star, e.g. 0.25 makes a 4-star photo count twice.
+    pub rating_bonus: f64,
+    /// Images that every collage must contain, e.g. the 5-star favorites.
+    pub required: HashSet<u32>,
+    /// Dominant color of every image, used by the color flow mode.
+    pub colors: HashMap<u32, Rgb<u8>>,
+    /// Strength of the fitness bonus for smooth color transitions between neighbours; 0 turns
+    /// color flow off.
+    pub color_flow: f64,
+    /// Salient regions of every image, which a pile should not cover, see `pile::saliency_grid`.
+    pub saliency: HashMap<u32, Vec<f32>>,
+}
+
+impl ImageInfo {
+    /// How much an image counts in the fitness: its weight from the file list, raised by its rating.
+    pub fn weight(&self, id: u32) -> f64 {
+        let weight = self.weights.get(&id).copied().unwrap_or(1.0);
+        let stars = self.ratings.get(&id).copied().unwrap_or(0) as f64;
+        weight * (1.0 + self.rating_bonus * stars)
+    }
+
+    fn spectrum_position(&self, id: u32) -> f64 {
+        self.colors.get(&id).map_or(0.0, |color| spectrum_position(*color))
+    }
+
+    /// 1 when all neighbouring images have the same hue, down to 0 for opposite hues.
+    fn color_smoothness(&self, packed: &[(u32, Rect)], packing: &PackingOptions) -> f64 {
+        let pairs = match packing.layout {
+            LayoutKind::Hex => hex_neighbours(packed),
+            _ => neighbours(packed),
+        };
+        let distances: Vec<f64> = pairs
+            .into_iter()
+            .filter_map(|(a, b)| Some(color_distance(*self.colors.get(&a)?, *self.colors.get(&b)?)))
+            .collect();
+        if distances.is_empty() {
+            return 1.0;
+        }
+        1.0 - distances.iter().sum::<f64>() / distances.len() as f64
+    }
+}
 
 #[derive(Clone)]
 pub struct Individual {
     pub image_ids: Vec<u32>,
     pub fitness: f64,
-    pub packed_layout: Option<(Vec<(u32, rect_packer::Rect)>, u32, u32)>,
+    pub packed_layout: Option<PackedLayout>,
+    /// Shift and height in the pile of the images with `--layout pile`; images without genes
+    /// lie where they were packed.
+    pub pile: HashMap<u32, PileGene>,
+    /// Size and rotation of the images with `--layout packed`; images without genes keep their
+    /// size and get a random rotation.
+    pub genes: HashMap<u32, ImageGene>,
 }
 
 pub fn create_random_individual(
     all_images: &[(u32, DynamicImage)],
+    info: &ImageInfo,
     min_images: usize,
     max_images: usize,
     rng: &mut impl Rng,
 ) -> Individual {
     let num_images = (rng.gen_range(min_images..=max_images)).min(all_images.len());
-    let mut shuffled = all_images.iter().map(|(id, _)| *id).collect::<Vec<u32>>();
-    shuffled.shuffle(rng);
+    // Weighted shuffle: sorting by u^(1/weight) puts heavier images first more often
+    let mut keyed: Vec<(f64, u32)> = all_images
+        .iter()
+        .map(|(id, _)| (rng.gen::<f64>().powf(1.0 / info.weight(*id).max(f64::MIN_POSITIVE)), *id))
+        .collect();
+    keyed.sort_by(|a, b| b.0.total_cmp(&a.0));
+    let mut shuffled: Vec<u32> = keyed.into_iter().map(|(_, id)| id).collect();
     shuffled.truncate(num_images);
 
     Individual {
         image_ids: shuffled,
         fitness: 0.0,
         packed_layout: None,
+        pile: HashMap::new(),
+        genes: HashMap::new(),
     }
 }
 
+/// Selections built by two deterministic heuristics, which give the first generation a better
+/// start than random draws: the largest images first, which leave few small gaps, and subsets
+/// whose landscape and portrait images balance out, which pack close to a square. Sizes are spread
+/// over the upper half of `min_images..=max_images`; each balanced subset starts from a different
+/// image.
+pub fn greedy_individuals(
+    all_images: &[(u32, DynamicImage)],
+    info: &ImageInfo,
+    min_images: usize,
+    max_images: usize,
+    count: usize,
+) -> Vec<Individual> {
+    let max_images = max_images.min(all_images.len());
+    let min_images = min_images.min(max_images);
+    if count == 0 || max_images == 0 {
+        return Vec::new();
+    }
+    // Heavier images count as larger, so favorites are among the first picks
+    let mut by_area: Vec<(u32, f64)> = all_images
+        .iter()
+        .map(|(id, img)| (*id, img.width() as f64 * img.height() as f64 * info.weight(*id)))
+        .collect();
+    by_area.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+    let log_aspect: HashMap<u32, f64> = all_images
+        .iter()
+        .map(|(id, img)| (*id, (img.width().max(1) as f64 / img.height().max(1) as f64).ln()))
+        .collect();
+
+    // More images raise the fitness, so the sizes go down from the maximum to the middle of the range
+    let pairs = count.div_ceil(2);
+    let range = (max_images - min_images) / 2;
+    let size = |i: usize| (max_images - range * (i / 2) / (pairs - 1).max(1)).max(1);
+    (0..count)
+        .map(|i| {
+            let image_ids = if i % 2 == 0 {
+                by_area.iter().take(size(i)).map(|(id, _)| *id).collect()
+            } else {
+                // Add the image that brings the summed log aspect ratio closest to 0, largest first on ties
+                let start = by_area[(i / 2) % by_area.len()].0;
+                let mut ids = vec![start];
+                let mut balance = log_aspect[&start];
+                while ids.len() < size(i) {
+                    let next = by_area
+                        .iter()
+                        .filter(|(id, _)| !ids.contains(id))
+                        .min_by(|a, b| (balance + log_aspect[&a.0]).abs().total_cmp(&(balance + log_aspect[&b.0]).abs()));
+                    let Some((id, _)) = next else { break };
+                    balance += log_aspect[id];
+                    ids.push(*id);
+                }
+                ids
+            };
+            Individual { image_ids, fitness: 0.0, packed_layout: None, pile: HashMap::new(), genes: HashMap::new() }
+        })
+        .collect()
+}
+
 pub fn enforce_image_limits(
     image_ids: &mut Vec<u32>,
     all_images: &[(u32, DynamicImage)],
@@ -59,31 +367,92 @@ pub fn enforce_image_limits(
 pub fn evaluate_individual(
     indiv: &mut Individual,
     all_images_map: &HashMap<u32, DynamicImage>,
+    info: &ImageInfo,
+    packing: &PackingOptions,
 ) {
-    let (packed_locations, w, h) = pack_images(&indiv.image_ids, all_images_map);
+    let pack = |ids: &[u32]| match packing.layout {
+        LayoutKind::Pile => pack_pile(ids, &indiv.pile, all_images_map, packing),
+        LayoutKind::Packed => pack_genes(ids, &indiv.genes, all_images_map, packing),
+        _ => pack_images(ids, all_images_map, packing),
+    };
+    // In a hexagonal grid and the golden splits the order assigns the images to the cells, which
+    // the GA optimizes itself
+    let packed = if info.color_flow > 0.0 && !matches!(packing.layout, LayoutKind::Hex | LayoutKind::Golden) {
+        // Packing in spectrum order lays the images out as a color gradient
+        let mut ordered = indiv.image_ids.clone();
+        ordered.sort_by(|a, b| info.spectrum_position(*a).total_cmp(&info.spectrum_position(*b)));
+        pack(&ordered)
+    } else {
+        pack(&indiv.image_ids)
+    };
+    // A selection that cannot be packed is simply unfit; one packed wrongly is also reported
+    if let Err(Error::InvalidLayout(problems)) = &packed {
+        eprintln!("Packing produced an invalid layout: {}", problems);
+    }
+    let Ok((packed_locations, w, h)) = packed else {
+        indiv.fitness = 0.0;
+        indiv.packed_layout = None;
+        return;
+    };
     if packed_locations.is_empty() || w == 0 || h == 0 {
         indiv.fitness = 0.0;
         indiv.packed_layout = None;
         return;
     }
-    let collage_area = (w as u64) * (h as u64);
-    let total_packed_area: u64 = packed_locations
-        .iter()
-        .map(|(_, rect)| rect.width as u64 * rect.height as u64)
-        .sum();
-    let free_area = collage_area.saturating_sub(total_packed_area);
-    let free_area_percentage = (free_area as f64 / collage_area as f64) * 100.0;
-    let aspect_ratio = if h == 0 { 9999.9 } else { w as f64 / h as f64 };
-    let aspect_ratio_diff = (aspect_ratio - DESIRED_ASPECT_RATIO).abs();
+    let (free_area_percentage, aspect_ratio_diff) = packing_quality(&packed_locations, w, h, packing);
 
-    let image_count_factor = indiv.image_ids.len() as f64;
+    // Images count with their weight, 1 unless given in the file list or raised by a rating
+    let image_count_factor: f64 = if packing.layout == LayoutKind::Golden {
+        // Only in part when the golden splits leave them a cell smaller than their footprint
+        packed_locations.iter().map(|(id, rect)| info.weight(*id) * shown_share(*id, rect, all_images_map, packing)).sum()
+    } else {
+        indiv.image_ids.iter().map(|id| info.weight(*id)).sum()
+    };
     // Fitness function considers number of images, free area, and aspect ratio deviation
-    let fitness = image_count_factor / (1.0 + free_area_percentage + aspect_ratio_diff * 10.0);
+    let mut fitness = image_count_factor / (1.0 + free_area_percentage + aspect_ratio_diff * 10.0);
+    if info.color_flow > 0.0 {
+        fitness *= 1.0 + info.color_flow * info.color_smoothness(&packed_locations, packing);
+    }
+    if packing.layout == LayoutKind::Pile {
+        // Overlaps fill the canvas, but should not hide what the photos show
+        fitness *= (1.0 - occlusion(&packed_locations, &info.saliency)).powi(2);
+    }
+    if packing.layout == LayoutKind::Golden {
+        // The cells are filled anyway; what counts is how much of the images is cropped away
+        fitness *= kept_share(&packed_locations, all_images_map, packing).powi(2);
+    }
+    if packing.min_tile > 0 {
+        // Images shrink with the collage when it is scaled down to the maximum output size
+        let scale = fit_scale(w, h, packing.max_size);
+        let smallest = packed_locations.iter().map(|(_, rect)| rect.width.min(rect.height)).min().unwrap_or(0) as f64 * scale;
+        if smallest < packing.min_tile as f64 {
+            fitness *= (smallest / packing.min_tile as f64).powi(2);
+        }
+    }
 
     indiv.fitness = fitness;
     indiv.packed_layout = Some((packed_locations, w, h));
 }
 
+/// Share of the canvas not covered by images in percent, and the deviation of the canvas from the
+/// aspect ratio of the layout mode.
+fn packing_quality(packed_locations: &[(u32, Rect)], w: u32, h: u32, packing: &PackingOptions) -> (f64, f64) {
+    let collage_area = (w as u64) * (h as u64);
+    let covered = match packing.layout {
+        // Overlapping images of a pile cover their common area only once
+        LayoutKind::Pile => covered_area(packed_locations),
+        LayoutKind::Hex => packed_locations.iter().map(|(_, rect)| hex_area(rect)).sum(),
+        _ => packed_locations.iter().map(|(_, rect)| rect.width as u64 * rect.height as u64).sum(),
+    };
+    let total_packed_area = covered + reserved_area(packing);
+    let free_area = collage_area.saturating_sub(total_packed_area);
+    let free_area_percentage = (free_area as f64 / collage_area as f64) * 100.0;
+    let aspect_ratio = if h == 0 { 9999.9 } else { w as f64 / h as f64 };
+    // Relative to the target, so that long strips are judged as strictly as square collages
+    let target = packing.aspect_ratio();
+    (free_area_percentage, (aspect_ratio - target).abs() / target * DESIRED_ASPECT_RATIO)
+}
+
 pub fn crossover(
     parent1: &Individual,
     parent2: &Individual,
@@ -100,6 +469,8 @@ pub fn crossover(
             image_ids: vec![],
             fitness: 0.0,
             packed_layout: None,
+            pile: HashMap::new(),
+            genes: HashMap::new(),
         };
     }
 
@@ -115,47 +486,634 @@ pub fn crossover(
     enforce_image_limits(&mut child_ids, all_images, min_images, max_images, rng);
 
     Individual {
+        pile: inherit(&child_ids, &parent1.pile, &parent2.pile),
+        genes: inherit(&child_ids, &parent1.genes, &parent2.genes),
         image_ids: child_ids,
         fitness: 0.0,
         packed_layout: None,
     }
 }
 
+/// Crossover that keeps spatial arrangements: the child inherits the images inside a random window
+/// of `parent1`'s layout and those outside the same window of `parent2`'s layout, each in the
+/// order their parent packed them, so images that were neighbours are packed next to each other
+/// again. Falls back to `crossover` when a parent has no layout.
+pub fn layout_crossover(
+    parent1: &Individual,
+    parent2: &Individual,
+    all_images: &[(u32, DynamicImage)],
+    min_images: usize,
+    max_images: usize,
+    rng: &mut impl Rng
+) -> Individual {
+    let (Some(layout1), Some(layout2)) = (&parent1.packed_layout, &parent2.packed_layout) else {
+        return crossover(parent1, parent2, all_images, min_images, max_images, rng);
+    };
+
+    // The window covers a quarter to three quarters of each side of the canvas
+    let window_w = rng.gen_range(0.25..=0.75);
+    let window_h = rng.gen_range(0.25..=0.75);
+    let window_x = rng.gen_range(0.0..=1.0 - window_w);
+    let window_y = rng.gen_range(0.0..=1.0 - window_h);
+    // An image belongs to the window if its center does, relative to its parent's canvas
+    let in_window = |(packed, w, h): &PackedLayout| -> HashSet<u32> {
+        packed
+            .iter()
+            .filter(|(_, rect)| {
+                let x = (rect.x as f64 + rect.width as f64 / 2.0) / (*w).max(1) as f64;
+                let y = (rect.y as f64 + rect.height as f64 / 2.0) / (*h).max(1) as f64;
+                (window_x..window_x + window_w).contains(&x) && (window_y..window_y + window_h).contains(&y)
+            })
+            .map(|(id, _)| *id)
+            .collect()
+    };
+    let inside1 = in_window(layout1);
+    let inside2 = in_window(layout2);
+
+    let mut child_ids: Vec<u32> = parent1.image_ids.iter().copied().filter(|id| inside1.contains(id)).collect();
+    for id in &parent2.image_ids {
+        if !inside2.contains(id) && !child_ids.contains(id) {
+            child_ids.push(*id);
+        }
+    }
+
+    enforce_image_limits(&mut child_ids, all_images, min_images, max_images, rng);
+
+    Individual {
+        pile: inherit(&child_ids, &parent1.pile, &parent2.pile),
+        genes: inherit(&child_ids, &parent1.genes, &parent2.genes),
+        image_ids: child_ids,
+        fitness: 0.0,
+        packed_layout: None,
+    }
+}
+
+/// Genes of the child's images, from the first parent that has them.
+fn inherit<T: Copy>(image_ids: &[u32], parent1: &HashMap<u32, T>, parent2: &HashMap<u32, T>) -> HashMap<u32, T> {
+    image_ids
+        .iter()
+        .filter_map(|id| Some((*id, *parent1.get(id).or_else(|| parent2.get(id))?)))
+        .collect()
+}
+
+/// Changes the selection with one of the operators that apply to it, chosen with the probabilities
+/// of `weights`, and returns the operator. `None` if no operator with a weight applies.
 pub fn mutate(
     indiv: &mut Individual,
     all_images: &[(u32, DynamicImage)],
+    info: &ImageInfo,
+    weights: &MutationWeights,
     min_images: usize,
     max_images: usize,
     rng: &mut impl Rng
+) -> Option<MutationOp> {
+    let len = indiv.image_ids.len();
+    if len == 0 {
+        return None;
+    }
+    let applicable: Vec<MutationOp> = MutationOp::ALL
+        .into_iter()
+        .filter(|op| match op {
+            MutationOp::Add => len < max_images && len < all_images.len(),
+            MutationOp::Remove => len > min_images,
+            MutationOp::Replace => len < all_images.len(),
+            MutationOp::Swap | MutationOp::Reorder => len > 1,
+            MutationOp::Nudge | MutationOp::Rescale | MutationOp::Rotate => true,
+        })
+        .collect();
+    let op = *applicable.choose_weighted(rng, |op| weights.weight(*op)).ok()?;
+
+    let mut available: Vec<u32> = all_images.iter().map(|(id, _)| *id).collect();
+    available.retain(|x| !indiv.image_ids.contains(x));
+    match op {
+        MutationOp::Add => {
+            if let Some(new_id) = choose_weighted(&available, info, rng) {
+                indiv.image_ids.push(new_id);
+            }
+        }
+        MutationOp::Remove => {
+            indiv.image_ids.remove(rng.gen_range(0..len));
+        }
+        MutationOp::Replace => {
+            let idx = rng.gen_range(0..len);
+            if let Some(new_id) = choose_weighted(&available, info, rng) {
+                indiv.image_ids[idx] = new_id;
+            }
+        }
+        MutationOp::Swap => {
+            let (a, b) = (rng.gen_range(0..len), rng.gen_range(0..len));
+            indiv.image_ids.swap(a, b);
+        }
+        MutationOp::Reorder => {
+            let id = indiv.image_ids.remove(rng.gen_range(0..len));
+            indiv.image_ids.insert(rng.gen_range(0..len), id);
+        }
+        MutationOp::Nudge => {
+            let gene = indiv.pile.entry(indiv.image_ids[rng.gen_range(0..len)]).or_default();
+            gene.dx = (gene.dx + rng.gen_range(-0.5..=0.5)).clamp(-1.0, 1.0);
+            gene.dy = (gene.dy + rng.gen_range(-0.5..=0.5)).clamp(-1.0, 1.0);
+            gene.z = (gene.z + rng.gen_range(-0.5..=0.5)).clamp(0.0, 1.0);
+        }
+        MutationOp::Rescale => {
+            // Steps on a log scale, so growing and shrinking by the same factor are equally likely
+            let gene = indiv.genes.entry(indiv.image_ids[rng.gen_range(0..len)]).or_default();
+            gene.scale = (gene.scale * rng.gen_range(-0.3f64..=0.3).exp()).clamp(MIN_GENE_SCALE, MAX_GENE_SCALE);
+        }
+        MutationOp::Rotate => {
+            // An image without a gene starts from a random angle, as it would be drawn
+            let gene = indiv.genes.entry(indiv.image_ids[rng.gen_range(0..len)]).or_default();
+            let turn = gene.turn.unwrap_or_else(|| rng.gen_range(-1.0..=1.0));
+            gene.turn = Some((turn + rng.gen_range(-0.5..=0.5)).clamp(-1.0, 1.0));
+        }
+    }
+
+    enforce_image_limits(&mut indiv.image_ids, all_images, min_images, max_images, rng);
+    Some(op)
+}
+
+/// Picks an image with a probability proportional to its weight, or uniformly if all weights are 0.
+fn choose_weighted(ids: &[u32], info: &ImageInfo, rng: &mut impl Rng) -> Option<u32> {
+    ids.choose_weighted(rng, |id| info.weight(*id))
+        .ok()
+        .or_else(|| ids.choose(rng))
+        .copied()
+}
+
+/// Adds images from input directories below `per_dir_min` and removes images from directories
+/// above `per_dir_max`, then trims the selection back to `max_images` without breaking the minimum
+/// where possible.
+pub fn enforce_dir_quotas(
+    image_ids: &mut Vec<u32>,
+    all_images: &[(u32, DynamicImage)],
+    info: &ImageInfo,
+    settings: &GaSettings,
+    rng: &mut impl Rng,
 ) {
-    if indiv.image_ids.is_empty() {
+    if info.groups.is_empty() || (settings.per_dir_min == 0 && settings.per_dir_max.is_none()) {
         return;
     }
+    let group_of = |id: &u32| info.groups.get(id).copied();
+    let count = |ids: &[u32], group: usize| ids.iter().filter(|id| group_of(id) == Some(group)).count();
+    let mut groups: Vec<usize> = all_images.iter().filter_map(|(id, _)| group_of(id)).collect();
+    groups.sort();
+    groups.dedup();
 
-    let roll = rng.gen::<f64>();
+    for &group in &groups {
+        if let Some(max) = settings.per_dir_max {
+            while count(image_ids, group) > max {
+                let members: Vec<usize> = (0..image_ids.len()).filter(|&i| group_of(&image_ids[i]) == Some(group)).collect();
+                image_ids.remove(members[rng.gen_range(0..members.len())]);
+            }
+        }
+        let missing = settings.per_dir_min.saturating_sub(count(image_ids, group));
+        if missing > 0 {
+            let mut available: Vec<u32> = all_images
+                .iter()
+                .map(|(id, _)| *id)
+                .filter(|id| group_of(id) == Some(group) && !image_ids.contains(id))
+                .collect();
+            available.shuffle(rng);
+            image_ids.extend(available.into_iter().take(missing));
+        }
+    }
 
-    if roll < 0.33 && indiv.image_ids.len() < max_images {
-        // Add a new image
-        let mut available: Vec<u32> = all_images.iter().map(|(id, _)| *id).collect();
-        available.retain(|x| !indiv.image_ids.contains(x));
-        if let Some(&new_id) = available.choose(rng) {
-            indiv.image_ids.push(new_id);
+    while image_ids.len() > settings.max_images {
+        let removable: Vec<usize> = (0..image_ids.len())
+            .filter(|&i| group_of(&image_ids[i]).is_none_or(|group| count(image_ids, group) > settings.per_dir_min))
+            .collect();
+        if removable.is_empty() {
+            break;
         }
-    } else if roll < 0.66 && indiv.image_ids.len() > min_images {
-        // Remove an image
-        let remove_idx = rng.gen_range(0..indiv.image_ids.len());
-        indiv.image_ids.remove(remove_idx);
-    } else {
-        // Replace an image
-        if !all_images.is_empty() {
-            let idx = rng.gen_range(0..indiv.image_ids.len());
-            let mut available: Vec<u32> = all_images.iter().map(|(id, _)| *id).collect();
-            available.retain(|x| !indiv.image_ids.contains(x));
-            if let Some(&new_id) = available.choose(rng) {
-                indiv.image_ids[idx] = new_id;
+        image_ids.remove(removable[rng.gen_range(0..removable.len())]);
+    }
+}
+
+/// Adds the required images that are missing from the selection and makes room for them by
+/// removing other images once the selection exceeds `max_images`.
+pub fn enforce_required(image_ids: &mut Vec<u32>, all_images: &[(u32, DynamicImage)], info: &ImageInfo, max_images: usize, rng: &mut impl Rng) {
+    if info.required.is_empty() {
+        return;
+    }
+    for (id, _) in all_images {
+        if info.required.contains(id) && !image_ids.contains(id) {
+            image_ids.push(*id);
+        }
+    }
+    while image_ids.len() > max_images {
+        let optional: Vec<usize> = (0..image_ids.len()).filter(|&i| !info.required.contains(&image_ids[i])).collect();
+        if optional.is_empty() {
+            break;
+        }
+        image_ids.remove(optional[rng.gen_range(0..optional.len())]);
+    }
+}
+
+/// Evolves a population of image selections from `all_images` and returns the final generation,
+/// best first, together with the statistics of every generation. `on_progress` is told about every
+/// phase and generation of the run (see `ProgressEvent`) and may change the mutation rate or stop
+/// early through the `GaControl` of every generation.
+pub fn run_ga(
+    all_images: &[(u32, DynamicImage)],
+    image_map: &HashMap<u32, DynamicImage>,
+    info: &ImageInfo,
+    packing: &PackingOptions,
+    settings: &GaSettings,
+    rng: &mut impl Rng,
+    mut on_progress: impl FnMut(ProgressEvent),
+) -> (Vec<Individual>, Vec<GenerationStats>) {
+    on_progress(ProgressEvent::Phase(Phase::Seeding));
+    let GaSettings { population_size, generations, min_images, max_images, mutation_rate, crossover_rate, .. } = *settings;
+    let mut population: Vec<Individual> = settings
+        .initial
+        .iter()
+        .take(population_size)
+        .map(|ids| {
+            let mut image_ids: Vec<u32> = ids.iter().copied().filter(|id| all_images.iter().any(|(known, _)| known == id)).collect();
+            enforce_image_limits(&mut image_ids, all_images, min_images, max_images, rng);
+            Individual { image_ids, fitness: 0.0, packed_layout: None, pile: HashMap::new(), genes: HashMap::new() }
+        })
+        .collect();
+    let seeds = population.len();
+    let variants = if seeds == 0 { 0 } else { ((population_size as f64 * INITIAL_VARIANT_SHARE).round() as usize).min(population_size - seeds) };
+    for i in 0..variants {
+        let mut variant = population[i % seeds].clone();
+        // A few changes each, from swapping one image to adding or dropping several
+        for _ in 0..rng.gen_range(1..=3) {
+            mutate(&mut variant, all_images, info, &settings.mutation_weights, min_images, max_images, rng);
+        }
+        population.push(variant);
+    }
+    let greedy = ((population_size as f64 * settings.greedy_share).round() as usize).min(population_size - population.len());
+    population.extend(greedy_individuals(all_images, info, min_images, max_images, greedy));
+    while population.len() < population_size {
+        population.push(create_random_individual(all_images, info, min_images, max_images, rng));
+    }
+    for indiv in &mut population {
+        enforce_dir_quotas(&mut indiv.image_ids, all_images, info, settings, rng);
+        enforce_required(&mut indiv.image_ids, all_images, info, max_images, rng);
+    }
+
+    // Evaluate initial population in parallel
+    let mut factor = coarse_factor(settings, 1);
+    let mut stage_packing = packing.downscaled(factor);
+    let mut evaluations = evaluate_population(&mut population, &[], image_map, info, &stage_packing, &settings.cancel);
+    let mut reused = population.len() - evaluations;
+
+    let progress = if settings.quiet { ProgressBar::hidden() } else { ProgressBar::new(generations as u64) };
+    progress.set_style(
+        ProgressStyle::with_template("{bar:40} {pos}/{len} generations [{elapsed_precise}, ETA {eta_precise}] {msg}")
+            .expect("Invalid progress template"),
+    );
+    let start = Instant::now();
+    let mut history = Vec::with_capacity(generations);
+    let mut control = GaControl { mutation_rate, stop: false };
+    let mut best_so_far = f64::NEG_INFINITY;
+    let mut stagnant = 0;
+    let mut previewed = f64::NEG_INFINITY;
+    // How often every mutation operator was applied and how often its child beat its parents
+    let mut operator_stats = [(0usize, 0usize); MutationOp::ALL.len()];
+
+    on_progress(ProgressEvent::Phase(Phase::Evolving));
+    // GA main loop
+    for gen in 1..=generations {
+        if coarse_factor(settings, gen) != factor {
+            // The next, finer stage judges the whole population again, as the elites of a coarse
+            // stage are not necessarily the best at the finer scale
+            factor = coarse_factor(settings, gen);
+            stage_packing = packing.downscaled(factor);
+            // Not cancelled halfway, which would leave the population judged at two scales
+            let evaluated = evaluate_population(&mut population, &[], image_map, info, &stage_packing, &CancellationToken::new());
+            evaluations += evaluated;
+            reused += population.len() - evaluated;
+            best_so_far = f64::NEG_INFINITY;
+            emit(json!({ "event": "refine", "generation": gen, "downscale": factor }));
+            progress.suspend(|| on_progress(ProgressEvent::Phase(Phase::Refining { downscale: factor })));
+            if !settings.quiet {
+                let scale = if factor == 1 { "full size".to_string() } else { format!("1/{} of their size", factor) };
+                progress.suspend(|| info!("Generation {}: packing the images at {} from now on", gen, scale));
+            }
+        }
+        population.sort_by(|a, b| b.fitness.total_cmp(&a.fitness));
+        let mean = population.iter().map(|indiv| indiv.fitness).sum::<f64>() / population.len() as f64;
+        let (free_area_percentage, aspect_ratio_diff) = match &population[0].packed_layout {
+            Some((packed, w, h)) => packing_quality(packed, *w, *h, &stage_packing),
+            None => (100.0, 0.0),
+        };
+        history.push(GenerationStats {
+            generation: gen,
+            best: population[0].fitness,
+            mean,
+            worst: population.last().map_or(0.0, |indiv| indiv.fitness),
+            free_area_percentage,
+            aspect_ratio_diff,
+        });
+        // The callback gets the best individual packed at full size, e.g. to save it as a preview
+        let full_size;
+        let best = if factor > 1 {
+            let mut best = population[0].clone();
+            evaluate_individual(&mut best, image_map, info, packing);
+            full_size = best;
+            &full_size
+        } else {
+            &population[0]
+        };
+        // Keep messages printed by the callback clear of the progress bar
+        progress.suspend(|| {
+            on_progress(ProgressEvent::Generation { stats: &history[history.len() - 1], best, control: &mut control });
+            if best.packed_layout.is_some() && best.fitness > previewed {
+                previewed = best.fitness;
+                on_progress(ProgressEvent::Preview { generation: gen, best });
+            }
+        });
+        let rate = evaluations as f64 / start.elapsed().as_secs_f64().max(1e-9);
+        progress.set_message(format!("best {:.5}, mean {:.5}, {:.0} evaluations/s", population[0].fitness, mean, rate));
+        progress.set_position(gen as u64 - 1);
+        // Without a terminal (e.g. output piped into a log) the bar is not drawn
+        emit(json!({ "event": "generation", "generation": gen, "best": population[0].fitness, "mean": mean }));
+        if progress.is_hidden() && !settings.quiet {
+            info!("Generation {}: Best fitness = {:.5}, mean = {:.5}", gen, population[0].fitness, mean);
+        }
+        if control.stop || settings.cancel.is_cancelled() {
+            break;
+        }
+
+        // A "cataclysmic" restart: the best few survive, the rest is drawn at random again
+        if population[0].fitness > best_so_far {
+            best_so_far = population[0].fitness;
+            stagnant = 0;
+        } else {
+            stagnant += 1;
+        }
+        if let Some(patience) = settings.restart_after.filter(|patience| stagnant >= *patience) {
+            let keep = settings.restart_keep.clamp(1, population_size);
+            let mut fresh: Vec<Individual> = (keep..population_size)
+                .map(|_| {
+                    let mut indiv = create_random_individual(all_images, info, min_images, max_images, rng);
+                    enforce_dir_quotas(&mut indiv.image_ids, all_images, info, settings, rng);
+                    enforce_required(&mut indiv.image_ids, all_images, info, max_images, rng);
+                    indiv
+                })
+                .collect();
+            let evaluated = evaluate_population(&mut fresh, &population, image_map, info, &stage_packing, &settings.cancel);
+            evaluations += evaluated;
+            reused += fresh.len() - evaluated;
+            population.truncate(keep);
+            population.extend(fresh);
+            population.sort_by(|a, b| b.fitness.total_cmp(&a.fitness));
+            stagnant = 0;
+            emit(json!({ "event": "restart", "generation": gen }));
+            progress.suspend(|| on_progress(ProgressEvent::Restart { generation: gen }));
+            if !settings.quiet {
+                progress.suspend(|| info!("Generation {}: no improvement for {} generations, restarting with the {} best", gen, patience, keep));
+            }
+        }
+
+        if let Some(radius) = settings.sharing_radius {
+            // Parents are chosen by shared fitness; the best individual stays first so it is never lost
+            let mut shared: Vec<(f64, Individual)> = shared_fitness(&population, radius).into_iter().zip(population.drain(..)).collect();
+            shared[0].0 = f64::INFINITY;
+            shared.sort_by(|a, b| b.0.total_cmp(&a.0));
+            population = shared.into_iter().map(|(_, indiv)| indiv).collect();
+        }
+
+        let half = population_size/2;
+        let elites = &population[..half];
+
+        let mut new_population = Vec::new();
+        // Keep elites
+        new_population.extend_from_slice(elites);
+        // Mutated children with their operator and the fitness of their fitter parent
+        let mut mutated = Vec::new();
+
+        // Create new individuals
+        while new_population.len() < population_size {
+            let parent1 = elites.choose(rng).unwrap();
+            let parent2 = elites.choose(rng).unwrap();
+
+            let crossed = rng.gen::<f64>() < crossover_rate;
+            let mut child = if crossed {
+                match settings.crossover {
+                    CrossoverKind::Cut => crossover(parent1, parent2, all_images, min_images, max_images, rng),
+                    CrossoverKind::Layout => layout_crossover(parent1, parent2, all_images, min_images, max_images, rng),
+                }
+            } else {
+                let mut c = parent1.clone();
+                enforce_image_limits(&mut c.image_ids, all_images, min_images, max_images, rng);
+                c
+            };
+
+            if rng.gen::<f64>() < control.mutation_rate {
+                if let Some(op) = mutate(&mut child, all_images, info, &settings.mutation_weights, min_images, max_images, rng) {
+                    let parent_fitness = if crossed { parent1.fitness.max(parent2.fitness) } else { parent1.fitness };
+                    mutated.push((new_population.len(), op, parent_fitness));
+                }
             }
+            enforce_dir_quotas(&mut child.image_ids, all_images, info, settings, rng);
+            enforce_required(&mut child.image_ids, all_images, info, max_images, rng);
+
+            new_population.push(child);
         }
+
+        // Evaluate the new population in parallel
+        let evaluated = evaluate_population(&mut new_population, &population, image_map, info, &stage_packing, &settings.cancel);
+        if settings.cancel.is_cancelled() {
+            // A partly evaluated generation is dropped; the previous one is complete
+            break;
+        }
+        evaluations += evaluated;
+        reused += new_population.len() - evaluated;
+        for (child, op, parent_fitness) in mutated {
+            let (applied, improved) = &mut operator_stats[op as usize];
+            *applied += 1;
+            if new_population[child].fitness > parent_fitness {
+                *improved += 1;
+            }
+        }
+        population = new_population;
+    }
+    progress.finish_and_clear();
+    if settings.cancel.is_cancelled() {
+        emit(json!({ "event": "cancelled", "generation": history.len() }));
+        if !settings.quiet {
+            info!("Cancelled after {} generations, keeping the best collage found so far", history.len());
+        }
+    }
+    if factor > 1 {
+        // Stopped during a coarse stage: the layouts returned are always packed at full size
+        evaluations += evaluate_population(&mut population, &[], image_map, info, packing, &CancellationToken::new());
+    }
+    if !settings.quiet && reused > 0 {
+        info!(
+            "Reused the fitness of {} duplicate collages instead of evaluating them again ({:.0}% of all)",
+            reused,
+            100.0 * reused as f64 / (evaluations + reused) as f64
+        );
+    }
+    let applied: Vec<(MutationOp, usize, usize)> = MutationOp::ALL
+        .into_iter()
+        .zip(operator_stats)
+        .filter(|(_, (applied, _))| *applied > 0)
+        .map(|(op, (applied, improved))| (op, applied, improved))
+        .collect();
+    emit(json!({
+        "event": "mutations",
+        "operators": applied.iter().map(|(op, applied, improved)| json!({ "operator": op.name(), "applied": applied, "improved": improved })).collect::<Vec<_>>(),
+    }));
+    if !settings.quiet && !applied.is_empty() {
+        let rates: Vec<String> = applied
+            .iter()
+            .map(|(op, applied, improved)| format!("{} {:.1}% of {}", op.name(), 100.0 * *improved as f64 / *applied as f64, applied))
+            .collect();
+        info!("Mutations that beat their parents: {}", rates.join(", "));
     }
 
-    enforce_image_limits(&mut indiv.image_ids, all_images, min_images, max_images, rng);
+    // Final solution
+    population.sort_by(|a, b| b.fitness.total_cmp(&a.fitness));
+    on_progress(ProgressEvent::Phase(Phase::Finished));
+    (population, history)
+}
+
+/// Evaluates the individuals of `population` in parallel, except for exact copies of an individual
+/// in `evaluated` (e.g. the elites carried over from the previous generation) or of one earlier in
+/// `population`, which take over its fitness and layout. Returns the number of evaluations. Once
+/// `cancel` is set, the individuals not evaluated yet are left unfit.
+fn same_genes(a: &Individual, b: &Individual) -> bool {
+    a.pile == b.pile && a.genes == b.genes
+}
+
+fn evaluate_population(
+    population: &mut [Individual],
+    evaluated: &[Individual],
+    image_map: &HashMap<u32, DynamicImage>,
+    info: &ImageInfo,
+    packing: &PackingOptions,
+    cancel: &CancellationToken,
+) -> usize {
+    // The order of the ids matters, it is the packing order; individuals also differ in their genes
+    let known: HashMap<&[u32], &Individual> = evaluated.iter().map(|indiv| (indiv.image_ids.as_slice(), indiv)).collect();
+    let mut first: HashMap<Vec<u32>, usize> = HashMap::new();
+    let mut copies = Vec::new();
+    let mut pending = vec![false; population.len()];
+    for i in 0..population.len() {
+        let indiv = &population[i];
+        if let Some(known) = known.get(indiv.image_ids.as_slice()).filter(|known| same_genes(known, indiv)) {
+            let (fitness, packed_layout) = (known.fitness, known.packed_layout.clone());
+            population[i].fitness = fitness;
+            population[i].packed_layout = packed_layout;
+        } else if let Some(&original) = first.get(&indiv.image_ids).filter(|original| same_genes(&population[**original], indiv)) {
+            copies.push((i, original));
+        } else {
+            first.entry(indiv.image_ids.clone()).or_insert(i);
+            pending[i] = true;
+        }
+    }
+
+    population
+        .par_iter_mut()
+        .zip(&pending)
+        .filter(|(_, pending)| **pending)
+        .for_each(|(indiv, _)| {
+            if cancel.is_cancelled() {
+                indiv.fitness = 0.0;
+                indiv.packed_layout = None;
+            } else {
+                evaluate_individual(indiv, image_map, info, packing);
+            }
+        });
+    for (copy, original) in copies {
+        population[copy].fitness = population[original].fitness;
+        population[copy].packed_layout = population[original].packed_layout.clone();
+    }
+    pending.iter().filter(|pending| **pending).count()
+}
+
+/// Factor the images are downscaled by when packing in generation `gen` (counted from 1) of a
+/// coarse-to-fine run: `coarse_factor` in the first stage, halved in every following one, and 1 once
+/// the coarse share of the generations is over. The last generation is always at full size.
+fn coarse_factor(settings: &GaSettings, gen: usize) -> u32 {
+    let coarse = ((settings.generations as f64 * settings.coarse_share).round() as usize).min(settings.generations.saturating_sub(1));
+    if gen > coarse || settings.coarse_factor < 2 {
+        return 1;
+    }
+    let stages = settings.coarse_factor.ilog2() as usize;
+    settings.coarse_factor >> ((gen - 1) * stages / coarse)
+}
+
+/// Up to `n` of the fittest individuals with a layout, best first, whose image selections differ
+/// from each other in at least `MIN_DISTINCTNESS` of their images. When the population has
+/// converged too far for that, any individual with a selection of its own is taken.
+pub fn distinct_best(population: &[Individual], n: usize) -> Vec<&Individual> {
+    let mut candidates: Vec<&Individual> = population.iter().filter(|indiv| indiv.packed_layout.is_some()).collect();
+    candidates.sort_by(|a, b| b.fitness.total_cmp(&a.fitness));
+    let mut chosen: Vec<&Individual> = Vec::with_capacity(n);
+    for min_distance in [MIN_DISTINCTNESS, f64::MIN_POSITIVE] {
+        for candidate in &candidates {
+            if chosen.len() == n {
+                break;
+            }
+            if chosen.iter().all(|other| selection_distance(other, candidate) >= min_distance) {
+                chosen.push(candidate);
+            }
+        }
+    }
+    chosen.sort_by(|a, b| b.fitness.total_cmp(&a.fitness));
+    chosen
+}
+
+/// Fitness of every individual divided by its niche count, the number of individuals with a
+/// similar selection (within `radius` Jaccard distance, weighted by closeness, itself included).
+fn shared_fitness(population: &[Individual], radius: f64) -> Vec<f64> {
+    let sorted: Vec<Vec<u32>> = population
+        .iter()
+        .map(|indiv| {
+            let mut ids = indiv.image_ids.clone();
+            ids.sort_unstable();
+            ids
+        })
+        .collect();
+    sorted
+        .par_iter()
+        .zip(population)
+        .map(|(a, indiv)| {
+            let niche: f64 = sorted
+                .iter()
+                .map(|b| sorted_distance(a, b))
+                .filter(|distance| *distance < radius)
+                .map(|distance| 1.0 - distance / radius)
+                .sum();
+            indiv.fitness / niche.max(1.0)
+        })
+        .collect()
+}
+
+/// Jaccard distance of two sorted selections without duplicates.
+fn sorted_distance(a: &[u32], b: &[u32]) -> f64 {
+    let (mut i, mut j, mut common) = (0, 0, 0);
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(&b[j]) {
+            std::cmp::Ordering::Less => i += 1,
+            std::cmp::Ordering::Greater => j += 1,
+            std::cmp::Ordering::Equal => {
+                common += 1;
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    let union = a.len() + b.len() - common;
+    if union == 0 {
+        return 0.0;
+    }
+    1.0 - common as f64 / union as f64
+}
+
+/// Jaccard distance of the image selections: 0 for the same images, 1 for none in common.
+fn selection_distance(a: &Individual, b: &Individual) -> f64 {
+    let a: HashSet<u32> = a.image_ids.iter().copied().collect();
+    let b: HashSet<u32> = b.image_ids.iter().copied().collect();
+    let union = a.union(&b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    1.0 - a.intersection(&b).count() as f64 / union as f64
 }
@@ -0,0 +1,101 @@
+use std::collections::HashMap;
+
+use image::DynamicImage;
+use rect_packer::Rect;
+
+use crate::error::Error;
+use crate::packing::{footprint, PackedLayout, PackingOptions, DESIRED_ASPECT_RATIO};
+use crate::verify::validate_packed;
+
+/// The golden ratio, (1 + sqrt(5)) / 2.
+const PHI: f64 = 1.618_033_988_749_895;
+
+/// Lays the images out in the cells of a canvas that is split recursively at the golden ratio:
+/// every rectangle is cut across its longer side into a part of 1/phi and one of 1/phi² of it,
+/// the next image in packing order gets the larger part and the smaller remainder is split again
+/// for the rest, so every cell has about 1/phi² of the area of the one before. The larger part
+/// moves around the remainder clockwise with every level, which gives the splits their spiral
+/// look. The canvas has the area of the footprints of the images and their padding; every image is
+/// cropped to fill its cell when it is drawn.
+pub fn pack_golden(image_ids: &[u32], image_map: &HashMap<u32, DynamicImage>, options: &PackingOptions) -> Result<PackedLayout, Error> {
+    let padding = options.padding() as i32;
+    let area = image_ids
+        .iter()
+        .map(|id| {
+            let img = image_map.get(id).ok_or(Error::UnknownImage(*id))?;
+            let (w, h) = footprint(img, options);
+            Ok((w as f64 + padding as f64) * (h as f64 + padding as f64))
+        })
+        .sum::<Result<f64, Error>>()?;
+    // The longer side must hold a pixel and the padding for every image, e.g. for tiny images
+    let width = ((area * DESIRED_ASPECT_RATIO).sqrt().round() as i32).max(needed_side(image_ids.len(), padding));
+    let height = ((width as f64 / DESIRED_ASPECT_RATIO).round() as i32).max(1);
+
+    let placed = split(Rect::new(0, 0, width, height), image_ids, padding);
+    let layout = (placed, width as u32, height as u32);
+    validate_packed(&layout, options)?;
+    Ok(layout)
+}
+
+/// Length of a side that fits `count` cells of one pixel with `padding` between them.
+fn needed_side(count: usize, padding: i32) -> i32 {
+    (count as i32 * (padding + 1) - padding).max(1)
+}
+
+/// Gives every image the larger golden part of what remains of `rect`, but no more than leaves
+/// room for the images after it, and the last image the whole remainder.
+fn split(mut rect: Rect, image_ids: &[u32], padding: i32) -> Vec<(u32, Rect)> {
+    let mut placed = Vec::with_capacity(image_ids.len());
+    for (depth, id) in image_ids.iter().enumerate() {
+        if depth + 1 == image_ids.len() {
+            placed.push((*id, rect));
+            break;
+        }
+        let horizontal = rect.width >= rect.height;
+        let side = if horizontal { rect.width } else { rect.height };
+        let available = (side - padding).max(2);
+        let rest = needed_side(image_ids.len() - depth - 1, padding);
+        let larger = ((available as f64 / PHI).round() as i32).clamp(1, (available - rest).max(1));
+        let smaller = available - larger;
+
+        // Left, top, right, bottom: the larger part comes first on two levels and second on the next two
+        let larger_first = depth % 4 < 2;
+        let (first, second) = if larger_first { (larger, smaller) } else { (smaller, larger) };
+        let (first_rect, second_rect) = if horizontal {
+            (Rect::new(rect.x, rect.y, first, rect.height), Rect::new(rect.x + first + padding, rect.y, second, rect.height))
+        } else {
+            (Rect::new(rect.x, rect.y, rect.width, first), Rect::new(rect.x, rect.y + first + padding, rect.width, second))
+        };
+        let (cell, remainder) = if larger_first { (first_rect, second_rect) } else { (second_rect, first_rect) };
+        placed.push((*id, cell));
+        rect = remainder;
+    }
+    placed
+}
+
+/// Average share of the images that remains visible when they are cropped to fill their cells,
+/// from 0 to 1; 1 when every image has the aspect ratio of its cell.
+pub fn kept_share(packed: &[(u32, Rect)], image_map: &HashMap<u32, DynamicImage>, options: &PackingOptions) -> f64 {
+    if packed.is_empty() {
+        return 1.0;
+    }
+    let total: f64 = packed
+        .iter()
+        .map(|(id, rect)| {
+            let Some(img) = image_map.get(id) else { return 1.0 };
+            let (w, h) = footprint(img, options);
+            let image_ratio = w as f64 / h.max(1) as f64;
+            let cell_ratio = rect.width as f64 / rect.height.max(1) as f64;
+            (image_ratio / cell_ratio).min(cell_ratio / image_ratio)
+        })
+        .sum();
+    total / packed.len() as f64
+}
+
+/// Share of its footprint area that an image gets in its cell, at most 1; the cells shrink with
+/// every split, so images further down the spiral are shown ever smaller.
+pub fn shown_share(id: u32, rect: &Rect, image_map: &HashMap<u32, DynamicImage>, options: &PackingOptions) -> f64 {
+    let Some(img) = image_map.get(&id) else { return 1.0 };
+    let (w, h) = footprint(img, options);
+    (rect.width as f64 * rect.height as f64 / (w as f64 * h as f64).max(1.0)).min(1.0)
+}
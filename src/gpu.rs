@@ -0,0 +1,355 @@
+use std::sync::OnceLock;
+
+use image::RgbaImage;
+
+/// Resizes by averaging the source over the footprint of every output pixel, with up to
+/// `MAX_TAPS`x`MAX_TAPS` bilinear samples, and draws the result into a rectangle of the target.
+const SHADER: &str = r#"
+struct Params {
+    // Target rectangle in pixels: left, top, right, bottom
+    rect: vec4<f32>,
+    source_size: vec2<f32>,
+    target_size: vec2<f32>,
+}
+
+const MAX_TAPS: f32 = 16.0;
+
+@group(0) @binding(0) var source: texture_2d<f32>;
+@group(0) @binding(1) var linear: sampler;
+@group(0) @binding(2) var<uniform> params: Params;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    var corners = array<vec2<f32>, 6>(
+        vec2(0.0, 0.0), vec2(1.0, 0.0), vec2(0.0, 1.0),
+        vec2(0.0, 1.0), vec2(1.0, 0.0), vec2(1.0, 1.0),
+    );
+    let uv = corners[index];
+    let pixel = mix(params.rect.xy, params.rect.zw, uv);
+    let ndc = pixel / params.target_size * vec2(2.0, -2.0) + vec2(-1.0, 1.0);
+    return VertexOutput(vec4(ndc, 0.0, 1.0), uv);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let scale = params.source_size / (params.rect.zw - params.rect.xy);
+    let taps = clamp(ceil(scale), vec2(1.0), vec2(MAX_TAPS));
+    let start = in.uv * params.source_size - scale / 2.0;
+    let step = scale / taps;
+    var sum = vec4(0.0);
+    for (var y = 0.0; y < taps.y; y += 1.0) {
+        for (var x = 0.0; x < taps.x; x += 1.0) {
+            let at = start + (vec2(x, y) + 0.5) * step;
+            sum += textureSampleLevel(source, linear, at / params.source_size, 0.0);
+        }
+    }
+    return sum / (taps.x * taps.y);
+}
+"#;
+
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+/// The device and the two pipelines: one that replaces the target with the resized source and
+/// one that blends it over the target like `imageops::overlay`.
+struct Gpu {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    replace: wgpu::RenderPipeline,
+    blend: wgpu::RenderPipeline,
+}
+
+static GPU: OnceLock<Option<Gpu>> = OnceLock::new();
+
+/// The GPU, set up on first use. Without a usable adapter everything runs on the CPU.
+fn gpu() -> Option<&'static Gpu> {
+    GPU.get_or_init(|| match Gpu::new() {
+        Ok(gpu) => Some(gpu),
+        Err(e) => {
+            eprintln!("{}, resizing and compositing on the CPU instead.", e);
+            None
+        }
+    })
+    .as_ref()
+}
+
+/// Resizes the image on the GPU. `None` if there is no GPU or the image exceeds its limits, in
+/// which case the caller resizes on the CPU.
+pub fn resize(img: &RgbaImage, width: u32, height: u32) -> Option<RgbaImage> {
+    let gpu = gpu()?;
+    if !gpu.fits(img.width(), img.height()) || !gpu.fits(width, height) {
+        return None;
+    }
+    let target = gpu.texture(width, height, None);
+    gpu.draw(&gpu.replace, img, &target, [0.0, 0.0, width as f32, height as f32]);
+    gpu.read(&target, width, height).map_err(|e| eprintln!("{}", e)).ok()
+}
+
+/// A canvas kept on the GPU while the tiles of a collage are blended onto it.
+pub struct GpuCanvas {
+    texture: wgpu::Texture,
+    width: u32,
+    height: u32,
+}
+
+impl GpuCanvas {
+    /// Uploads the background. `None` if there is no GPU or the canvas exceeds its texture size.
+    pub fn new(background: &RgbaImage) -> Option<GpuCanvas> {
+        let gpu = gpu()?;
+        let (width, height) = background.dimensions();
+        if !gpu.fits(width, height) {
+            return None;
+        }
+        Some(GpuCanvas { texture: gpu.texture(width, height, Some(background)), width, height })
+    }
+
+    /// Blends a tile over the canvas with its top-left corner at `x`, `y`. Tiles larger than the
+    /// texture limit are blended on the CPU by the caller, so this returns whether it was drawn.
+    pub fn overlay(&self, tile: &RgbaImage, x: i64, y: i64) -> bool {
+        let Some(gpu) = gpu() else {
+            return false;
+        };
+        if !gpu.fits(tile.width(), tile.height()) {
+            return false;
+        }
+        let rect = [x as f32, y as f32, (x + tile.width() as i64) as f32, (y + tile.height() as i64) as f32];
+        gpu.draw(&gpu.blend, tile, &self.texture, rect);
+        true
+    }
+
+    /// Reads the finished canvas back.
+    pub fn finish(self) -> Result<RgbaImage, String> {
+        let gpu = gpu().ok_or("No GPU available")?;
+        gpu.read(&self.texture, self.width, self.height)
+    }
+}
+
+impl Gpu {
+    fn new() -> Result<Gpu, String> {
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            ..Default::default()
+        }))
+        .ok_or("No GPU adapter found")?;
+        info!("Using the GPU: {}", adapter.get_info().name);
+        let (device, queue) = pollster::block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                label: Some("ImageGridOptimizer"),
+                // Large canvases need the largest textures and buffers the adapter supports
+                required_limits: adapter.limits(),
+                ..Default::default()
+            },
+            None,
+        ))
+        .map_err(|e| format!("Error opening the GPU: {}", e))?;
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |blend: wgpu::BlendState| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: None,
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: Some("vs_main"),
+                    buffers: &[],
+                    compilation_options: Default::default(),
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: Some("fs_main"),
+                    targets: &[Some(wgpu::ColorTargetState { format: FORMAT, blend: Some(blend), write_mask: wgpu::ColorWrites::ALL })],
+                    compilation_options: Default::default(),
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+                cache: None,
+            })
+        };
+        let replace = pipeline(wgpu::BlendState::REPLACE);
+        let blend = pipeline(wgpu::BlendState::ALPHA_BLENDING);
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        Ok(Gpu { device, queue, bind_group_layout, sampler, replace, blend })
+    }
+
+    fn fits(&self, width: u32, height: u32) -> bool {
+        let max = self.device.limits().max_texture_dimension_2d;
+        width > 0 && height > 0 && width <= max && height <= max
+    }
+
+    /// A texture that can be drawn into, sampled and read back, filled with `pixels` if given.
+    fn texture(&self, width: u32, height: u32, pixels: Option<&RgbaImage>) -> wgpu::Texture {
+        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        if let Some(pixels) = pixels {
+            self.queue.write_texture(
+                texture.as_image_copy(),
+                pixels.as_raw(),
+                wgpu::TexelCopyBufferLayout { offset: 0, bytes_per_row: Some(4 * width), rows_per_image: Some(height) },
+                texture.size(),
+            );
+        }
+        texture
+    }
+
+    /// Draws `source` resized into `rect` of the target with the given pipeline.
+    fn draw(&self, pipeline: &wgpu::RenderPipeline, source: &RgbaImage, target: &wgpu::Texture, rect: [f32; 4]) {
+        let source_texture = self.texture(source.width(), source.height(), Some(source));
+        let values = [
+            rect[0],
+            rect[1],
+            rect[2],
+            rect[3],
+            source.width() as f32,
+            source.height() as f32,
+            target.width() as f32,
+            target.height() as f32,
+        ];
+        let params = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: (values.len() * 4) as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bytes: Vec<u8> = values.iter().flat_map(|value| value.to_le_bytes()).collect();
+        self.queue.write_buffer(&params, 0, &bytes);
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&source_texture.create_view(&Default::default())),
+                },
+                wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::Sampler(&self.sampler) },
+                wgpu::BindGroupEntry { binding: 2, resource: params.as_entire_binding() },
+            ],
+        });
+
+        let view = target.create_view(&Default::default());
+        let mut encoder = self.device.create_command_encoder(&Default::default());
+        {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations { load: wgpu::LoadOp::Load, store: wgpu::StoreOp::Store },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.draw(0..6, 0..1);
+        }
+        self.queue.submit([encoder.finish()]);
+    }
+
+    /// Copies a texture back to memory, in bands of rows that fit the largest buffer.
+    fn read(&self, texture: &wgpu::Texture, width: u32, height: u32) -> Result<RgbaImage, String> {
+        let row_bytes = 4 * width;
+        let padded_row_bytes = row_bytes.div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT) * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let band_rows = ((self.device.limits().max_buffer_size / padded_row_bytes as u64) as u32).clamp(1, height);
+        let mut pixels = Vec::with_capacity(row_bytes as usize * height as usize);
+        for top in (0..height).step_by(band_rows as usize) {
+            let rows = band_rows.min(height - top);
+            let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+                label: None,
+                size: padded_row_bytes as u64 * rows as u64,
+                usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            });
+            let mut encoder = self.device.create_command_encoder(&Default::default());
+            encoder.copy_texture_to_buffer(
+                wgpu::TexelCopyTextureInfo { origin: wgpu::Origin3d { x: 0, y: top, z: 0 }, ..texture.as_image_copy() },
+                wgpu::TexelCopyBufferInfo {
+                    buffer: &buffer,
+                    layout: wgpu::TexelCopyBufferLayout { offset: 0, bytes_per_row: Some(padded_row_bytes), rows_per_image: Some(rows) },
+                },
+                wgpu::Extent3d { width, height: rows, depth_or_array_layers: 1 },
+            );
+            self.queue.submit([encoder.finish()]);
+
+            let slice = buffer.slice(..);
+            let (sender, receiver) = std::sync::mpsc::channel();
+            slice.map_async(wgpu::MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+            self.device.poll(wgpu::Maintain::Wait);
+            receiver
+                .recv()
+                .map_err(|e| e.to_string())
+                .and_then(|result| result.map_err(|e| e.to_string()))
+                .map_err(|e| format!("Error reading from the GPU: {}", e))?;
+            for row in slice.get_mapped_range().chunks(padded_row_bytes as usize) {
+                pixels.extend_from_slice(&row[..row_bytes as usize]);
+            }
+            buffer.unmap();
+        }
+        RgbaImage::from_raw(width, height, pixels).ok_or_else(|| "Error reading from the GPU: incomplete image".to_string())
+    }
+}
@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+
+use image::DynamicImage;
+use rect_packer::Rect;
+
+use crate::error::Error;
+use crate::packing::{PackedLayout, PackingOptions, DESIRED_ASPECT_RATIO};
+use crate::verify::validate_packed;
+
+/// Height of a hexagon with pointy top and bottom relative to its width, 2/sqrt(3).
+const HEIGHT_FACTOR: f64 = 1.154_700_538_379_251_5;
+
+/// Outward normals of the edges of a hexagon with pointy top and bottom, one per pair of
+/// opposite edges.
+const EDGE_NORMALS: [(f64, f64); 3] = [(1.0, 0.0), (0.5, 0.866_025_403_784_438_6), (0.5, -0.866_025_403_784_438_6)];
+
+/// Cells of a hexagonal grid with pointy top and bottom, filled row by row. Odd rows are shifted
+/// by half a cell so the rows interlock.
+#[derive(Clone, Copy)]
+struct HexGrid {
+    cell_width: i32,
+    cell_height: i32,
+    step_x: i32,
+    step_y: i32,
+    columns: usize,
+    rows: usize,
+}
+
+impl HexGrid {
+    /// The grid for `count` cells of `cell_width` pixels with `padding` between neighbouring
+    /// cells, with the number of columns that brings the canvas closest to the desired aspect ratio.
+    fn new(count: usize, cell_width: u32, padding: u32) -> HexGrid {
+        let cell_width = cell_width.max(1) as i32;
+        let step_x = cell_width + padding as i32;
+        // Neighbours in the rows above and below are as far from each other as those in a row
+        let step_y = (step_x as f64 * 3f64.sqrt() / 2.0).ceil() as i32;
+        let mut grid = HexGrid {
+            cell_width,
+            cell_height: (cell_width as f64 * HEIGHT_FACTOR).round() as i32,
+            step_x,
+            step_y,
+            columns: 1,
+            rows: count.max(1),
+        };
+        let deviation = |grid: &HexGrid| (grid.width() as f64 / grid.height() as f64 / DESIRED_ASPECT_RATIO).ln().abs();
+        let mut best = f64::MAX;
+        for columns in 1..=count.max(1) {
+            let candidate = HexGrid { columns, rows: count.max(1).div_ceil(columns), ..grid };
+            if deviation(&candidate) < best {
+                best = deviation(&candidate);
+                grid = candidate;
+            }
+        }
+        grid
+    }
+
+    fn width(&self) -> u32 {
+        let shift = if self.rows > 1 { self.step_x / 2 } else { 0 };
+        (self.columns as i32 * self.step_x - (self.step_x - self.cell_width) + shift) as u32
+    }
+
+    fn height(&self) -> u32 {
+        ((self.rows as i32 - 1) * self.step_y + self.cell_height) as u32
+    }
+
+    /// Bounding box of the cell at `index`.
+    fn cell(&self, index: usize) -> Rect {
+        let (column, row) = ((index % self.columns) as i32, (index / self.columns) as i32);
+        let shift = if row % 2 == 1 { self.step_x / 2 } else { 0 };
+        Rect::new(column * self.step_x + shift, row * self.step_y, self.cell_width, self.cell_height)
+    }
+}
+
+/// The median shorter side of the images, the default cell width of a hexagonal grid.
+pub fn median_cell_size(image_map: &HashMap<u32, DynamicImage>) -> u32 {
+    let mut sizes: Vec<u32> = image_map.values().map(|img| img.width().min(img.height())).collect();
+    sizes.sort_unstable();
+    sizes.get(sizes.len() / 2).copied().unwrap_or(1)
+}
+
+/// Lays the images out on a hexagonal grid in packing order, one image per cell, so the GA
+/// decides which image goes into which cell. Returns the bounding box of every cell; the images
+/// are cropped to the hexagons when they are drawn.
+pub fn pack_hex(image_ids: &[u32], image_map: &HashMap<u32, DynamicImage>, options: &PackingOptions) -> Result<PackedLayout, Error> {
+    if let Some(id) = image_ids.iter().find(|id| !image_map.contains_key(id)) {
+        return Err(Error::UnknownImage(*id));
+    }
+    let cell_width = options.hex_size.unwrap_or(1).div_ceil(options.scale());
+    let grid = HexGrid::new(image_ids.len(), cell_width, options.padding());
+    let placed: Vec<(u32, Rect)> = image_ids.iter().enumerate().map(|(index, id)| (*id, grid.cell(index))).collect();
+    let layout = (placed, grid.width(), grid.height());
+    validate_packed(&layout, options)?;
+    Ok(layout)
+}
+
+/// Area of the hexagon in a cell's bounding box.
+pub fn hex_area(rect: &Rect) -> u64 {
+    rect.width as u64 * rect.height as u64 * 3 / 4
+}
+
+/// Distance between the edges of the hexagons in two cells, negative when they overlap. Hexagons
+/// of the same orientation are apart exactly when they are apart along one of their edge normals.
+pub fn hex_gap(a: &Rect, b: &Rect) -> i32 {
+    let dx = (b.x as f64 + b.width as f64 / 2.0) - (a.x as f64 + a.width as f64 / 2.0);
+    let dy = (b.y as f64 + b.height as f64 / 2.0) - (a.y as f64 + a.height as f64 / 2.0);
+    let radii = (a.width + b.width) as f64 / 2.0;
+    EDGE_NORMALS
+        .iter()
+        .map(|(nx, ny)| (dx * nx + dy * ny).abs() - radii)
+        .fold(f64::MIN, f64::max)
+        .round() as i32
+}
+
+/// Pairs of cells that share an edge of the grid.
+pub fn hex_neighbours(packed: &[(u32, Rect)]) -> Vec<(u32, u32)> {
+    let mut pairs = Vec::new();
+    for (i, (id_a, a)) in packed.iter().enumerate() {
+        for (id_b, b) in &packed[i + 1..] {
+            // Cells two steps apart are at least sqrt(3) cell widths away
+            if hex_gap(a, b) <= a.width.max(b.width) / 4 {
+                pairs.push((*id_a, *id_b));
+            }
+        }
+    }
+    pairs
+}
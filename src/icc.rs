@@ -0,0 +1,134 @@
+use std::borrow::Cow;
+use std::fs;
+use std::io::Cursor;
+
+use image::codecs::jpeg::JpegDecoder;
+use image::codecs::png::PngDecoder;
+use image::codecs::tiff::TiffDecoder;
+use image::codecs::webp::WebPDecoder;
+use image::{DynamicImage, ImageBuffer, ImageDecoder, ImageFormat};
+use moxcms::{ColorProfile, DataColorSpace, Layout, TransformOptions};
+
+/// The ICC profile embedded in an encoded JPEG, PNG, TIFF or WebP file, if any.
+pub fn embedded_profile(bytes: &[u8], format: ImageFormat) -> Option<Vec<u8>> {
+    match format {
+        ImageFormat::Jpeg => JpegDecoder::new(Cursor::new(bytes)).ok()?.icc_profile(),
+        ImageFormat::Png => PngDecoder::new(Cursor::new(bytes)).ok()?.icc_profile(),
+        ImageFormat::Tiff => TiffDecoder::new(Cursor::new(bytes)).ok()?.icc_profile(),
+        ImageFormat::WebP => WebPDecoder::new(Cursor::new(bytes)).ok()?.icc_profile(),
+        _ => None,
+    }
+}
+
+/// Converts an image from the color space of its embedded ICC profile to sRGB, which the
+/// collage is composed and saved in. Grayscale profiles give an RGB image; images with CMYK or
+/// other profiles are returned as the decoder made them.
+pub fn to_srgb<'a>(img: &'a DynamicImage, profile: &[u8]) -> Result<Cow<'a, DynamicImage>, String> {
+    let source = ColorProfile::new_from_slice(profile).map_err(|e| format!("Invalid ICC profile: {}", e))?;
+    let alpha = img.color().has_alpha();
+    let (from, to) = match (source.color_space, alpha) {
+        (DataColorSpace::Rgb, false) => (Layout::Rgb, Layout::Rgb),
+        (DataColorSpace::Rgb, true) => (Layout::Rgba, Layout::Rgba),
+        (DataColorSpace::Gray, false) => (Layout::Gray, Layout::Rgb),
+        (DataColorSpace::Gray, true) => (Layout::GrayAlpha, Layout::Rgba),
+        _ => return Ok(Cow::Borrowed(img)),
+    };
+    let srgb = ColorProfile::new_srgb();
+    let (width, height) = (img.width(), img.height());
+    let samples = (width * height) as usize * to.channels();
+    let failed = |e: moxcms::CmsError| format!("Error converting colors to sRGB: {}", e);
+
+    // 16-bit and floating point images keep their precision through the conversion
+    let deep = img.color().bytes_per_pixel() > img.color().channel_count();
+    if deep {
+        let pixels = match from {
+            Layout::Rgb => img.to_rgb16().into_raw(),
+            Layout::Rgba => img.to_rgba16().into_raw(),
+            Layout::Gray => img.to_luma16().into_raw(),
+            _ => img.to_luma_alpha16().into_raw(),
+        };
+        let mut converted = vec![0u16; samples];
+        let transform = source.create_transform_16bit(from, &srgb, to, TransformOptions::default()).map_err(failed)?;
+        transform.transform(&pixels, &mut converted).map_err(failed)?;
+        return Ok(Cow::Owned(if alpha {
+            DynamicImage::ImageRgba16(ImageBuffer::from_raw(width, height, converted).ok_or("Invalid converted image")?)
+        } else {
+            DynamicImage::ImageRgb16(ImageBuffer::from_raw(width, height, converted).ok_or("Invalid converted image")?)
+        }));
+    }
+    let pixels = match from {
+        Layout::Rgb => img.to_rgb8().into_raw(),
+        Layout::Rgba => img.to_rgba8().into_raw(),
+        Layout::Gray => img.to_luma8().into_raw(),
+        _ => img.to_luma_alpha8().into_raw(),
+    };
+    let mut converted = vec![0u8; samples];
+    let transform = source.create_transform_8bit(from, &srgb, to, TransformOptions::default()).map_err(failed)?;
+    transform.transform(&pixels, &mut converted).map_err(failed)?;
+    Ok(Cow::Owned(if alpha {
+        DynamicImage::ImageRgba8(ImageBuffer::from_raw(width, height, converted).ok_or("Invalid converted image")?)
+    } else {
+        DynamicImage::ImageRgb8(ImageBuffer::from_raw(width, height, converted).ok_or("Invalid converted image")?)
+    }))
+}
+
+/// The sRGB profile that is embedded into output files with `--output-profile srgb`.
+pub fn srgb_profile() -> Vec<u8> {
+    ColorProfile::new_srgb().encode().unwrap_or_default()
+}
+
+/// Reads the profile for `--output-profile`: `srgb` or an ICC file of an RGB color space.
+pub fn read_output_profile(value: &str) -> Result<Vec<u8>, String> {
+    if value.eq_ignore_ascii_case("srgb") {
+        return Ok(srgb_profile());
+    }
+    let bytes = fs::read(value).map_err(|e| format!("Error reading ICC profile {}: {}", value, e))?;
+    let profile = ColorProfile::new_from_slice(&bytes).map_err(|e| format!("Invalid ICC profile {}: {}", value, e))?;
+    if profile.color_space != DataColorSpace::Rgb {
+        return Err(format!("ICC profile {} is not an RGB profile", value));
+    }
+    Ok(bytes)
+}
+
+/// Reads the profile for `--print-profile`, which must be a CMYK profile.
+pub fn read_print_profile(value: &str) -> Result<Vec<u8>, String> {
+    let bytes = fs::read(value).map_err(|e| format!("Error reading ICC profile {}: {}", value, e))?;
+    let profile = ColorProfile::new_from_slice(&bytes).map_err(|e| format!("Invalid ICC profile {}: {}", value, e))?;
+    if profile.color_space != DataColorSpace::Cmyk {
+        return Err(format!("ICC profile {} is not a CMYK profile", value));
+    }
+    Ok(bytes)
+}
+
+/// Converts the sRGB collage to the CMYK color space of `profile`; returns the interleaved C, M,
+/// Y and K values of every pixel. Transparent areas are dropped as in JPEG output.
+pub fn to_cmyk(img: &DynamicImage, profile: &[u8]) -> Result<Vec<u8>, String> {
+    let target = ColorProfile::new_from_slice(profile).map_err(|e| format!("Invalid ICC profile: {}", e))?;
+    let rgb = img.to_rgb8();
+    let mut cmyk = vec![0u8; rgb.len() / 3 * 4];
+    let failed = |e: moxcms::CmsError| format!("Error converting colors to CMYK: {}", e);
+    // CMYK data uses the four-channel layout
+    let transform = ColorProfile::new_srgb()
+        .create_transform_8bit(Layout::Rgb, &target, Layout::Rgba, TransformOptions::default())
+        .map_err(failed)?;
+    transform.transform(&rgb, &mut cmyk).map_err(failed)?;
+    Ok(cmyk)
+}
+
+/// Converts the sRGB collage to the color space of `profile` before it is saved with the profile
+/// embedded. The sRGB profile of `srgb_profile` leaves the pixels as they are.
+pub fn from_srgb<'a>(img: &'a DynamicImage, profile: &[u8]) -> Result<Cow<'a, DynamicImage>, String> {
+    if profile == srgb_profile() {
+        return Ok(Cow::Borrowed(img));
+    }
+    let target = ColorProfile::new_from_slice(profile).map_err(|e| format!("Invalid ICC profile: {}", e))?;
+    let rgba = img.to_rgba8();
+    let mut converted = vec![0u8; rgba.len()];
+    let failed = |e: moxcms::CmsError| format!("Error converting colors to the output profile: {}", e);
+    let transform = ColorProfile::new_srgb()
+        .create_transform_8bit(Layout::Rgba, &target, Layout::Rgba, TransformOptions::default())
+        .map_err(failed)?;
+    transform.transform(&rgba, &mut converted).map_err(failed)?;
+    let converted = ImageBuffer::from_raw(rgba.width(), rgba.height(), converted).ok_or("Invalid converted image")?;
+    Ok(Cow::Owned(DynamicImage::ImageRgba8(converted)))
+}
@@ -0,0 +1,103 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use image::{DynamicImage, RgbaImage};
+
+use crate::image_handling::LoadOptions;
+
+/// Changes whenever loading the same file with the same options gives different pixels, so that
+/// entries of older versions are not used; 2 converts colors to sRGB.
+const VERSION: u32 = 2;
+
+/// A loaded image read back from the cache.
+pub struct CachedImage {
+    /// Size of the source image before scaling, which the size filters are checked on.
+    pub original: (u32, u32),
+    pub image: DynamicImage,
+}
+
+/// Describes the source file and every option that changes the loaded pixels, so a cache entry
+/// is only used for the same file, unchanged, loaded the same way. `None` for files that are not
+/// on disk, such as archive members.
+fn cache_key(path: &Path, options: &LoadOptions) -> Option<String> {
+    let meta = fs::metadata(path).ok()?;
+    let modified = meta.modified().ok()?.duration_since(UNIX_EPOCH).ok()?.as_nanos();
+    let path = fs::canonicalize(path).ok()?;
+    Some(format!(
+        "{}|{}|{}|{}|{:?}|{:?}|{:?}|{:?}|{}|{:?}",
+        VERSION,
+        path.display(),
+        modified,
+        meta.len(),
+        options.standard_size,
+        options.scale_mode,
+        options.max_pixels,
+        options.animated_frame,
+        options.raster_dpi,
+        options.retarget_max,
+    ))
+}
+
+fn entry_path(dir: &Path, key: &str) -> PathBuf {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    dir.join(format!("{:016x}.rgba.gz", hasher.finish()))
+}
+
+/// The cached image for `path`, if an earlier run stored one with the same options.
+pub fn load(dir: &Path, path: &Path, options: &LoadOptions) -> Option<CachedImage> {
+    let key = cache_key(path, options)?;
+    let mut bytes = Vec::new();
+    GzDecoder::new(fs::File::open(entry_path(dir, &key)).ok()?).read_to_end(&mut bytes).ok()?;
+
+    // The key is stored in the entry as well, which rules out hash collisions
+    let number = |at: usize| Some(u32::from_le_bytes(bytes.get(at..at + 4)?.try_into().ok()?));
+    let key_len = number(0)? as usize;
+    if bytes.get(4..4 + key_len)? != key.as_bytes() {
+        return None;
+    }
+    let header = 4 + key_len;
+    let original = (number(header)?, number(header + 4)?);
+    let (width, height) = (number(header + 8)?, number(header + 12)?);
+    let pixels = bytes.get(header + 16..)?.to_vec();
+    let image = RgbaImage::from_raw(width, height, pixels)?;
+    Some(CachedImage { original, image: DynamicImage::ImageRgba8(image) })
+}
+
+/// Stores the loaded image of `path` for later runs. Failing to write the cache only costs time,
+/// so errors are reported and otherwise ignored.
+pub fn store(dir: &Path, path: &Path, options: &LoadOptions, original: (u32, u32), image: &DynamicImage) {
+    let Some(key) = cache_key(path, options) else {
+        return;
+    };
+    let rgba = image.to_rgba8();
+    let mut bytes = Vec::with_capacity(20 + key.len() + rgba.len());
+    bytes.extend((key.len() as u32).to_le_bytes());
+    bytes.extend(key.as_bytes());
+    for number in [original.0, original.1, rgba.width(), rgba.height()] {
+        bytes.extend(number.to_le_bytes());
+    }
+    bytes.extend(rgba.as_raw());
+
+    let target = entry_path(dir, &key);
+    // Write to a temporary name first so an interrupted run never leaves a truncated entry
+    let partial = target.with_extension("partial");
+    let result = fs::create_dir_all(dir)
+        .and_then(|_| {
+            let mut encoder = GzEncoder::new(fs::File::create(&partial)?, Compression::fast());
+            encoder.write_all(&bytes)?;
+            encoder.finish()?;
+            fs::rename(&partial, &target)
+        })
+        .map_err(|e| format!("Error writing {}: {}", target.display(), e));
+    if let Err(e) = result {
+        eprintln!("{}", e);
+    }
+}
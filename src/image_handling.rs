@@ -1,20 +1,279 @@
+use std::borrow::Cow;
+use std::collections::hash_map::{DefaultHasher, Entry};
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
+use std::fmt::Display;
 use std::fs;
-use image::imageops::{resize, FilterType};
-use image::{DynamicImage, GenericImageView};
+use std::io::{self, Cursor};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+use clap::ValueEnum;
+use image::codecs::gif::GifDecoder;
+use image::codecs::hdr::{HdrDecoder, HdrMetadata};
+use image::codecs::png::PngDecoder;
+use image::codecs::webp::WebPDecoder;
+use image::imageops::{self, FilterType};
+use image::error::{DecodingError, ImageFormatHint};
+use image::{AnimationDecoder, DynamicImage, Frames, GenericImageView, ImageError, ImageFormat, ImageResult, Rgb32FImage, RgbaImage};
+use rayon::prelude::*;
+use regex::Regex;
+use resvg::{tiny_skia, usvg};
 
-pub fn load_images(dir: &str, filter: Option<String>, standard_width: Option<u32>) -> Vec<(u32, DynamicImage)> {
-    println!("Loading images from directory: {}", dir);
-    let entries = match fs::read_dir(dir) {
-        Ok(entries) => entries,
-        Err(e) => {
-            eprintln!("Error reading directory {}: {}", dir, e);
-            return Vec::new();
+use crate::collage::resize_rgba;
+use crate::archive::{for_each_entry, is_archive, read_member};
+use crate::error::Error;
+use crate::icc;
+use crate::image_cache;
+use crate::file_list::ListEntry;
+use crate::retarget::retarget_to_standard_ratio;
+use crate::text::DEFAULT_FONT;
+use crate::tone;
+use crate::video::{extract_frames, is_video};
+
+const IMAGE_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "webp", "bmp", "tif", "tiff", "gif", "hdr", "exr"];
+
+/// Files that Windows and its tools create in photo folders, in lower case.
+const SYSTEM_FILES: [&str; 3] = ["thumbs.db", "desktop.ini", "ehthumbs.db"];
+
+/// Where the caption text drawn for each image comes from.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum LabelSource {
+    Filename,
+    ExifDate,
+    Sidecar,
+}
+
+/// Which frame of an animated GIF, WebP or PNG is used as the photo.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum AnimatedFrame {
+    #[default]
+    First,
+    Middle,
+    /// Zero-based frame index; the last frame is used for shorter animations.
+    Index(usize),
+}
+
+/// Which dimension `--width` fixes when scaling the loaded images.
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum ScaleMode {
+    /// Every image gets the standard width; portraits end up taller than landscapes.
+    #[default]
+    Width,
+    /// Every image gets the standard height.
+    Height,
+    /// Every image gets the pixel area of a square with the standard side length.
+    Area,
+}
+
+/// An image with its id and source file, scaled to the standard size.
+pub type LoadedImage = (u32, PathBuf, DynamicImage);
+
+/// Id of an image that stays the same across runs: a CRC32 of its file content, so that adding,
+/// removing, renaming or reordering other files does not change it.
+pub fn content_id(bytes: &[u8]) -> u32 {
+    crc32fast::hash(bytes)
+}
+
+/// `id`, or the next one after it that no image has yet, for the rare files whose hashes collide.
+fn unique_id(images: &[LoadedImage], mut id: u32) -> u32 {
+    while images.iter().any(|(taken, _, _)| *taken == id) {
+        id = id.wrapping_add(1);
+    }
+    id
+}
+
+/// Length and hash of the content of a file.
+type Fingerprint = (usize, u64);
+
+fn fingerprint(bytes: &[u8]) -> Fingerprint {
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    (bytes.len(), hasher.finish())
+}
+
+/// What the parallel pass of `load_files` made of a file.
+enum Prepared {
+    /// Too small, unreadable or outside the date range.
+    Skipped,
+    /// A still image with the fingerprint and content id of its file; `None` if it could not be
+    /// decoded or is filtered out.
+    Still(Fingerprint, u32, Option<DynamicImage>),
+    /// A video or PDF, which is loaded afterwards.
+    Extracted,
+}
+
+/// Remembers the content of the files loaded so far to skip byte-identical copies, which are
+/// common after copying folders around.
+#[derive(Default)]
+pub struct DuplicateFilter {
+    seen: HashMap<Fingerprint, PathBuf>,
+    /// Skipped files and the file with the same content that was loaded instead.
+    skipped: Vec<(PathBuf, PathBuf)>,
+}
+
+impl DuplicateFilter {
+    /// Returns the earlier file with the same content, or remembers `path` if there is none.
+    fn check(&mut self, path: &Path, fingerprint: Fingerprint) -> Option<PathBuf> {
+        match self.seen.entry(fingerprint) {
+            Entry::Occupied(original) => {
+                self.skipped.push((path.to_path_buf(), original.get().clone()));
+                Some(original.get().clone())
+            }
+            Entry::Vacant(slot) => {
+                slot.insert(path.to_path_buf());
+                None
+            }
         }
-    };
+    }
 
-    let mut images = Vec::new();
-    let mut id_counter = 0;
+    pub fn print_summary(&self) {
+        if self.skipped.is_empty() {
+            return;
+        }
+        info!("Skipped {} duplicate files:", self.skipped.len());
+        for (duplicate, original) in &self.skipped {
+            info!("  {} -> {}", duplicate.display(), original.display());
+        }
+    }
+}
 
+/// Files that could not be read or decoded and why, collected while loading so that they are
+/// reported together at the end instead of between the progress messages.
+#[derive(Default)]
+pub struct LoadFailures {
+    failed: Mutex<Vec<(PathBuf, String)>>,
+}
+
+impl LoadFailures {
+    fn record(&self, path: &Path, reason: impl Display) {
+        let mut failed = self.failed.lock().unwrap_or_else(|e| e.into_inner());
+        failed.push((path.to_path_buf(), reason.to_string()));
+    }
+
+    /// Prints the failed files grouped by reason, the most common reason first.
+    pub fn print_summary(&self) {
+        let failed = self.failed.lock().unwrap_or_else(|e| e.into_inner());
+        if failed.is_empty() {
+            return;
+        }
+        let mut reasons: BTreeMap<&str, Vec<&Path>> = BTreeMap::new();
+        for (path, reason) in failed.iter() {
+            reasons.entry(reason).or_default().push(path);
+        }
+        let mut reasons: Vec<(&str, Vec<&Path>)> = reasons.into_iter().collect();
+        reasons.sort_by_key(|(_, paths)| std::cmp::Reverse(paths.len()));
+        eprintln!("Could not load {} files:", failed.len());
+        for (reason, mut paths) in reasons {
+            paths.sort();
+            eprintln!("  {}:", reason);
+            for path in paths {
+                eprintln!("    {}", path.display());
+            }
+        }
+    }
+
+    /// With `--strict`, fails if more than `max_percent` percent of the images that were opened
+    /// could not be loaded.
+    fn check(&self, loaded: usize, max_percent: Option<f64>) -> Result<(), Error> {
+        let failed = self.failed.lock().unwrap_or_else(|e| e.into_inner()).len();
+        let Some(max_percent) = max_percent else {
+            return Ok(());
+        };
+        let percent = 100.0 * failed as f64 / (failed + loaded).max(1) as f64;
+        if percent > max_percent {
+            return Err(Error::ReadInput(format!(
+                "{} of {} images could not be loaded ({:.1}%), more than the {}% allowed by --strict.",
+                failed,
+                failed + loaded,
+                percent,
+                max_percent
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Settings for reading the images of the input directory.
+#[derive(Clone)]
+pub struct LoadOptions {
+    /// Only files whose name contains this text are loaded.
+    pub filter: Option<String>,
+    /// Only files whose name matches this regular expression are loaded.
+    pub filter_regex: Option<Regex>,
+    /// Files in input directories that are symbolic links are loaded as well.
+    pub follow_symlinks: bool,
+    /// Hidden and system files in input directories are loaded as well, see `is_hidden`.
+    pub include_hidden: bool,
+    /// Images are scaled so that the dimension chosen by `scale_mode` has this size.
+    pub standard_size: Option<u32>,
+    pub scale_mode: ScaleMode,
+    /// Larger images are scaled down to this many pixels, after the standard size, to keep the
+    /// run within `--max-memory`.
+    pub max_pixels: Option<u64>,
+    /// Loaded images are kept in this directory and reused by later runs on the same files.
+    pub image_cache: Option<PathBuf>,
+    pub animated_frame: AnimatedFrame,
+    /// Extract a frame from every video file at this interval in seconds; videos are skipped when unset.
+    pub video_every: Option<f64>,
+    /// Resolution at which SVG files and PDF pages are rasterized.
+    pub raster_dpi: f32,
+    /// Smaller images (thumbnails, icons) are skipped.
+    pub min_width: u32,
+    pub min_height: u32,
+    /// Smaller files are skipped before decoding.
+    pub min_bytes: u64,
+    /// Images with a width/height ratio outside this range (after EXIF rotation) are skipped.
+    pub aspect_ratio_min: Option<f64>,
+    pub aspect_ratio_max: Option<f64>,
+    /// Images whose sharpness (variance of the Laplacian) is below this are skipped as blurry.
+    pub min_sharpness: Option<f64>,
+    /// Only photos taken in this range (`YYYY-MM-DD`, inclusive) according to EXIF are loaded.
+    pub since: Option<String>,
+    pub until: Option<String>,
+    /// Seam-carve images to the nearest standard aspect ratio, removing at most this fraction of
+    /// their width or height.
+    pub retarget_max: Option<f64>,
+    /// Loading fails if more than this percentage of the images could not be read or decoded.
+    pub max_failed_percent: Option<f64>,
+}
+
+impl Default for LoadOptions {
+    fn default() -> Self {
+        LoadOptions {
+            filter: None,
+            filter_regex: None,
+            follow_symlinks: false,
+            include_hidden: false,
+            standard_size: None,
+            scale_mode: ScaleMode::Width,
+            max_pixels: None,
+            image_cache: None,
+            animated_frame: AnimatedFrame::First,
+            video_every: None,
+            raster_dpi: 96.0,
+            min_width: 0,
+            min_height: 0,
+            min_bytes: 0,
+            aspect_ratio_min: None,
+            aspect_ratio_max: None,
+            min_sharpness: None,
+            since: None,
+            until: None,
+            retarget_max: None,
+            max_failed_percent: None,
+        }
+    }
+}
+
+pub fn load_images(dir: &str, options: &LoadOptions, duplicates: &mut DuplicateFilter, failures: &LoadFailures) -> Result<Vec<LoadedImage>, Error> {
+    if is_archive(Path::new(dir)) {
+        return load_archive(Path::new(dir), options, duplicates, failures);
+    }
+    info!("Loading images from directory: {}", dir);
+    let entries = fs::read_dir(dir).map_err(|source| Error::ReadDirectory { path: PathBuf::from(dir), source })?;
+
+    let mut paths = Vec::new();
     for entry in entries {
         let entry = match entry {
             Ok(e) => e,
@@ -24,51 +283,830 @@ pub fn load_images(dir: &str, filter: Option<String>, standard_width: Option<u32
             }
         };
         let path = entry.path();
-        let passes_filter = if let Some(f) = &filter {
-            if let Some(name) = path.file_name().and_then(|s| s.to_str()) {
-                name.contains(f)
-            } else {
-                false
-            }
+        if !options.include_hidden && is_hidden(&path) {
+            continue;
+        }
+        if !options.follow_symlinks && path.is_symlink() {
+            info!("Skipping symbolic link (use --follow-symlinks): {}", path.display());
+        } else if path.is_file() && is_supported(&path) && passes_filter(&path, options) {
+            paths.push(path);
         } else {
-            true
+            info!("Skipping: {}", path.display());
+        }
+    }
+
+    let mut images = Vec::new();
+    load_files(&paths, options, &mut images, duplicates, failures);
+    info!("Total images loaded: {}", images.len());
+    Ok(images)
+}
+
+/// Loads several directories (or archives) as one set of images. Returns the images and the index
+/// of the directory every id came from.
+pub fn load_directories(dirs: &[String], options: &LoadOptions) -> Result<(Vec<LoadedImage>, HashMap<u32, usize>), Error> {
+    let mut images = Vec::new();
+    let mut groups = HashMap::new();
+    let mut duplicates = DuplicateFilter::default();
+    let failures = LoadFailures::default();
+    for (group, dir) in dirs.iter().enumerate() {
+        for (id, path, img) in load_images(dir, options, &mut duplicates, &failures)? {
+            let id = unique_id(&images, id);
+            groups.insert(id, group);
+            images.push((id, path, img));
+        }
+    }
+    duplicates.print_summary();
+    failures.print_summary();
+    failures.check(images.len(), options.max_failed_percent)?;
+    Ok((images, groups))
+}
+
+/// Loads the files of a `--from-list` list in the given order instead of scanning a directory.
+/// Returns the images and, for every image id, the list entry it came from.
+pub fn load_list<'a>(entries: &'a [ListEntry], options: &LoadOptions) -> Result<(Vec<LoadedImage>, HashMap<u32, &'a ListEntry>), Error> {
+    info!("Loading {} images from list", entries.len());
+    let mut images = Vec::new();
+    let mut sources = HashMap::new();
+    let mut duplicates = DuplicateFilter::default();
+    let failures = LoadFailures::default();
+    let entries: Vec<&ListEntry> = entries
+        .iter()
+        .filter(|entry| {
+            let passes = passes_filter(&entry.path, options);
+            if !passes {
+                info!("Skipping: {}", entry.path.display());
+            }
+            passes
+        })
+        .collect();
+    let paths: Vec<PathBuf> = entries.iter().map(|entry| entry.path.clone()).collect();
+    let counts = load_files(&paths, options, &mut images, &mut duplicates, &failures);
+    // PDFs and videos add several images that all share the entry
+    let mut loaded = images.iter();
+    for (entry, count) in entries.into_iter().zip(counts) {
+        for (id, _, _) in loaded.by_ref().take(count) {
+            sources.insert(*id, entry);
+        }
+    }
+
+    duplicates.print_summary();
+    failures.print_summary();
+    info!("Total images loaded: {}", images.len());
+    failures.check(images.len(), options.max_failed_percent)?;
+    Ok((images, sources))
+}
+
+/// Opens the files in order and appends their images; videos and PDFs can add several. Returns
+/// the number of images each file added.
+fn load_files(
+    paths: &[PathBuf],
+    options: &LoadOptions,
+    images: &mut Vec<LoadedImage>,
+    duplicates: &mut DuplicateFilter,
+    failures: &LoadFailures,
+) -> Vec<usize> {
+    // Decoding and scaling are the slow part of loading, so still images are prepared in
+    // parallel; videos and PDFs run external tools and are loaded one at a time afterwards
+    let prepared: Vec<Prepared> = paths.par_iter().map(|path| prepare_file(path, options, failures)).collect();
+    paths
+        .iter()
+        .zip(prepared)
+        .map(|(path, prepared)| {
+            let count = images.len();
+            match prepared {
+                Prepared::Skipped => {}
+                Prepared::Still(fingerprint, id, img) => match duplicates.check(path, fingerprint) {
+                    Some(original) => info!("Skipping duplicate: {} (same content as {})", path.display(), original.display()),
+                    None => images.extend(img.map(|img| (unique_id(images, id), path.clone(), img))),
+                },
+                Prepared::Extracted => load_extracted(path, options, images, failures),
+            }
+            images.len() - count
+        })
+        .collect()
+}
+
+/// Reads, decodes and scales a still image; videos and PDFs are only recognized.
+fn prepare_file(path: &Path, options: &LoadOptions, failures: &LoadFailures) -> Prepared {
+    if fs::metadata(path).is_ok_and(|meta| meta.len() < options.min_bytes) {
+        info!("Skipping small file: {}", path.display());
+        return Prepared::Skipped;
+    }
+    if is_video(path) || is_pdf(path) {
+        return Prepared::Extracted;
+    }
+    let bytes = match read_source(path) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            failures.record(path, e);
+            return Prepared::Skipped;
+        }
+    };
+    if !in_date_range(|| exif_date(&bytes), options) {
+        info!("Skipping photo outside the date range: {}", path.display());
+        return Prepared::Skipped;
+    }
+    Prepared::Still(fingerprint(&bytes), content_id(&bytes), prepare_bytes(path, &bytes, options, failures))
+}
+
+/// Extracts the frames of a video or the pages of a PDF and appends them.
+fn load_extracted(path: &Path, options: &LoadOptions, images: &mut Vec<LoadedImage>, failures: &LoadFailures) {
+    if !in_date_range(|| read_exif_date(path), options) {
+        info!("Skipping photo outside the date range: {}", path.display());
+        return;
+    }
+    let candidates = if is_video(path) {
+        let Some(every) = options.video_every else {
+            info!("Skipping video (use --video-every to extract frames): {}", path.display());
+            return;
         };
+        info!("Extracting frames from video: {}", path.display());
+        match extract_frames(path, every) {
+            Ok(frames) => frames,
+            Err(e) => {
+                failures.record(path, e);
+                return;
+            }
+        }
+    } else {
+        info!("Rasterizing PDF pages: {}", path.display());
+        match rasterize_pdf(path, options.raster_dpi) {
+            Ok(pages) => pages,
+            Err(e) => {
+                failures.record(path, e);
+                return;
+            }
+        }
+    };
 
-        if path.is_file() && passes_filter {
-            println!("Opening image: {}", path.display());
-            let img_result = image::open(&path);
-            match img_result {
-                Ok(img) => {
-                    println!("Successfully opened: {}", path.display());
-                    let scaled_img = scale_to_standard_width(&img, standard_width);
-                    images.push((id_counter, scaled_img));
-                    id_counter += 1;
+    // The frames are extracted anew every run, so their ids come from the source and their number
+    let source = path;
+    for (index, path) in candidates.into_iter().enumerate() {
+        info!("Opening image: {}", path.display());
+        match open_image(&path, options) {
+            Ok(img) => {
+                if let Some(img) = prepare_image(&path, &img, options) {
+                    let id = content_id(format!("{}#{}", source.display(), index).as_bytes());
+                    images.push((unique_id(images, id), path, img));
                 }
-                Err(e) => {
-                    eprintln!("Error opening {}: {}", path.display(), e);
-                    continue;
+            }
+            Err(e) => failures.record(&path, e),
+        }
+    }
+}
+
+/// Checks, decodes and appends an image file that has been read into memory.
+fn load_bytes(
+    path: PathBuf,
+    bytes: &[u8],
+    options: &LoadOptions,
+    images: &mut Vec<LoadedImage>,
+    duplicates: &mut DuplicateFilter,
+    failures: &LoadFailures,
+) {
+    if !in_date_range(|| exif_date(bytes), options) {
+        info!("Skipping photo outside the date range: {}", path.display());
+        return;
+    }
+    if let Some(original) = duplicates.check(&path, fingerprint(bytes)) {
+        info!("Skipping duplicate: {} (same content as {})", path.display(), original.display());
+        return;
+    }
+    if let Some(img) = prepare_bytes(&path, bytes, options, failures) {
+        images.push((unique_id(images, content_id(bytes)), path, img));
+    }
+}
+
+/// Decodes an image file that has been read into memory and scales it, or takes it from the image
+/// cache. `None` if it cannot be decoded or is filtered out.
+fn prepare_bytes(path: &Path, bytes: &[u8], options: &LoadOptions, failures: &LoadFailures) -> Option<DynamicImage> {
+    // Sharpness is measured on the decoded original, so the cache is not used with --min-sharpness
+    let cache = options.image_cache.as_deref().filter(|_| options.min_sharpness.is_none());
+    if let Some(cached) = cache.and_then(|dir| image_cache::load(dir, path, options)) {
+        let (width, height) = cached.original;
+        if !passes_size(path, width, height, options) {
+            return None;
+        }
+        info!("Using cached image: {}", path.display());
+        return Some(cached.image);
+    }
+    info!("Opening image: {}", path.display());
+    match decode_image(bytes, path, options) {
+        Ok(img) => {
+            let loaded = prepare_image(path, &img, options)?;
+            if let Some(dir) = cache {
+                image_cache::store(dir, path, options, img.dimensions(), &loaded);
+            }
+            Some(loaded)
+        }
+        Err(e) => {
+            failures.record(path, e);
+            None
+        }
+    }
+}
+
+/// Opens an image like `image::open`, but decodes animated GIF, WebP and PNG files explicitly
+/// and returns the requested frame instead of whatever the still-image decoder yields. SVG files
+/// are rasterized at the configured DPI, and paths inside a ZIP/TAR input are read from the archive.
+pub fn open_image(path: &Path, options: &LoadOptions) -> ImageResult<DynamicImage> {
+    let bytes = read_source(path).map_err(ImageError::IoError)?;
+    decode_image(&bytes, path, options)
+}
+
+/// Reads a file from disk or, for paths inside a ZIP/TAR input, from the archive.
+pub fn read_source(path: &Path) -> io::Result<Vec<u8>> {
+    match fs::read(path) {
+        Err(e) => match read_member(path) {
+            Some(member) => member.map_err(io::Error::other),
+            None => Err(e),
+        },
+        bytes => bytes,
+    }
+}
+
+/// Decodes an image from memory; `path` only determines the format, falling back to sniffing the
+/// data. Colors are converted to sRGB according to the embedded ICC profile, so photos from
+/// cameras and phones set to Adobe RGB or Display P3 do not look washed out next to the others.
+fn decode_image(bytes: &[u8], path: &Path, options: &LoadOptions) -> ImageResult<DynamicImage> {
+    if is_svg(path) {
+        return rasterize_svg(bytes, path, options.raster_dpi)
+            .map_err(|e| ImageError::Decoding(DecodingError::new(ImageFormatHint::Name("SVG".to_string()), e)));
+    }
+    let format = ImageFormat::from_path(path).or_else(|_| image::guess_format(bytes))?;
+    let img = decode_frame(bytes, format, options)?;
+    if let Some(tonemapped) = tone::tonemap(&img) {
+        return Ok(tonemapped);
+    }
+    match icc::embedded_profile(bytes, format).map(|profile| icc::to_srgb(&img, &profile)) {
+        Some(Ok(Cow::Owned(converted))) => Ok(converted),
+        Some(Err(e)) => {
+            eprintln!("Ignoring the color profile of {}: {}", path.display(), e);
+            Ok(img)
+        }
+        _ => Ok(img),
+    }
+}
+
+/// Decodes a still image or the frame of an animation selected by `--animated-frame`.
+fn decode_frame(bytes: &[u8], format: ImageFormat, options: &LoadOptions) -> ImageResult<DynamicImage> {
+    let frames: Frames = match format {
+        ImageFormat::Gif => GifDecoder::new(Cursor::new(bytes))?.into_frames(),
+        ImageFormat::WebP => {
+            let decoder = WebPDecoder::new(Cursor::new(bytes))?;
+            if !decoder.has_animation() {
+                return decode_still(bytes, format);
+            }
+            decoder.into_frames()
+        }
+        ImageFormat::Png => {
+            let decoder = PngDecoder::new(Cursor::new(bytes))?;
+            if !decoder.is_apng() {
+                return decode_still(bytes, format);
+            }
+            decoder.apng().into_frames()
+        }
+        ImageFormat::Hdr => return decode_hdr(bytes),
+        _ => return decode_still(bytes, format),
+    };
+
+    let selected = match options.animated_frame {
+        AnimatedFrame::First => frames.take(1).collect::<ImageResult<Vec<_>>>()?.pop(),
+        AnimatedFrame::Middle => {
+            let mut all = frames.collect_frames()?;
+            let middle = all.len() / 2;
+            (middle < all.len()).then(|| all.swap_remove(middle))
+        }
+        AnimatedFrame::Index(index) => frames.take(index + 1).collect::<ImageResult<Vec<_>>>()?.pop(),
+    };
+    match selected {
+        Some(frame) => Ok(DynamicImage::ImageRgba8(frame.into_buffer())),
+        None => decode_still(bytes, format),
+    }
+}
+
+/// Scales a decoded image to the standard size, unless it is below the minimum resolution
+/// (checked on the original pixels), outside the aspect ratio range or blurry.
+fn prepare_image(path: &Path, img: &DynamicImage, options: &LoadOptions) -> Option<DynamicImage> {
+    let (width, height) = img.dimensions();
+    if !passes_size(path, width, height, options) {
+        return None;
+    }
+    if let Some(min) = options.min_sharpness {
+        let sharpness = sharpness(img);
+        if sharpness < min {
+            info!("Skipping blurry image (sharpness {:.1}): {}", sharpness, path.display());
+            return None;
+        }
+    }
+    info!("Successfully opened: {}", path.display());
+    let scaled_img = scale_to_standard_size(img, options);
+    // Carve after scaling, which keeps seam carving fast for large photos
+    Some(retarget(scaled_img, path, options))
+}
+
+/// Checks the minimum resolution and the aspect ratio range on the size of the original image.
+fn passes_size(path: &Path, width: u32, height: u32, options: &LoadOptions) -> bool {
+    if width < options.min_width || height < options.min_height {
+        info!("Skipping small image ({}x{}): {}", width, height, path.display());
+        return false;
+    }
+    let aspect_ratio = width as f64 / height.max(1) as f64;
+    if options.aspect_ratio_min.is_some_and(|min| aspect_ratio < min) || options.aspect_ratio_max.is_some_and(|max| aspect_ratio > max) {
+        info!("Skipping image with aspect ratio {:.2}: {}", aspect_ratio, path.display());
+        return false;
+    }
+    true
+}
+
+/// Applies `--retarget` to a loaded image; `render` calls this too so that redrawn images keep the
+/// aspect ratio they were packed with.
+pub fn retarget(img: DynamicImage, path: &Path, options: &LoadOptions) -> DynamicImage {
+    match options.retarget_max.and_then(|max| retarget_to_standard_ratio(&img, max)) {
+        Some(carved) => {
+            info!("Retargeted {} from {}x{} to {}x{}", path.display(), img.width(), img.height(), carved.width(), carved.height());
+            carved
+        }
+        None => img,
+    }
+}
+
+/// Variance of the Laplacian of the grayscale image: low for out-of-focus shots with few sharp
+/// edges. Large images are measured at 512 pixels on the longer side so values are comparable
+/// across resolutions; smaller ones are not enlarged, as that would blur them.
+fn sharpness(img: &DynamicImage) -> f64 {
+    let gray = if img.width().max(img.height()) > 512 {
+        img.resize(512, 512, FilterType::Triangle).to_luma8()
+    } else {
+        img.to_luma8()
+    };
+    let (width, height) = gray.dimensions();
+    if width < 3 || height < 3 {
+        return 0.0;
+    }
+    let pixel = |x: u32, y: u32| gray.get_pixel(x, y)[0] as f64;
+    let mut sum = 0.0;
+    let mut sum_of_squares = 0.0;
+    for y in 1..height - 1 {
+        for x in 1..width - 1 {
+            let laplacian = pixel(x - 1, y) + pixel(x + 1, y) + pixel(x, y - 1) + pixel(x, y + 1) - 4.0 * pixel(x, y);
+            sum += laplacian;
+            sum_of_squares += laplacian * laplacian;
+        }
+    }
+    let count = ((width - 2) * (height - 2)) as f64;
+    let mean = sum / count;
+    sum_of_squares / count - mean * mean
+}
+
+/// Decodes the images of a ZIP or TAR archive straight from memory. Videos and PDFs need external
+/// tools that read from disk and are skipped.
+fn load_archive(archive: &Path, options: &LoadOptions, duplicates: &mut DuplicateFilter, failures: &LoadFailures) -> Result<Vec<LoadedImage>, Error> {
+    info!("Loading images from archive: {}", archive.display());
+    let mut images = Vec::new();
+    let result = for_each_entry(archive, |path, bytes| {
+        if !is_supported(&path) || !passes_filter(&path, options) || is_video(&path) || is_pdf(&path) {
+            info!("Skipping: {}", path.display());
+            return;
+        }
+        if (bytes.len() as u64) < options.min_bytes {
+            info!("Skipping small file: {}", path.display());
+            return;
+        }
+        load_bytes(path, &bytes, options, &mut images, duplicates, failures);
+    });
+    result.map_err(Error::ReadInput)?;
+
+    info!("Total images loaded: {}", images.len());
+    Ok(images)
+}
+
+/// What `scan_headers` found in the inputs.
+pub struct HeaderScan {
+    /// Supported files in the inputs.
+    pub files: usize,
+    /// Upright pixel size of every image that passes the filters.
+    pub sizes: Vec<(u32, u32)>,
+    /// Videos, PDFs and SVGs, whose images are only known after extracting or rasterizing them.
+    pub unchecked: usize,
+}
+
+/// Applies the filters that need no decoding (name, file size, date, resolution and aspect ratio)
+/// to the files of the input directories, archives or files, reading only their headers.
+/// Sharpness and duplicates need the pixels and are not checked. Used by `--dry-run`.
+pub fn scan_headers(inputs: &[PathBuf], options: &LoadOptions) -> HeaderScan {
+    let mut scan = HeaderScan { files: 0, sizes: Vec::new(), unchecked: 0 };
+    let mut files = Vec::new();
+    for input in inputs {
+        if is_archive(input) {
+            let result = for_each_entry(input, |path, bytes| {
+                if is_supported(&path) && passes_filter(&path, options) {
+                    scan.files += 1;
+                    if has_extension(&path, IMAGE_EXTENSIONS) {
+                        scan.sizes.extend(header_size(&bytes, options));
+                    } else {
+                        scan.unchecked += 1;
+                    }
                 }
+            });
+            if let Err(e) = result {
+                eprintln!("{}", e);
+            }
+        } else if input.is_dir() {
+            match sorted_files(input) {
+                Ok(entries) => files.extend(entries.into_iter().filter(|path| {
+                    (options.include_hidden || !is_hidden(path)) && (options.follow_symlinks || !path.is_symlink())
+                })),
+                Err(e) => eprintln!("{}", e),
             }
         } else {
-            println!("Skipping: {}", path.display());
+            files.push(input.clone());
+        }
+    }
+
+    let files: Vec<PathBuf> = files.into_iter().filter(|path| is_supported(path) && passes_filter(path, options)).collect();
+    scan.files += files.len();
+    let (images, others): (Vec<PathBuf>, Vec<PathBuf>) = files.into_iter().partition(|path| has_extension(path, IMAGE_EXTENSIONS));
+    scan.unchecked += others.len();
+    let sizes: Vec<Option<(u32, u32)>> = images
+        .par_iter()
+        .map(|path| header_size(&read_source(path).ok()?, options))
+        .collect();
+    scan.sizes.extend(sizes.into_iter().flatten());
+    scan
+}
+
+/// Upright size of an image read from its header, if it passes the filters.
+fn header_size(bytes: &[u8], options: &LoadOptions) -> Option<(u32, u32)> {
+    if (bytes.len() as u64) < options.min_bytes || !in_date_range(|| exif_date(bytes), options) {
+        return None;
+    }
+    let (width, height) = image::io::Reader::new(Cursor::new(bytes)).with_guessed_format().ok()?.into_dimensions().ok()?;
+    let (width, height) = if exif_orientation(bytes).is_some_and(|o| o >= 5) { (height, width) } else { (width, height) };
+    let aspect_ratio = width as f64 / height.max(1) as f64;
+    let passes = width >= options.min_width
+        && height >= options.min_height
+        && options.aspect_ratio_min.is_none_or(|min| aspect_ratio >= min)
+        && options.aspect_ratio_max.is_none_or(|max| aspect_ratio <= max);
+    passes.then_some((width, height))
+}
+
+/// Whether the EXIF date lies within `--since`/`--until`; `date` is only read when a range is
+/// set. Files without a date are outside every range.
+fn in_date_range(date: impl FnOnce() -> Option<String>, options: &LoadOptions) -> bool {
+    if options.since.is_none() && options.until.is_none() {
+        return true;
+    }
+    // ISO dates compare correctly as strings
+    date().is_some_and(|date| {
+        options.since.as_ref().is_none_or(|since| date >= *since) && options.until.as_ref().is_none_or(|until| date <= *until)
+    })
+}
+
+fn passes_filter(path: &Path, options: &LoadOptions) -> bool {
+    // Names that are not valid UTF-8 are matched with their invalid bytes replaced, so they are
+    // still loaded and only miss filters that ask for those very characters
+    let name = path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+    options.filter.as_ref().is_none_or(|f| name.contains(f.as_str()))
+        && options.filter_regex.as_ref().is_none_or(|regex| regex.is_match(&name))
+}
+
+/// Dot files such as `.DS_Store` or the `._IMG_0001.jpg` resource forks macOS leaves on shared
+/// drives, system files such as `Thumbs.db` and `desktop.ini`, and on Windows files with the hidden
+/// or system attribute. They are skipped without a message.
+pub fn is_hidden(path: &Path) -> bool {
+    let name = path.file_name().map(|name| name.to_string_lossy().to_ascii_lowercase()).unwrap_or_default();
+    name.starts_with('.') || SYSTEM_FILES.contains(&name.as_str()) || has_hidden_attribute(path)
+}
+
+#[cfg(windows)]
+fn has_hidden_attribute(path: &Path) -> bool {
+    use std::os::windows::fs::MetadataExt;
+    const HIDDEN_OR_SYSTEM: u32 = 0x2 | 0x4;
+    fs::symlink_metadata(path).is_ok_and(|meta| meta.file_attributes() & HIDDEN_OR_SYSTEM != 0)
+}
+
+#[cfg(not(windows))]
+fn has_hidden_attribute(_path: &Path) -> bool {
+    false
+}
+
+/// Decodes a Radiance HDR file with its floating point values; the still-image decoder of the
+/// `image` crate clips them to 8 bits.
+fn decode_hdr(bytes: &[u8]) -> ImageResult<DynamicImage> {
+    let decoder = HdrDecoder::new(Cursor::new(bytes))?;
+    let HdrMetadata { width, height, .. } = decoder.metadata();
+    let pixels = decoder.read_image_hdr()?.into_iter().flat_map(|pixel| pixel.0).collect();
+    let hdr = Rgb32FImage::from_raw(width, height, pixels)
+        .ok_or_else(|| ImageError::Decoding(DecodingError::new(ImageFormat::Hdr.into(), "Incomplete HDR image")))?;
+    Ok(DynamicImage::ImageRgb32F(hdr))
+}
+
+/// Decodes a still image and turns it upright according to its EXIF orientation, as cameras
+/// store portrait photos in landscape pixels with a tag saying how to rotate them.
+fn decode_still(bytes: &[u8], format: ImageFormat) -> ImageResult<DynamicImage> {
+    let img = image::load_from_memory_with_format(bytes, format)?;
+    Ok(match exif_orientation(bytes) {
+        Some(2) => img.fliph(),
+        Some(3) => img.rotate180(),
+        Some(4) => img.flipv(),
+        Some(5) => img.rotate90().fliph(),
+        Some(6) => img.rotate90(),
+        Some(7) => img.rotate270().fliph(),
+        Some(8) => img.rotate270(),
+        _ => img,
+    })
+}
+
+/// Whether a file found in an input directory or archive is worth opening: a raster image, SVG,
+/// PDF or video. Sidecars, RAW files and anything else are skipped without trying to decode them.
+fn is_supported(path: &Path) -> bool {
+    has_extension(path, IMAGE_EXTENSIONS) || is_svg(path) || is_pdf(path) || is_video(path)
+}
+
+fn is_svg(path: &Path) -> bool {
+    has_extension(path, &["svg", "svgz"])
+}
+
+fn is_pdf(path: &Path) -> bool {
+    has_extension(path, &["pdf"])
+}
+
+fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| extensions.contains(&ext.to_ascii_lowercase().as_str()))
+}
+
+/// Renders an SVG at `dpi` (96 DPI is the SVG's own pixel size). Text uses the system fonts with
+/// the embedded DejaVu Sans as fallback.
+fn rasterize_svg(data: &[u8], path: &Path, dpi: f32) -> Result<DynamicImage, String> {
+    let mut options = usvg::Options {
+        resources_dir: path.parent().map(Path::to_path_buf),
+        font_family: "DejaVu Sans".to_string(),
+        ..Default::default()
+    };
+    let fonts = options.fontdb_mut();
+    fonts.load_system_fonts();
+    fonts.load_font_data(DEFAULT_FONT.to_vec());
+    fonts.set_serif_family("DejaVu Sans");
+    fonts.set_sans_serif_family("DejaVu Sans");
+    let tree = usvg::Tree::from_data(data, &options).map_err(|e| e.to_string())?;
+
+    let scale = dpi / 96.0;
+    let size = tree.size().to_int_size().scale_by(scale).ok_or("SVG has no size")?;
+    let mut pixmap = tiny_skia::Pixmap::new(size.width(), size.height()).ok_or("SVG is too large")?;
+    resvg::render(&tree, tiny_skia::Transform::from_scale(scale, scale), &mut pixmap.as_mut());
+
+    // tiny-skia works with premultiplied alpha
+    let pixels = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|pixel| {
+            let color = pixel.demultiply();
+            [color.red(), color.green(), color.blue(), color.alpha()]
+        })
+        .collect();
+    let img = RgbaImage::from_raw(size.width(), size.height(), pixels).ok_or("Invalid SVG raster")?;
+    Ok(DynamicImage::ImageRgba8(img))
+}
+
+/// Renders every page of a PDF to a PNG file with pdftoppm (part of poppler) and returns the
+/// files, named `<name>_page1.png`, `<name>_page2.png`, ... in a `scratch_dir` of the PDF.
+fn rasterize_pdf(path: &Path, dpi: f32) -> Result<Vec<PathBuf>, String> {
+    let stem = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let dir = scratch_dir(path, &format!("{}dpi", dpi));
+    let out_dir = staging_dir(&dir)?;
+    let status = Command::new("pdftoppm")
+        .arg("-r")
+        .arg(dpi.to_string())
+        .arg("-png")
+        .arg(path)
+        .arg(out_dir.join("page"))
+        .status()
+        .map_err(|e| format!("Error running pdftoppm (is poppler installed and on the PATH?): {}", e))?;
+    if !status.success() {
+        return Err(format!("pdftoppm failed on {} ({})", path.display(), status));
+    }
+
+    // pdftoppm pads the page numbers depending on the page count, so sort them numerically
+    let mut pages: Vec<(u32, PathBuf)> = sorted_files(&out_dir)?
+        .into_iter()
+        .filter_map(|page| {
+            let number = page.file_stem()?.to_str()?.rsplit('-').next()?.parse().ok()?;
+            Some((number, page))
+        })
+        .collect();
+    pages.sort();
+
+    let mut named = Vec::with_capacity(pages.len());
+    for (number, page) in pages {
+        let target = out_dir.join(format!("{}_page{}.png", stem, number));
+        fs::rename(&page, &target).map_err(|e| format!("Error renaming {}: {}", page.display(), e))?;
+        named.push(target);
+    }
+    publish(&out_dir, &dir, named)
+}
+
+/// Directory below the system temp dir for the files generated from `source` (video frames, PDF
+/// pages), named after it, `variant` and a hash of its canonical path, so inputs of the same name in
+/// other folders get their own. It is kept after the run so layouts referring to it stay renderable.
+pub fn scratch_dir(source: &Path, variant: &str) -> PathBuf {
+    let stem = source.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let mut hasher = DefaultHasher::new();
+    source.canonicalize().unwrap_or_else(|_| source.to_path_buf()).hash(&mut hasher);
+    std::env::temp_dir().join("ImageGridOptimizer").join(format!("{}-{:016x}-{}", stem, hasher.finish(), variant))
+}
+
+/// Returns an empty directory next to `dir` that only this process writes to, so that a run on the
+/// same input at the same time does not clear the files while they are generated.
+pub fn staging_dir(dir: &Path) -> Result<PathBuf, String> {
+    let mut name = dir.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}", std::process::id()));
+    let staging = dir.with_file_name(name);
+    if staging.exists() {
+        fs::remove_dir_all(&staging).map_err(|e| format!("Error clearing {}: {}", staging.display(), e))?;
+    }
+    fs::create_dir_all(&staging).map_err(|e| format!("Error creating {}: {}", staging.display(), e))?;
+    Ok(staging)
+}
+
+/// Replaces `dir` with the finished `staging` directory and returns where `files` ended up.
+pub fn publish(staging: &Path, dir: &Path, files: Vec<PathBuf>) -> Result<Vec<PathBuf>, String> {
+    // Another run may publish the same files in between, so try again once after clearing
+    for attempt in 0..2 {
+        match fs::remove_dir_all(dir) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(format!("Error clearing {}: {}", dir.display(), e)),
+            _ => {}
+        }
+        match fs::rename(staging, dir) {
+            Ok(()) => break,
+            Err(_) if attempt == 0 => continue,
+            Err(e) => return Err(format!("Error moving {} to {}: {}", staging.display(), dir.display(), e)),
         }
     }
+    Ok(files.iter().filter_map(|file| file.file_name()).map(|name| dir.join(name)).collect())
+}
+
+pub fn sorted_files(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)
+        .map_err(|e| format!("Error reading {}: {}", dir.display(), e))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// Size of a `width`x`height` image after scaling the dimension chosen by `mode` to `size`.
+pub fn scaled_size(width: u32, height: u32, size: u32, mode: ScaleMode) -> (u32, u32) {
+    let factor = match mode {
+        ScaleMode::Width => size as f64 / width as f64,
+        ScaleMode::Height => size as f64 / height as f64,
+        ScaleMode::Area => size as f64 / (width as f64 * height as f64).sqrt(),
+    };
+    (((width as f64 * factor) as u32).max(1), ((height as f64 * factor) as u32).max(1))
+}
 
-    println!("Total images loaded: {}", images.len());
-    images
+/// Size a `width`x`height` image is loaded at: scaled to the standard size, then down to at most
+/// `max_pixels`.
+pub fn loaded_size(width: u32, height: u32, options: &LoadOptions) -> (u32, u32) {
+    let (width, height) = options.standard_size.map_or((width, height), |size| scaled_size(width, height, size, options.scale_mode));
+    match options.max_pixels {
+        Some(max) if width as u64 * height as u64 > max => {
+            let factor = (max as f64 / (width as f64 * height as f64)).sqrt();
+            (((width as f64 * factor) as u32).max(1), ((height as f64 * factor) as u32).max(1))
+        }
+        _ => (width, height),
+    }
 }
 
-fn scale_to_standard_width(
-    img: &DynamicImage,
-    standard_width: Option<u32>,
-) -> DynamicImage {
-    if let Some(width) = standard_width {
-        let (current_width, current_height) = img.dimensions();
-        let new_height = (width as f64 / current_width as f64 * current_height as f64) as u32;
-        let rgba_img = img.to_rgba8();
-        let resized = resize(&rgba_img, width, new_height, FilterType::Lanczos3);
-        DynamicImage::ImageRgba8(resized)
+fn scale_to_standard_size(img: &DynamicImage, options: &LoadOptions) -> DynamicImage {
+    let (current_width, current_height) = img.dimensions();
+    let (new_width, new_height) = loaded_size(current_width, current_height, options);
+    // 16-bit and tonemapped HDR images keep their precision until they are drawn
+    if img.color().bytes_per_pixel() > img.color().channel_count() {
+        let deep = img.to_rgba16();
+        if (new_width, new_height) == (current_width, current_height) {
+            return DynamicImage::ImageRgba16(deep);
+        }
+        return DynamicImage::ImageRgba16(imageops::resize(&deep, new_width, new_height, FilterType::Lanczos3));
+    }
+    if options.standard_size.is_some() || (new_width, new_height) != (current_width, current_height) {
+        DynamicImage::ImageRgba8(resize_rgba(&img.to_rgba8(), new_width, new_height))
     } else {
         img.to_rgba8().into()
     }
 }
+
+/// Reads the caption for the image at `path`. Sidecar captions fall back to the filename
+/// when neither `<name>.txt` nor `<name>.json` exists next to the photo.
+pub fn read_caption(path: &Path, source: LabelSource) -> Option<String> {
+    match source {
+        LabelSource::Filename => file_stem(path),
+        LabelSource::ExifDate => read_exif_date(path),
+        LabelSource::Sidecar => read_sidecar_caption(path).or_else(|| file_stem(path)),
+    }
+}
+
+fn file_stem(path: &Path) -> Option<String> {
+    path.file_stem().map(|s| s.to_string_lossy().into_owned())
+}
+
+fn read_exif_date(path: &Path) -> Option<String> {
+    exif_date(&read_source(path).ok()?)
+}
+
+/// EXIF orientation tag: 1 for upright pixels, 5 to 8 when width and height are swapped.
+fn exif_orientation(bytes: &[u8]) -> Option<u32> {
+    let exif = exif::Reader::new().read_from_container(&mut Cursor::new(bytes)).ok()?;
+    exif.get_field(exif::Tag::Orientation, exif::In::PRIMARY)?.value.get_uint(0)
+}
+
+/// The date a photo was taken as `YYYY-MM-DD`, from DateTimeOriginal or DateTime.
+fn exif_date(bytes: &[u8]) -> Option<String> {
+    let exif = exif::Reader::new().read_from_container(&mut Cursor::new(bytes)).ok()?;
+    let field = exif
+        .get_field(exif::Tag::DateTimeOriginal, exif::In::PRIMARY)
+        .or_else(|| exif.get_field(exif::Tag::DateTime, exif::In::PRIMARY))?;
+    match &field.value {
+        exif::Value::Ascii(values) if !values.is_empty() => {
+            let date = exif::DateTime::from_ascii(&values[0]).ok()?;
+            Some(format!("{:04}-{:02}-{:02}", date.year, date.month, date.day))
+        }
+        _ => None,
+    }
+}
+
+/// Exposure time, aperture, ISO and focal length of a photo from its EXIF data, e.g.
+/// `1/250 s  f/5.6  ISO 200  35 mm`; None if it records none of them.
+pub fn read_exposure(path: &Path) -> Option<String> {
+    let bytes = read_source(path).ok()?;
+    let exif = exif::Reader::new().read_from_container(&mut Cursor::new(&bytes)).ok()?;
+    let field = |tag| exif.get_field(tag, exif::In::PRIMARY);
+    let mut parts = Vec::new();
+    if let Some(time) = field(exif::Tag::ExposureTime) {
+        parts.push(time.display_value().with_unit(&exif).to_string());
+    }
+    if let Some(aperture) = field(exif::Tag::FNumber) {
+        parts.push(format!("f/{}", aperture.display_value()));
+    }
+    if let Some(iso) = field(exif::Tag::PhotographicSensitivity) {
+        parts.push(format!("ISO {}", iso.display_value()));
+    }
+    if let Some(focal_length) = field(exif::Tag::FocalLength) {
+        parts.push(focal_length.display_value().with_unit(&exif).to_string());
+    }
+    (!parts.is_empty()).then(|| parts.join("  "))
+}
+
+fn read_sidecar_caption(path: &Path) -> Option<String> {
+    if let Ok(text) = fs::read_to_string(path.with_extension("txt")) {
+        let text = text.trim();
+        if !text.is_empty() {
+            return Some(text.lines().next().unwrap_or(text).to_string());
+        }
+    }
+    let json = fs::read_to_string(path.with_extension("json")).ok()?;
+    let value: serde_json::Value = serde_json::from_str(&json).ok()?;
+    ["caption", "title", "description"]
+        .iter()
+        .find_map(|key| value.get(key).and_then(|v| v.as_str()))
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+}
+
+/// Star rating (0-5) from an XMP sidecar (`photo.xmp` or `photo.jpg.xmp`, as written by
+/// Lightroom, darktable and digiKam), from XMP embedded in the file, or from the EXIF rating tag
+/// written by Windows. Rejected photos (-1) count as 0 stars.
+pub fn read_rating(path: &Path) -> Option<u8> {
+    let mut sidecar = path.as_os_str().to_owned();
+    sidecar.push(".xmp");
+    [path.with_extension("xmp"), PathBuf::from(sidecar)]
+        .iter()
+        .find_map(|sidecar| fs::read(sidecar).ok().and_then(|xmp| xmp_rating(&xmp)))
+        .or_else(|| {
+            let bytes = read_source(path).ok()?;
+            xmp_rating(&bytes).or_else(|| exif_rating(&bytes))
+        })
+}
+
+/// Finds `xmp:Rating="N"` or `<xmp:Rating>N</xmp:Rating>` in an XMP packet or a whole file.
+fn xmp_rating(bytes: &[u8]) -> Option<u8> {
+    const KEY: &[u8] = b"xmp:Rating";
+    let start = bytes.windows(KEY.len()).position(|window| window == KEY)? + KEY.len();
+    let value: String = bytes[start..]
+        .iter()
+        .skip_while(|&&b| b == b'=' || b == b'"' || b == b'\'' || b == b'>' || b.is_ascii_whitespace())
+        .take_while(|&&b| b == b'-' || b.is_ascii_digit())
+        .map(|&b| b as char)
+        .collect();
+    value.parse::<i32>().ok().map(|rating| rating.clamp(0, 5) as u8)
+}
+
+fn exif_rating(bytes: &[u8]) -> Option<u8> {
+    // Rating tag of Windows Photo Gallery, not part of the EXIF standard
+    const RATING: exif::Tag = exif::Tag(exif::Context::Tiff, 0x4746);
+    let exif = exif::Reader::new().read_from_container(&mut Cursor::new(bytes)).ok()?;
+    let rating = exif.get_field(RATING, exif::In::PRIMARY)?.value.get_uint(0)?;
+    Some(rating.min(5) as u8)
+}
@@ -0,0 +1,222 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+use image::io::Reader;
+use rayon::prelude::*;
+use serde_json::{json, Value};
+
+use crate::archive::{for_each_entry, is_archive};
+use crate::image_handling::{is_hidden, loaded_size, LoadOptions};
+
+/// Upper bounds in megapixels of the resolution classes.
+const RESOLUTION_CLASSES: &[(&str, f64)] =
+    &[("< 1 MP", 1.0), ("1-4 MP", 4.0), ("4-12 MP", 12.0), ("12-24 MP", 24.0), (">= 24 MP", f64::INFINITY)];
+
+/// Upper bounds of the aspect ratio (width / height) classes.
+const ASPECT_CLASSES: &[(&str, f64)] = &[
+    ("tall (< 1:2)", 0.5),
+    ("portrait", 0.8),
+    ("square-ish", 1.25),
+    ("landscape", 2.0),
+    ("panorama (> 2:1)", f64::INFINITY),
+];
+
+/// Loaded images are kept as 8-bit RGBA.
+pub const BYTES_PER_PIXEL: f64 = 4.0;
+
+/// Rendering holds the background, the overlay and the tiles of the collage, and the flattened
+/// copy, each about the size of the canvas.
+pub const RENDER_BUFFERS: f64 = 4.0;
+
+/// A memory budget never scales images below about 100x100 pixels.
+const MIN_PIXELS: u64 = 100 * 100;
+
+/// What the files of the input directories look like, read from the image headers only.
+pub struct InputStats {
+    pub files_by_extension: BTreeMap<String, usize>,
+    /// Pixel size of every file whose header could be read.
+    pub sizes: Vec<(u32, u32)>,
+    /// Standard width the estimate of the memory use assumes (`-w`).
+    pub standard_width: Option<u32>,
+}
+
+/// Reads the headers of all files in the directories or archives.
+pub fn inspect(dirs: &[String], standard_width: Option<u32>) -> InputStats {
+    let mut files_by_extension = BTreeMap::new();
+    let mut sizes = Vec::new();
+    for dir in dirs {
+        let path = Path::new(dir);
+        let files: Vec<(PathBuf, Option<(u32, u32)>)> = if is_archive(path) {
+            let mut files = Vec::new();
+            let result = for_each_entry(path, |name, bytes| {
+                let size = Reader::new(Cursor::new(bytes)).with_guessed_format().ok().and_then(|r| r.into_dimensions().ok());
+                files.push((name, size));
+            });
+            if let Err(e) = result {
+                eprintln!("{}", e);
+            }
+            files
+        } else {
+            let entries = match fs::read_dir(path) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    eprintln!("Error reading directory {}: {}", dir, e);
+                    continue;
+                }
+            };
+            let paths: Vec<PathBuf> = entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.is_file() && !is_hidden(path))
+                .collect();
+            paths
+                .into_par_iter()
+                .map(|path| {
+                    let size = image::image_dimensions(&path).ok();
+                    (path, size)
+                })
+                .collect()
+        };
+        for (file, size) in files {
+            let extension = file.extension().map_or_else(|| "(none)".to_string(), |ext| ext.to_string_lossy().to_ascii_lowercase());
+            *files_by_extension.entry(extension).or_insert(0) += 1;
+            sizes.extend(size);
+        }
+    }
+    InputStats { files_by_extension, sizes, standard_width }
+}
+
+impl InputStats {
+    fn files(&self) -> usize {
+        self.files_by_extension.values().sum()
+    }
+
+    fn megapixels(&self) -> f64 {
+        self.sizes.iter().map(|&(w, h)| w as f64 * h as f64 / 1e6).sum()
+    }
+
+    fn resolution_histogram(&self) -> Vec<(&'static str, usize)> {
+        histogram(RESOLUTION_CLASSES, self.sizes.iter().map(|&(w, h)| w as f64 * h as f64 / 1e6))
+    }
+
+    fn aspect_histogram(&self) -> Vec<(&'static str, usize)> {
+        histogram(ASPECT_CLASSES, self.sizes.iter().map(|&(w, h)| w as f64 / h.max(1) as f64))
+    }
+
+    fn memory_estimate(&self) -> (f64, f64) {
+        memory_estimate(&self.sizes, &LoadOptions { standard_size: self.standard_width, ..Default::default() })
+    }
+
+    pub fn print(&self) {
+        info!("Files: {}", self.files());
+        for (extension, count) in &self.files_by_extension {
+            info!("  {:<18} {:>6}", extension, count);
+        }
+        info!("Readable images: {} ({} files without a readable image header)", self.sizes.len(), self.files() - self.sizes.len());
+        info!("Resolution:");
+        for (class, count) in self.resolution_histogram() {
+            info!("  {:<18} {:>6}", class, count);
+        }
+        info!("Aspect ratio:");
+        for (class, count) in self.aspect_histogram() {
+            info!("  {:<18} {:>6}", class, count);
+        }
+        info!("Total: {:.1} megapixels", self.megapixels());
+        let (loaded, decoding) = self.memory_estimate();
+        let scaling = match self.standard_width {
+            Some(width) => format!("scaled to a width of {} px", width),
+            None => "at full size, use -w to estimate scaled images".to_string(),
+        };
+        info!("Estimated memory: {:.0} MB for the loaded images ({}), plus {:.0} MB while decoding the largest file", loaded, scaling, decoding);
+    }
+
+    pub fn to_json(&self) -> Value {
+        let classes = |histogram: Vec<(&str, usize)>| -> Value {
+            histogram.into_iter().map(|(class, count)| json!({ "class": class, "count": count })).collect()
+        };
+        let (loaded, decoding) = self.memory_estimate();
+        json!({
+            "files": self.files(),
+            "files_by_extension": self.files_by_extension,
+            "readable_images": self.sizes.len(),
+            "resolution": classes(self.resolution_histogram()),
+            "aspect_ratio": classes(self.aspect_histogram()),
+            "megapixels": self.megapixels(),
+            "standard_width": self.standard_width,
+            "estimated_memory_mb": loaded,
+            "decoding_memory_mb": decoding,
+        })
+    }
+}
+
+/// Estimated memory in MB for images of the given sizes once loaded, which are kept for the whole
+/// run, and for decoding the largest file on top of them.
+pub fn memory_estimate(sizes: &[(u32, u32)], options: &LoadOptions) -> (f64, f64) {
+    let loaded: f64 = sizes
+        .iter()
+        .map(|&(w, h)| {
+            let (w, h) = loaded_size(w, h, options);
+            w as f64 * h as f64
+        })
+        .sum();
+    let largest = sizes.iter().map(|&(w, h)| w as f64 * h as f64).fold(0.0, f64::max);
+    (loaded * BYTES_PER_PIXEL / 1e6, largest * BYTES_PER_PIXEL / 1e6)
+}
+
+/// Estimated peak memory in MB of a run over images of the given sizes: the loaded images plus
+/// the larger of decoding the largest file on every worker thread and rendering a collage of
+/// `share` of them.
+pub fn peak_memory(sizes: &[(u32, u32)], options: &LoadOptions, share: f64) -> f64 {
+    let (loaded, decoding) = memory_estimate(sizes, options);
+    loaded + (decoding * rayon::current_num_threads() as f64).max(loaded * share * RENDER_BUFFERS)
+}
+
+/// The largest number of pixels per loaded image (`LoadOptions::max_pixels`) that keeps the
+/// estimated peak memory under `budget` MB, or `None` if the images fit as they are. Decoding
+/// happens at full size, so no limit helps when decoding alone exceeds the budget.
+pub fn pixel_limit(sizes: &[(u32, u32)], options: &LoadOptions, share: f64, budget: f64) -> Option<u64> {
+    let peak = |max_pixels| peak_memory(sizes, &LoadOptions { max_pixels, ..options.clone() }, share);
+    if peak(options.max_pixels) <= budget {
+        return options.max_pixels;
+    }
+    // The peak grows with the limit, so search for the largest limit that still fits
+    let high = sizes.iter().map(|&(w, h)| w as u64 * h as u64).max().unwrap_or(1);
+    let (mut low, mut high) = (MIN_PIXELS.min(high), high);
+    while low < high {
+        let middle = low + (high - low).div_ceil(2);
+        if peak(Some(middle)) <= budget {
+            low = middle;
+        } else {
+            high = middle - 1;
+        }
+    }
+    Some(low)
+}
+
+/// Lowers the resolution the images of the given sizes are loaded at until the estimated peak
+/// memory of a run selecting up to `max_images` of them fits in `budget` bytes (`--max-memory`).
+pub fn fit_memory_budget(sizes: &[(u32, u32)], options: &LoadOptions, max_images: usize, budget: u64) -> LoadOptions {
+    let share = (max_images as f64 / sizes.len().max(1) as f64).min(1.0);
+    let budget = budget as f64 / 1e6;
+    let options = LoadOptions { max_pixels: pixel_limit(sizes, options, share, budget), ..options.clone() };
+    if let Some(max_pixels) = options.max_pixels {
+        info!("Memory budget of {:.0} MB: loading images at up to {:.2} megapixels.", budget, max_pixels as f64 / 1e6);
+    }
+    let peak = peak_memory(sizes, &options, share);
+    if peak > budget {
+        eprintln!("Warning: the estimated peak memory of {:.0} MB still exceeds the budget of {:.0} MB.", peak, budget);
+    }
+    options
+}
+
+/// Counts the values falling into each class, given by its upper bound.
+fn histogram(classes: &[(&'static str, f64)], values: impl Iterator<Item = f64>) -> Vec<(&'static str, usize)> {
+    let mut counts = vec![0; classes.len()];
+    for value in values {
+        if let Some(index) = classes.iter().position(|(_, bound)| value < *bound) {
+            counts[index] += 1;
+        }
+    }
+    classes.iter().map(|(class, _)| *class).zip(counts).collect()
+}
@@ -0,0 +1,255 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use image::{DynamicImage, GenericImageView};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::packing::{gene_footprint, ImageGene, LayoutKind, PackedLayout, PackingOptions};
+use crate::pile::{salient_focus, saliency_grid};
+
+/// Final arrangement of a collage: everything needed to render it again or to audit which
+/// photos were used. Coordinates are canvas pixels, excluding any title banner.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Layout {
+    pub width: u32,
+    pub height: u32,
+    pub images: Vec<PlacedImage>,
+    /// Whether the images may overlap, as in a pile; they are listed from the bottom to the top.
+    #[serde(default)]
+    pub overlapping: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PlacedImage {
+    /// Content id of the image, the same in every run, see `image_handling::content_id`.
+    pub id: u32,
+    #[serde(with = "os_path")]
+    pub path: PathBuf,
+    /// Footprint of the image on the canvas, including room for its caption and rotation.
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+    /// Rotation in degrees, clockwise.
+    pub rotation: f64,
+    /// Drawn size relative to the pixel size of the source file.
+    pub scale: f64,
+    pub source_width: u32,
+    pub source_height: u32,
+    /// Outline the image is cropped to; layouts written before shapes existed hold rectangles.
+    #[serde(default)]
+    pub shape: Shape,
+    /// Point of the image the crop of a cropped shape is centered on, as a share of its width and
+    /// height; the most salient part of the image that fits the footprint.
+    #[serde(default = "centered")]
+    pub focus: (f64, f64),
+}
+
+/// Focus on the center of the image, for layouts written before the focus existed.
+fn centered() -> (f64, f64) {
+    (0.5, 0.5)
+}
+
+/// Outline of an image on the canvas.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Shape {
+    /// The whole image, scaled to fit its footprint.
+    #[default]
+    Rectangle,
+    /// The hexagon with pointy top and bottom that fills the footprint; the image is scaled to
+    /// cover the footprint and cropped to it.
+    Hexagon,
+    /// The whole footprint; the image is scaled to cover it and cropped to it.
+    Fill,
+}
+
+impl PlacedImage {
+    /// Size of the image itself on the canvas, before captions and rotation.
+    pub fn drawn_size(&self) -> (u32, u32) {
+        let width = (self.source_width as f64 * self.scale).round().max(1.0) as u32;
+        let height = (self.source_height as f64 * self.scale).round().max(1.0) as u32;
+        (width, height)
+    }
+
+    /// Size of what is visible of the image on the canvas: the drawn image, or the footprint it
+    /// is cropped to.
+    pub fn visible_size(&self) -> (u32, u32) {
+        match self.shape {
+            Shape::Rectangle => self.drawn_size(),
+            Shape::Hexagon | Shape::Fill => (self.width.max(0) as u32, self.height.max(0) as u32),
+        }
+    }
+
+    /// Top-left corner of the part of the drawn image that a cropped shape keeps, in pixels of the
+    /// drawn image: the footprint centered on the focus, as far as the image reaches.
+    pub fn crop_origin(&self) -> (u32, u32) {
+        let (drawn_width, drawn_height) = self.drawn_size();
+        let origin = |drawn: u32, size: i32, focus: f64| {
+            let slack = drawn.saturating_sub(size.max(0) as u32);
+            ((focus * drawn as f64 - size as f64 / 2.0).round().max(0.0) as u32).min(slack)
+        };
+        (origin(drawn_width, self.width, self.focus.0), origin(drawn_height, self.height, self.focus.1))
+    }
+
+    /// Corners of the visible part of the image on the canvas, clockwise from the top, or `None`
+    /// when the whole drawn image is visible.
+    pub fn clip_polygon(&self) -> Option<Vec<(f64, f64)>> {
+        let (x, y, w, h) = (self.x as f64, self.y as f64, self.width as f64, self.height as f64);
+        match self.shape {
+            Shape::Rectangle => None,
+            Shape::Fill => Some(vec![(x, y), (x + w, y), (x + w, y + h), (x, y + h)]),
+            Shape::Hexagon => Some(vec![
+                (x + w / 2.0, y),
+                (x + w, y + h / 4.0),
+                (x + w, y + h * 3.0 / 4.0),
+                (x + w / 2.0, y + h),
+                (x, y + h * 3.0 / 4.0),
+                (x, y + h / 4.0),
+            ]),
+        }
+    }
+}
+
+impl Layout {
+    /// Turns packed rectangles into a layout: centers the packed area on the canvas, takes the
+    /// rotation of every image from its gene or draws a random one, and records its scale relative
+    /// to the source file.
+    pub fn build(
+        packed: &PackedLayout,
+        genes: &HashMap<u32, ImageGene>,
+        image_map: &HashMap<u32, DynamicImage>,
+        image_paths: &HashMap<u32, PathBuf>,
+        packing: &PackingOptions,
+        rng: &mut impl Rng,
+    ) -> Layout {
+        let (packed_locations, max_width, max_height) = (&packed.0, packed.1, packed.2);
+        let mut min_x = u32::MAX;
+        let mut min_y = u32::MAX;
+        let mut max_x = 0;
+        let mut max_y = 0;
+
+        let rects = packed_locations.iter().map(|(_, rect)| rect).chain(&packing.reserved);
+        for rect in rects {
+            min_x = min_x.min(rect.x as u32);
+            min_y = min_y.min(rect.y as u32);
+            max_x = max_x.max(rect.right() as u32);
+            max_y = max_y.max(rect.bottom() as u32);
+        }
+
+        let bounding_width = max_x.saturating_sub(min_x);
+        let bounding_height = max_y.saturating_sub(min_y);
+        let offset_x = (max_width.saturating_sub(bounding_width)) / 2;
+        let offset_y = (max_height.saturating_sub(bounding_height)) / 2;
+
+        let mut images = Vec::new();
+        for (id, rect) in packed_locations {
+            let Some(img) = image_map.get(id) else { continue };
+            let path = image_paths.get(id).cloned().unwrap_or_default();
+            let (loaded_width, loaded_height) = img.dimensions();
+            let (source_width, source_height) = image::image_dimensions(&path).unwrap_or((loaded_width, loaded_height));
+
+            // Images packed into a footprint of another size (gap-filling thumbnails, strips) are
+            // scaled to fit it; cropped shapes cover it
+            let gene = genes.get(id).copied().unwrap_or_default();
+            let (footprint_w, footprint_h) = gene_footprint(img, &gene, packing);
+            let (fit_w, fit_h) = (rect.width as f64 / footprint_w as f64, rect.height as f64 / footprint_h as f64);
+            let shape = packing.layout.shape();
+            let fit = if shape == Shape::Rectangle { fit_w.min(fit_h) } else { fit_w.max(fit_h) };
+            // Cropped shapes keep the part of the image where the eye goes, not just its center
+            let focus = if shape == Shape::Rectangle { centered() } else { salient_focus(&saliency_grid(img), (fit_w / fit, fit_h / fit)) };
+            let rotation = match gene.turn {
+                _ if packing.max_rotation == 0.0 => 0.0,
+                Some(turn) => turn.clamp(-1.0, 1.0) * packing.max_rotation,
+                None => rng.gen_range(-packing.max_rotation..=packing.max_rotation),
+            };
+
+            images.push(PlacedImage {
+                id: *id,
+                path: fs::canonicalize(&path).unwrap_or(path),
+                x: (offset_x + (rect.x as u32 - min_x)) as i32,
+                y: (offset_y + (rect.y as u32 - min_y)) as i32,
+                width: rect.width,
+                height: rect.height,
+                rotation,
+                scale: loaded_width as f64 * gene.scale * fit / source_width as f64,
+                source_width,
+                source_height,
+                shape,
+                focus,
+            });
+        }
+
+        Layout { width: max_width, height: max_height, images, overlapping: packing.layout == LayoutKind::Pile }
+    }
+
+    pub fn load(path: &Path) -> Result<Layout, String> {
+        let json = fs::read_to_string(path).map_err(|e| format!("Error reading {}: {}", path.display(), e))?;
+        serde_json::from_str(&json).map_err(|e| format!("Error parsing {}: {}", path.display(), e))
+    }
+
+    /// Returns the layout enlarged (or shrunk) by `factor`, e.g. to render a print version.
+    pub fn scaled(&self, factor: f64) -> Layout {
+        let scale = |v: i32| (v as f64 * factor).round() as i32;
+        Layout {
+            width: (self.width as f64 * factor).round() as u32,
+            height: (self.height as f64 * factor).round() as u32,
+            images: self
+                .images
+                .iter()
+                .map(|placed| PlacedImage {
+                    x: scale(placed.x),
+                    y: scale(placed.y),
+                    width: scale(placed.width),
+                    height: scale(placed.height),
+                    scale: placed.scale * factor,
+                    ..placed.clone()
+                })
+                .collect(),
+            overlapping: self.overlapping,
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        fs::write(path, json).map_err(|e| format!("Error writing {}: {}", path.display(), e))
+    }
+}
+
+/// Paths are stored as strings, or on Unix as their raw bytes when they are not valid UTF-8, so
+/// that files with such names can be rendered again.
+mod os_path {
+    use std::path::{Path, PathBuf};
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(untagged)]
+    enum Stored {
+        Text(String),
+        Bytes(Vec<u8>),
+    }
+
+    pub fn serialize<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+        match path.to_str() {
+            Some(text) => Stored::Text(text.to_string()),
+            #[cfg(unix)]
+            None => Stored::Bytes(std::os::unix::ffi::OsStrExt::as_bytes(path.as_os_str()).to_vec()),
+            #[cfg(not(unix))]
+            None => Stored::Text(path.to_string_lossy().into_owned()),
+        }
+        .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<PathBuf, D::Error> {
+        Ok(match Stored::deserialize(deserializer)? {
+            Stored::Text(text) => PathBuf::from(text),
+            #[cfg(unix)]
+            Stored::Bytes(bytes) => PathBuf::from(<std::ffi::OsStr as std::os::unix::ffi::OsStrExt>::from_bytes(&bytes)),
+            #[cfg(not(unix))]
+            Stored::Bytes(bytes) => PathBuf::from(String::from_utf8_lossy(&bytes).into_owned()),
+        })
+    }
+}
@@ -0,0 +1,95 @@
+//! The optimizer behind the ImageGridOptimizer command line tool: loading, packing, the genetic
+//! algorithm and rendering. The command line front end lives in `main.rs`; the library lets the
+//! benchmarks in `benches/` and other front ends call the same code, e.g. `ga::run_ga` with a
+//! callback for its `ProgressEvent`s.
+//!
+//! Without the default `cli` feature, only the optimizer and the renderer are built, without
+//! filesystem inputs or threads of their own, so that the library compiles to wasm32; `wasm`
+//! takes the images as encoded bytes instead of paths.
+
+/// Prints a progress message, see `events::message`.
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if !$crate::events::silent() {
+            $crate::events::message(format!($($arg)*))
+        }
+    };
+}
+
+#[cfg(feature = "cli")]
+pub mod archive;
+#[cfg(feature = "cli")]
+pub mod bench;
+pub mod cancel;
+#[cfg(feature = "cli")]
+pub mod cli;
+#[cfg(feature = "cli")]
+pub mod file_list;
+#[cfg(feature = "gpu")]
+pub mod gpu;
+#[cfg(feature = "cli")]
+pub mod icc;
+#[cfg(feature = "cli")]
+pub mod image_cache;
+#[cfg(feature = "cli")]
+pub mod image_handling;
+#[cfg(feature = "cli")]
+pub mod inspect;
+pub mod ga;
+pub mod hex;
+pub mod packing;
+pub mod pile;
+pub mod golden;
+#[cfg(feature = "cli")]
+pub mod remote;
+#[cfg(feature = "cli")]
+pub mod report;
+#[cfg(feature = "cli")]
+pub mod retarget;
+#[cfg(feature = "cli")]
+pub mod samples;
+#[cfg(feature = "cli")]
+pub mod serve;
+pub mod collage;
+pub mod color;
+#[cfg(feature = "cli")]
+pub mod compare;
+#[cfg(feature = "cli")]
+pub mod config;
+#[cfg(feature = "cli")]
+pub mod contact_sheet;
+#[cfg(feature = "cli")]
+pub mod dashboard;
+#[cfg(feature = "cli")]
+pub mod dry_run;
+pub mod error;
+#[cfg(feature = "cli")]
+pub mod dedup;
+pub mod events;
+pub mod ffi;
+pub mod layout;
+#[cfg(feature = "cli")]
+pub mod metadata;
+#[cfg(feature = "cli")]
+pub mod ora;
+#[cfg(feature = "cli")]
+pub mod output;
+#[cfg(feature = "cli")]
+pub mod stats;
+pub mod spiral;
+pub mod strip;
+#[cfg(feature = "cli")]
+pub mod svg;
+pub mod text;
+#[cfg(feature = "cli")]
+pub mod timelapse;
+pub mod tone;
+#[cfg(feature = "cli")]
+pub mod tune;
+pub mod verify;
+pub mod wasm;
+#[cfg(feature = "cli")]
+pub mod video;
+#[cfg(feature = "cli")]
+pub mod watch;
@@ -1,107 +1,850 @@
 use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 
-mod cli;
-mod image_handling;
-mod ga;
-mod packing;
-mod collage;
-
-use crate::cli::parse_args;
-use crate::image_handling::load_images;
-use crate::ga::{create_random_individual, evaluate_individual, crossover, mutate, enforce_image_limits, Individual};
-use crate::collage::create_collage;
-use crate::packing::DESIRED_ASPECT_RATIO;
-use rand::seq::SliceRandom;
-use rand::Rng;
+use image_grid_optimizer::info;
+use image_grid_optimizer::cli::{parse_args, print_completions, Args, Command, CompareArgs, Input, PageCount, RenderArgs, VerifyArgs};
+use image_grid_optimizer::bench::bench;
+use image_grid_optimizer::cancel::cancel_on_ctrl_c;
+use image_grid_optimizer::inspect::{fit_memory_budget, inspect};
+use image_grid_optimizer::file_list::{download_entries, read_file_list, read_weights, write_file_list};
+use image_grid_optimizer::image_handling::{load_directories, load_list, open_image, read_caption, read_rating, retarget, scan_headers, LoadOptions, ScaleMode};
+use image_grid_optimizer::ga::{distinct_best, run_ga, GaSettings, ImageInfo, Individual, ProgressEvent};
+use image_grid_optimizer::collage::{can_stream, create_collage, create_collage_streamed, render_layers, BackgroundColor, RenderOptions};
+use image_grid_optimizer::color::dominant_color;
+use image_grid_optimizer::contact_sheet::contact_sheet;
+use image_grid_optimizer::compare::{diff_image, layout_metrics, shared_images};
+use image_grid_optimizer::dashboard::Dashboard;
+use image_grid_optimizer::dedup::drop_near_duplicates;
+use image_grid_optimizer::dry_run::dry_run;
+use image_grid_optimizer::error::Error;
+use image_grid_optimizer::events::emit;
+use image_grid_optimizer::layout::{Layout, PlacedImage};
+use image_grid_optimizer::pile::saliency_grid;
+use image_grid_optimizer::metadata::Provenance;
+use image_grid_optimizer::ora::save_ora;
+use image_grid_optimizer::output::{page_path, save_collage, save_pdf, suffixed_path, OutputOptions};
+use image_grid_optimizer::samples::generate_samples;
+use image_grid_optimizer::serve::serve;
+use image_grid_optimizer::report::{save_report, Report};
+use image_grid_optimizer::stats::{plot_fitness, save_stats_csv};
+use image_grid_optimizer::svg::save_svg;
+use image_grid_optimizer::timelapse::{Timelapse, FRAME_SIZE};
+use image_grid_optimizer::tune::tune;
+use image_grid_optimizer::verify::{check_geometry, check_sources};
+use image_grid_optimizer::watch::InputWatcher;
+use image_grid_optimizer::packing::{fill_gaps, fit_scale, LayoutKind, DESIRED_ASPECT_RATIO, PADDING_SIZE};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
 use rayon::prelude::*;
+use serde_json::json;
 
 fn main() {
-    let (dir, filter, standard_width, population_size, generations, min_images, max_images, mutation_rate, crossover_rate) = parse_args();
-    println!("Parameters:");
-    println!("Directory: {}", dir);
-    println!("Filter: {:?}", filter);
-    println!("Standard width: {:?}", standard_width);
-    println!("Population size: {}", population_size);
-    println!("Generations: {}", generations);
-    println!("min_images: {}", min_images);
-    println!("max_images: {}", max_images);
-    println!("Mutation rate: {}", mutation_rate);
-    println!("Crossover rate: {}", crossover_rate);
-    println!("Desired aspect ratio: {}", DESIRED_ASPECT_RATIO);
-
-    println!("Loading images...");
-    let images_vec = load_images(&dir, filter, standard_width);
-    if images_vec.is_empty() {
-        eprintln!("No images loaded.");
-        return;
+    let command = parse_args();
+    if matches!(&command, Command::Optimize(args) if args.json) {
+        image_grid_optimizer::events::enable();
     }
+    match command {
+        Command::Optimize(args) if args.dry_run => dry_run(&args),
+        Command::Optimize(args) if args.watch => watch_inputs(&args),
+        Command::Optimize(args) => {
+            // The first Ctrl+C stops the GA and saves the best collage so far
+            if let Err(e) = cancel_on_ctrl_c(&args.ga.cancel) {
+                eprintln!("{}", e);
+            }
+            if let Err(e) = optimize(&args, &[]) {
+                eprintln!("{}", e);
+                std::process::exit(e.exit_code());
+            }
+        }
+        Command::Render(args) => {
+            if let Err(e) = render_layout(*args) {
+                eprintln!("{}", e);
+                std::process::exit(e.exit_code());
+            }
+        }
+        Command::ContactSheet(args) => {
+            if let Err(e) = contact_sheet(&args) {
+                eprintln!("{}", e);
+                std::process::exit(e.exit_code());
+            }
+        }
+        Command::Compare(args) => {
+            if let Err(e) = compare_layouts(args) {
+                eprintln!("{}", e);
+                std::process::exit(e.exit_code());
+            }
+        }
+        Command::Bench(args) => bench(&args),
+        Command::Tune(args) => {
+            if let Err(e) = tune(&args) {
+                eprintln!("{}", e);
+                std::process::exit(e.exit_code());
+            }
+        }
+        Command::GenerateSamples(args) => {
+            if let Err(e) = generate_samples(&args) {
+                eprintln!("{}", e);
+                std::process::exit(e.exit_code());
+            }
+        }
+        Command::Serve(args) => {
+            if let Err(e) = serve(&args, |args| optimize(args, &[]).map(|_| ())) {
+                eprintln!("{}", e);
+                std::process::exit(e.exit_code());
+            }
+        }
+        Command::Completions(shell) => print_completions(shell),
+        Command::Inspect(args) => {
+            let stats = inspect(&args.dirs, args.standard_width);
+            if args.json {
+                println!("{}", serde_json::to_string_pretty(&stats.to_json()).unwrap_or_default());
+            } else {
+                stats.print();
+            }
+        }
+        Command::Verify(args) => {
+            if !verify_layout(args) {
+                std::process::exit(1);
+            }
+        }
+    }
+}
 
-    let image_map: HashMap<u32, image::DynamicImage> = images_vec.into_iter().collect();
-    let mut rng = rand::thread_rng();
+/// Optimizes, then optimizes again and overwrites the output whenever the input directories change.
+fn watch_inputs(args: &Args) {
+    let dirs: Vec<PathBuf> = match &args.input {
+        Input::Directories(dirs) => dirs.iter().map(PathBuf::from).collect(),
+        Input::List(_) => Vec::new(),
+    };
+    // Watching starts before the first run so that no change is missed while optimizing
+    let watcher = match InputWatcher::new(&dirs) {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+    let mut outputs = vec![
+        args.output.clone(),
+        args.output.with_file_name("layout.json"),
+        args.output.with_file_name("best_gen.jpg"),
+        args.output.with_file_name("used_images.txt"),
+        args.output.with_file_name("unused_images.txt"),
+    ];
+    outputs.extend(
+        [&args.export_svg, &args.export_ora, &args.combined_pdf, &args.stats_out, &args.plot, &args.report, &args.preview, &args.evolution_gif]
+            .into_iter()
+            .flatten()
+            .cloned(),
+    );
 
-    let all_images = image_map.iter().map(|(id,i)|(id.clone(),i.clone())).collect::<Vec<_>>();
-    let mut population: Vec<Individual> = (0..population_size)
-        .map(|_| create_random_individual(&all_images, min_images, max_images, &mut rng))
-        .collect();
+    // A failed run does not end watching, the next change may fix it
+    let run = |previous: &[PathBuf]| {
+        optimize(args, previous).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            Vec::new()
+        })
+    };
+    let mut used = run(&[]);
+    loop {
+        info!("Watching {} for changes (press Ctrl+C to stop)...", dirs.iter().map(|d| d.display().to_string()).collect::<Vec<_>>().join(", "));
+        if let Err(e) = watcher.wait_for_change(&outputs) {
+            eprintln!("{}", e);
+            return;
+        }
+        info!("Inputs changed, optimizing again...");
+        let previous = if args.warm_start { used } else { Vec::new() };
+        used = run(&previous);
+    }
+}
 
-    // Evaluate initial population in parallel
-    population.par_iter_mut().for_each(|indiv| {
-        evaluate_individual(indiv, &image_map);
-    });
+/// Runs the optimization and saves the results. `previous` lists images from an earlier run that
+/// seed the first generation. Returns the images used in the collage.
+fn optimize(args: &Args, previous: &[PathBuf]) -> Result<Vec<PathBuf>, Error> {
+    let &Args { ref input, ref load, max_memory, ref download, color_flow, ref weights, rating_bonus, pin_rating, dedup_threshold, ref ga, seed, pages, ref combined_pdf, label_source, gap_fill, ref export_svg, svg_embed, ref export_ora, ref stats_out, ref plot, ref report, save_every, ref preview, ref evolution_gif, evolution_every, tui, top_n, restarts, ref output, ref packing, ref render, ref output_options, .. } = args;
+    let started = Instant::now();
+    info!("Parameters:");
+    match &input {
+        Input::Directories(dirs) => info!("Directories: {}", dirs.join(", ")),
+        Input::List(list) => info!("File list: {}", list),
+    }
+    info!("Filter: {:?}", load.filter);
+    if let Some(regex) = &load.filter_regex {
+        info!("Filter regex: {}", regex);
+    }
+    info!("Standard size: {:?} ({:?})", load.standard_size, load.scale_mode);
+    info!("Population size: {}", ga.population_size);
+    info!("Generations: {}", ga.generations);
+    info!("min_images: {}", ga.min_images);
+    info!("max_images: {}", ga.max_images);
+    info!("Mutation rate: {}", ga.mutation_rate);
+    info!("Crossover rate: {}", ga.crossover_rate);
+    info!("Per-directory minimum: {}", ga.per_dir_min);
+    if let Some(max) = ga.per_dir_max {
+        info!("Per-directory maximum: {}", max);
+    }
+    match packing.layout {
+        LayoutKind::Packed => info!("Desired aspect ratio: {}", DESIRED_ASPECT_RATIO),
+        LayoutKind::Strip => info!("Layout: {:?} strip in {} rows", packing.strip.direction, packing.strip.rows),
+        LayoutKind::Hex => info!("Layout: hexagonal grid"),
+        LayoutKind::Spiral => info!("Layout: spiral around a hero image {}x its size", packing.spiral.hero_scale),
+        LayoutKind::Pile => info!("Layout: pile overlapping by up to {}%", packing.max_overlap * 100.0),
+        LayoutKind::Golden => info!("Layout: golden-ratio splits"),
+    }
 
-    // GA main loop
-    for gen in 1..=generations {
-        population.sort_by(|a,b| b.fitness.partial_cmp(&a.fitness).unwrap());
-        println!("Generation {}: Best fitness = {:.5}", gen, population[0].fitness);
+    // Read before loading, so that a wrong path fails fast
+    let seed_layout = args.seed_layout.as_deref().map(Layout::load).transpose().map_err(Error::ReadInput)?;
 
-        let half = population_size/2;
-        let elites = &population[..half];
+    info!("Loading images...");
+    let entries = match &input {
+        Input::Directories(_) => Vec::new(),
+        Input::List(list) => download_entries(read_file_list(list).map_err(Error::ReadInput)?, download),
+    };
+    // The budget is checked on the image headers before any image is decoded
+    let budgeted;
+    let load = match max_memory {
+        Some(budget) => {
+            let inputs: Vec<PathBuf> = match &input {
+                Input::Directories(dirs) => dirs.iter().map(PathBuf::from).collect(),
+                Input::List(_) => entries.iter().map(|entry| entry.path.clone()).collect(),
+            };
+            budgeted = fit_memory_budget(&scan_headers(&inputs, load).sizes, load, ga.max_images, budget);
+            &budgeted
+        }
+        None => load,
+    };
+    let mut info = ImageInfo::default();
+    let (mut images_vec, list_sources) = match &input {
+        Input::Directories(dirs) => {
+            let (images, groups) = load_directories(dirs, load)?;
+            info.groups = groups;
+            (images, HashMap::new())
+        }
+        Input::List(_) => load_list(&entries, load)?,
+    };
+    if let Some(threshold) = dedup_threshold {
+        info!("Looking for near-duplicates...");
+        images_vec = drop_near_duplicates(images_vec, threshold);
+    }
+    if packing.min_tile > 0 {
+        images_vec.retain(|(_, path, img)| {
+            let keep = img.width().min(img.height()) >= packing.min_tile;
+            if !keep {
+                info!("Skipping image smaller than the minimum tile size ({}x{}): {}", img.width(), img.height(), path.display());
+            }
+            keep
+        });
+    }
+    if images_vec.is_empty() {
+        return Err(Error::NoImages);
+    }
+    emit(json!({ "event": "loaded", "images": images_vec.len() }));
+
+    // Captions from the file list take precedence over the --labels source
+    let captions: HashMap<u32, String> = match label_source {
+        Some(source) => images_vec
+            .iter()
+            .filter_map(|(id, path, _)| {
+                let listed = list_sources.get(id).and_then(|entry| entry.caption.clone());
+                listed.or_else(|| read_caption(path, source)).map(|caption| (*id, caption))
+            })
+            .collect(),
+        None => HashMap::new(),
+    };
+    info.weights = list_sources
+        .iter()
+        .filter_map(|(id, entry)| entry.weight.map(|weight| (*id, weight)))
+        .collect();
+    if let Some(strength) = color_flow {
+        info.color_flow = strength;
+        info.colors = images_vec.par_iter().map(|(id, _, img)| (*id, dominant_color(img))).collect();
+    }
+    if packing.layout == LayoutKind::Pile {
+        info.saliency = images_vec.par_iter().map(|(id, _, img)| (*id, saliency_grid(img))).collect();
+    }
+    if let Some(weights_path) = weights {
+        match read_weights(weights_path) {
+            Ok(named) => {
+                for (id, path, _) in &images_vec {
+                    let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+                    let weight = named.iter().find(|(key, _)| *key == name || path.ends_with(key)).map(|(_, weight)| *weight);
+                    // Weights from the file list win
+                    if let Some(weight) = weight {
+                        info.weights.entry(*id).or_insert(weight);
+                    }
+                }
+                info!("Weighted images: {}", info.weights.len());
+            }
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+    if let Some(bonus) = rating_bonus {
+        info.rating_bonus = bonus;
+        info.ratings = images_vec.iter().filter_map(|(id, path, _)| read_rating(path).map(|stars| (*id, stars))).collect();
+        if let Some(stars) = pin_rating {
+            info.required = info.ratings.iter().filter(|(_, rating)| **rating >= stars).map(|(id, _)| *id).collect();
+        }
+        info!("Rated images: {}, always included: {}", info.ratings.len(), info.required.len());
+        if info.required.len() > ga.max_images {
+            eprintln!("Warning: {} pinned images exceed --max-images {}", info.required.len(), ga.max_images);
+        }
+    }
+    let image_paths: HashMap<u32, PathBuf> = images_vec.iter().map(|(id, path, _)| (*id, path.clone())).collect();
+    let selected = |used: &[PathBuf]| {
+        let mut ids: Vec<u32> = image_paths.iter().filter(|(_, path)| used.contains(path)).map(|(id, _)| *id).collect();
+        ids.sort();
+        ids
+    };
+    let mut initial = vec![selected(previous)];
+    if let Some(layout) = &seed_layout {
+        // Ids follow the content, so they still match after the files moved; layouts store
+        // canonical paths
+        let canonical: HashMap<PathBuf, &PathBuf> =
+            image_paths.values().map(|path| (fs::canonicalize(path).unwrap_or_else(|_| path.clone()), path)).collect();
+        let used: Vec<PathBuf> = layout
+            .images
+            .iter()
+            .filter_map(|placed| image_paths.get(&placed.id).or_else(|| canonical.get(&placed.path).copied()))
+            .cloned()
+            .collect();
+        info!("Seed layout: {} of its {} images found", used.len(), layout.images.len());
+        initial.push(selected(&used));
+    }
+    initial.retain(|ids| !ids.is_empty());
+    let image_map: HashMap<u32, image::DynamicImage> = images_vec.into_iter().map(|(id, _, img)| (id, img)).collect();
+    let mut packing = packing.for_images(&image_map);
+    if packing.layout == LayoutKind::Spiral {
+        // The images of a spiral are as large as they count in the fitness
+        packing.spiral.weights = image_map.keys().map(|id| (*id, info.weight(*id))).collect();
+    }
+    let packing = &packing;
+    let loading_time = started.elapsed();
+    let seed = seed.unwrap_or_else(rand::random);
+    info!("Seed: {}", seed);
+    let mut rng = StdRng::seed_from_u64(seed);
 
-        let mut new_population = Vec::new();
-        // Keep elites
-        new_population.extend_from_slice(elites);
+    // Sorted so that the same seed gives the same collage
+    let mut all_images = image_map.iter().map(|(id,i)|(*id,i.clone())).collect::<Vec<_>>();
+    all_images.sort_by_key(|(id, _)| *id);
+    let mut parameters = vec![
+        ("PopulationSize", ga.population_size.to_string()),
+        ("Generations", ga.generations.to_string()),
+        ("MinImages", ga.min_images.to_string()),
+        ("MaxImages", ga.max_images.to_string()),
+        ("MutationRate", ga.mutation_rate.to_string()),
+        ("CrossoverRate", ga.crossover_rate.to_string()),
+    ];
+    if ga.per_dir_min > 0 {
+        parameters.push(("PerDirMin", ga.per_dir_min.to_string()));
+    }
+    if let Some(max) = ga.per_dir_max {
+        parameters.push(("PerDirMax", max.to_string()));
+    }
+    if let Some(size) = load.standard_size {
+        let key = match load.scale_mode {
+            ScaleMode::Width => "StandardWidth",
+            ScaleMode::Height => "StandardHeight",
+            ScaleMode::Area => "StandardArea",
+        };
+        parameters.push((key, size.to_string()));
+    }
+    let mut all_sources = Vec::new();
+    let mut used_ids = Vec::new();
+    let mut failed_saves = 0;
+    let page_images = match pages {
+        Some(count) => split_into_pages(all_images, count, ga.max_images),
+        None => vec![all_images],
+    };
 
-        // Create new individuals
-        while new_population.len() < population_size {
-            let parent1 = elites.choose(&mut rng).unwrap();
-            let parent2 = elites.choose(&mut rng).unwrap();
+    let mut collages = Vec::new();
+    for (index, images) in page_images.iter().enumerate() {
+        if ga.cancel.is_cancelled() {
+            info!("Cancelled, skipping the remaining {} pages", page_images.len() - index);
+            break;
+        }
+        if pages.is_some() {
+            info!("Optimizing page {} of {} with {} images...", index + 1, page_images.len(), images.len());
+        }
+        let settings = GaSettings {
+            min_images: ga.min_images.min(images.len()),
+            max_images: ga.max_images.min(images.len()),
+            initial: initial.clone(),
+            // The dashboard replaces the progress bar
+            quiet: ga.quiet || tui,
+            ..ga.clone()
+        };
+        // Every page gets its own numbered set of files
+        let numbered = |path: &Path| if pages.is_some() { page_path(path, index + 1) } else { path.to_path_buf() };
+        // With --top-n, the collage and its alternatives are numbered by rank as well
+        let ranked = |path: &Path, rank: usize| if top_n.is_some() { suffixed_path(&numbered(path), &rank.to_string()) } else { numbered(path) };
 
-            let mut child = if rng.gen::<f64>() < crossover_rate {
-                crossover(parent1, parent2, &all_images, min_images, max_images, &mut rng)
-            } else {
-                let mut c = parent1.clone();
-                enforce_image_limits(&mut c.image_ids, &all_images, min_images, max_images, &mut rng);
-                c
+        // Intermediate collages use their own random numbers so they do not change the result
+        let mut preview_rng = StdRng::seed_from_u64(seed);
+        let mut timelapse = evolution_gif.as_ref().map(|_| Timelapse::new(match render.background {
+            BackgroundColor::Color(color) => color,
+            BackgroundColor::Auto => image::Rgba([255, 255, 255, 255]),
+        }));
+        let mut dashboard = if tui {
+            match Dashboard::start(format!("'{}'", numbered(output).display()), &settings, images.len()) {
+                Ok(dashboard) => Some(dashboard),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        let mut on_progress = |event: ProgressEvent| {
+            let ProgressEvent::Generation { stats, best, control } = event else {
+                return;
             };
+            if let Some(dashboard) = &mut dashboard {
+                dashboard.update(stats, control);
+            }
+            let Some(packed) = &best.packed_layout else {
+                return;
+            };
+            let record_frame = timelapse.is_some() && (stats.generation == 1 || stats.generation.is_multiple_of(evolution_every));
+            let save = save_every.is_some_and(|every| stats.generation.is_multiple_of(every));
+            if !record_frame && !save {
+                return;
+            }
+            let layout = Layout::build(packed, &best.genes, &image_map, &image_paths, packing, &mut preview_rng);
+            if let Some(timelapse) = timelapse.as_mut().filter(|_| record_frame) {
+                timelapse.push(&render_frame(&layout, render, &image_map, &captions));
+            }
+            if !save {
+                return;
+            }
+            let path = match preview {
+                Some(path) => numbered(path),
+                None => numbered(&output.with_file_name(format!("best_gen_{}.jpg", stats.generation))),
+            };
+            let (layout, render) = fit_to_output(layout, render, output_options);
+            let collage = create_collage(&image_map, &layout, &captions, &render);
+            let options = OutputOptions { tiles: None, metadata: false, ..output_options.clone() };
+            match save_collage(&collage, &path, &options, None) {
+                Ok(_) => {
+                    info!("Saved the best collage of generation {} as '{}'.", stats.generation, path.display());
+                    emit_saved("preview", &path);
+                }
+                Err(e) => eprintln!("Error saving intermediate collage: {}", e),
+            }
+        };
+        let optimization_start = Instant::now();
+        // With --restarts, independent runs continue the same random numbers and the best one wins
+        let mut fitnesses = Vec::with_capacity(restarts);
+        let mut best_run = None;
+        for run in 1..=restarts {
+            if restarts > 1 {
+                info!("Run {} of {}...", run, restarts);
+            }
+            let run_start = Instant::now();
+            let (population, history) = run_ga(images, &image_map, &info, packing, &settings, &mut rng, &mut on_progress);
+            let fitness = population[0].fitness;
+            if restarts > 1 {
+                info!("Run {}: best fitness {:.5} after {} generations ({:.1} s)", run, fitness, history.len(), run_start.elapsed().as_secs_f64());
+                emit(json!({ "event": "run", "run": run, "best": fitness, "generations": history.len() }));
+            }
+            if fitnesses.iter().all(|best| fitness > *best) {
+                best_run = Some((run, population, history));
+            }
+            fitnesses.push(fitness);
+            if settings.cancel.is_cancelled() {
+                break;
+            }
+        }
+        drop(dashboard);
+        let Some((run, population, history)) = best_run else {
+            return Err(Error::Packing(0));
+        };
+        if restarts > 1 {
+            info!(
+                "Best of {} runs: run {}; the best fitness per run ranged from {:.5} to {:.5} (mean {:.5})",
+                fitnesses.len(),
+                run,
+                fitnesses.iter().copied().fold(f64::INFINITY, f64::min),
+                population[0].fitness,
+                fitnesses.iter().sum::<f64>() / fitnesses.len() as f64
+            );
+        }
+        let best = &population[0];
+        info!("Best solution fitness: {:.5}", best.fitness);
+        let optimization_time = optimization_start.elapsed();
+
+        if let (Some(timelapse), Some(gif_path), Some(packed)) = (&mut timelapse, evolution_gif, &best.packed_layout) {
+            let gif_path = numbered(gif_path);
+            let layout = Layout::build(packed, &best.genes, &image_map, &image_paths, packing, &mut preview_rng);
+            timelapse.push(&render_frame(&layout, render, &image_map, &captions));
+            info!("Saving evolution animation as '{}'...", gif_path.display());
+            check_saved(timelapse.save(&gif_path), "evolution", &gif_path, &mut failed_saves);
+        }
+
+        if let Some(stats_path) = &stats_out {
+            let stats_path = numbered(stats_path);
+            info!("Saving statistics as '{}'...", stats_path.display());
+            check_saved(save_stats_csv(&history, &stats_path), "stats", &stats_path, &mut failed_saves);
+        }
+        if let Some(plot_path) = &plot {
+            let plot_path = numbered(plot_path);
+            info!("Saving fitness plot as '{}'...", plot_path.display());
+            check_saved(plot_fitness(&history, &plot_path), "plot", &plot_path, &mut failed_saves);
+        }
 
-            if rng.gen::<f64>() < mutation_rate {
-                mutate(&mut child, &all_images, min_images, max_images, &mut rng);
+        let rendering_start = Instant::now();
+        let build_layout = |individual: &Individual, rng: &mut StdRng| {
+            let packed = individual.packed_layout.as_ref().ok_or(Error::Packing(individual.image_ids.len()))?;
+            let (mut packed_locations, w, h) = packed.clone();
+            if let Some(min_size) = gap_fill {
+                let unused: Vec<u32> = images
+                    .iter()
+                    .map(|(id, _)| *id)
+                    .filter(|id| !individual.image_ids.contains(id))
+                    .collect();
+                let extra = fill_gaps(packed, &unused, &image_map, packing, min_size.max(packing.min_tile));
+                info!("Gap filling placed {} additional images.", extra.len());
+                packed_locations.extend(extra);
+            }
+            let layout = Layout::build(&(packed_locations, w, h), &individual.genes, &image_map, &image_paths, packing, rng);
+            // Debug builds report what --validate-layout turns into an error
+            if cfg!(debug_assertions) || packing.validate {
+                let problems = check_geometry(&layout, PADDING_SIZE as i32);
+                if !problems.is_empty() && packing.validate {
+                    return Err(Error::InvalidLayout(problems.join("; ")));
+                }
+                if !problems.is_empty() {
+                    eprintln!("Warning: packing produced an invalid layout: {}", problems.join("; "));
+                }
             }
+            Ok(layout)
+        };
+        let layout = build_layout(best, &mut rng)?;
+        let (layout, render) = fit_to_output(layout, render, output_options);
+        check_min_tile(&layout, packing.min_tile)?;
+        check_print_resolution(&layout, output_options);
+        emit(json!({
+            "event": "layout",
+            "page": index + 1,
+            "fitness": best.fitness,
+            "width": layout.width,
+            "height": layout.height,
+            "images": layout.images.iter().map(|placed| placed.path.display().to_string()).collect::<Vec<_>>(),
+        }));
+        let layers = render_layers(&image_map, &layout, &captions, &render);
+        let collage = layers.flatten();
+        let rendering_time = rendering_start.elapsed();
+        let saving_start = Instant::now();
 
-            new_population.push(child);
+        let layout_path = ranked(&output.with_file_name("layout.json"), 1);
+        info!("Saving layout as '{}'...", layout_path.display());
+        check_saved(layout.save(&layout_path), "layout", &layout_path, &mut failed_saves);
+        if let Some(svg_path) = &export_svg {
+            let svg_path = numbered(svg_path);
+            info!("Saving SVG as '{}'...", svg_path.display());
+            check_saved(save_svg(&layout, &svg_path, svg_embed), "svg", &svg_path, &mut failed_saves);
+        }
+        if let Some(ora_path) = &export_ora {
+            let ora_path = numbered(ora_path);
+            info!("Saving layers as '{}'...", ora_path.display());
+            check_saved(save_ora(&layers, &layout, &ora_path), "ora", &ora_path, &mut failed_saves);
         }
+        let sources: Vec<PathBuf> = layout.images.iter().map(|placed| placed.path.clone()).collect();
+        let mut page_parameters = parameters.clone();
+        if pages.is_some() {
+            page_parameters.push(("Page", (index + 1).to_string()));
+        }
+        let provenance = Provenance { sources: sources.clone(), seed: Some(seed), parameters: page_parameters };
+        all_sources.extend(sources);
+        used_ids.extend(layout.images.iter().map(|placed| placed.id));
 
-        // Evaluate the new population in parallel
-        new_population.par_iter_mut().for_each(|indiv| {
-            evaluate_individual(indiv, &image_map);
-        });
+        let image_path = ranked(output, 1);
+        info!("Saving image as '{}'...", image_path.display());
+        if check_saved(save_collage(&collage, &image_path, output_options, Some(&provenance)), "image", &image_path, &mut failed_saves) {
+            info!("Image saved successfully.");
+        }
+        if let Some(report_path) = &report {
+            let report_path = numbered(report_path);
+            info!("Saving report as '{}'...", report_path.display());
+            let in_collage: Vec<u32> = layout.images.iter().map(|placed| placed.id).collect();
+            let with_path = |id: &u32| Some((image_paths.get(id)?.as_path(), image_map.get(id)?));
+            let mut report_parameters = vec![("Seed", seed.to_string())];
+            report_parameters.extend(provenance.parameters.iter().cloned());
+            let report = Report {
+                collage: &collage,
+                fitness: best.fitness,
+                parameters: &report_parameters,
+                history: &history,
+                used: in_collage.iter().filter_map(with_path).collect(),
+                unused: images.iter().map(|(id, _)| id).filter(|id| !in_collage.contains(id)).filter_map(with_path).collect(),
+                timings: &[
+                    ("Loading", loading_time),
+                    ("Optimization", optimization_time),
+                    ("Rendering", rendering_time),
+                    ("Saving", saving_start.elapsed()),
+                ],
+            };
+            check_saved(save_report(&report, &report_path), "report", &report_path, &mut failed_saves);
+        }
+        if let Some(n) = top_n {
+            let alternatives = distinct_best(&population, n);
+            if alternatives.len() < n {
+                info!("Only {} distinct layouts were found for --top-n {}.", alternatives.len(), n);
+            }
+            for (rank, alternative) in alternatives.iter().enumerate().skip(1) {
+                let rank = rank + 1;
+                let layout = match build_layout(alternative, &mut rng) {
+                    Ok(layout) => layout,
+                    Err(Error::Packing(_)) => continue,
+                    Err(e) => return Err(e),
+                };
+                // `render` was scaled for the best collage already
+                let (layout, render) = fit_to_output(layout, &args.render, output_options);
+                if let Err(e) = check_min_tile(&layout, packing.min_tile) {
+                    info!("Skipping alternative {}: {}", rank, e);
+                    continue;
+                }
+                let layout_path = ranked(&output.with_file_name("layout.json"), rank);
+                check_saved(layout.save(&layout_path), "layout", &layout_path, &mut failed_saves);
+                let collage = create_collage(&image_map, &layout, &captions, &render);
+                let sources = layout.images.iter().map(|placed| placed.path.clone()).collect();
+                let provenance = Provenance { sources, seed: Some(seed), parameters: provenance.parameters.clone() };
+                let image_path = ranked(output, rank);
+                info!("Saving alternative {} (fitness {:.5}) as '{}'...", rank, alternative.fitness, image_path.display());
+                check_saved(save_collage(&collage, &image_path, output_options, Some(&provenance)), "image", &image_path, &mut failed_saves);
+            }
+        }
+        if combined_pdf.is_some() {
+            collages.push(collage);
+        }
+    }
 
-        population = new_population;
+    // Which of the inputs made it into the collages, in the order they were placed, and which did not
+    let used: Vec<&Path> = used_ids.iter().filter_map(|id| image_paths.get(id).map(PathBuf::as_path)).collect();
+    let mut unused: Vec<&Path> = image_paths.iter().filter(|(id, _)| !used_ids.contains(id)).map(|(_, path)| path.as_path()).collect();
+    unused.sort();
+    for (paths, name) in [(&used, "used_images.txt"), (&unused, "unused_images.txt")] {
+        let path = output.with_file_name(name);
+        info!("Saving the list of {} images as '{}'...", paths.len(), path.display());
+        check_saved(write_file_list(paths, &path), "manifest", &path, &mut failed_saves);
+    }
+    if let Some(pdf_path) = &combined_pdf {
+        info!("Saving {} pages as '{}'...", collages.len(), pdf_path.display());
+        let provenance = Provenance { sources: all_sources.clone(), seed: Some(seed), parameters };
+        check_saved(save_pdf(&collages, pdf_path, output_options, Some(&provenance)), "pdf", pdf_path, &mut failed_saves);
     }
+    if failed_saves > 0 {
+        return Err(Error::Save(failed_saves));
+    }
+    Ok(all_sources)
+}
 
-    // Final solution
-    population.sort_by(|a,b| b.fitness.partial_cmp(&a.fitness).unwrap());
-    let best = &population[0];
-    println!("Best solution fitness: {:.5}", best.fitness);
+/// Scales the layout and the drawing options to the pixel size of `--physical-size`, or down
+/// when the collage would exceed `--max-output`.
+fn fit_to_output(layout: Layout, render: &RenderOptions, options: &OutputOptions) -> (Layout, RenderOptions) {
+    let factor = options.scale_for(layout.width, layout.height);
+    if factor == 1.0 {
+        return (layout, render.clone());
+    }
+    if options.physical_size.is_some() {
+        info!("Scaling the collage by {:.3} to {}x{} pixels for the physical size.", factor, (layout.width as f64 * factor).round(), (layout.height as f64 * factor).round());
+    } else {
+        info!("Scaling the collage by {:.3} to fit the maximum output size.", factor);
+    }
+    (layout.scaled(factor), render.scaled(factor))
+}
+
+/// Enforces `--min-tile` on a collage at its final size, which also covers the cells of hex,
+/// strip and golden layouts that are smaller than the images cropped to them.
+fn check_min_tile(layout: &Layout, min_tile: u32) -> Result<(), Error> {
+    let smallest = layout.images.iter().map(|placed| (placed, placed.visible_size())).min_by_key(|(_, (w, h))| *w.min(h));
+    match smallest {
+        Some((placed, (w, h))) if min_tile > 0 && w.min(h) < min_tile => Err(Error::TileTooSmall { path: placed.path.clone(), size: w.min(h), min: min_tile }),
+        _ => Ok(()),
+    }
+}
 
-    if let Some((packed_locations, w, h)) = &best.packed_layout {
-        let collage = create_collage(&image_map, packed_locations, *w, *h);
-        println!("Saving image as 'output.jpg'...");
-        match collage.save("output.jpg") {
-            Ok(_) => println!("Image saved successfully."),
-            Err(e) => eprintln!("Error saving image: {}", e),
+/// With `--physical-size`, warns about images that are enlarged so much that they print at fewer
+/// pixels of the source file per inch than `--min-dpi`.
+fn check_print_resolution(layout: &Layout, options: &OutputOptions) {
+    if options.physical_size.is_none() {
+        return;
+    }
+    let mut low: Vec<(&Path, f64)> = layout
+        .images
+        .iter()
+        .map(|placed| (placed.path.as_path(), options.dpi as f64 / placed.scale.max(f64::MIN_POSITIVE)))
+        .filter(|(_, dpi)| *dpi < options.min_dpi as f64)
+        .collect();
+    if low.is_empty() {
+        return;
+    }
+    low.sort_by(|a, b| a.1.total_cmp(&b.1));
+    eprintln!("Warning: {} images print at less than {} DPI at this size:", low.len(), options.min_dpi);
+    for (path, dpi) in low {
+        eprintln!("  {:.0} DPI  {}", dpi, path.display());
+    }
+}
+
+/// A small render of the layout for the `--evolution-gif` animation.
+fn render_frame(layout: &Layout, render: &RenderOptions, images: &HashMap<u32, image::DynamicImage>, captions: &HashMap<u32, String>) -> image::DynamicImage {
+    let factor = (FRAME_SIZE as f64 / layout.width.max(layout.height).max(1) as f64).min(1.0);
+    create_collage(images, &layout.scaled(factor), captions, &render.scaled(factor))
+}
+
+fn emit_saved(kind: &str, path: &Path) {
+    emit(json!({ "event": "saved", "kind": kind, "path": path.display().to_string() }));
+}
+
+/// Reports a saved output file, or prints why it could not be saved and counts the failure.
+fn check_saved(result: Result<(), String>, kind: &str, path: &Path, failed: &mut usize) -> bool {
+    match result {
+        Ok(_) => {
+            emit_saved(kind, path);
+            true
+        }
+        Err(e) => {
+            eprintln!("Error saving {}: {}", kind, e);
+            *failed += 1;
+            false
+        }
+    }
+}
+
+/// Splits the images in load order into evenly sized groups, one per page. With `auto`, as many
+/// pages are used as needed so that no page has more than `max_images`.
+fn split_into_pages<T>(images: Vec<T>, count: PageCount, max_images: usize) -> Vec<Vec<T>> {
+    let total = images.len();
+    let pages = match count {
+        PageCount::Fixed(n) => n,
+        PageCount::Auto => total.div_ceil(max_images.max(1)),
+    }
+    .clamp(1, total.max(1));
+
+    let mut groups = Vec::with_capacity(pages);
+    let mut images = images.into_iter();
+    for page in 0..pages {
+        let size = (page + 1) * total / pages - page * total / pages;
+        groups.push(images.by_ref().take(size).collect());
+    }
+    groups
+}
+
+fn render_layout(args: RenderArgs) -> Result<(), Error> {
+    let RenderArgs { layout: layout_path, output, scale, load, label_source, render, output_options } = args;
+    info!("Loading layout from {}...", layout_path.display());
+    let layout = Layout::load(&layout_path).map_err(Error::ReadInput)?;
+    // A physical size replaces the requested scale, and the maximum output size shrinks it further
+    let scale = match output_options.physical_size {
+        Some(_) => output_options.scale_for(layout.width, layout.height),
+        None => scale * fit_scale((layout.width as f64 * scale) as u32, (layout.height as f64 * scale) as u32, output_options.max_size),
+    };
+    let layout = layout.scaled(scale);
+    let render = render.scaled(scale);
+    check_print_resolution(&layout, &output_options);
+
+    let captions: HashMap<u32, String> = layout
+        .images
+        .iter()
+        .filter_map(|placed| Some((placed.id, read_caption(&placed.path, label_source?)?)))
+        .collect();
+    let open = |placed: &PlacedImage| match open_image(&placed.path, &load) {
+        Ok(img) => Some(retarget(img, &placed.path, &load)),
+        Err(e) => {
+            eprintln!("Error opening {}: {}", placed.path.display(), e);
+            None
         }
+    };
+    // Full-size sources can take a lot of memory, so they are only loaded all at once when the
+    // drawing options need to compare them
+    let collage = if can_stream(&render) {
+        create_collage_streamed(&layout, &captions, &render, open)
     } else {
-        eprintln!("No layout found for the best solution.");
+        let image_map: HashMap<u32, image::DynamicImage> =
+            layout.images.iter().filter_map(|placed| Some((placed.id, open(placed)?))).collect();
+        create_collage(&image_map, &layout, &captions, &render)
+    };
+    info!("Saving image as '{}'...", output.display());
+    let provenance = Provenance {
+        sources: layout.images.iter().map(|placed| placed.path.clone()).collect(),
+        seed: None,
+        parameters: vec![("Layout", layout_path.display().to_string()), ("Scale", scale.to_string())],
+    };
+    let mut failed_saves = 0;
+    if !check_saved(save_collage(&collage, &output, &output_options, Some(&provenance)), "image", &output, &mut failed_saves) {
+        return Err(Error::Save(failed_saves));
+    }
+    info!("Image saved successfully.");
+    Ok(())
+}
+
+fn compare_layouts(args: CompareArgs) -> Result<(), Error> {
+    let CompareArgs { layouts: paths, output } = args;
+    let layouts = paths.iter().map(|path| Layout::load(path)).collect::<Result<Vec<_>, _>>().map_err(Error::ReadInput)?;
+    let (a, b) = (&layouts[0], &layouts[1]);
+    let names = paths.each_ref().map(|path| path.display().to_string());
+    let metrics = [layout_metrics(a), layout_metrics(b)];
+    info!("{:<18} {:>20} {:>20}", "", names[0], names[1]);
+    info!("{:<18} {:>20} {:>20}", "Canvas", format!("{}x{}", a.width, a.height), format!("{}x{}", b.width, b.height));
+    info!("{:<18} {:>20} {:>20}", "Images", metrics[0].images, metrics[1].images);
+    info!("{:<18} {:>19.1}% {:>19.1}%", "Coverage", metrics[0].coverage, metrics[1].coverage);
+    info!("{:<18} {:>20.3} {:>20.3}", "Aspect ratio", metrics[0].aspect_ratio, metrics[1].aspect_ratio);
+    info!("{:<18} {:>20.3} {:>20.3}", "Aspect deviation", metrics[0].aspect_deviation, metrics[1].aspect_deviation);
+
+    let shared = shared_images(a, b);
+    info!("Shared images: {}", shared.len());
+    for (name, layout) in names.iter().zip([a, b]) {
+        let unique: Vec<String> = layout
+            .images
+            .iter()
+            .filter(|placed| !shared.contains(&placed.path))
+            .map(|placed| placed.path.file_name().unwrap_or(placed.path.as_os_str()).to_string_lossy().into_owned())
+            .collect();
+        info!("Only in {}: {}", name, if unique.is_empty() { "-".to_string() } else { unique.join(", ") });
+    }
+
+    let mut sources: Vec<&PathBuf> = a.images.iter().chain(&b.images).map(|placed| &placed.path).collect();
+    sources.sort();
+    sources.dedup();
+    let images: HashMap<PathBuf, image::DynamicImage> = sources
+        .par_iter()
+        .filter_map(|path| match open_image(path, &LoadOptions::default()) {
+            Ok(img) => Some(((*path).clone(), img)),
+            Err(e) => {
+                eprintln!("Error opening {}: {}", path.display(), e);
+                None
+            }
+        })
+        .collect();
+    let diff = diff_image([(&paths[0], a), (&paths[1], b)], &images);
+    info!("Saving comparison as '{}'...", output.display());
+    if let Err(e) = image::DynamicImage::ImageRgba8(diff).to_rgb8().save(&output) {
+        eprintln!("Error saving comparison: {}", e);
+        return Err(Error::Save(1));
+    }
+    Ok(())
+}
+
+/// Prints the problems found in a saved layout. Returns whether it is valid.
+fn verify_layout(args: VerifyArgs) -> bool {
+    let layout = match Layout::load(&args.layout) {
+        Ok(layout) => layout,
+        Err(e) => {
+            eprintln!("{}", e);
+            return false;
+        }
+    };
+    let mut problems = check_geometry(&layout, args.padding);
+    problems.extend(check_sources(&layout));
+    if problems.is_empty() {
+        info!("{}: {} images, no problems found.", args.layout.display(), layout.images.len());
+        return true;
+    }
+    for problem in &problems {
+        eprintln!("{}", problem);
     }
+    eprintln!("{}: {} problems found.", args.layout.display(), problems.len());
+    false
 }
@@ -0,0 +1,166 @@
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::path::PathBuf;
+
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+use crate::svg::escape_xml;
+
+const XMP_NAMESPACE: &str = "https://github.com/intervall-ludger/ImageGridOptimizer/ns/1.0/";
+const JPEG_XMP_HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+const JPEG_ICC_HEADER: &[u8] = b"ICC_PROFILE\0";
+/// Profile bytes per APP2 segment: the segment length limit minus the length field, the header
+/// and the sequence number and count.
+const JPEG_ICC_CHUNK: usize = u16::MAX as usize - 2 - 12 - 2;
+
+/// Where a collage came from: the source files, the random seed and the parameters of the run,
+/// written into the output file so it can be traced back to its inputs.
+#[derive(Clone, Default)]
+pub struct Provenance {
+    pub sources: Vec<PathBuf>,
+    pub seed: Option<u64>,
+    /// Parameter names (CamelCase, used as XMP property names) and their values.
+    pub parameters: Vec<(&'static str, String)>,
+}
+
+impl Provenance {
+    pub fn creator_tool() -> String {
+        format!("ImageGridOptimizer {}", env!("CARGO_PKG_VERSION"))
+    }
+
+    /// Serializes the provenance as an XMP packet.
+    pub fn to_xmp(&self) -> String {
+        let mut xmp = String::new();
+        let _ = writeln!(xmp, r#"<?xpacket begin="" id="W5M0MpCehiHzreSzNTczkc9d"?>"#);
+        let _ = writeln!(xmp, r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">"#);
+        let _ = writeln!(xmp, r#" <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">"#);
+        let _ = write!(
+            xmp,
+            r#"  <rdf:Description rdf:about="" xmlns:xmp="http://ns.adobe.com/xap/1.0/" xmlns:igo="{}" xmp:CreatorTool="{}""#,
+            XMP_NAMESPACE,
+            escape_xml(&Self::creator_tool())
+        );
+        if let Some(seed) = self.seed {
+            let _ = write!(xmp, r#" igo:Seed="{}""#, seed);
+        }
+        for (name, value) in &self.parameters {
+            let _ = write!(xmp, r#" igo:{}="{}""#, name, escape_xml(value));
+        }
+        let _ = writeln!(xmp, ">");
+        let _ = writeln!(xmp, "   <igo:Sources>");
+        let _ = writeln!(xmp, "    <rdf:Seq>");
+        for source in &self.sources {
+            let _ = writeln!(xmp, "     <rdf:li>{}</rdf:li>", escape_xml(&source.to_string_lossy()));
+        }
+        let _ = writeln!(xmp, "    </rdf:Seq>");
+        let _ = writeln!(xmp, "   </igo:Sources>");
+        let _ = writeln!(xmp, "  </rdf:Description>");
+        let _ = writeln!(xmp, " </rdf:RDF>");
+        let _ = writeln!(xmp, "</x:xmpmeta>");
+        let _ = write!(xmp, r#"<?xpacket end="w"?>"#);
+        xmp
+    }
+}
+
+/// Inserts an XMP packet into encoded PNG or JPEG data. Other formats are returned unchanged.
+pub fn embed_xmp(bytes: Vec<u8>, xmp: &str) -> Result<Vec<u8>, String> {
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Ok(embed_png(bytes, xmp))
+    } else if bytes.starts_with(&[0xFF, 0xD8]) {
+        embed_jpeg(bytes, xmp)
+    } else {
+        Ok(bytes)
+    }
+}
+
+/// Inserts an ICC profile into encoded PNG or JPEG data. Other formats are returned unchanged.
+pub fn embed_icc(bytes: Vec<u8>, profile: &[u8]) -> Result<Vec<u8>, String> {
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        // The profile name, compression method 0 (zlib) and the compressed profile
+        let mut data = b"ICC Profile\0\0".to_vec();
+        let mut encoder = ZlibEncoder::new(&mut data, Compression::default());
+        encoder.write_all(profile).map_err(|e| format!("Error compressing the ICC profile: {}", e))?;
+        encoder.finish().map_err(|e| format!("Error compressing the ICC profile: {}", e))?;
+        Ok(insert_png_chunk(bytes, b"iCCP", &data))
+    } else if bytes.starts_with(&[0xFF, 0xD8]) {
+        // Profiles larger than a segment are split into numbered APP2 segments
+        let chunks: Vec<&[u8]> = profile.chunks(JPEG_ICC_CHUNK).collect();
+        let mut segments = Vec::new();
+        for (index, chunk) in chunks.iter().enumerate() {
+            let mut segment = Vec::with_capacity(JPEG_ICC_HEADER.len() + 2 + chunk.len());
+            segment.extend_from_slice(JPEG_ICC_HEADER);
+            segment.extend_from_slice(&[index as u8 + 1, chunks.len() as u8]);
+            segment.extend_from_slice(chunk);
+            segments.push(segment);
+        }
+        Ok(segments.iter().rev().fold(bytes, |bytes, segment| insert_jpeg_segment(bytes, 0xE2, segment)))
+    } else {
+        Ok(bytes)
+    }
+}
+
+/// Adds an uncompressed `iTXt` chunk with the standard XMP keyword right after the header chunk.
+/// Records the resolution in a pHYs chunk of PNG output, in pixels per meter as PNG requires.
+pub fn embed_png_dpi(bytes: Vec<u8>, dpi: f32) -> Vec<u8> {
+    let per_meter = (dpi as f64 / 0.0254).round() as u32;
+    let mut data = Vec::with_capacity(9);
+    data.extend_from_slice(&per_meter.to_be_bytes());
+    data.extend_from_slice(&per_meter.to_be_bytes());
+    // Unit 1: the meter
+    data.push(1);
+    insert_png_chunk(bytes, b"pHYs", &data)
+}
+
+fn embed_png(bytes: Vec<u8>, xmp: &str) -> Vec<u8> {
+    let mut data = Vec::new();
+    data.extend_from_slice(b"XML:com.adobe.xmp\0");
+    // No compression, no language tag and no translated keyword
+    data.extend_from_slice(&[0, 0, 0, 0]);
+    data.extend_from_slice(xmp.as_bytes());
+    insert_png_chunk(bytes, b"iTXt", &data)
+}
+
+/// Adds an APP1 segment with the XMP packet after the JFIF header.
+fn embed_jpeg(bytes: Vec<u8>, xmp: &str) -> Result<Vec<u8>, String> {
+    let length = 2 + JPEG_XMP_HEADER.len() + xmp.len();
+    if length > u16::MAX as usize {
+        return Err("Metadata is too large for a JPEG segment; use PNG output or --no-metadata".to_string());
+    }
+    let mut data = JPEG_XMP_HEADER.to_vec();
+    data.extend_from_slice(xmp.as_bytes());
+    Ok(insert_jpeg_segment(bytes, 0xE1, &data))
+}
+
+/// Inserts a chunk right after the header chunk of a PNG file.
+fn insert_png_chunk(bytes: Vec<u8>, kind: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    // Signature (8 bytes) followed by IHDR: length, type, 13 bytes of data and the CRC
+    const AFTER_IHDR: usize = 8 + 4 + 4 + 13 + 4;
+
+    let mut chunk = Vec::with_capacity(data.len() + 12);
+    chunk.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    chunk.extend_from_slice(kind);
+    chunk.extend_from_slice(data);
+    chunk.extend_from_slice(&crc32fast::hash(&chunk[4..]).to_be_bytes());
+
+    let mut result = bytes;
+    result.splice(AFTER_IHDR..AFTER_IHDR, chunk);
+    result
+}
+
+/// Inserts a segment with the given APPn marker after the JFIF header of a JPEG file; `data` must
+/// fit into a segment.
+fn insert_jpeg_segment(bytes: Vec<u8>, marker: u8, data: &[u8]) -> Vec<u8> {
+    let mut segment = vec![0xFF, marker];
+    segment.extend_from_slice(&((2 + data.len()) as u16).to_be_bytes());
+    segment.extend_from_slice(data);
+
+    // Keep the JFIF APP0 segment first, as some readers expect it directly after SOI
+    let mut position = 2;
+    if bytes.len() > 6 && bytes[2..4] == [0xFF, 0xE0] {
+        position += 2 + u16::from_be_bytes([bytes[4], bytes[5]]) as usize;
+    }
+    let mut result = bytes;
+    result.splice(position..position, segment);
+    result
+}
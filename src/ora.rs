@@ -0,0 +1,75 @@
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{Cursor, Write};
+use std::path::Path;
+
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat, RgbaImage};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::collage::CollageLayers;
+use crate::layout::Layout;
+use crate::svg::escape_xml;
+
+/// Writes the collage as an OpenRaster file (the layered format of GIMP and Krita) with the
+/// background, one movable layer per photo and the banner/watermark overlay on top.
+pub fn save_ora(layers: &CollageLayers, layout: &Layout, path: &Path) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| format!("Error creating {}: {}", path.display(), e))?;
+    let mut zip = ZipWriter::new(file);
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    let (width, height) = layers.background.dimensions();
+
+    // The mimetype must be the first entry and uncompressed so the format can be sniffed
+    add_file(&mut zip, "mimetype", b"image/openraster", stored)?;
+
+    // stack.xml lists the layers from top to bottom
+    let mut stack = String::new();
+    let _ = writeln!(stack, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    let _ = writeln!(stack, r#"<image version="0.0.3" w="{}" h="{}">"#, width, height);
+    let _ = writeln!(stack, "  <stack>");
+    let _ = writeln!(stack, r#"    <layer name="Title and watermark" src="data/overlay.png" x="0" y="0"/>"#);
+    add_png(&mut zip, "data/overlay.png", &layers.overlay)?;
+
+    for (id, tile, x, y) in layers.tiles.iter().rev() {
+        let name = layout
+            .images
+            .iter()
+            .find(|placed| placed.id == *id)
+            .and_then(|placed| placed.path.file_name())
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| format!("Image {}", id));
+        let src = format!("data/image-{}.png", id);
+        let _ = writeln!(stack, r#"    <layer name="{}" src="{}" x="{}" y="{}"/>"#, escape_xml(&name), src, x, y);
+        add_png(&mut zip, &src, tile)?;
+    }
+
+    let _ = writeln!(stack, r#"    <layer name="Background" src="data/background.png" x="0" y="0"/>"#);
+    add_png(&mut zip, "data/background.png", &layers.background)?;
+    let _ = writeln!(stack, "  </stack>");
+    let _ = writeln!(stack, "</image>");
+    add_file(&mut zip, "stack.xml", stack.as_bytes(), deflated)?;
+
+    let merged = layers.flatten();
+    add_png(&mut zip, "mergedimage.png", &merged.to_rgba8())?;
+    let thumbnail = merged.resize(256, 256, FilterType::Triangle);
+    add_png(&mut zip, "Thumbnails/thumbnail.png", &thumbnail.to_rgba8())?;
+
+    zip.finish().map_err(|e| format!("Error writing {}: {}", path.display(), e))?;
+    Ok(())
+}
+
+fn add_file(zip: &mut ZipWriter<File>, name: &str, bytes: &[u8], options: SimpleFileOptions) -> Result<(), String> {
+    zip.start_file(name, options).map_err(|e| format!("Error adding {}: {}", name, e))?;
+    zip.write_all(bytes).map_err(|e| format!("Error adding {}: {}", name, e))
+}
+
+/// PNG is already compressed, so layers are stored without deflating them again.
+fn add_png(zip: &mut ZipWriter<File>, name: &str, img: &RgbaImage) -> Result<(), String> {
+    let mut png = Vec::new();
+    DynamicImage::ImageRgba8(img.clone())
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .map_err(|e| format!("Error encoding {}: {}", name, e))?;
+    add_file(zip, name, &png, SimpleFileOptions::default().compression_method(CompressionMethod::Stored))
+}
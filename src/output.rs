@@ -0,0 +1,444 @@
+use std::borrow::Cow;
+use std::fs::{self, File};
+use std::io::{BufWriter, Cursor};
+use std::path::{Path, PathBuf};
+
+use image::codecs::jpeg::{JpegEncoder, PixelDensity};
+use image::codecs::png::{self, CompressionType, PngEncoder};
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat, Rgba, RgbaImage};
+use printpdf::{ColorBits, ColorSpace, Image, ImageFilter, ImageTransform, ImageXObject, Mm, PdfDocument, Px};
+use ravif::{Img, RGB8};
+use tiff::encoder::colortype::{ColorType, CMYK8, RGBA8};
+use tiff::encoder::compression::Lzw;
+use tiff::encoder::{Rational, TiffEncoder, TiffValue};
+use tiff::tags::{ResolutionUnit, Tag, Type};
+use tiff::TiffError;
+
+use crate::icc;
+use crate::metadata::{embed_icc, embed_png_dpi, embed_xmp, Provenance};
+use crate::packing::fit_scale;
+
+/// TIFF tag of an embedded ICC profile.
+const TIFF_ICC_PROFILE: u16 = 34675;
+
+/// Encoder speed from 1 (smallest files) to 10 (fastest); AVIF encoding of large collages is
+/// slow enough that the ravif default of 4 feels like a hang.
+const AVIF_SPEED: u8 = 6;
+
+/// How the collage file is written: encoder settings, the physical page of PDF output and tiling.
+#[derive(Clone)]
+pub struct OutputOptions {
+    /// Trim size of the page in millimeters (portrait); the page is turned to match the collage.
+    pub paper: (f32, f32),
+    pub dpi: f32,
+    /// Records `dpi` in the headers of JPEG, PNG and TIFF output (`--dpi` or `--physical-size`).
+    pub embed_dpi: bool,
+    /// Print size in millimeters; the collage is scaled to the pixel size this takes at `dpi`.
+    pub physical_size: Option<(f32, f32)>,
+    /// With a physical size, images printed at fewer pixels per inch than this are reported.
+    pub min_dpi: f32,
+    /// Extra margin around the trim size that is printed and cut off afterwards.
+    pub bleed_mm: f32,
+    /// Splits the collage into this many columns and rows for printing in pieces.
+    pub tiles: Option<(u32, u32)>,
+    /// Overlap between neighbouring tiles in percent of the tile size.
+    pub tile_overlap: f32,
+    /// Embed the source files and parameters into the output.
+    pub metadata: bool,
+    /// JPEG, WebP and AVIF quality from 1 to 100, also used for the images embedded into PDFs.
+    pub quality: u8,
+    pub png_compression: CompressionType,
+    /// Larger collages are scaled down to fit, as viewers choke on huge images.
+    pub max_size: Option<(u32, u32)>,
+    /// ICC profile embedded into PNG and JPEG output, after converting the collage from sRGB to it.
+    pub color_profile: Option<Vec<u8>>,
+    /// CMYK ICC profile that TIFF output is converted to for printing.
+    pub print_profile: Option<Vec<u8>>,
+}
+
+impl Default for OutputOptions {
+    fn default() -> Self {
+        OutputOptions {
+            paper: (210.0, 297.0),
+            dpi: 300.0,
+            embed_dpi: false,
+            physical_size: None,
+            min_dpi: 150.0,
+            bleed_mm: 3.0,
+            tiles: None,
+            tile_overlap: 5.0,
+            metadata: true,
+            quality: 90,
+            png_compression: CompressionType::Default,
+            max_size: None,
+            color_profile: None,
+            print_profile: None,
+        }
+    }
+}
+
+/// Parses a paper name (A0-A6, letter, legal, tabloid) or a custom `WxH` size in millimeters.
+pub fn parse_paper(value: &str) -> Option<(f32, f32)> {
+    let size = match value.to_ascii_lowercase().as_str() {
+        "a0" => (841.0, 1189.0),
+        "a1" => (594.0, 841.0),
+        "a2" => (420.0, 594.0),
+        "a3" => (297.0, 420.0),
+        "a4" => (210.0, 297.0),
+        "a5" => (148.0, 210.0),
+        "a6" => (105.0, 148.0),
+        "letter" => (215.9, 279.4),
+        "legal" => (215.9, 355.6),
+        "tabloid" => (279.4, 431.8),
+        custom => {
+            let (w, h) = custom.trim_end_matches("mm").split_once('x')?;
+            (w.trim().parse().ok()?, h.trim().parse().ok()?)
+        }
+    };
+    Some(size)
+}
+
+/// Parses a print size given as `WxH` with a unit of cm, mm or in, e.g. `60x40cm`, into millimeters.
+pub fn parse_physical_size(value: &str) -> Option<(f32, f32)> {
+    let value = value.trim().to_ascii_lowercase();
+    let (size, mm_per_unit) = if let Some(size) = value.strip_suffix("cm") {
+        (size, 10.0)
+    } else if let Some(size) = value.strip_suffix("mm") {
+        (size, 1.0)
+    } else {
+        (value.strip_suffix("in")?, 25.4)
+    };
+    let (w, h) = size.split_once('x')?;
+    let (w, h): (f32, f32) = (w.trim().parse().ok()?, h.trim().parse().ok()?);
+    (w > 0.0 && h > 0.0).then_some((w * mm_per_unit, h * mm_per_unit))
+}
+
+impl OutputOptions {
+    /// Pixel size of the physical size at the configured DPI.
+    pub fn physical_pixels(&self) -> Option<(u32, u32)> {
+        let (width_mm, height_mm) = self.physical_size?;
+        let pixels = |mm: f32| (mm / 25.4 * self.dpi).round().max(1.0) as u32;
+        Some((pixels(width_mm), pixels(height_mm)))
+    }
+
+    /// Factor a collage of `width` x `height` is scaled by before it is saved: up or down to fit
+    /// the pixels of the physical size, then down to fit the maximum output size.
+    pub fn scale_for(&self, width: u32, height: u32) -> f64 {
+        let (width, height) = (width.max(1) as f64, height.max(1) as f64);
+        let factor = match self.physical_pixels() {
+            Some((target_width, target_height)) => (target_width as f64 / width).min(target_height as f64 / height),
+            None => 1.0,
+        };
+        factor * fit_scale((width * factor).round() as u32, (height * factor).round() as u32, self.max_size)
+    }
+
+    /// The largest collage that is saved without shrinking it, for the minimum tile size checks
+    /// of the packer.
+    pub fn size_limit(&self) -> Option<(u32, u32)> {
+        match (self.max_size, self.physical_pixels()) {
+            (Some((w1, h1)), Some((w2, h2))) => Some((w1.min(w2), h1.min(h2))),
+            (max_size, physical) => max_size.or(physical),
+        }
+    }
+}
+
+/// Parses two positive numbers given as `AxB`, e.g. a `2x2` tile grid or an `8000x8000` pixel size.
+pub fn parse_dimensions(value: &str) -> Option<(u32, u32)> {
+    let (cols, rows) = value.to_ascii_lowercase().split_once('x').map(|(c, r)| (c.trim().parse().ok(), r.trim().parse().ok()))?;
+    match (cols?, rows?) {
+        (cols, rows) if cols > 0 && rows > 0 => Some((cols, rows)),
+        _ => None,
+    }
+}
+
+/// Inserts the page number before the extension, e.g. `output.jpg` becomes `output_03.jpg`.
+pub fn page_path(path: &Path, page: usize) -> PathBuf {
+    suffixed_path(path, &format!("{:02}", page))
+}
+
+/// Appends `_<suffix>` to the file stem, e.g. `output_3.jpg`.
+pub fn suffixed_path(path: &Path, suffix: &str) -> PathBuf {
+    let stem = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let name = match path.extension() {
+        Some(ext) => format!("{}_{}.{}", stem, suffix, ext.to_string_lossy()),
+        None => format!("{}_{}", stem, suffix),
+    };
+    path.with_file_name(name)
+}
+
+/// Saves the collage; the format follows the file extension, with `.pdf` rendered onto a
+/// physical page. With tiling, a PDF gets one page per tile and images are saved as one file
+/// per tile, e.g. `output_r1c2.jpg`. The provenance, if given, is embedded as XMP metadata.
+pub fn save_collage(
+    collage: &DynamicImage,
+    path: &Path,
+    options: &OutputOptions,
+    provenance: Option<&Provenance>,
+) -> Result<(), String> {
+    let provenance = provenance.filter(|_| options.metadata);
+    let is_pdf = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("pdf"));
+    let Some((cols, rows)) = options.tiles else {
+        return if is_pdf {
+            save_pdf(std::slice::from_ref(collage), path, options, provenance)
+        } else {
+            save_image(collage, path, options, provenance)
+        };
+    };
+
+    let tiles = split_into_tiles(&collage.to_rgba8(), cols, rows, options.tile_overlap);
+    info!("Split the collage into {} tiles.", tiles.len());
+    if is_pdf {
+        let tiles: Vec<DynamicImage> = tiles.into_iter().map(|(_, _, tile)| DynamicImage::ImageRgba8(tile)).collect();
+        return save_pdf(&tiles, path, options, provenance);
+    }
+    for (row, col, tile) in tiles {
+        let stem = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+        let ext = path.extension().map(|e| e.to_string_lossy().into_owned()).unwrap_or_default();
+        let tile_path = path.with_file_name(format!("{}_r{}c{}.{}", stem, row + 1, col + 1, ext));
+        save_image(&DynamicImage::ImageRgba8(tile), &tile_path, options, provenance)?;
+    }
+    Ok(())
+}
+
+/// Encodes the image in the format given by the file extension, using the configured quality
+/// (JPEG, WebP, AVIF) and PNG compression instead of the encoder defaults. Formats that cannot
+/// carry the color profile are written in sRGB without one.
+fn save_image(img: &DynamicImage, path: &Path, options: &OutputOptions, provenance: Option<&Provenance>) -> Result<(), String> {
+    let format = ImageFormat::from_path(path).map_err(|e| format!("Error saving {}: {}", path.display(), e))?;
+    if let (ImageFormat::Tiff, Some(print_profile)) = (format, &options.print_profile) {
+        return save_cmyk_tiff(img, path, print_profile, options.dpi);
+    }
+    let dpi = options.embed_dpi.then_some(options.dpi);
+    let profile = options.color_profile.as_deref().filter(|_| matches!(format, ImageFormat::Png | ImageFormat::Jpeg));
+    let converted = match profile {
+        Some(profile) => icc::from_srgb(img, profile)?,
+        None => Cow::Borrowed(img),
+    };
+    let img = converted.as_ref();
+    let mut bytes = Vec::new();
+    let mut writer = Cursor::new(&mut bytes);
+    let result = match format {
+        ImageFormat::Jpeg => {
+            let mut encoder = JpegEncoder::new_with_quality(&mut writer, options.quality);
+            if let Some(dpi) = dpi {
+                encoder.set_pixel_density(PixelDensity::dpi(dpi.round().clamp(1.0, u16::MAX as f32) as u16));
+            }
+            encoder.encode_image(&img.to_rgb8())
+        }
+        ImageFormat::Png => img.write_with_encoder(PngEncoder::new_with_quality(
+            &mut writer,
+            options.png_compression,
+            png::FilterType::Adaptive,
+        )),
+        ImageFormat::WebP => {
+            let rgba = img.to_rgba8();
+            let encoded = webp::Encoder::from_rgba(&rgba, rgba.width(), rgba.height()).encode(options.quality as f32);
+            writer.get_mut().extend_from_slice(&encoded);
+            Ok(())
+        }
+        ImageFormat::Avif => {
+            let rgb = img.to_rgb8();
+            let pixels: Vec<RGB8> = rgb.pixels().map(|p| RGB8::new(p[0], p[1], p[2])).collect();
+            let encoded = ravif::Encoder::new()
+                .with_quality(options.quality as f32)
+                .with_speed(AVIF_SPEED)
+                .encode_rgb(Img::new(&pixels[..], rgb.width() as usize, rgb.height() as usize))
+                .map_err(|e| format!("Error encoding {}: {}", path.display(), e))?;
+            writer.get_mut().extend_from_slice(&encoded.avif_file);
+            Ok(())
+        }
+        ImageFormat::Tiff if dpi.is_some() => {
+            let rgba = img.to_rgba8();
+            let encoded = encode_tiff::<RGBA8>(rgba.width(), rgba.height(), &rgba, options.dpi, None)
+                .map_err(|e| format!("Error encoding {}: {}", path.display(), e))?;
+            writer.get_mut().extend_from_slice(&encoded);
+            Ok(())
+        }
+        _ => img.write_to(&mut writer, format),
+    };
+    result.map_err(|e| format!("Error encoding {}: {}", path.display(), e))?;
+    if let Some(provenance) = provenance {
+        bytes = embed_xmp(bytes, &provenance.to_xmp())?;
+    }
+    if let Some(profile) = profile {
+        bytes = embed_icc(bytes, profile)?;
+    }
+    if let (ImageFormat::Png, Some(dpi)) = (format, dpi) {
+        bytes = embed_png_dpi(bytes, dpi);
+    }
+    fs::write(path, bytes).map_err(|e| format!("Error writing {}: {}", path.display(), e))
+}
+
+/// Saves the collage as an LZW-compressed CMYK TIFF for a print shop, converted with and carrying
+/// its ICC profile, with the resolution the collage is meant to be printed at.
+fn save_cmyk_tiff(img: &DynamicImage, path: &Path, profile: &[u8], dpi: f32) -> Result<(), String> {
+    let cmyk = icc::to_cmyk(img, profile)?;
+    let bytes = encode_tiff::<CMYK8>(img.width(), img.height(), &cmyk, dpi, Some(profile))
+        .map_err(|e| format!("Error encoding {}: {}", path.display(), e))?;
+    fs::write(path, bytes).map_err(|e| format!("Error writing {}: {}", path.display(), e))
+}
+
+/// Encodes an LZW-compressed TIFF with the resolution in DPI and, if given, an ICC profile.
+fn encode_tiff<C: ColorType<Inner = u8>>(width: u32, height: u32, data: &[u8], dpi: f32, profile: Option<&[u8]>) -> Result<Vec<u8>, TiffError> {
+    let mut bytes = Vec::new();
+    let mut encoder = TiffEncoder::new(Cursor::new(&mut bytes))?;
+    let mut image = encoder.new_image_with_compression::<C, _>(width, height, Lzw)?;
+    image.resolution(ResolutionUnit::Inch, Rational { n: (dpi * 100.0).round() as u32, d: 100 });
+    if let Some(profile) = profile {
+        image.encoder().write_tag(Tag::Unknown(TIFF_ICC_PROFILE), IccProfile(profile))?;
+    }
+    image.write_data(data)?;
+    Ok(bytes)
+}
+
+/// An ICC profile as a TIFF tag value, which has the type UNDEFINED rather than BYTE.
+struct IccProfile<'a>(&'a [u8]);
+
+impl TiffValue for IccProfile<'_> {
+    const BYTE_LEN: u8 = 1;
+    const FIELD_TYPE: Type = Type::UNDEFINED;
+
+    fn count(&self) -> usize {
+        self.0.len()
+    }
+
+    fn data(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self.0)
+    }
+}
+
+/// Cuts the collage into a grid of tiles that extend into their neighbours by `overlap_percent`
+/// of the tile size. Crop marks on every tile show where it meets the next one, so the overlap
+/// can be trimmed or glued under the neighbouring print. Returns (row, column, tile).
+fn split_into_tiles(collage: &RgbaImage, cols: u32, rows: u32, overlap_percent: f32) -> Vec<(u32, u32, RgbaImage)> {
+    let (width, height) = collage.dimensions();
+    let overlap_x = (width as f32 / cols as f32 * overlap_percent / 100.0).round() as u32;
+    let overlap_y = (height as f32 / rows as f32 * overlap_percent / 100.0).round() as u32;
+
+    let mut tiles = Vec::new();
+    for row in 0..rows {
+        for col in 0..cols {
+            // The part of the collage this tile is responsible for, before the overlap
+            let cut_left = col * width / cols;
+            let cut_right = (col + 1) * width / cols;
+            let cut_top = row * height / rows;
+            let cut_bottom = (row + 1) * height / rows;
+
+            let left = cut_left.saturating_sub(overlap_x);
+            let top = cut_top.saturating_sub(overlap_y);
+            let right = (cut_right + overlap_x).min(width);
+            let bottom = (cut_bottom + overlap_y).min(height);
+            let mut tile = image::imageops::crop_imm(collage, left, top, right - left, bottom - top).to_image();
+
+            let mark_x = overlap_x.clamp(1, 40);
+            let mark_y = overlap_y.clamp(1, 40);
+            let tile_h = tile.height();
+            let tile_w = tile.width();
+            for cut_x in [cut_left, cut_right].into_iter().filter(|&x| x > left && x < right) {
+                let x = cut_x - left;
+                draw_crop_mark(&mut tile, x, 0, 0, mark_y.min(tile_h));
+                draw_crop_mark(&mut tile, x, tile_h.saturating_sub(mark_y), 0, mark_y.min(tile_h));
+            }
+            for cut_y in [cut_top, cut_bottom].into_iter().filter(|&y| y > top && y < bottom) {
+                let y = cut_y - top;
+                draw_crop_mark(&mut tile, 0, y, mark_x.min(tile_w), 0);
+                draw_crop_mark(&mut tile, tile_w.saturating_sub(mark_x), y, mark_x.min(tile_w), 0);
+            }
+            tiles.push((row, col, tile));
+        }
+    }
+    tiles
+}
+
+/// Draws a horizontal (`height` 0) or vertical (`width` 0) crop mark: a black line with a white
+/// outline so it stays visible on dark and light photos.
+fn draw_crop_mark(img: &mut RgbaImage, x: u32, y: u32, width: u32, height: u32) {
+    let (along_x, along_y) = (width.max(1), height.max(1));
+    for (outline, color) in [(1i64, Rgba([255, 255, 255, 255])), (0, Rgba([0, 0, 0, 255]))] {
+        for dy in 0..along_y as i64 {
+            for dx in 0..along_x as i64 {
+                for offset in -outline..=outline {
+                    let (px, py) = if width == 0 {
+                        (x as i64 + offset, y as i64 + dy)
+                    } else {
+                        (x as i64 + dx, y as i64 + offset)
+                    };
+                    if px >= 0 && py >= 0 && (px as u32) < img.width() && (py as u32) < img.height() {
+                        img.put_pixel(px as u32, py as u32, color);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Writes one PDF page per collage. Every collage is placed centered on a page of the configured
+/// paper size plus bleed, resampled to the requested DPI and embedded as JPEG.
+pub fn save_pdf(
+    collages: &[DynamicImage],
+    path: &Path,
+    options: &OutputOptions,
+    provenance: Option<&Provenance>,
+) -> Result<(), String> {
+    let mut doc = PdfDocument::empty("ImageGridOptimizer collage").with_creator(Provenance::creator_tool());
+    if let Some(provenance) = provenance.filter(|_| options.metadata) {
+        let sources = provenance.sources.iter().filter_map(|p| p.file_name()).map(|n| n.to_string_lossy().into_owned());
+        let parameters = provenance.parameters.iter().map(|(name, value)| format!("{}={}", name, value));
+        let seed = provenance.seed.map(|seed| format!("Seed={}", seed));
+        doc = doc
+            .with_subject(format!("Collage of {} images", provenance.sources.len()))
+            .with_keywords(seed.into_iter().chain(parameters).chain(sources).collect());
+    }
+    for (index, collage) in collages.iter().enumerate() {
+        let (short, long) = (options.paper.0.min(options.paper.1), options.paper.0.max(options.paper.1));
+        let landscape = collage.width() > collage.height();
+        let (trim_w, trim_h) = if landscape { (long, short) } else { (short, long) };
+        let page_w = trim_w + 2.0 * options.bleed_mm;
+        let page_h = trim_h + 2.0 * options.bleed_mm;
+
+        // Fit the collage into the page including the bleed so full-bleed layouts reach the edge
+        let fit = (page_w / collage.width() as f32).min(page_h / collage.height() as f32);
+        let image_w_mm = collage.width() as f32 * fit;
+        let image_h_mm = collage.height() as f32 * fit;
+        let pixels_w = (image_w_mm / 25.4 * options.dpi).round().max(1.0) as u32;
+        let pixels_h = (image_h_mm / 25.4 * options.dpi).round().max(1.0) as u32;
+        info!(
+            "PDF page {}: {:.1}x{:.1} mm incl. {:.1} mm bleed, image {}x{} px at {} DPI",
+            index + 1, page_w, page_h, options.bleed_mm, pixels_w, pixels_h, options.dpi
+        );
+
+        let resampled = collage.resize_exact(pixels_w, pixels_h, FilterType::Lanczos3).to_rgb8();
+        let mut jpeg = Vec::new();
+        JpegEncoder::new_with_quality(&mut Cursor::new(&mut jpeg), options.quality)
+            .encode_image(&resampled)
+            .map_err(|e| format!("Error encoding PDF image: {}", e))?;
+
+        let (page, layer) = doc.add_page(Mm(page_w), Mm(page_h), "Collage");
+        let image = Image::from(ImageXObject {
+            width: Px(pixels_w as usize),
+            height: Px(pixels_h as usize),
+            color_space: ColorSpace::Rgb,
+            bits_per_component: ColorBits::Bit8,
+            interpolate: true,
+            image_data: jpeg,
+            image_filter: Some(ImageFilter::DCT),
+            smask: None,
+            clipping_bbox: None,
+        });
+        image.add_to_layer(
+            doc.get_page(page).get_layer(layer),
+            ImageTransform {
+                translate_x: Some(Mm((page_w - image_w_mm) / 2.0)),
+                translate_y: Some(Mm((page_h - image_h_mm) / 2.0)),
+                dpi: Some(options.dpi),
+                ..Default::default()
+            },
+        );
+    }
+
+    let file = File::create(path).map_err(|e| format!("Error creating {}: {}", path.display(), e))?;
+    doc.save(&mut BufWriter::new(file)).map_err(|e| format!("Error writing {}: {}", path.display(), e))
+}
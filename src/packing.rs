@@ -1,26 +1,217 @@
 use std::collections::HashMap;
+use clap::ValueEnum;
 use image::{DynamicImage, GenericImageView};
 use rect_packer::{Config, Packer, Rect};
 
+use crate::error::Error;
+use crate::golden::pack_golden;
+use crate::hex::{median_cell_size, pack_hex};
+use crate::layout::Shape;
+use crate::pile::pack_pile;
+use crate::spiral::{pack_spiral, SpiralOptions};
+use crate::strip::{median_size, pack_strip, StripOptions};
+use crate::verify::validate_packed;
+
 pub const DESIRED_ASPECT_RATIO: f64 = 1.0;
-const PADDING_SIZE: u32 = 5;
+/// Space the packer leaves between images.
+pub const PADDING_SIZE: u32 = 5;
+
+/// How the images of a collage are arranged (`--layout`).
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum LayoutKind {
+    /// Rectangle packing: every image keeps its size and is fitted in wherever there is room.
+    #[default]
+    Packed,
+    /// One row or column of images scaled to a common size, optionally wrapped into several.
+    Strip,
+    /// A honeycomb of equally sized hexagonal cells, one image cropped to each.
+    Hex,
+    /// Images along a spiral around a central hero image, sized by their weight.
+    Spiral,
+    /// Packed images that may overlap like a pile of prints, with an evolved height in the pile
+    /// and shift for every image.
+    Pile,
+    /// The canvas split recursively at the golden ratio, one image cropped to fill each cell.
+    Golden,
+}
+
+impl LayoutKind {
+    /// How the images fill the rectangles of this layout.
+    pub fn shape(self) -> Shape {
+        match self {
+            LayoutKind::Packed | LayoutKind::Strip | LayoutKind::Spiral | LayoutKind::Pile => Shape::Rectangle,
+            LayoutKind::Hex => Shape::Hexagon,
+            LayoutKind::Golden => Shape::Fill,
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct PackingOptions {
+    pub layout: LayoutKind,
+    pub strip: StripOptions,
+    /// Width of the cells of `--layout hex`; the median shorter side of the images when not set,
+    /// see `for_images`.
+    pub hex_size: Option<u32>,
+    pub spiral: SpiralOptions,
+    /// How far an image of `--layout pile` may reach over its neighbours, as a share of its width
+    /// and height.
+    pub max_overlap: f64,
+    /// Largest rotation in degrees an image may receive at render time; its footprint is
+    /// enlarged to the axis-aligned bounding box of that rotation.
+    pub max_rotation: f64,
+    /// Extra height reserved below every image for its caption.
+    pub caption_height: u32,
+    /// Regions of the canvas that must stay empty, e.g. for text added later.
+    pub reserved: Vec<Rect>,
+    /// No image may end up smaller than this on its shorter side in the final collage. The fitness
+    /// penalizes layouts whose cells would be smaller; a final collage that still has a smaller
+    /// image fails with `Error::TileTooSmall`.
+    pub min_tile: u32,
+    /// The final collage is scaled down to fit this size (`--max-output`), which shrinks every
+    /// image along with it.
+    pub max_size: Option<(u32, u32)>,
+    /// Checks every packed layout for overlaps, placements outside the canvas and too little
+    /// padding, and fails with `Error::InvalidLayout` on an invalid one (`--validate-layout`).
+    pub validate: bool,
+    /// Images are packed at 1/`downscale` of their size, with the padding and the other sizes
+    /// shrunk along; 0 and 1 pack at full size. Set by `downscaled` for the coarse generations.
+    pub downscale: u32,
+}
+
+impl PackingOptions {
+    /// The options for packing the images at 1/`factor` of their size: the caption height,
+    /// reserved regions and size limits shrink by the same factor, so fitness values stay
+    /// comparable with packing at full size.
+    pub fn downscaled(&self, factor: u32) -> PackingOptions {
+        let factor = factor.max(1);
+        let shrink = |value: u32| value.div_ceil(factor);
+        PackingOptions {
+            caption_height: shrink(self.caption_height),
+            reserved: self
+                .reserved
+                .iter()
+                .map(|r| Rect::new(r.x / factor as i32, r.y / factor as i32, shrink(r.width as u32) as i32, shrink(r.height as u32) as i32))
+                .collect(),
+            min_tile: self.min_tile / factor,
+            max_size: self.max_size.map(|(w, h)| (shrink(w), shrink(h))),
+            downscale: self.scale() * factor,
+            ..self.clone()
+        }
+    }
+
+    /// The options with the settings that default to a property of the images filled in, e.g.
+    /// the size of a strip or of the cells of a hexagonal grid.
+    pub fn for_images(&self, image_map: &HashMap<u32, DynamicImage>) -> PackingOptions {
+        let mut options = self.clone();
+        if options.layout == LayoutKind::Strip && options.strip.size.is_none() {
+            options.strip.size = Some(median_size(image_map, options.strip.direction));
+        }
+        if options.layout == LayoutKind::Hex && options.hex_size.is_none() {
+            options.hex_size = Some(median_cell_size(image_map));
+        }
+        options
+    }
+
+    /// Aspect ratio of the canvas the fitness aims for: `DESIRED_ASPECT_RATIO`, or for a strip
+    /// the one at its target length.
+    pub fn aspect_ratio(&self) -> f64 {
+        match self.layout {
+            LayoutKind::Packed | LayoutKind::Hex | LayoutKind::Spiral | LayoutKind::Pile | LayoutKind::Golden => DESIRED_ASPECT_RATIO,
+            LayoutKind::Strip => self.strip.aspect_ratio(self.caption_height),
+        }
+    }
+
+    /// Factor the images are shrunk by when packing, at least 1.
+    pub fn scale(&self) -> u32 {
+        self.downscale.max(1)
+    }
+
+    /// Space the packer leaves between images. Downscaled packing rounds it down, which relaxes
+    /// it a little; from 1/6 of the size on, the images may touch.
+    pub fn padding(&self) -> u32 {
+        PADDING_SIZE / self.scale()
+    }
+}
 
+/// Packed rectangles per image id together with the used canvas width and height.
+pub type PackedLayout = (Vec<(u32, Rect)>, u32, u32);
+
+/// Genes of an image in `--layout packed`: its size and rotation, evolved by the `rescale` and
+/// `rotate` mutations.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ImageGene {
+    /// Size relative to the loaded image, from `MIN_GENE_SCALE` to `MAX_GENE_SCALE`.
+    pub scale: f64,
+    /// Rotation from -1 to 1 of `--max-rotation`; `None` leaves it to a random angle when the
+    /// layout is built, for which the packing reserves room.
+    pub turn: Option<f64>,
+}
+
+impl Default for ImageGene {
+    fn default() -> Self {
+        ImageGene { scale: 1.0, turn: None }
+    }
+}
+
+/// Range of `ImageGene::scale`.
+pub const MIN_GENE_SCALE: f64 = 0.5;
+pub const MAX_GENE_SCALE: f64 = 2.0;
+
+/// Packs the images onto a canvas that grows until all of them fit. Fails if an id is not in
+/// `image_map` or the images still do not fit after a few enlargements.
 pub fn pack_images(
-    image_ids: &Vec<u32>,
+    image_ids: &[u32],
     image_map: &HashMap<u32, DynamicImage>,
-) -> (Vec<(u32, Rect)>, u32, u32) {
+    options: &PackingOptions,
+) -> Result<PackedLayout, Error> {
     if image_ids.is_empty() {
-        return (vec![], 0, 0);
+        return Ok((vec![], 0, 0));
+    }
+    match options.layout {
+        LayoutKind::Packed => pack_genes(image_ids, &HashMap::new(), image_map, options),
+        LayoutKind::Strip => pack_strip(image_ids, image_map, options),
+        LayoutKind::Hex => pack_hex(image_ids, image_map, options),
+        LayoutKind::Spiral => pack_spiral(image_ids, image_map, options),
+        LayoutKind::Pile => pack_pile(image_ids, &HashMap::new(), image_map, options),
+        LayoutKind::Golden => pack_golden(image_ids, image_map, options),
     }
+}
+
+/// Packs the images as rectangles of their own sizes, changed by their `genes`; images without
+/// genes keep their size.
+pub fn pack_genes(
+    image_ids: &[u32],
+    genes: &HashMap<u32, ImageGene>,
+    image_map: &HashMap<u32, DynamicImage>,
+    options: &PackingOptions,
+) -> Result<PackedLayout, Error> {
+    let footprints = image_ids
+        .iter()
+        .map(|id| {
+            let img = image_map.get(id).ok_or(Error::UnknownImage(*id))?;
+            Ok((*id, gene_footprint(img, &genes.get(id).copied().unwrap_or_default(), options)))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    pack_rects(&footprints, options)
+}
 
-    let total_area: u64 = image_ids.iter().map(|id| {
-        let img = image_map.get(id).unwrap();
-        let (w, h) = img.dimensions();
-        (w as u64) * (h as u64)
-    }).sum();
+/// Packs rectangles of the given sizes per image id with the padding and reserved regions of
+/// `options`, in the way `pack_images` packs the footprints of the images.
+pub fn pack_rects(footprints: &[(u32, (u32, u32))], options: &PackingOptions) -> Result<PackedLayout, Error> {
+    // Every image takes its padding along, which outweighs the image itself for tiny ones
+    let padding = options.padding() as u64;
+    let total_area: u64 =
+        footprints.iter().map(|(_, (w, h))| (*w as u64 + padding) * (*h as u64 + padding)).sum::<u64>() + reserved_area(options);
 
+    let (reserved_right, reserved_bottom) = reserved_extent(options);
     let estimated_height = ((total_area as f64 / DESIRED_ASPECT_RATIO).sqrt()) as u32;
     let estimated_width = (DESIRED_ASPECT_RATIO * estimated_height as f64) as u32;
+    // The canvas must at least hold the widest and the tallest image, e.g. a single panorama
+    let widest = footprints.iter().map(|(_, (w, _))| *w).max().unwrap_or(0);
+    let tallest = footprints.iter().map(|(_, (_, h))| *h).max().unwrap_or(0);
+    let estimated_height = estimated_height.max(reserved_bottom).max(tallest);
+    let estimated_width = estimated_width.max(reserved_right).max(widest);
 
     let mut scale_factor = 1.0;
     let max_attempts = 5;
@@ -32,19 +223,21 @@ pub fn pack_images(
             width: pack_w,
             height: pack_h,
             border_padding: 0,
-            rectangle_padding: PADDING_SIZE as i32,
+            rectangle_padding: options.padding() as i32,
         };
 
-        let mut packer = Packer::new(config);
+        let mut packer = if options.reserved.is_empty() {
+            AnyPacker::Skyline(Packer::new(config))
+        } else {
+            AnyPacker::FreeRects(FreeRects::new(pack_w, pack_h, options.padding() as i32, &options.reserved))
+        };
         let mut packed_locations = Vec::new();
-        let mut max_width = 0;
-        let mut max_height = 0;
+        let mut max_width = reserved_right;
+        let mut max_height = reserved_bottom;
 
         let mut all_fit = true;
-        for id in image_ids {
-            let img = image_map.get(id).unwrap();
-            let (w, h) = img.dimensions();
-            if let Some(rect) = packer.pack(w as i32, h as i32, false) {
+        for (id, (w, h)) in footprints {
+            if let Some(rect) = packer.pack(*w as i32, *h as i32) {
                 packed_locations.push((*id, rect));
                 if (rect.x + rect.width) as u32 > max_width {
                     max_width = (rect.x + rect.width) as u32;
@@ -59,11 +252,229 @@ pub fn pack_images(
         }
 
         if all_fit {
-            return (packed_locations, max_width, max_height);
+            let layout = (packed_locations, max_width, max_height);
+            validate_packed(&layout, options)?;
+            return Ok(layout);
         }
 
         scale_factor *= 1.2;
     }
 
-    (vec![], 0, 0)
+    Err(Error::Packing(footprints.len()))
+}
+
+/// Pairs of images whose rectangles lie side by side or on top of each other, separated only by
+/// the padding between images.
+pub fn neighbours(packed: &[(u32, Rect)]) -> Vec<(u32, u32)> {
+    let tolerance = 2 * PADDING_SIZE as i32;
+    let overlap = |a_start: i32, a_len: i32, b_start: i32, b_len: i32| a_start.max(b_start) < (a_start + a_len).min(b_start + b_len);
+    let mut pairs = Vec::new();
+    for (i, (id_a, a)) in packed.iter().enumerate() {
+        for (id_b, b) in &packed[i + 1..] {
+            let gap_x = (b.x - a.right()).max(a.x - b.right());
+            let gap_y = (b.y - a.bottom()).max(a.y - b.bottom());
+            let beside = (0..=tolerance).contains(&gap_x) && overlap(a.y, a.height, b.y, b.height);
+            let above = (0..=tolerance).contains(&gap_y) && overlap(a.x, a.width, b.x, b.width);
+            if beside || above {
+                pairs.push((*id_a, *id_b));
+            }
+        }
+    }
+    pairs
+}
+
+/// Second packing pass: places images that are not part of `layout` into the free space
+/// left over, scaled down as far as needed but never below `min_size` on their shorter side.
+pub fn fill_gaps(
+    layout: &PackedLayout,
+    unused_ids: &[u32],
+    image_map: &HashMap<u32, DynamicImage>,
+    options: &PackingOptions,
+    min_size: u32,
+) -> Vec<(u32, Rect)> {
+    let (packed_locations, width, height) = layout;
+    let mut blocked: Vec<Rect> = packed_locations.iter().map(|(_, rect)| *rect).collect();
+    blocked.extend(options.reserved.iter().copied());
+    let mut free = FreeRects::new(*width as i32, *height as i32, options.padding() as i32, &blocked);
+
+    // Try the largest images first so they get the largest gaps
+    let mut candidates: Vec<(u32, (u32, u32))> = unused_ids
+        .iter()
+        .filter_map(|id| image_map.get(id).map(|img| (*id, footprint(img, options))))
+        .collect();
+    candidates.sort_by_key(|(_, (w, h))| std::cmp::Reverse(*w as u64 * *h as u64));
+
+    let min_size = min_size.max(1) as i32;
+    let mut placed = Vec::new();
+    for (id, (w, h)) in candidates {
+        let best_scale = free
+            .free_rects(min_size, min_size)
+            .into_iter()
+            .map(|gap| (gap.width as f64 / w as f64).min(gap.height as f64 / h as f64).min(1.0))
+            .filter(|scale| (w.min(h) as f64 * scale) >= min_size as f64)
+            .max_by(|a, b| a.total_cmp(b));
+        if let Some(scale) = best_scale {
+            let thumb_w = ((w as f64 * scale) as i32).max(1);
+            let thumb_h = ((h as f64 * scale) as i32).max(1);
+            if let Some(rect) = free.pack(thumb_w, thumb_h) {
+                placed.push((id, rect));
+            }
+        }
+    }
+    placed
+}
+
+/// Total area of the reserved regions, which counts as used space of the canvas.
+pub fn reserved_area(options: &PackingOptions) -> u64 {
+    options.reserved.iter().map(|r| r.width as u64 * r.height as u64).sum()
+}
+
+/// Right and bottom edge of the reserved regions; the canvas always covers them.
+fn reserved_extent(options: &PackingOptions) -> (u32, u32) {
+    options.reserved.iter().fold((0, 0), |(right, bottom), r| {
+        (right.max(r.right() as u32), bottom.max(r.bottom() as u32))
+    })
+}
+
+enum AnyPacker {
+    Skyline(Packer),
+    FreeRects(FreeRects),
+}
+
+impl AnyPacker {
+    fn pack(&mut self, width: i32, height: i32) -> Option<Rect> {
+        match self {
+            AnyPacker::Skyline(packer) => packer.pack(width, height, false),
+            AnyPacker::FreeRects(packer) => packer.pack(width, height),
+        }
+    }
+}
+
+/// Maximal-rectangles packer. Unlike the skyline packer of `rect_packer` it tracks every free
+/// area explicitly, so parts of the canvas can be blocked out before packing starts.
+pub struct FreeRects {
+    free: Vec<Rect>,
+    padding: i32,
+}
+
+impl FreeRects {
+    pub fn new(width: i32, height: i32, padding: i32, blocked: &[Rect]) -> FreeRects {
+        // Every rectangle occupies its padding to the right and bottom, so the canvas is
+        // extended by the padding to let images touch its edges.
+        let mut packer = FreeRects {
+            free: vec![Rect::new(0, 0, width + padding, height + padding)],
+            padding,
+        };
+        for rect in blocked {
+            packer.occupy(Rect::new(rect.x, rect.y, rect.width + padding, rect.height + padding));
+        }
+        packer
+    }
+
+    /// Places a `width`x`height` rectangle as far up and then as far left as possible.
+    pub fn pack(&mut self, width: i32, height: i32) -> Option<Rect> {
+        let padded_w = width + self.padding;
+        let padded_h = height + self.padding;
+        let best = self
+            .free
+            .iter()
+            .filter(|f| f.width >= padded_w && f.height >= padded_h)
+            .min_by_key(|f| (f.y + padded_h, f.x))?;
+        let placed = Rect::new(best.x, best.y, padded_w, padded_h);
+        self.occupy(placed);
+        Some(Rect::new(placed.x, placed.y, width, height))
+    }
+
+    /// Free rectangles that are at least `min_width`x`min_height` (padding excluded).
+    pub fn free_rects(&self, min_width: i32, min_height: i32) -> Vec<Rect> {
+        self.free
+            .iter()
+            .filter(|f| f.width - self.padding >= min_width && f.height - self.padding >= min_height)
+            .map(|f| Rect::new(f.x, f.y, f.width - self.padding, f.height - self.padding))
+            .collect()
+    }
+
+    /// Marks `used` as occupied and splits every overlapping free rectangle around it.
+    pub fn occupy(&mut self, used: Rect) {
+        let mut split = Vec::new();
+        self.free.retain(|free| {
+            if !overlaps(free, &used) {
+                return true;
+            }
+            if used.x > free.x {
+                split.push(Rect::new(free.x, free.y, used.x - free.x, free.height));
+            }
+            if used.right() < free.right() {
+                split.push(Rect::new(used.right(), free.y, free.right() - used.right(), free.height));
+            }
+            if used.y > free.y {
+                split.push(Rect::new(free.x, free.y, free.width, used.y - free.y));
+            }
+            if used.bottom() < free.bottom() {
+                split.push(Rect::new(free.x, used.bottom(), free.width, free.bottom() - used.bottom()));
+            }
+            false
+        });
+        self.free.extend(split);
+
+        // Drop free rectangles that are fully covered by another one
+        let mut i = 0;
+        while i < self.free.len() {
+            let covered = self
+                .free
+                .iter()
+                .enumerate()
+                .any(|(j, other)| j != i && other.contains(&self.free[i]) && (other != &self.free[i] || j < i));
+            if covered {
+                self.free.swap_remove(i);
+            } else {
+                i += 1;
+            }
+        }
+    }
+}
+
+pub fn overlaps(a: &Rect, b: &Rect) -> bool {
+    a.x < b.right() && b.x < a.right() && a.y < b.bottom() && b.y < a.bottom()
+}
+
+/// Size an image occupies in the packing, including room for its render-time rotation.
+pub fn footprint(img: &DynamicImage, options: &PackingOptions) -> (u32, u32) {
+    gene_footprint(img, &ImageGene::default(), options)
+}
+
+/// Size an image with `gene` occupies in the packing: scaled by the gene and with room for the
+/// rotation of the gene, or for any rotation up to `--max-rotation` if the gene has none.
+pub fn gene_footprint(img: &DynamicImage, gene: &ImageGene, options: &PackingOptions) -> (u32, u32) {
+    let (w, h) = img.dimensions();
+    let (w, h) = if gene.scale == 1.0 { (w, h) } else { (gene_scaled(w, gene.scale), gene_scaled(h, gene.scale)) };
+    let (w, h) = (w.div_ceil(options.scale()), h.div_ceil(options.scale()));
+    let h = h + options.caption_height;
+    if options.max_rotation == 0.0 {
+        return (w, h);
+    }
+    let angle = gene.turn.map_or(options.max_rotation, |turn| turn.clamp(-1.0, 1.0) * options.max_rotation);
+    rotated_bounds(w, h, angle.to_radians())
+}
+
+fn gene_scaled(side: u32, scale: f64) -> u32 {
+    ((side as f64 * scale.clamp(MIN_GENE_SCALE, MAX_GENE_SCALE)).round() as u32).max(1)
+}
+
+/// Axis-aligned bounding box of a `w`x`h` rectangle rotated by `angle` radians.
+pub fn rotated_bounds(w: u32, h: u32, angle: f64) -> (u32, u32) {
+    let (sin, cos) = angle.sin_cos();
+    let (sin, cos) = (sin.abs(), cos.abs());
+    let bounds_w = (w as f64 * cos + h as f64 * sin).ceil() as u32;
+    let bounds_h = (w as f64 * sin + h as f64 * cos).ceil() as u32;
+    (bounds_w, bounds_h)
+}
+
+/// Factor that shrinks a canvas of `width` x `height` to fit within `max_size`; 1 when it
+/// already fits.
+pub fn fit_scale(width: u32, height: u32, max_size: Option<(u32, u32)>) -> f64 {
+    match max_size {
+        Some((max_width, max_height)) => (max_width as f64 / width.max(1) as f64).min(max_height as f64 / height.max(1) as f64).min(1.0),
+        None => 1.0,
+    }
 }
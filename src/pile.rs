@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, GenericImageView, Rgba};
+use rect_packer::Rect;
+
+use crate::error::Error;
+use crate::packing::{footprint, pack_rects, PackedLayout, PackingOptions};
+use crate::verify::validate_packed;
+
+/// Side of the grid of cells the salient regions of an image are measured in.
+pub const SALIENCY_GRID: usize = 8;
+/// Side of the thumbnail the saliency is computed on, in pixels.
+const SALIENCY_SIZE: u32 = 32;
+
+/// Genes of an image in `--layout pile`: how far it is moved from its packed place and how high it
+/// lies in the pile.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PileGene {
+    /// Shift to the right and down, from -1 to 1 of the overlap an image may have.
+    pub dx: f64,
+    pub dy: f64,
+    /// Height in the pile from 0 (bottom) to 1 (top); images of the same height are drawn in
+    /// packing order.
+    pub z: f64,
+}
+
+impl Default for PileGene {
+    fn default() -> Self {
+        PileGene { dx: 0.0, dy: 0.0, z: 0.5 }
+    }
+}
+
+/// Lays the images out as a pile: they are packed with every footprint shrunk by the overlap they
+/// may have, then grown back around their place and shifted by their genes, so every image reaches
+/// over its neighbours by at most `max_overlap` of its width and height. The rectangles are
+/// returned from the bottom of the pile to the top, which is the order they are drawn in.
+pub fn pack_pile(
+    image_ids: &[u32],
+    genes: &HashMap<u32, PileGene>,
+    image_map: &HashMap<u32, DynamicImage>,
+    options: &PackingOptions,
+) -> Result<PackedLayout, Error> {
+    let overlap = options.max_overlap.clamp(0.0, 1.0);
+    let footprints = image_ids
+        .iter()
+        .map(|id| image_map.get(id).map(|img| (*id, footprint(img, options))).ok_or(Error::UnknownImage(*id)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let shrink = |side: u32| ((side as f64 * (1.0 - overlap)).round() as u32).max(1);
+    let cores: Vec<(u32, (u32, u32))> = footprints.iter().map(|(id, (w, h))| (*id, (shrink(*w), shrink(*h)))).collect();
+    let (packed, _, _) = pack_rects(&cores, options)?;
+
+    let mut pile: Vec<(u32, Rect, f64)> = packed
+        .iter()
+        .zip(&footprints)
+        .map(|((id, core), (_, (w, h)))| {
+            let gene = genes.get(id).copied().unwrap_or_default();
+            let (w, h) = (*w as f64, *h as f64);
+            let center_x = core.x as f64 + core.width as f64 / 2.0 + gene.dx.clamp(-1.0, 1.0) * overlap * w / 2.0;
+            let center_y = core.y as f64 + core.height as f64 / 2.0 + gene.dy.clamp(-1.0, 1.0) * overlap * h / 2.0;
+            let rect = Rect::new((center_x - w / 2.0).round() as i32, (center_y - h / 2.0).round() as i32, w as i32, h as i32);
+            (*id, rect, gene.z)
+        })
+        .collect();
+    pile.sort_by(|a, b| a.2.total_cmp(&b.2));
+
+    // Images grown over the edge of the packed area move the canvas along
+    let left = pile.iter().map(|(_, rect, _)| rect.x).min().unwrap_or(0);
+    let top = pile.iter().map(|(_, rect, _)| rect.y).min().unwrap_or(0);
+    let right = pile.iter().map(|(_, rect, _)| rect.right()).max().unwrap_or(0);
+    let bottom = pile.iter().map(|(_, rect, _)| rect.bottom()).max().unwrap_or(0);
+    let placed = pile.into_iter().map(|(id, rect, _)| (id, Rect::new(rect.x - left, rect.y - top, rect.width, rect.height))).collect();
+    let layout = (placed, (right - left) as u32, (bottom - top) as u32);
+    validate_packed(&layout, options)?;
+    Ok(layout)
+}
+
+/// Area covered by at least one of the rectangles, counting overlaps once.
+pub fn covered_area(packed: &[(u32, Rect)]) -> u64 {
+    let mut xs: Vec<i32> = packed.iter().flat_map(|(_, rect)| [rect.x, rect.right()]).collect();
+    xs.sort_unstable();
+    xs.dedup();
+    let mut area = 0;
+    for slab in xs.windows(2) {
+        let mut spans: Vec<(i32, i32)> = packed
+            .iter()
+            .filter(|(_, rect)| rect.x <= slab[0] && rect.right() >= slab[1])
+            .map(|(_, rect)| (rect.y, rect.bottom()))
+            .collect();
+        spans.sort_unstable();
+        let mut covered = 0;
+        let mut reached = i32::MIN;
+        for (start, end) in spans {
+            let start = start.max(reached);
+            if end > start {
+                covered += (end - start) as u64;
+                reached = end;
+            }
+        }
+        area += covered * (slab[1] - slab[0]) as u64;
+    }
+    area
+}
+
+/// Where the eye goes in an image: a `SALIENCY_GRID`x`SALIENCY_GRID` grid of weights that add up
+/// to 1, row by row. Regions count the more, the more their color stands out from the average of
+/// the image and the more detail they have, with a bias to the center, where faces and subjects
+/// usually are.
+pub fn saliency_grid<I: GenericImageView<Pixel = Rgba<u8>>>(img: &I) -> Vec<f32> {
+    let thumb = imageops::resize(img, SALIENCY_SIZE, SALIENCY_SIZE, FilterType::Triangle);
+    let pixels = (SALIENCY_SIZE * SALIENCY_SIZE) as f32;
+    let mut mean = [0.0f32; 3];
+    for pixel in thumb.pixels() {
+        for (sum, value) in mean.iter_mut().zip(pixel.0) {
+            *sum += value as f32 / pixels;
+        }
+    }
+    let luma = |x: u32, y: u32| {
+        let [r, g, b, _] = thumb.get_pixel(x.min(SALIENCY_SIZE - 1), y.min(SALIENCY_SIZE - 1)).0;
+        0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32
+    };
+
+    let cell = SALIENCY_SIZE as usize / SALIENCY_GRID;
+    let half = SALIENCY_SIZE as f32 / 2.0;
+    let mut grid = vec![0.0f32; SALIENCY_GRID * SALIENCY_GRID];
+    for (x, y, pixel) in thumb.enumerate_pixels() {
+        let contrast = pixel.0[..3].iter().zip(mean).map(|(value, mean)| (*value as f32 - mean).powi(2)).sum::<f32>().sqrt();
+        let detail = (luma(x + 1, y) - luma(x, y)).abs() + (luma(x, y + 1) - luma(x, y)).abs();
+        let distance = ((x as f32 + 0.5 - half).powi(2) + (y as f32 + 0.5 - half).powi(2)).sqrt() / (half * std::f32::consts::SQRT_2);
+        grid[(y as usize / cell) * SALIENCY_GRID + x as usize / cell] += (contrast + detail) * (1.0 - 0.5 * distance);
+    }
+    let total: f32 = grid.iter().sum();
+    if total <= 0.0 {
+        return vec![1.0 / grid.len() as f32; grid.len()];
+    }
+    grid.iter().map(|weight| weight / total).collect()
+}
+
+/// Center of the window that takes in most of the salient regions of `grid` (see
+/// `saliency_grid`), as a share of the width and height of the image. `window` is the width and
+/// height of the part of the image that remains visible when it is cropped, also as a share of
+/// the image; of windows that take in as much, the one closest to the center wins.
+pub fn salient_focus(grid: &[f32], window: (f64, f64)) -> (f64, f64) {
+    const STEPS: usize = 32;
+    let (window_w, window_h) = (window.0.clamp(0.0, 1.0), window.1.clamp(0.0, 1.0));
+    let centered = ((1.0 - window_w) / 2.0, (1.0 - window_h) / 2.0);
+    if grid.len() != SALIENCY_GRID * SALIENCY_GRID {
+        return (0.5, 0.5);
+    }
+    let cell = 1.0 / SALIENCY_GRID as f64;
+    // Share of cell `index` in a row or column that lies between `start` and `start + len`
+    let overlap = |start: f64, len: f64, index: usize| ((start + len).min((index + 1) as f64 * cell) - start.max(index as f64 * cell)).max(0.0) / cell;
+    let contained = |(left, top): (f64, f64)| {
+        grid.iter()
+            .enumerate()
+            .map(|(index, weight)| *weight as f64 * overlap(left, window_w, index % SALIENCY_GRID) * overlap(top, window_h, index / SALIENCY_GRID))
+            .sum::<f64>()
+    };
+    let offsets = |len: f64| -> Vec<f64> {
+        if len >= 1.0 {
+            return vec![0.0];
+        }
+        (0..=STEPS).map(|step| (1.0 - len) * step as f64 / STEPS as f64).collect()
+    };
+    let distance = |(left, top): (f64, f64)| (left - centered.0).abs() + (top - centered.1).abs();
+
+    let (mut best, mut best_share) = (centered, contained(centered));
+    for left in offsets(window_w) {
+        for top in offsets(window_h) {
+            let share = contained((left, top));
+            if share > best_share + 1e-6 || (share > best_share - 1e-6 && distance((left, top)) < distance(best)) {
+                (best, best_share) = ((left, top), share);
+            }
+        }
+    }
+    (best.0 + window_w / 2.0, best.1 + window_h / 2.0)
+}
+
+/// Average share of the salient regions of the images that lies under images higher up in the
+/// pile, from 0 to 1. `packed` goes from the bottom of the pile to the top; images without a
+/// saliency grid count every region the same.
+pub fn occlusion(packed: &[(u32, Rect)], saliency: &HashMap<u32, Vec<f32>>) -> f64 {
+    if packed.is_empty() {
+        return 0.0;
+    }
+    let uniform = vec![1.0 / (SALIENCY_GRID * SALIENCY_GRID) as f32; SALIENCY_GRID * SALIENCY_GRID];
+    let mut total = 0.0;
+    for (index, (id, rect)) in packed.iter().enumerate() {
+        let above: Vec<&Rect> = packed[index + 1..]
+            .iter()
+            .map(|(_, other)| other)
+            .filter(|other| other.x < rect.right() && rect.x < other.right() && other.y < rect.bottom() && rect.y < other.bottom())
+            .collect();
+        if above.is_empty() {
+            continue;
+        }
+        let weights = saliency.get(id).filter(|grid| grid.len() == uniform.len()).unwrap_or(&uniform);
+        for (cell, weight) in weights.iter().enumerate() {
+            // Cells count as hidden when their center is
+            let x = rect.x as f64 + ((cell % SALIENCY_GRID) as f64 + 0.5) * rect.width as f64 / SALIENCY_GRID as f64;
+            let y = rect.y as f64 + ((cell / SALIENCY_GRID) as f64 + 0.5) * rect.height as f64 / SALIENCY_GRID as f64;
+            let hidden = above
+                .iter()
+                .any(|other| x >= other.x as f64 && x < other.right() as f64 && y >= other.y as f64 && y < other.bottom() as f64);
+            if hidden {
+                total += *weight as f64;
+            }
+        }
+    }
+    total / packed.len() as f64
+}
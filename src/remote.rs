@@ -0,0 +1,93 @@
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use rayon::prelude::*;
+
+/// Settings for downloading images listed by URL.
+#[derive(Clone)]
+pub struct DownloadOptions {
+    /// Downloads are kept here and reused by later runs.
+    pub cache_dir: PathBuf,
+    /// Larger files are rejected.
+    pub max_bytes: u64,
+    /// Number of simultaneous downloads.
+    pub jobs: usize,
+}
+
+impl Default for DownloadOptions {
+    fn default() -> Self {
+        DownloadOptions {
+            cache_dir: std::env::temp_dir().join("ImageGridOptimizer").join("downloads"),
+            max_bytes: 50 * 1024 * 1024,
+            jobs: 8,
+        }
+    }
+}
+
+pub fn is_url(value: &str) -> bool {
+    let lower = value.to_ascii_lowercase();
+    lower.starts_with("http://") || lower.starts_with("https://")
+}
+
+/// Downloads the URLs concurrently into the cache directory and returns the local file of each,
+/// in the order of `urls`. Files already in the cache are not downloaded again.
+pub fn download_all(urls: &[String], options: &DownloadOptions) -> Vec<Result<PathBuf, String>> {
+    let pool = match rayon::ThreadPoolBuilder::new().num_threads(options.jobs.max(1)).build() {
+        Ok(pool) => pool,
+        Err(e) => return urls.iter().map(|_| Err(format!("Error starting downloads: {}", e))).collect(),
+    };
+    let agent = ureq::AgentBuilder::new().timeout(Duration::from_secs(60)).build();
+    pool.install(|| urls.par_iter().map(|url| download(&agent, url, options)).collect())
+}
+
+fn download(agent: &ureq::Agent, url: &str, options: &DownloadOptions) -> Result<PathBuf, String> {
+    let target = cache_path(&options.cache_dir, url);
+    if target.is_file() {
+        info!("Using cached download: {}", url);
+        return Ok(target);
+    }
+
+    info!("Downloading: {}", url);
+    let error = |e: &dyn std::fmt::Display| format!("Error downloading {}: {}", url, e);
+    let response = agent.get(url).call().map_err(|e| error(&e))?;
+    if let Some(length) = response.header("Content-Length").and_then(|l| l.parse::<u64>().ok()) {
+        if length > options.max_bytes {
+            return Err(error(&format!("{} bytes exceed the limit of {} bytes", length, options.max_bytes)));
+        }
+    }
+    // The server may not announce the size, so never read more than the limit
+    let mut bytes = Vec::new();
+    response
+        .into_reader()
+        .take(options.max_bytes + 1)
+        .read_to_end(&mut bytes)
+        .map_err(|e| error(&e))?;
+    if bytes.len() as u64 > options.max_bytes {
+        return Err(error(&format!("file exceeds the limit of {} bytes", options.max_bytes)));
+    }
+
+    let dir = target.parent().unwrap_or(&options.cache_dir);
+    fs::create_dir_all(dir).map_err(|e| format!("Error creating {}: {}", dir.display(), e))?;
+    // Write to a temporary name first so an interrupted run never leaves a truncated cache entry
+    let partial = dir.join(".partial");
+    fs::write(&partial, &bytes).map_err(|e| format!("Error writing {}: {}", partial.display(), e))?;
+    fs::rename(&partial, &target).map_err(|e| format!("Error writing {}: {}", target.display(), e))?;
+    Ok(target)
+}
+
+/// Location of a URL in the cache: a directory named after a hash of the whole URL, so different
+/// URLs never collide, containing the file under its own name so labels and extensions still work.
+fn cache_path(cache_dir: &Path, url: &str) -> PathBuf {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    let name: String = path
+        .rsplit('/')
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || "._-".contains(c) { c } else { '_' })
+        .collect();
+    let name = if name.trim_matches('.').is_empty() { "download".to_string() } else { name };
+    cache_dir.join(format!("{:08x}", crc32fast::hash(url.as_bytes()))).join(name)
+}
@@ -0,0 +1,107 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+use std::time::Duration;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use image::{DynamicImage, ImageFormat};
+
+use crate::ga::GenerationStats;
+use crate::stats::fitness_chart;
+use crate::svg::escape_xml;
+
+/// Longer side of the collage preview in the report.
+const PREVIEW_SIZE: u32 = 1200;
+const THUMBNAIL_SIZE: u32 = 160;
+
+/// Everything shown in the HTML report of a run.
+pub struct Report<'a> {
+    pub collage: &'a DynamicImage,
+    pub fitness: f64,
+    pub parameters: &'a [(&'static str, String)],
+    pub history: &'a [GenerationStats],
+    /// Source file and loaded image of every image in the collage.
+    pub used: Vec<(&'a Path, &'a DynamicImage)>,
+    /// Candidates the optimizer left out.
+    pub unused: Vec<(&'a Path, &'a DynamicImage)>,
+    pub timings: &'a [(&'static str, Duration)],
+}
+
+/// Writes a self-contained HTML page with a preview of the collage, the parameters, the fitness
+/// curve, thumbnails of the used and unused images and the time spent in every step. All images
+/// are embedded, so the file can be sent to others as it is.
+pub fn save_report(report: &Report, path: &Path) -> Result<(), String> {
+    let error = |e: &dyn std::fmt::Display| format!("Error writing report {}: {}", path.display(), e);
+    let preview = data_uri(&report.collage.thumbnail(PREVIEW_SIZE, PREVIEW_SIZE), ImageFormat::Jpeg).map_err(|e| error(&e))?;
+    let chart = DynamicImage::ImageRgba8(fitness_chart(report.history, 900, 400));
+    let chart = data_uri(&chart, ImageFormat::Png).map_err(|e| error(&e))?;
+
+    let mut html = String::new();
+    let _ = writeln!(html, "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Collage report</title>");
+    let _ = writeln!(html, "<style>{}</style>\n</head>\n<body>", STYLE);
+    let _ = writeln!(html, "<h1>Collage report</h1>");
+    let _ = writeln!(
+        html,
+        "<p>{} of {} images used, {}x{} pixels, fitness {:.5}.</p>",
+        report.used.len(),
+        report.used.len() + report.unused.len(),
+        report.collage.width(),
+        report.collage.height(),
+        report.fitness
+    );
+    let _ = writeln!(html, "<img class=\"preview\" src=\"{}\" alt=\"Collage preview\">", preview);
+
+    let _ = writeln!(html, "<h2>Parameters</h2>\n<table>");
+    for (name, value) in report.parameters {
+        let _ = writeln!(html, "<tr><th>{}</th><td>{}</td></tr>", name, escape_xml(value));
+    }
+    let _ = writeln!(html, "</table>");
+
+    let _ = writeln!(html, "<h2>Fitness</h2>\n<img src=\"{}\" alt=\"Fitness over the generations\">", chart);
+
+    let _ = writeln!(html, "<h2>Timing</h2>\n<table>");
+    for (step, duration) in report.timings {
+        let _ = writeln!(html, "<tr><th>{}</th><td>{:.2} s</td></tr>", step, duration.as_secs_f64());
+    }
+    let total: Duration = report.timings.iter().map(|(_, duration)| *duration).sum();
+    let _ = writeln!(html, "<tr><th>Total</th><td>{:.2} s</td></tr>\n</table>", total.as_secs_f64());
+
+    for (title, images) in [("Used images", &report.used), ("Unused images", &report.unused)] {
+        let _ = writeln!(html, "<h2>{} ({})</h2>\n<div class=\"thumbnails\">", title, images.len());
+        for (source, img) in images {
+            let thumbnail = data_uri(&img.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE), ImageFormat::Jpeg).map_err(|e| error(&e))?;
+            let name = source.file_name().map_or_else(|| source.display().to_string(), |n| n.to_string_lossy().into_owned());
+            let _ = writeln!(
+                html,
+                "<figure><img src=\"{}\" title=\"{}\"><figcaption>{}</figcaption></figure>",
+                thumbnail,
+                escape_xml(&source.display().to_string()),
+                escape_xml(&name)
+            );
+        }
+        let _ = writeln!(html, "</div>");
+    }
+    let _ = writeln!(html, "</body>\n</html>");
+    fs::write(path, html).map_err(|e| error(&e))
+}
+
+fn data_uri(img: &DynamicImage, format: ImageFormat) -> Result<String, String> {
+    let (mime, img) = match format {
+        // JPEG has no alpha channel
+        ImageFormat::Jpeg => ("image/jpeg", DynamicImage::ImageRgb8(img.to_rgb8())),
+        _ => ("image/png", img.clone()),
+    };
+    let mut bytes = Vec::new();
+    img.write_to(&mut Cursor::new(&mut bytes), format).map_err(|e| e.to_string())?;
+    Ok(format!("data:{};base64,{}", mime, STANDARD.encode(bytes)))
+}
+
+const STYLE: &str = "body { font-family: sans-serif; max-width: 1240px; margin: 2em auto; color: #222; }
+.preview { max-width: 100%; box-shadow: 0 2px 8px rgba(0, 0, 0, 0.3); }
+table { border-collapse: collapse; }
+th, td { text-align: left; padding: 0.2em 1em 0.2em 0; }
+.thumbnails { display: flex; flex-wrap: wrap; gap: 0.8em; }
+figure { margin: 0; width: 160px; }
+figcaption { font-size: 0.8em; overflow: hidden; text-overflow: ellipsis; white-space: nowrap; }";
@@ -0,0 +1,91 @@
+use image::imageops::{rotate270, rotate90};
+use image::{DynamicImage, RgbaImage};
+
+/// Aspect ratios (width / height) that images are retargeted to. Images sharing a ratio line up in
+/// rows and columns, which leaves fewer gaps when packing.
+const STANDARD_RATIOS: [f64; 9] = [1.0, 5.0 / 4.0, 4.0 / 3.0, 3.0 / 2.0, 16.0 / 9.0, 4.0 / 5.0, 3.0 / 4.0, 2.0 / 3.0, 9.0 / 16.0];
+
+/// Images this close to a standard ratio are left alone.
+const TOLERANCE: f64 = 0.005;
+
+/// Removes low-energy seams (paths of pixels through uniform areas such as sky or walls) until the
+/// image has the nearest standard aspect ratio, shrinking the width or height by at most
+/// `max_reduction` (a fraction). Returns `None` when no standard ratio is close enough or the image
+/// already has one.
+pub fn retarget_to_standard_ratio(img: &DynamicImage, max_reduction: f64) -> Option<DynamicImage> {
+    let (width, height) = (img.width(), img.height());
+    let ratio = width as f64 / height.max(1) as f64;
+    if STANDARD_RATIOS.iter().any(|standard| (ratio / standard - 1.0).abs() < TOLERANCE) {
+        return None;
+    }
+    // Only shrinking is content-aware: narrower ratios remove columns, wider ones remove rows
+    let (reduction, target) = STANDARD_RATIOS
+        .iter()
+        .map(|&standard| if standard < ratio { (1.0 - standard / ratio, standard) } else { (1.0 - ratio / standard, standard) })
+        .min_by(|a, b| a.0.total_cmp(&b.0))?;
+    if reduction > max_reduction {
+        return None;
+    }
+
+    let rgba = img.to_rgba8();
+    let carved = if target < ratio {
+        carve_columns(&rgba, (height as f64 * target).round() as u32)
+    } else {
+        let target_height = (width as f64 / target).round() as u32;
+        rotate270(&carve_columns(&rotate90(&rgba), target_height))
+    };
+    Some(DynamicImage::ImageRgba8(carved))
+}
+
+/// Narrows the image to `target_width` by repeatedly removing the vertical seam with the lowest
+/// total gradient energy.
+fn carve_columns(img: &RgbaImage, target_width: u32) -> RgbaImage {
+    let height = img.height() as usize;
+    let mut rows: Vec<Vec<[u8; 4]>> = img.rows().map(|row| row.map(|p| p.0).collect()).collect();
+    let mut width = img.width() as usize;
+    while width > (target_width as usize).max(1) {
+        let seam = lowest_energy_seam(&rows, width);
+        for (row, x) in rows.iter_mut().zip(seam) {
+            row.remove(x);
+        }
+        width -= 1;
+    }
+    RgbaImage::from_fn(width as u32, height as u32, |x, y| image::Rgba(rows[y as usize][x as usize]))
+}
+
+/// Column of the seam in every row, found by dynamic programming over the gradient energy.
+fn lowest_energy_seam(rows: &[Vec<[u8; 4]>], width: usize) -> Vec<usize> {
+    let height = rows.len();
+    let luma: Vec<Vec<f32>> = rows
+        .iter()
+        .map(|row| row.iter().map(|[r, g, b, _]| 0.299 * *r as f32 + 0.587 * *g as f32 + 0.114 * *b as f32).collect())
+        .collect();
+    let energy = |x: usize, y: usize| {
+        let dx = luma[y][(x + 1).min(width - 1)] - luma[y][x.saturating_sub(1)];
+        let dy = luma[(y + 1).min(height - 1)][x] - luma[y.saturating_sub(1)][x];
+        dx.abs() + dy.abs()
+    };
+
+    let mut cost: Vec<Vec<f32>> = vec![(0..width).map(|x| energy(x, 0)).collect()];
+    for y in 1..height {
+        let previous = &cost[y - 1];
+        let row = (0..width)
+            .map(|x| {
+                let cheapest = previous[x.saturating_sub(1)..=(x + 1).min(width - 1)].iter().copied().fold(f32::INFINITY, f32::min);
+                energy(x, y) + cheapest
+            })
+            .collect();
+        cost.push(row);
+    }
+
+    let mut seam = vec![0; height];
+    let last = &cost[height - 1];
+    seam[height - 1] = (0..width).min_by(|&a, &b| last[a].total_cmp(&last[b])).unwrap_or(0);
+    for y in (0..height - 1).rev() {
+        let x = seam[y + 1];
+        seam[y] = (x.saturating_sub(1)..=(x + 1).min(width - 1))
+            .min_by(|&a, &b| cost[y][a].total_cmp(&cost[y][b]))
+            .unwrap_or(x);
+    }
+    seam
+}
@@ -0,0 +1,69 @@
+use std::fs;
+
+use image::{Rgba, RgbaImage};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+use crate::bench::ASPECT_RATIOS;
+use crate::cli::SamplesArgs;
+use crate::error::Error;
+use crate::text::{default_font, draw_text, text_width};
+
+/// Width of the darker frame around every sample, relative to its shorter side.
+const BORDER_SHARE: f64 = 0.02;
+
+/// Writes `count` colored rectangles of mixed photo formats to the output directory, labeled with
+/// their number and size, so the optimizer can be tried without real photos.
+pub fn generate_samples(args: &SamplesArgs) -> Result<(), Error> {
+    if let Err(e) = fs::create_dir_all(&args.output) {
+        eprintln!("Error creating {}: {}", args.output.display(), e);
+        return Err(Error::Save(args.count));
+    }
+    let font = default_font();
+    let mut rng = StdRng::seed_from_u64(args.seed);
+    let digits = args.count.to_string().len();
+    let mut failed = 0;
+    for number in 1..=args.count {
+        let (w, h) = *ASPECT_RATIOS.choose(&mut rng).unwrap_or(&(1, 1));
+        // Longer sides vary between half and all of --size, as photos from different cameras do
+        let longer = rng.gen_range(args.size / 2..=args.size).max(1);
+        let width = (longer * w / w.max(h)).max(1);
+        let height = (longer * h / w.max(h)).max(1);
+        let color = Rgba([rng.gen(), rng.gen(), rng.gen(), 255]);
+
+        let mut img = RgbaImage::from_pixel(width, height, darken(color));
+        let border = (width.min(height) as f64 * BORDER_SHARE).ceil() as u32;
+        for y in border..height.saturating_sub(border) {
+            for x in border..width.saturating_sub(border) {
+                img.put_pixel(x, y, color);
+            }
+        }
+
+        // Black or white, whichever stands out more from the fill
+        let luma = 0.299 * color[0] as f32 + 0.587 * color[1] as f32 + 0.114 * color[2] as f32;
+        let ink = if luma > 140.0 { Rgba([0, 0, 0, 255]) } else { Rgba([255, 255, 255, 255]) };
+        let lines = [format!("#{}", number), format!("{}x{}", width, height)];
+        let size = width.min(height) as f32 / 6.0;
+        for (i, line) in lines.iter().enumerate() {
+            let x = (width as f32 - text_width(&font, size, line)) / 2.0;
+            let y = height as f32 / 2.0 - size * 1.2 + i as f32 * size * 1.4;
+            draw_text(&mut img, &font, size, line, x, y, ink);
+        }
+
+        let path = args.output.join(format!("sample_{:0digits$}.png", number, digits = digits));
+        if let Err(e) = img.save(&path) {
+            eprintln!("Error saving {}: {}", path.display(), e);
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        return Err(Error::Save(failed));
+    }
+    info!("Wrote {} sample images to {}", args.count, args.output.display());
+    Ok(())
+}
+
+fn darken(color: Rgba<u8>) -> Rgba<u8> {
+    Rgba([color[0] / 2, color[1] / 2, color[2] / 2, 255])
+}
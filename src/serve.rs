@@ -0,0 +1,458 @@
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use base64::Engine;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::cancel::CancellationToken;
+use crate::cli::{parse_optimize_args, Args, Input, ServeArgs};
+use crate::error::{panic_message, Error};
+use crate::events;
+
+/// Options a job may set: those of the optimization and the drawing. Everything that reads or
+/// writes a file of its own, such as `--output`, `--cache-dir`, `--font` or `--from-list` (which
+/// would also wait for the server's stdin with `-`), is left to the server, as are the modes that
+/// watch, only estimate or take over the terminal. `--follow-symlinks` is left out too, so links
+/// cannot lead a job out of the directories it may read.
+const JOB_OPTIONS: &[&str] = &[
+    "-f", "--filter", "--filter-regex", "--include-hidden", "--strict", "-w", "--width", "--scale-mode",
+    "--max-memory", "--min-width", "--min-height", "--min-bytes", "--ar-min", "--ar-max", "--min-sharpness", "--since", "--until",
+    "--dedup", "--dedup-threshold", "--video-every", "--animated-frame", "--raster-dpi", "--retarget", "--retarget-max",
+    "--color-flow", "--color-flow-strength", "--ratings", "--rating-bonus", "--pin-rating", "--pop-size", "--gens",
+    "--min-images", "--max-images", "--per-dir-min", "--per-dir-max", "--mutation-rate", "--mutation-weights",
+    "--crossover-rate", "--crossover", "--greedy-seeds", "--restart-after", "--restart-keep", "--sharing-radius",
+    "--coarse-to-fine", "--coarse-factor", "--seed", "--top-n", "--restarts", "--layout", "--direction", "--strip-size",
+    "--strip-length", "--strip-rows", "--hex-size", "--spiral-hero", "--max-overlap", "--pages", "--scrapbook",
+    "--max-rotation", "--reserve", "--min-tile", "--gap-fill", "--validate-layout", "--shadow", "--shadow-blur",
+    "--shadow-offset", "--shadow-opacity", "--corner-radius", "--labels", "--label-position", "--label-size", "--title",
+    "--subtitle", "--title-position", "--title-size", "--watermark-pos", "--watermark-opacity", "--background",
+    "--background-mode", "--fill", "--style-filter", "--normalize-tones", "--max-output", "--paper", "--dpi",
+    "--physical-size", "--min-dpi", "--bleed", "--tile", "--tile-overlap", "--no-metadata", "--quality",
+    "--png-compression", "-q", "--quiet", "--save-every",
+];
+
+/// File name of the collage when the job does not choose one.
+const DEFAULT_OUTPUT: &str = "collage.jpg";
+
+/// A client gives up on a request that sends nothing for this long.
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Body of `POST /jobs`.
+#[derive(Deserialize)]
+struct JobRequest {
+    /// Directories or archives on the server.
+    #[serde(default)]
+    dirs: Vec<String>,
+    /// Uploaded images, used instead of `dirs`.
+    #[serde(default)]
+    files: Vec<UploadedFile>,
+    /// Command line options of the optimization, e.g. `["--pop-size", "200"]`.
+    #[serde(default)]
+    args: Vec<String>,
+    /// File name of the collage; its extension selects the format.
+    output: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct UploadedFile {
+    name: String,
+    /// The file's content in base64.
+    data: String,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum JobState {
+    Queued,
+    Running,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+impl JobState {
+    fn name(self) -> &'static str {
+        match self {
+            JobState::Queued => "queued",
+            JobState::Running => "running",
+            JobState::Done => "done",
+            JobState::Failed => "failed",
+            JobState::Cancelled => "cancelled",
+        }
+    }
+}
+
+struct Job {
+    state: JobState,
+    generation: usize,
+    generations: usize,
+    best: Option<f64>,
+    error: Option<String>,
+    output: PathBuf,
+    cancel: CancellationToken,
+}
+
+impl Job {
+    fn to_json(&self, id: usize) -> Value {
+        let saved = self.output.exists() && matches!(self.state, JobState::Done | JobState::Cancelled);
+        json!({
+            "id": id,
+            "state": self.state.name(),
+            "generation": self.generation,
+            "generations": self.generations,
+            "best": self.best,
+            "error": self.error,
+            "result": saved.then(|| format!("/jobs/{}/result", id)),
+            "layout": saved.then(|| format!("/jobs/{}/layout", id)),
+        })
+    }
+}
+
+type Jobs = Arc<Mutex<Vec<Job>>>;
+
+/// Settings of the server that every connection needs.
+struct Config {
+    jobs_dir: PathBuf,
+    max_upload: u64,
+    allow_origin: Option<String>,
+    /// Canonical `--allow-dir` directories.
+    allow_dirs: Vec<PathBuf>,
+}
+
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn json(status: u16, value: Value) -> Response {
+        Response { status, content_type: "application/json", body: value.to_string().into_bytes() }
+    }
+
+    fn error(status: u16, message: impl Into<String>) -> Response {
+        Response::json(status, json!({ "error": message.into() }))
+    }
+}
+
+/// Listens for HTTP requests and runs the submitted jobs one after the other with `run`, which
+/// optimizes and saves a collage as the command line does:
+///
+/// - `POST /jobs` submits a job and returns its id
+/// - `GET /jobs` and `GET /jobs/{id}` report the state and progress of the jobs
+/// - `GET /jobs/{id}/result` and `GET /jobs/{id}/layout` download the collage and its layout
+/// - `DELETE /jobs/{id}` cancels a job; a running one still saves its best collage so far
+pub fn serve(args: &ServeArgs, run: fn(&Args) -> Result<(), Error>) -> Result<(), Error> {
+    let listener = TcpListener::bind((args.host.as_str(), args.port))
+        .map_err(|e| Error::Serve(format!("Error listening on {}:{}: {}", args.host, args.port, e)))?;
+    fs::create_dir_all(&args.jobs_dir).map_err(|e| Error::Serve(format!("Error creating {}: {}", args.jobs_dir.display(), e)))?;
+    let allow_dirs = args
+        .allow_dirs
+        .iter()
+        .map(|dir| dir.canonicalize().map_err(|e| Error::Serve(format!("Error reading --allow-dir {}: {}", dir.display(), e))))
+        .collect::<Result<Vec<_>, _>>()?;
+    let config = Arc::new(Config {
+        jobs_dir: args.jobs_dir.canonicalize().unwrap_or_else(|_| args.jobs_dir.clone()),
+        max_upload: args.max_upload,
+        allow_origin: args.allow_origin.clone(),
+        allow_dirs,
+    });
+    let jobs: Jobs = Arc::new(Mutex::new(Vec::new()));
+
+    // The optimizer uses all cores, so jobs run one at a time
+    let (queue, pending) = channel::<(usize, Box<Args>)>();
+    let worker_jobs = Arc::clone(&jobs);
+    thread::spawn(move || {
+        for (id, job_args) in pending {
+            run_job(&worker_jobs, id, &job_args, run);
+        }
+    });
+
+    info!("Serving on http://{}:{}, jobs are kept in {}", args.host, args.port, args.jobs_dir.display());
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else {
+            continue;
+        };
+        let jobs = Arc::clone(&jobs);
+        let queue = queue.clone();
+        let config = Arc::clone(&config);
+        thread::spawn(move || handle_connection(stream, &jobs, &queue, &config));
+    }
+    Ok(())
+}
+
+fn run_job(jobs: &Jobs, id: usize, args: &Args, run: fn(&Args) -> Result<(), Error>) {
+    if let Ok(mut jobs) = jobs.lock() {
+        if jobs[id - 1].state == JobState::Cancelled {
+            return;
+        }
+        jobs[id - 1].state = JobState::Running;
+    }
+    info!("Job {}: optimizing...", id);
+    let progress = Arc::clone(jobs);
+    events::set_sink(Some(Box::new(move |event: &Value| {
+        if event["event"] != "generation" {
+            return;
+        }
+        if let Ok(mut jobs) = progress.lock() {
+            let job = &mut jobs[id - 1];
+            job.generation = event["generation"].as_u64().unwrap_or(0) as usize;
+            job.best = event["best"].as_f64();
+        }
+    })));
+    // A panicking job must not take the worker, and with it every later job, down
+    let result = catch_unwind(AssertUnwindSafe(|| run(args))).unwrap_or_else(|panic| Err(Error::Serve(format!("The job panicked: {}", panic_message(&*panic)))));
+    events::set_sink(None);
+
+    if let Ok(mut jobs) = jobs.lock() {
+        let job = &mut jobs[id - 1];
+        job.state = match &result {
+            Err(e) => {
+                job.error = Some(e.to_string());
+                JobState::Failed
+            }
+            Ok(()) if job.cancel.is_cancelled() => JobState::Cancelled,
+            Ok(()) => JobState::Done,
+        };
+        info!("Job {}: {}", id, job.state.name());
+    }
+}
+
+fn handle_connection(mut stream: TcpStream, jobs: &Jobs, queue: &Sender<(usize, Box<Args>)>, config: &Config) {
+    let _ = stream.set_read_timeout(Some(READ_TIMEOUT));
+    let response = match read_request(&mut stream, config.max_upload) {
+        Ok((method, path, body)) => route(&method, &path, &body, jobs, queue, config),
+        Err(response) => response,
+    };
+    let reason = match response.status {
+        200 => "OK",
+        201 => "Created",
+        204 => "No Content",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        413 => "Payload Too Large",
+        _ => "Internal Server Error",
+    };
+    // Only the `--allow-origin` pages may use the API from a browser, e.g. a kiosk front end
+    // served elsewhere; any other page could read the photos on this machine through it
+    let cors = config
+        .allow_origin
+        .as_ref()
+        .map(|origin| {
+            format!(
+                "Access-Control-Allow-Origin: {}\r\nAccess-Control-Allow-Methods: GET, POST, DELETE, OPTIONS\r\n\
+                 Access-Control-Allow-Headers: Content-Type\r\nVary: Origin\r\n",
+                origin
+            )
+        })
+        .unwrap_or_default();
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n{}Connection: close\r\n\r\n",
+        response.status,
+        reason,
+        response.content_type,
+        response.body.len(),
+        cors
+    );
+    let _ = stream.write_all(head.as_bytes()).and_then(|_| stream.write_all(&response.body));
+}
+
+/// Reads the method, the path without its query and the body of a request.
+fn read_request(stream: &mut TcpStream, max_upload: u64) -> Result<(String, String, Vec<u8>), Response> {
+    let bad_request = |e: std::io::Error| Response::error(400, format!("Error reading the request: {}", e));
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line).map_err(bad_request)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(Response::error(400, "Malformed request line"));
+    };
+    let (method, path) = (method.to_string(), target.split('?').next().unwrap_or_default().to_string());
+
+    let mut length = 0u64;
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header).map_err(bad_request)?;
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().map_err(|_| Response::error(400, "Invalid Content-Length"))?;
+            }
+        }
+    }
+    if length > max_upload {
+        return Err(Response::error(413, format!("The request is larger than the limit of {} bytes (--max-upload)", max_upload)));
+    }
+    // The body grows as it arrives, so a large Content-Length alone does not take any memory
+    let mut body = Vec::new();
+    reader.take(length).read_to_end(&mut body).map_err(bad_request)?;
+    if (body.len() as u64) < length {
+        return Err(Response::error(400, "The request ended before its Content-Length"));
+    }
+    Ok((method, path, body))
+}
+
+fn route(method: &str, path: &str, body: &[u8], jobs: &Jobs, queue: &Sender<(usize, Box<Args>)>, config: &Config) -> Response {
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    match (method, segments.as_slice()) {
+        ("OPTIONS", _) if config.allow_origin.is_some() => Response { status: 204, content_type: "text/plain", body: Vec::new() },
+        ("POST", ["jobs"]) => submit(body, jobs, queue, config),
+        ("GET", ["jobs"]) => {
+            let list: Vec<Value> = jobs.lock().map(|jobs| jobs.iter().enumerate().map(|(i, job)| job.to_json(i + 1)).collect()).unwrap_or_default();
+            Response::json(200, Value::Array(list))
+        }
+        ("GET", ["jobs", id]) => with_job(jobs, id, |job, id| Response::json(200, job.to_json(id))).unwrap_or_else(|| Response::error(404, format!("No job {}", id))),
+        ("DELETE", ["jobs", id]) => with_job(jobs, id, |job, id| {
+            match job.state {
+                JobState::Queued => job.state = JobState::Cancelled,
+                JobState::Running => job.cancel.cancel(),
+                JobState::Done | JobState::Failed | JobState::Cancelled => {
+                    return Response::error(409, format!("Job {} has already ended", id));
+                }
+            }
+            Response::json(200, job.to_json(id))
+        })
+        .unwrap_or_else(|| Response::error(404, format!("No job {}", id))),
+        ("GET", ["jobs", id, file @ ("result" | "layout")]) => {
+            let Some((state, output)) = with_job(jobs, id, |job, _| (job.state, job.output.clone())) else {
+                return Response::error(404, format!("No job {}", id));
+            };
+            let path = if *file == "layout" { output.with_file_name("layout.json") } else { output };
+            if !matches!(state, JobState::Done | JobState::Cancelled) || !path.exists() {
+                return Response::error(409, format!("Job {} has no {} yet", id, file));
+            }
+            match fs::read(&path) {
+                Ok(body) => Response { status: 200, content_type: content_type(&path), body },
+                Err(e) => Response::error(500, format!("Error reading {}: {}", path.display(), e)),
+            }
+        }
+        (_, ["jobs", ..]) => Response::error(405, format!("{} is not supported for {}", method, path)),
+        _ => Response::error(404, format!("Nothing at {}", path)),
+    }
+}
+
+/// Calls `f` with the job and its id, `None` if there is no such job.
+fn with_job<T>(jobs: &Jobs, id: &str, f: impl FnOnce(&mut Job, usize) -> T) -> Option<T> {
+    let id = id.parse::<usize>().ok()?;
+    let mut jobs = jobs.lock().ok()?;
+    jobs.get_mut(id.checked_sub(1)?).map(|job| f(job, id))
+}
+
+/// Checks a job, stores its uploaded images and queues it.
+fn submit(body: &[u8], jobs: &Jobs, queue: &Sender<(usize, Box<Args>)>, config: &Config) -> Response {
+    let request: JobRequest = match serde_json::from_slice(body) {
+        Ok(request) => request,
+        Err(e) => return Response::error(400, format!("Invalid job: {}", e)),
+    };
+    if request.dirs.is_empty() == request.files.is_empty() {
+        return Response::error(400, "A job needs either \"dirs\" on the server or uploaded \"files\"");
+    }
+    if !request.dirs.is_empty() && config.allow_dirs.is_empty() {
+        return Response::error(403, "The server only takes uploaded \"files\"; start it with --allow-dir to let jobs read its directories");
+    }
+    // Values of the options are not checked, except for looking like an option themselves
+    let is_option = |arg: &&String| arg.starts_with('-') && arg.parse::<f64>().is_err();
+    let name = |arg: &String| arg.split('=').next().unwrap_or_default().to_string();
+    if let Some(option) = request.args.iter().filter(is_option).map(name).find(|option| !JOB_OPTIONS.contains(&option.as_str())) {
+        return Response::error(400, format!("{} cannot be set for a job", option));
+    }
+    let output_name = request.output.unwrap_or_else(|| DEFAULT_OUTPUT.to_string());
+    if Path::new(&output_name).file_name().is_none_or(|name| name != output_name.as_str()) {
+        return Response::error(400, format!("\"output\" must be a file name, not {}", output_name));
+    }
+
+    let Ok(mut jobs_list) = jobs.lock() else {
+        return Response::error(500, "The job list is unavailable");
+    };
+    let id = jobs_list.len() + 1;
+    let dir = config.jobs_dir.join(id.to_string());
+    let input = dir.join("input");
+    if let Err(e) = fs::create_dir_all(&input) {
+        return Response::error(500, format!("Error creating {}: {}", input.display(), e));
+    }
+    for file in &request.files {
+        let Some(name) = Path::new(&file.name).file_name() else {
+            return Response::error(400, format!("Invalid file name {}", file.name));
+        };
+        let data = match base64::engine::general_purpose::STANDARD.decode(&file.data) {
+            Ok(data) => data,
+            Err(e) => return Response::error(400, format!("Invalid base64 data of {}: {}", file.name, e)),
+        };
+        if let Err(e) = fs::write(input.join(name), data) {
+            return Response::error(500, format!("Error saving {}: {}", file.name, e));
+        }
+    }
+
+    let output = dir.join(output_name);
+    let dirs = if request.files.is_empty() { request.dirs } else { vec![input.display().to_string()] };
+    let mut argv = request.args;
+    if !argv.iter().any(|arg| arg == "-q" || arg == "--quiet") {
+        argv.push("--quiet".to_string());
+    }
+    argv.extend(["--output".to_string(), output.display().to_string()]);
+    argv.extend(dirs);
+    let args = match parse_optimize_args(&argv) {
+        Ok(args) => args,
+        Err(e) => return Response::error(400, e.trim().to_string()),
+    };
+    // Checked after parsing, as values in "args" that are not options are read as inputs too
+    let Input::Directories(inputs) = &args.input else {
+        return Response::error(400, "A job cannot read a file list");
+    };
+    if let Some(input) = inputs.iter().find(|path| !readable(Path::new(path), &dir, &config.allow_dirs)) {
+        return Response::error(403, format!("{} is not inside a directory the server allows (--allow-dir)", input));
+    }
+
+    let job = Job {
+        state: JobState::Queued,
+        generation: 0,
+        generations: args.ga.generations,
+        best: None,
+        error: None,
+        output,
+        cancel: args.ga.cancel.clone(),
+    };
+    let response = Response::json(201, job.to_json(id));
+    jobs_list.push(job);
+    drop(jobs_list);
+    if queue.send((id, Box::new(args))).is_err() {
+        return Response::error(500, "The job queue is unavailable");
+    }
+    info!("Job {}: queued", id);
+    response
+}
+
+/// Whether a job may read `path`: its own uploads, or what lies inside an `--allow-dir`.
+fn readable(path: &Path, job_dir: &Path, allow_dirs: &[PathBuf]) -> bool {
+    // Canonical paths resolve `..` and symbolic links before they are compared
+    let Ok(path) = path.canonicalize() else {
+        return false;
+    };
+    path.starts_with(job_dir) || allow_dirs.iter().any(|dir| path.starts_with(dir))
+}
+
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase()).as_deref() {
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("png") => "image/png",
+        Some("webp") => "image/webp",
+        Some("avif") => "image/avif",
+        Some("tif" | "tiff") => "image/tiff",
+        Some("pdf") => "application/pdf",
+        Some("json") => "application/json",
+        _ => "application/octet-stream",
+    }
+}
@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+use std::f64::consts::TAU;
+
+use image::DynamicImage;
+use rect_packer::Rect;
+
+use crate::error::Error;
+use crate::packing::{footprint, PackedLayout, PackingOptions};
+use crate::verify::validate_packed;
+
+/// Settings of `--layout spiral`.
+#[derive(Clone, Debug)]
+pub struct SpiralOptions {
+    /// The hero image in the center is drawn this many times as large as its weight asks for.
+    pub hero_scale: f64,
+    /// Weight of every image in the fitness; the area of an image grows with its weight. Images
+    /// without a weight count as 1.
+    pub weights: HashMap<u32, f64>,
+}
+
+impl Default for SpiralOptions {
+    fn default() -> Self {
+        SpiralOptions { hero_scale: 2.0, weights: HashMap::new() }
+    }
+}
+
+impl SpiralOptions {
+    fn weight(&self, id: u32) -> f64 {
+        self.weights.get(&id).copied().unwrap_or(1.0).max(f64::MIN_POSITIVE)
+    }
+}
+
+/// Lays the images out along an Archimedean spiral: the heaviest image (the first of them in
+/// packing order) is the hero in the center, and every other image in packing order is placed at
+/// the first point of the spiral where it does not collide with the images before it. The search
+/// for each image starts one turn before the previous image, so small images can still fill the
+/// holes between larger ones.
+pub fn pack_spiral(image_ids: &[u32], image_map: &HashMap<u32, DynamicImage>, options: &PackingOptions) -> Result<PackedLayout, Error> {
+    let spiral = &options.spiral;
+    let mut sizes = image_ids
+        .iter()
+        .map(|id| {
+            let img = image_map.get(id).ok_or(Error::UnknownImage(*id))?;
+            let (w, h) = footprint(img, options);
+            Ok((*id, w as f64, h as f64, spiral.weight(*id)))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    let hero = sizes.iter().enumerate().fold(0, |best, (index, size)| if size.3 > sizes[best].3 { index } else { best });
+    let hero = sizes.remove(hero);
+    sizes.insert(0, (hero.0, hero.1 * spiral.hero_scale, hero.2 * spiral.hero_scale, hero.3));
+
+    // Footprints are scaled as a whole, so the area of an image grows with its weight
+    let rects: Vec<(u32, i32, i32)> = sizes
+        .iter()
+        .map(|(id, w, h, weight)| {
+            let factor = weight.sqrt();
+            (*id, ((w * factor).round() as i32).max(1), ((h * factor).round() as i32).max(1))
+        })
+        .collect();
+    // Arms a quarter of the smallest image apart, close enough for it to find the holes
+    let step = (rects.iter().map(|(_, w, h)| (*w).min(*h)).min().unwrap_or(1) as f64 / 4.0).max(1.0);
+    let arm = step / TAU;
+    let padding = options.padding() as i32;
+
+    let mut placed: Vec<(u32, Rect)> = Vec::with_capacity(rects.len());
+    let mut theta: f64 = 0.0;
+    for (id, w, h) in rects {
+        let mut t = (theta - TAU).max(0.0);
+        let rect = loop {
+            let radius = arm * t;
+            let (x, y) = (radius * t.cos(), radius * t.sin());
+            let rect = Rect::new((x - w as f64 / 2.0).round() as i32, (y - h as f64 / 2.0).round() as i32, w, h);
+            if !placed.iter().any(|(_, other)| collides(&rect, other, padding)) {
+                break rect;
+            }
+            // Steps of about `step` pixels along the spiral
+            t += step / radius.max(step);
+        };
+        placed.push((id, rect));
+        theta = theta.max(t);
+    }
+
+    // Move the spiral from around the origin onto the canvas
+    let left = placed.iter().map(|(_, rect)| rect.x).min().unwrap_or(0);
+    let top = placed.iter().map(|(_, rect)| rect.y).min().unwrap_or(0);
+    let right = placed.iter().map(|(_, rect)| rect.right()).max().unwrap_or(0);
+    let bottom = placed.iter().map(|(_, rect)| rect.bottom()).max().unwrap_or(0);
+    for (_, rect) in &mut placed {
+        rect.x -= left;
+        rect.y -= top;
+    }
+    let layout = (placed, (right - left) as u32, (bottom - top) as u32);
+    validate_packed(&layout, options)?;
+    Ok(layout)
+}
+
+/// Whether two rectangles overlap or are closer than `padding` to each other.
+fn collides(a: &Rect, b: &Rect, padding: i32) -> bool {
+    a.x < b.right() + padding && b.x < a.right() + padding && a.y < b.bottom() + padding && b.y < a.bottom() + padding
+}
@@ -0,0 +1,109 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+use image::{DynamicImage, Rgba, RgbaImage};
+
+use crate::ga::GenerationStats;
+use crate::text::{default_font, draw_text, text_width};
+
+const PLOT_WIDTH: u32 = 900;
+const PLOT_HEIGHT: u32 = 500;
+const MARGIN: u32 = 60;
+const BEST_COLOR: Rgba<u8> = Rgba([31, 119, 180, 255]);
+const MEAN_COLOR: Rgba<u8> = Rgba([255, 127, 14, 255]);
+const WORST_COLOR: Rgba<u8> = Rgba([160, 160, 160, 255]);
+const AXIS_COLOR: Rgba<u8> = Rgba([60, 60, 60, 255]);
+
+/// Name, color and value of one curve of the chart.
+type Series = (&'static str, Rgba<u8>, fn(&GenerationStats) -> f64);
+
+/// Writes one CSV line per generation with the fitness of the population and the packing quality
+/// of its best collage.
+pub fn save_stats_csv(history: &[GenerationStats], path: &Path) -> Result<(), String> {
+    let mut csv = String::from("generation,best,mean,worst,free_area_percent,aspect_deviation\n");
+    for stats in history {
+        let _ = writeln!(
+            csv,
+            "{},{:.6},{:.6},{:.6},{:.3},{:.4}",
+            stats.generation, stats.best, stats.mean, stats.worst, stats.free_area_percentage, stats.aspect_ratio_diff
+        );
+    }
+    fs::write(path, csv).map_err(|e| format!("Error writing {}: {}", path.display(), e))
+}
+
+/// Draws the best, mean and worst fitness over the generations as a line chart.
+pub fn plot_fitness(history: &[GenerationStats], path: &Path) -> Result<(), String> {
+    // Without an alpha channel the chart can also be saved as JPEG
+    let plot = DynamicImage::ImageRgba8(fitness_chart(history, PLOT_WIDTH, PLOT_HEIGHT)).to_rgb8();
+    plot.save(path).map_err(|e| format!("Error writing {}: {}", path.display(), e))
+}
+
+/// Renders the fitness curve of `history` onto a white image of the given size.
+pub fn fitness_chart(history: &[GenerationStats], width: u32, height: u32) -> RgbaImage {
+    let mut chart = RgbaImage::from_pixel(width, height, Rgba([255, 255, 255, 255]));
+    let font = default_font();
+    let (left, top) = (MARGIN as f64, MARGIN as f64 / 2.0);
+    let (right, bottom) = ((width - MARGIN / 2) as f64, (height - MARGIN) as f64);
+
+    draw_line(&mut chart, (left, top), (left, bottom), AXIS_COLOR);
+    draw_line(&mut chart, (left, bottom), (right, bottom), AXIS_COLOR);
+    let max = history.iter().map(|stats| stats.best).fold(0.0, f64::max);
+    let min = history.iter().map(|stats| stats.worst).fold(max, f64::min);
+    let range = (max - min).max(1e-9);
+    let last_generation = history.last().map_or(1, |stats| stats.generation).max(2);
+    let to_point = |generation: usize, fitness: f64| {
+        let x = left + (generation - 1) as f64 / (last_generation - 1) as f64 * (right - left);
+        let y = bottom - (fitness - min) / range * (bottom - top);
+        (x, y)
+    };
+
+    let series: [Series; 3] = [
+        ("worst", WORST_COLOR, |stats| stats.worst),
+        ("mean", MEAN_COLOR, |stats| stats.mean),
+        ("best", BEST_COLOR, |stats| stats.best),
+    ];
+    for (_, color, value) in &series {
+        for pair in history.windows(2) {
+            let from = to_point(pair[0].generation, value(&pair[0]));
+            let to = to_point(pair[1].generation, value(&pair[1]));
+            draw_line(&mut chart, from, to, *color);
+        }
+    }
+
+    let size = 14.0;
+    draw_text(&mut chart, &font, size, &format!("{:.3}", max), 4.0, top as f32 - 7.0, AXIS_COLOR);
+    draw_text(&mut chart, &font, size, &format!("{:.3}", min), 4.0, bottom as f32 - 7.0, AXIS_COLOR);
+    draw_text(&mut chart, &font, size, "1", left as f32, bottom as f32 + 6.0, AXIS_COLOR);
+    let last = last_generation.to_string();
+    draw_text(&mut chart, &font, size, &last, right as f32 - text_width(&font, size, &last), bottom as f32 + 6.0, AXIS_COLOR);
+    let caption = "generation";
+    let caption_x = ((left + right) / 2.0) as f32 - text_width(&font, size, caption) / 2.0;
+    draw_text(&mut chart, &font, size, caption, caption_x, bottom as f32 + 6.0, AXIS_COLOR);
+
+    // Legend in a row above the chart, where it cannot hide a curve
+    let mut legend_x = right as f32 - 85.0 * series.len() as f32;
+    for (name, color, _) in series.iter().rev() {
+        let line_y = top / 2.0;
+        draw_line(&mut chart, (legend_x as f64, line_y), (legend_x as f64 + 20.0, line_y), *color);
+        draw_text(&mut chart, &font, size, name, legend_x + 28.0, line_y as f32 - 9.0, AXIS_COLOR);
+        legend_x += 85.0;
+    }
+    chart
+}
+
+/// Draws a two pixel wide line by stepping along its longer axis.
+fn draw_line(img: &mut RgbaImage, from: (f64, f64), to: (f64, f64), color: Rgba<u8>) {
+    let steps = (to.0 - from.0).abs().max((to.1 - from.1).abs()).ceil().max(1.0) as usize;
+    for step in 0..=steps {
+        let t = step as f64 / steps as f64;
+        let x = (from.0 + (to.0 - from.0) * t).round() as i64;
+        let y = (from.1 + (to.1 - from.1) * t).round() as i64;
+        for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            let (px, py) = (x + dx, y + dy);
+            if px >= 0 && py >= 0 && px < img.width() as i64 && py < img.height() as i64 {
+                img.put_pixel(px as u32, py as u32, color);
+            }
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+
+use clap::ValueEnum;
+use image::{DynamicImage, GenericImageView};
+use rect_packer::Rect;
+
+use crate::error::Error;
+use crate::packing::{footprint, PackedLayout, PackingOptions};
+
+/// Rows of a strip are this many times as long as the images are high, unless set.
+const DEFAULT_LENGTH_FACTOR: u32 = 10;
+
+/// Which way a strip runs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum Direction {
+    /// Images side by side, scaled to a common height.
+    #[default]
+    Horizontal,
+    /// Images on top of each other, scaled to a common width.
+    Vertical,
+}
+
+/// Settings of `--layout strip`.
+#[derive(Clone, Debug)]
+pub struct StripOptions {
+    pub direction: Direction,
+    /// Common height of the images in a horizontal strip, width in a vertical one; the median
+    /// of the images when not set, see `PackingOptions::for_images`.
+    pub size: Option<u32>,
+    /// Length every row (column) should reach, by default 10 times the size.
+    pub length: Option<u32>,
+    /// The images wrap into this many rows (columns) of the target length.
+    pub rows: u32,
+}
+
+impl Default for StripOptions {
+    fn default() -> Self {
+        StripOptions { direction: Direction::Horizontal, size: None, length: None, rows: 1 }
+    }
+}
+
+impl StripOptions {
+    fn size(&self) -> u32 {
+        self.size.unwrap_or(1).max(1)
+    }
+
+    fn length(&self) -> u32 {
+        self.length.unwrap_or(self.size() * DEFAULT_LENGTH_FACTOR)
+    }
+
+    /// Aspect ratio of the canvas when every row has the target length.
+    pub fn aspect_ratio(&self, caption_height: u32) -> f64 {
+        let across = self.rows as f64 * self.size() as f64;
+        match self.direction {
+            Direction::Horizontal => self.length() as f64 / (across + self.rows as f64 * caption_height as f64),
+            Direction::Vertical => across / self.length() as f64,
+        }
+    }
+}
+
+/// The median height (horizontal) or width (vertical) of the images, the default size of a strip.
+pub fn median_size(image_map: &HashMap<u32, DynamicImage>, direction: Direction) -> u32 {
+    let mut sizes: Vec<u32> = image_map
+        .values()
+        .map(|img| match direction {
+            Direction::Horizontal => img.height(),
+            Direction::Vertical => img.width(),
+        })
+        .collect();
+    sizes.sort_unstable();
+    sizes.get(sizes.len() / 2).copied().unwrap_or(1)
+}
+
+/// Lays the images out in packing order as a strip: every image is scaled to the common size and
+/// placed next to the previous one. With several rows, an image that would take the row past the
+/// target length starts the next row; rows are as high as their tallest footprint.
+pub fn pack_strip(image_ids: &[u32], image_map: &HashMap<u32, DynamicImage>, options: &PackingOptions) -> Result<PackedLayout, Error> {
+    let strip = &options.strip;
+    let size = strip.size().div_ceil(options.scale()).max(1) as f64;
+    let length = strip.length().div_ceil(options.scale()) as i32;
+    let padding = options.padding() as i32;
+    let horizontal = strip.direction == Direction::Horizontal;
+
+    // Positions along the strip and across it, swapped into x and y for vertical strips
+    let mut placed = Vec::with_capacity(image_ids.len());
+    let (mut along, mut row_start, mut row_depth, mut rows) = (0, 0, 0, 1);
+    let (mut width, mut height) = (0, 0);
+    for id in image_ids {
+        let img = image_map.get(id).ok_or(Error::UnknownImage(*id))?;
+        // Footprints are scaled as a whole, as for gap-filling thumbnails, so Layout::build
+        // derives the same scale from them
+        let (w, h) = img.dimensions();
+        let side = if horizontal { h } else { w }.div_ceil(options.scale()).max(1) as f64;
+        let scale = size / side;
+        let (footprint_w, footprint_h) = footprint(img, options);
+        let rect_w = ((footprint_w as f64 * scale).round() as i32).max(1);
+        let rect_h = ((footprint_h as f64 * scale).round() as i32).max(1);
+        let (rect_along, rect_across) = if horizontal { (rect_w, rect_h) } else { (rect_h, rect_w) };
+
+        if along > 0 && along + rect_along > length && rows < strip.rows {
+            row_start += row_depth + padding;
+            (along, row_depth) = (0, 0);
+            rows += 1;
+        }
+        let rect = if horizontal {
+            Rect::new(along, row_start, rect_w, rect_h)
+        } else {
+            Rect::new(row_start, along, rect_w, rect_h)
+        };
+        placed.push((*id, rect));
+        width = width.max(rect.right() as u32);
+        height = height.max(rect.bottom() as u32);
+        along += rect_along + padding;
+        row_depth = row_depth.max(rect_across);
+    }
+    Ok((placed, width, height))
+}
@@ -0,0 +1,106 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use image::ImageFormat;
+
+use crate::image_handling::read_source;
+use crate::layout::{Layout, PlacedImage, Shape};
+
+/// Writes the layout as an SVG document with one `<image>` per photo, so the arrangement can be
+/// fine-tuned in vector editors. Images are linked by path unless `embed` is set, in which case
+/// they are inlined as base64 data URIs.
+pub fn save_svg(layout: &Layout, path: &Path, embed: bool) -> Result<(), String> {
+    let mut svg = String::new();
+    let _ = writeln!(svg, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" width="{w}" height="{h}" viewBox="0 0 {w} {h}">"#,
+        w = layout.width,
+        h = layout.height
+    );
+    let _ = writeln!(svg, r#"  <rect width="100%" height="100%" fill="white"/>"#);
+
+    for placed in &layout.images {
+        let href = if embed {
+            match data_uri(placed) {
+                Ok(uri) => uri,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    continue;
+                }
+            }
+        } else {
+            placed.path.to_string_lossy().into_owned()
+        };
+
+        let (width, height) = placed.drawn_size();
+        // Cropped shapes are cut around their focus, whole images are centered in their footprint
+        let (x, y) = match placed.shape {
+            Shape::Rectangle => (placed.x as f64 + (placed.width as f64 - width as f64) / 2.0, placed.y as f64 + (placed.height as f64 - height as f64) / 2.0),
+            Shape::Hexagon | Shape::Fill => {
+                let (left, top) = placed.crop_origin();
+                ((placed.x - left as i32) as f64, (placed.y - top as i32) as f64)
+            }
+        };
+        let center_x = placed.x as f64 + placed.width as f64 / 2.0;
+        let center_y = placed.y as f64 + placed.height as f64 / 2.0;
+        // Cropped shapes get a clip path of their outline
+        let clip = match placed.clip_polygon() {
+            Some(corners) => {
+                let points: Vec<String> = corners.iter().map(|(x, y)| format!("{:.2},{:.2}", x, y)).collect();
+                let _ = writeln!(svg, r#"  <clipPath id="clip-{}"><polygon points="{}"/></clipPath>"#, placed.id, points.join(" "));
+                format!(r#" clip-path="url(#clip-{})""#, placed.id)
+            }
+            None => String::new(),
+        };
+        let _ = writeln!(
+            svg,
+            r#"  <image id="image-{}" x="{:.2}" y="{:.2}" width="{}" height="{}" transform="rotate({:.4} {:.2} {:.2})" preserveAspectRatio="none"{} xlink:href="{}"/>"#,
+            placed.id,
+            x,
+            y,
+            width,
+            height,
+            placed.rotation,
+            center_x,
+            center_y,
+            clip,
+            escape_xml(&href)
+        );
+    }
+
+    svg.push_str("</svg>\n");
+    fs::write(path, svg).map_err(|e| format!("Error writing {}: {}", path.display(), e))
+}
+
+/// Inlines the source file. Formats browsers and editors can display are embedded as they are,
+/// everything else (e.g. TIFF) is converted to PNG first.
+fn data_uri(placed: &PlacedImage) -> Result<String, String> {
+    let bytes = read_source(&placed.path).map_err(|e| format!("Error reading {}: {}", placed.path.display(), e))?;
+    let format = image::guess_format(&bytes).ok();
+    let (mime, bytes) = match format {
+        Some(ImageFormat::Jpeg) => ("image/jpeg", bytes),
+        Some(ImageFormat::Png) => ("image/png", bytes),
+        Some(ImageFormat::Gif) => ("image/gif", bytes),
+        Some(ImageFormat::WebP) => ("image/webp", bytes),
+        _ => {
+            let img = image::load_from_memory(&bytes).map_err(|e| format!("Error decoding {}: {}", placed.path.display(), e))?;
+            let mut png = Vec::new();
+            img.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+                .map_err(|e| format!("Error encoding {}: {}", placed.path.display(), e))?;
+            ("image/png", png)
+        }
+    };
+    Ok(format!("data:{};base64,{}", mime, STANDARD.encode(bytes)))
+}
+
+pub fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,101 @@
+use ab_glyph::{point, Font, FontArc, PxScale, ScaleFont};
+use image::{Rgba, RgbaImage};
+use std::fs;
+use std::path::Path;
+
+pub static DEFAULT_FONT: &[u8] = include_bytes!("../assets/fonts/DejaVuSans.ttf");
+
+/// DejaVu Sans, embedded into the binary so captions work without any installed fonts.
+pub fn default_font() -> FontArc {
+    FontArc::try_from_slice(DEFAULT_FONT).expect("Embedded font is invalid")
+}
+
+/// Loads a TrueType/OpenType font from disk.
+pub fn load_font(path: &Path) -> Result<FontArc, String> {
+    let bytes = fs::read(path).map_err(|e| format!("Error reading font {}: {}", path.display(), e))?;
+    FontArc::try_from_vec(bytes).map_err(|e| format!("Error parsing font {}: {}", path.display(), e))
+}
+
+/// Height of one line of text including the font's line gap.
+pub fn line_height(font: &FontArc, size: f32) -> f32 {
+    let scaled = font.as_scaled(PxScale::from(size));
+    scaled.height() + scaled.line_gap()
+}
+
+pub fn text_width(font: &FontArc, size: f32, text: &str) -> f32 {
+    let scaled = font.as_scaled(PxScale::from(size));
+    let mut width = 0.0;
+    let mut previous = None;
+    for c in text.chars() {
+        let glyph = scaled.glyph_id(c);
+        if let Some(previous) = previous {
+            width += scaled.kern(previous, glyph);
+        }
+        width += scaled.h_advance(glyph);
+        previous = Some(glyph);
+    }
+    width
+}
+
+/// Shortens `text` with a trailing ellipsis until it is at most `max_width` pixels wide.
+pub fn fit_text(font: &FontArc, size: f32, text: &str, max_width: f32) -> String {
+    if text_width(font, size, text) <= max_width {
+        return text.to_string();
+    }
+    let mut chars: Vec<char> = text.chars().collect();
+    while !chars.is_empty() {
+        chars.pop();
+        let candidate = format!("{}…", chars.iter().collect::<String>().trim_end());
+        if text_width(font, size, &candidate) <= max_width {
+            return candidate;
+        }
+    }
+    String::new()
+}
+
+/// Draws a single line of text with its top-left corner at (x, y), alpha-blended onto `img`.
+pub fn draw_text(img: &mut RgbaImage, font: &FontArc, size: f32, text: &str, x: f32, y: f32, color: Rgba<u8>) {
+    let scale = PxScale::from(size);
+    let scaled = font.as_scaled(scale);
+    let baseline = y + scaled.ascent();
+    let mut caret = x;
+    let mut previous = None;
+
+    for c in text.chars() {
+        let glyph_id = scaled.glyph_id(c);
+        if let Some(previous) = previous {
+            caret += scaled.kern(previous, glyph_id);
+        }
+        let glyph = glyph_id.with_scale_and_position(scale, point(caret, baseline));
+        caret += scaled.h_advance(glyph_id);
+        previous = Some(glyph_id);
+
+        if let Some(outlined) = font.outline_glyph(glyph) {
+            let bounds = outlined.px_bounds();
+            outlined.draw(|gx, gy, coverage| {
+                let px = bounds.min.x as i64 + gx as i64;
+                let py = bounds.min.y as i64 + gy as i64;
+                if px < 0 || py < 0 || px >= img.width() as i64 || py >= img.height() as i64 {
+                    return;
+                }
+                blend_pixel(img.get_pixel_mut(px as u32, py as u32), color, coverage);
+            });
+        }
+    }
+}
+
+/// Composites `color` over `pixel` with the given coverage using the "over" operator.
+pub fn blend_pixel(pixel: &mut Rgba<u8>, color: Rgba<u8>, coverage: f32) {
+    let src_alpha = coverage.clamp(0.0, 1.0) * color[3] as f32 / 255.0;
+    if src_alpha <= 0.0 {
+        return;
+    }
+    let dst_alpha = pixel[3] as f32 / 255.0;
+    let out_alpha = src_alpha + dst_alpha * (1.0 - src_alpha);
+    for channel in 0..3 {
+        let src = color[channel] as f32;
+        let dst = pixel[channel] as f32;
+        pixel[channel] = ((src * src_alpha + dst * dst_alpha * (1.0 - src_alpha)) / out_alpha).round() as u8;
+    }
+    pixel[3] = (out_alpha * 255.0).round() as u8;
+}
@@ -0,0 +1,111 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use std::process::Command;
+
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{imageops, Delay, DynamicImage, Frame, Rgba, RgbaImage};
+
+use crate::image_handling::staging_dir;
+
+/// Frames are scaled to fit into a square of this size.
+pub const FRAME_SIZE: u32 = 480;
+
+const FRAME_DELAY_MS: u32 = 100;
+
+/// The last frame stays on screen this much longer, so the result can be seen before the
+/// animation loops.
+const FINAL_FRAME_DELAY_MS: u32 = 2000;
+
+/// Collects downscaled renders of the best collage during the optimization.
+pub struct Timelapse {
+    frames: Vec<RgbaImage>,
+    background: Rgba<u8>,
+}
+
+impl Timelapse {
+    /// `background` fills the border of frames whose collage is narrower or shorter than others.
+    pub fn new(background: Rgba<u8>) -> Self {
+        Timelapse { frames: Vec::new(), background }
+    }
+
+    pub fn push(&mut self, collage: &DynamicImage) {
+        self.frames.push(collage.to_rgba8());
+    }
+
+    /// Writes the frames as an animated GIF, or as a video with ffmpeg for other extensions such as
+    /// `.mp4`. The collage changes its shape while it converges, so every frame is centered on a
+    /// canvas large enough for all of them.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let width = self.frames.iter().map(|frame| frame.width()).max().unwrap_or(1);
+        let height = self.frames.iter().map(|frame| frame.height()).max().unwrap_or(1);
+        // Video encoders need even dimensions
+        let (width, height) = (width + width % 2, height + height % 2);
+        let frames = self.frames.iter().map(|frame| {
+            let mut canvas = RgbaImage::from_pixel(width, height, self.background);
+            let x = (width - frame.width()) / 2;
+            let y = (height - frame.height()) / 2;
+            imageops::overlay(&mut canvas, frame, x as i64, y as i64);
+            canvas
+        });
+
+        let is_gif = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_none_or(|ext| ext.eq_ignore_ascii_case("gif"));
+        if is_gif {
+            save_gif(frames, self.frames.len(), path)
+        } else {
+            save_video(frames, path)
+        }
+    }
+}
+
+fn save_gif(frames: impl Iterator<Item = RgbaImage>, count: usize, path: &Path) -> Result<(), String> {
+    let error = |e: &dyn std::fmt::Display| format!("Error saving {}: {}", path.display(), e);
+    let file = File::create(path).map_err(|e| error(&e))?;
+    // Speed 10 quantizes colors much faster than the default at hardly visible cost
+    let mut encoder = GifEncoder::new_with_speed(BufWriter::new(file), 10);
+    encoder.set_repeat(Repeat::Infinite).map_err(|e| error(&e))?;
+    for (index, frame) in frames.enumerate() {
+        let delay = if index + 1 == count { FINAL_FRAME_DELAY_MS } else { FRAME_DELAY_MS };
+        let frame = Frame::from_parts(frame, 0, 0, Delay::from_numer_denom_ms(delay, 1));
+        encoder.encode_frame(frame).map_err(|e| error(&e))?;
+    }
+    Ok(())
+}
+
+fn save_video(frames: impl Iterator<Item = RgbaImage>, path: &Path) -> Result<(), String> {
+    let frame_dir = staging_dir(&std::env::temp_dir().join("ImageGridOptimizer").join("timelapse"))?;
+    let mut count = 0;
+    for (index, frame) in frames.enumerate() {
+        let frame_path = frame_dir.join(format!("frame_{:06}.png", index));
+        frame.save(&frame_path).map_err(|e| format!("Error saving {}: {}", frame_path.display(), e))?;
+        count += 1;
+    }
+    // Hold the result like the GIF does by repeating the last frame
+    let last = frame_dir.join(format!("frame_{:06}.png", count.max(1) - 1));
+    for index in count..count + (FINAL_FRAME_DELAY_MS / FRAME_DELAY_MS) as usize {
+        let target = frame_dir.join(format!("frame_{:06}.png", index));
+        std::fs::copy(&last, &target).map_err(|e| format!("Error writing {}: {}", target.display(), e))?;
+    }
+
+    let status = Command::new("ffmpeg")
+        .arg("-v")
+        .arg("error")
+        .arg("-y")
+        .arg("-framerate")
+        .arg((1000 / FRAME_DELAY_MS).to_string())
+        .arg("-i")
+        .arg(frame_dir.join("frame_%06d.png"))
+        .arg("-pix_fmt")
+        .arg("yuv420p")
+        .arg(path)
+        .status()
+        .map_err(|e| format!("Error running ffmpeg (is it installed and on the PATH?): {}", e))?;
+    if !status.success() {
+        return Err(format!("ffmpeg failed to write {} ({})", path.display(), status));
+    }
+    let _ = std::fs::remove_dir_all(&frame_dir);
+    Ok(())
+}
@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageBuffer, Rgba, RgbaImage};
+use rayon::prelude::*;
+
+/// Maps every 8-bit tone value of one image to a new value.
+pub type ToneCurve = [u8; 256];
+
+/// Middle gray, which the average luminance of an HDR image is exposed to.
+const HDR_KEY: f32 = 0.18;
+
+/// Share of the brightest pixels of an HDR image that are allowed to clip to white, so that a few
+/// hot pixels or the sun itself do not darken the rest of the image.
+const HDR_CLIPPED: f32 = 0.001;
+
+/// Thresholds of a 4x4 ordered dither, in sixteenths of an 8-bit step.
+const BAYER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// Tonemaps a high dynamic range image, with linear floating point values as in Radiance HDR and
+/// OpenEXR files, to 16-bit sRGB with the extended Reinhard operator: the log-average luminance is
+/// exposed to middle gray and highlights roll off smoothly to white instead of clipping. `None`
+/// for images with integer pixels, which are already in display range.
+pub fn tonemap(img: &DynamicImage) -> Option<DynamicImage> {
+    let hdr = match img {
+        DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => img.to_rgba32f(),
+        _ => return None,
+    };
+    if hdr.is_empty() {
+        return None;
+    }
+    let luminance = |p: &Rgba<f32>| (0.2126 * p[0] + 0.7152 * p[1] + 0.0722 * p[2]).max(0.0);
+    let count = hdr.pixels().len() as f32;
+    let log_average = (hdr.pixels().map(|p| (1e-4 + luminance(p)).ln()).sum::<f32>() / count).exp();
+    let exposure = HDR_KEY / log_average;
+
+    // The exposed luminance that becomes white
+    let mut exposed: Vec<f32> = hdr.pixels().map(|p| exposure * luminance(p)).collect();
+    let brightest = ((exposed.len() as f32 * (1.0 - HDR_CLIPPED)) as usize).min(exposed.len() - 1);
+    let white = exposed.select_nth_unstable_by(brightest, f32::total_cmp).1.max(1.0);
+
+    let pixels = hdr.pixels().flat_map(|p| {
+        let l = exposure * luminance(p);
+        let mapped = l * (1.0 + l / (white * white)) / (1.0 + l);
+        let scale = if l > 0.0 { exposure * mapped / l } else { 0.0 };
+        let [r, g, b] = [p[0], p[1], p[2]].map(|c| encode_srgb(c * scale));
+        [r, g, b, (p[3].clamp(0.0, 1.0) * 65535.0).round() as u16]
+    });
+    let sdr = ImageBuffer::from_raw(hdr.width(), hdr.height(), pixels.collect())?;
+    Some(DynamicImage::ImageRgba16(sdr))
+}
+
+/// Gamma-encodes a linear value for sRGB at 16 bits.
+fn encode_srgb(linear: f32) -> u16 {
+    let linear = linear.clamp(0.0, 1.0);
+    let encoded = if linear <= 0.003_130_8 { 12.92 * linear } else { 1.055 * linear.powf(1.0 / 2.4) - 0.055 };
+    (encoded * 65535.0).round() as u16
+}
+
+/// Reduces a 16-bit image to 8 bits with an ordered dither, so smooth gradients such as skies do
+/// not break into visible bands.
+pub fn dither_to_rgba8(img: &ImageBuffer<Rgba<u16>, Vec<u16>>) -> RgbaImage {
+    RgbaImage::from_fn(img.width(), img.height(), |x, y| {
+        let threshold = (BAYER[y as usize % 4][x as usize % 4] as f32 + 0.5) / 16.0;
+        let p = img.get_pixel(x, y);
+        let [r, g, b] = [p[0], p[1], p[2]].map(|c| (c as f32 / 257.0 + threshold).floor().min(255.0) as u8);
+        Rgba([r, g, b, (p[3] as f32 / 257.0).round() as u8])
+    })
+}
+
+/// Curves that match the luminance histogram of every image to the average histogram of all of
+/// them, so that dark phone shots and bright DSLR shots end up with similar tones.
+pub fn tone_curves(images: &[(u32, &DynamicImage)]) -> HashMap<u32, ToneCurve> {
+    let histograms: Vec<[f64; 256]> = images.par_iter().map(|(_, img)| luminance_histogram(img)).collect();
+    if histograms.is_empty() {
+        return HashMap::new();
+    }
+    let mut average = [0.0; 256];
+    for histogram in &histograms {
+        for (total, share) in average.iter_mut().zip(histogram) {
+            *total += share / histograms.len() as f64;
+        }
+    }
+    let target = cumulative(&average);
+    images
+        .iter()
+        .zip(&histograms)
+        .map(|((id, _), histogram)| {
+            let source = cumulative(histogram);
+            // Every tone goes to the lowest target tone that is at least as far up the histogram
+            let curve = source.map(|share| target.iter().position(|t| *t >= share - 1e-9).unwrap_or(255) as u8);
+            (*id, curve)
+        })
+        .collect()
+}
+
+/// Applies the curve to the red, green and blue channels; alpha is left alone.
+pub fn apply_tone_curve(img: &mut RgbaImage, curve: &ToneCurve) {
+    for pixel in img.pixels_mut() {
+        for channel in &mut pixel.0[..3] {
+            *channel = curve[*channel as usize];
+        }
+    }
+}
+
+/// Share of the pixels of a 256x256 thumbnail at every luminance value.
+fn luminance_histogram(img: &DynamicImage) -> [f64; 256] {
+    let small = img.resize_exact(256, 256, FilterType::Triangle).to_luma8();
+    let mut histogram = [0.0; 256];
+    for pixel in small.pixels() {
+        histogram[pixel[0] as usize] += 1.0;
+    }
+    let total = (small.width() * small.height()) as f64;
+    histogram.map(|count| count / total)
+}
+
+fn cumulative(histogram: &[f64; 256]) -> [f64; 256] {
+    let mut sum = 0.0;
+    histogram.map(|share| {
+        sum += share;
+        sum
+    })
+}
+
+/// A look applied to every image for a stylistically uniform collage.
+#[derive(Clone, Copy, PartialEq)]
+pub enum StyleFilter {
+    Grayscale,
+    Sepia,
+    /// Maps dark tones to the first color and light tones to the second.
+    Duotone(Rgba<u8>, Rgba<u8>),
+}
+
+pub fn apply_style_filter(img: &mut RgbaImage, filter: StyleFilter) {
+    for pixel in img.pixels_mut() {
+        let [r, g, b, a] = pixel.0.map(|c| c as f32);
+        let luminance = 0.299 * r + 0.587 * g + 0.114 * b;
+        let [r, g, b] = match filter {
+            StyleFilter::Grayscale => [luminance; 3],
+            StyleFilter::Sepia => [
+                0.393 * r + 0.769 * g + 0.189 * b,
+                0.349 * r + 0.686 * g + 0.168 * b,
+                0.272 * r + 0.534 * g + 0.131 * b,
+            ],
+            StyleFilter::Duotone(dark, light) => {
+                let t = luminance / 255.0;
+                [0, 1, 2].map(|i| dark[i] as f32 * (1.0 - t) + light[i] as f32 * t)
+            }
+        };
+        pixel.0 = [r, g, b, a].map(|c| c.round().clamp(0.0, 255.0) as u8);
+    }
+}
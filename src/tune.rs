@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+use std::fs;
+use std::time::Instant;
+
+use image::DynamicImage;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+
+use crate::cancel::CancellationToken;
+use crate::cli::TuneArgs;
+use crate::error::Error;
+use crate::ga::{run_ga, CrossoverKind, GaSettings, ImageInfo, MutationWeights};
+use crate::image_handling::{load_directories, LoadOptions};
+use crate::packing::PackingOptions;
+
+/// Values tried for every parameter; the defaults of a run come first.
+const POPULATION_SIZES: [usize; 5] = [1000, 100, 200, 500, 2000];
+const MUTATION_RATES: [f64; 6] = [0.1, 0.05, 0.2, 0.3, 0.4, 0.5];
+const CROSSOVER_RATES: [f64; 5] = [0.7, 0.5, 0.6, 0.8, 0.9];
+
+/// Every trial evaluates at least this many generations, even for large populations.
+const MIN_GENERATIONS: usize = 5;
+
+struct Trial {
+    population_size: usize,
+    generations: usize,
+    mutation_rate: f64,
+    crossover_rate: f64,
+    fitness: f64,
+    seconds: f64,
+}
+
+/// Runs the GA on the images with random combinations of population size, mutation rate and
+/// crossover rate, each with the same number of evaluations and the same seeds, and reports the
+/// combination with the best mean fitness. With `write_config`, it is saved as a config file for
+/// `--config`.
+pub fn tune(args: &TuneArgs) -> Result<(), Error> {
+    let load = LoadOptions { standard_size: args.standard_width, ..Default::default() };
+    let (images, groups) = load_directories(&args.dirs, &load)?;
+    if images.is_empty() {
+        return Err(Error::NoImages);
+    }
+    let image_map: HashMap<u32, DynamicImage> = images.into_iter().map(|(id, _, img)| (id, img)).collect();
+    let mut all_images: Vec<(u32, DynamicImage)> = image_map.iter().map(|(id, img)| (*id, img.clone())).collect();
+    all_images.sort_by_key(|(id, _)| *id);
+    let info = ImageInfo { groups, ..Default::default() };
+    let packing = PackingOptions::default();
+
+    // The defaults are always tried first as the baseline, then distinct random combinations
+    let mut rng = StdRng::seed_from_u64(args.seed);
+    let mut combinations: Vec<(usize, f64, f64)> = Vec::new();
+    for &population_size in &POPULATION_SIZES {
+        for &mutation_rate in &MUTATION_RATES {
+            for &crossover_rate in &CROSSOVER_RATES {
+                combinations.push((population_size, mutation_rate, crossover_rate));
+            }
+        }
+    }
+    combinations[1..].shuffle(&mut rng);
+    combinations.truncate(args.trials);
+
+    info!(
+        "Tuning on {} images: {} trials of {} runs with {} evaluations each",
+        all_images.len(),
+        combinations.len(),
+        args.repeats,
+        args.evaluations
+    );
+    let mut trials = Vec::new();
+    for (index, (population_size, mutation_rate, crossover_rate)) in combinations.into_iter().enumerate() {
+        let generations = (args.evaluations / population_size).max(MIN_GENERATIONS);
+        let settings = GaSettings {
+            population_size,
+            generations,
+            min_images: args.min_images.min(all_images.len()),
+            max_images: args.max_images.min(all_images.len()),
+            mutation_rate,
+            mutation_weights: MutationWeights::default(),
+            crossover_rate,
+            crossover: CrossoverKind::Cut,
+            per_dir_min: 0,
+            per_dir_max: None,
+            quiet: true,
+            initial: Vec::new(),
+            greedy_share: 0.1,
+            restart_after: None,
+            restart_keep: 2,
+            sharing_radius: None,
+            coarse_share: 0.0,
+            coarse_factor: 4,
+            cancel: CancellationToken::new(),
+        };
+        // Every trial runs with the same seeds, so they differ only in their parameters; averaging
+        // over several runs keeps a lucky seed from deciding
+        let start = Instant::now();
+        let fitness: f64 = (0..args.repeats as u64)
+            .map(|repeat| {
+                let mut rng = StdRng::seed_from_u64(args.seed + repeat);
+                let (population, _) = run_ga(&all_images, &image_map, &info, &packing, &settings, &mut rng, |_| {});
+                population.first().map_or(0.0, |best| best.fitness)
+            })
+            .sum();
+        let trial = Trial {
+            population_size,
+            generations,
+            mutation_rate,
+            crossover_rate,
+            fitness: fitness / args.repeats as f64,
+            seconds: start.elapsed().as_secs_f64(),
+        };
+        info!(
+            "Trial {:>2}: --pop-size {:>4} --gens {:>5} --mutation-rate {:.2} --crossover-rate {:.2}  mean best fitness {:.5} ({:.1} s)",
+            index + 1,
+            trial.population_size,
+            trial.generations,
+            trial.mutation_rate,
+            trial.crossover_rate,
+            trial.fitness,
+            trial.seconds
+        );
+        trials.push(trial);
+    }
+
+    let baseline = trials[0].fitness;
+    let Some(best) = trials.iter().max_by(|a, b| a.fitness.total_cmp(&b.fitness)) else {
+        return Ok(());
+    };
+    info!(
+        "Best: --pop-size {} --mutation-rate {} --crossover-rate {} with fitness {:.5}, {:+.1}% over the defaults",
+        best.population_size,
+        best.mutation_rate,
+        best.crossover_rate,
+        best.fitness,
+        100.0 * (best.fitness / baseline.max(f64::MIN_POSITIVE) - 1.0)
+    );
+
+    if let Some(path) = &args.write_config {
+        let config = format!(
+            "# Written by `ImageGridOptimizer tune`, use it with --config FILE.\n\
+             # Best of {} trials with {} evaluations per run; set gens for the real run.\n\n\
+             [ga]\npop-size = {}\nmutation-rate = {}\ncrossover-rate = {}\n",
+            trials.len(),
+            args.evaluations,
+            best.population_size,
+            best.mutation_rate,
+            best.crossover_rate
+        );
+        if let Err(e) = fs::write(path, config) {
+            eprintln!("Error writing config file {}: {}", path.display(), e);
+            return Err(Error::Save(1));
+        }
+        info!("Configuration written to {}", path.display());
+    }
+    Ok(())
+}
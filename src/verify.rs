@@ -0,0 +1,103 @@
+use rect_packer::Rect;
+
+#[cfg(feature = "cli")]
+use crate::archive::read_member;
+use crate::error::Error;
+use crate::hex::hex_gap;
+use crate::layout::{Layout, PlacedImage, Shape};
+use crate::packing::{LayoutKind, PackedLayout, PackingOptions};
+
+/// Problems with the placement of the images: footprints that overlap, stick out of the canvas or
+/// are closer to each other than `padding` pixels. Returns one message per problem.
+pub fn check_geometry(layout: &Layout, padding: i32) -> Vec<String> {
+    let images: Vec<(String, Rect, Shape)> = layout
+        .images
+        .iter()
+        .map(|placed| (describe(placed), Rect::new(placed.x, placed.y, placed.width, placed.height), placed.shape))
+        .collect();
+    check_rects(&images, &[], layout.width, layout.height, padding, layout.overlapping)
+}
+
+/// With `--validate-layout`, fails with the problems of a layout just packed, see `check_packed`.
+pub fn validate_packed(layout: &PackedLayout, options: &PackingOptions) -> Result<(), Error> {
+    if !options.validate {
+        return Ok(());
+    }
+    let problems = check_packed(layout, options);
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::InvalidLayout(problems.join("; ")))
+    }
+}
+
+/// The same checks for the rectangles of `pack_images`, which must also keep the padding to the
+/// reserved regions.
+pub fn check_packed(layout: &PackedLayout, options: &PackingOptions) -> Vec<String> {
+    let (packed, width, height) = layout;
+    let shape = options.layout.shape();
+    let images: Vec<(String, Rect, Shape)> = packed.iter().map(|(id, rect)| (format!("image {}", id), *rect, shape)).collect();
+    check_rects(&images, &options.reserved, *width, *height, options.padding() as i32, options.layout == LayoutKind::Pile)
+}
+
+fn check_rects(
+    images: &[(String, Rect, Shape)],
+    reserved: &[Rect],
+    width: u32,
+    height: u32,
+    padding: i32,
+    overlapping: bool,
+) -> Vec<String> {
+    let mut problems = Vec::new();
+    for (name, rect, _) in images {
+        let inside = rect.x >= 0 && rect.y >= 0 && rect.right() <= width as i32 && rect.bottom() <= height as i32;
+        if !inside {
+            problems.push(format!(
+                "{} at ({}, {}) with size {}x{} is outside the {}x{} canvas",
+                name, rect.x, rect.y, rect.width, rect.height, width, height
+            ));
+        }
+    }
+    // The images of a pile lie on top of each other on purpose
+    if overlapping {
+        return problems;
+    }
+
+    let reserved: Vec<(String, Rect, Shape)> = reserved
+        .iter()
+        .enumerate()
+        .map(|(i, rect)| (format!("reserved region {}", i + 1), *rect, Shape::Rectangle))
+        .collect();
+    for (index, (name_a, a, shape_a)) in images.iter().enumerate() {
+        for (name_b, b, shape_b) in images[index + 1..].iter().chain(&reserved) {
+            // Hexagons interlock, so their bounding boxes overlap while the hexagons keep apart
+            let gap = if (*shape_a, *shape_b) == (Shape::Hexagon, Shape::Hexagon) {
+                hex_gap(a, b)
+            } else {
+                (b.x - a.right()).max(a.x - b.right()).max((b.y - a.bottom()).max(a.y - b.bottom()))
+            };
+            if gap < 0 {
+                problems.push(format!("{} overlaps {}", name_a, name_b));
+            } else if gap < padding {
+                problems.push(format!("{} and {} are only {} px apart (padding: {} px)", name_a, name_b, gap, padding));
+            }
+        }
+    }
+    problems
+}
+
+/// Images whose source file (or archive member) can no longer be read.
+#[cfg(feature = "cli")]
+pub fn check_sources(layout: &Layout) -> Vec<String> {
+    layout
+        .images
+        .iter()
+        .filter(|placed| !placed.path.is_file() && read_member(&placed.path).is_none_or(|member| member.is_err()))
+        .map(|placed| format!("{}: source file is missing", describe(placed)))
+        .collect()
+}
+
+fn describe(placed: &PlacedImage) -> String {
+    let name = placed.path.file_name().unwrap_or(placed.path.as_os_str()).to_string_lossy();
+    format!("image {} ({})", placed.id, name)
+}
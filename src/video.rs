@@ -0,0 +1,71 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::image_handling::{publish, scratch_dir, sorted_files, staging_dir};
+
+const VIDEO_EXTENSIONS: &[&str] = &["mp4", "m4v", "mov", "mkv", "avi", "webm", "mpg", "mpeg", "wmv"];
+
+pub fn is_video(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| VIDEO_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+}
+
+/// Parses an interval such as `5s`, `500ms`, `2m` or a plain number of seconds.
+pub fn parse_interval(value: &str) -> Option<f64> {
+    let value = value.trim();
+    let (number, factor) = if let Some(ms) = value.strip_suffix("ms") {
+        (ms, 0.001)
+    } else if let Some(s) = value.strip_suffix('s') {
+        (s, 1.0)
+    } else if let Some(m) = value.strip_suffix('m') {
+        (m, 60.0)
+    } else {
+        (value, 1.0)
+    };
+    let seconds = number.trim().parse::<f64>().ok()? * factor;
+    (seconds > 0.0).then_some(seconds)
+}
+
+/// Extracts one frame every `every` seconds with ffmpeg and returns the frame files. The frames are
+/// written to a `scratch_dir` of the video, so layouts referring to them can still be rendered
+/// later. File names carry the timestamp (e.g.
+/// `clip_1m05s.png`) so filename labels show where in the video a frame comes from.
+pub fn extract_frames(path: &Path, every: f64) -> Result<Vec<PathBuf>, String> {
+    let stem = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let dir = scratch_dir(path, &format!("every-{}s", every));
+    let out_dir = staging_dir(&dir)?;
+
+    let status = Command::new("ffmpeg")
+        .arg("-v")
+        .arg("error")
+        .arg("-i")
+        .arg(path)
+        .arg("-vf")
+        .arg(format!("fps=1/{}", every))
+        .arg(out_dir.join("frame_%06d.png"))
+        .status()
+        .map_err(|e| format!("Error running ffmpeg (is it installed and on the PATH?): {}", e))?;
+    if !status.success() {
+        return Err(format!("ffmpeg failed on {} ({})", path.display(), status));
+    }
+
+    let frames = sorted_files(&out_dir)?;
+
+    // ffmpeg numbers the frames from 1; frame n shows the video at (n - 1) * every seconds
+    let mut named = Vec::with_capacity(frames.len());
+    for (index, frame) in frames.into_iter().enumerate() {
+        let millis = (index as f64 * every * 1000.0).round() as u64;
+        let (minutes, seconds) = (millis / 60_000, millis / 1000 % 60);
+        let name = if every >= 1.0 {
+            format!("{}_{}m{:02}s.png", stem, minutes, seconds)
+        } else {
+            format!("{}_{}m{:02}.{:03}s.png", stem, minutes, seconds, millis % 1000)
+        };
+        let target = out_dir.join(name);
+        fs::rename(&frame, &target).map_err(|e| format!("Error renaming {}: {}", frame.display(), e))?;
+        named.push(target);
+    }
+    publish(&out_dir, &dir, named)
+}
@@ -0,0 +1,199 @@
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::path::PathBuf;
+
+use image::{DynamicImage, ImageFormat, RgbaImage};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use serde::Deserialize;
+
+use crate::cancel::CancellationToken;
+use crate::collage::{create_collage, resize_rgba, RenderOptions};
+use crate::error::Error;
+use crate::events;
+use crate::ga::{run_ga, CrossoverKind, GaSettings, ImageInfo, MutationWeights, ProgressEvent};
+use crate::layout::Layout;
+use crate::packing::PackingOptions;
+use crate::tone;
+
+/// Settings of `ImageSet::optimize`, named and defaulted like the options of the command line
+/// tool, e.g. `{"gens": 300, "max-images": 20}` as JSON.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Options {
+    pub pop_size: usize,
+    pub gens: usize,
+    pub min_images: usize,
+    pub max_images: usize,
+    pub mutation_rate: f64,
+    pub crossover_rate: f64,
+    /// Seed of the random numbers; the same images and options give the same layout.
+    pub seed: u64,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options { pop_size: 1000, gens: 3000, min_images: 6, max_images: 60, mutation_rate: 0.1, crossover_rate: 0.7, seed: 0 }
+    }
+}
+
+/// Images decoded from encoded bytes (any format of the `image` crate), for front ends without a
+/// filesystem such as a browser. Image ids are the positions of the images in the input.
+#[derive(Default)]
+pub struct ImageSet {
+    images: HashMap<u32, DynamicImage>,
+}
+
+impl ImageSet {
+    /// Decodes the images, scaled to `standard_width` pixels if given, as `--width` does.
+    pub fn decode(encoded: &[&[u8]], standard_width: Option<u32>) -> Result<ImageSet, String> {
+        let mut set = ImageSet::default();
+        for (index, bytes) in encoded.iter().enumerate() {
+            set.push(bytes, standard_width).map_err(|e| format!("{} (image {})", e, index))?;
+        }
+        Ok(set)
+    }
+
+    /// Decodes one more image and returns its id, the number of images before it.
+    pub fn push(&mut self, bytes: &[u8], standard_width: Option<u32>) -> Result<u32, String> {
+        let id = self.images.len() as u32;
+        let img = image::load_from_memory(bytes).map_err(|e| format!("Error decoding image: {}", e))?;
+        let img = tone::tonemap(&img).unwrap_or(img);
+        let img = match standard_width {
+            Some(width) if width != img.width() => {
+                let height = ((img.height() as f64 * width as f64 / img.width().max(1) as f64).round() as u32).max(1);
+                DynamicImage::ImageRgba8(resize_rgba(&img.to_rgba8(), width, height))
+            }
+            _ => img,
+        };
+        self.images.insert(id, img);
+        Ok(id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.images.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.images.is_empty()
+    }
+
+    /// Runs the GA on the images and returns the layout of the best collage. The layout has no
+    /// paths; its image ids refer to the input of `decode`.
+    pub fn optimize(&self, options: &Options) -> Result<Layout, Error> {
+        self.optimize_with_progress(options, |_| {})
+    }
+
+    /// `optimize` with a callback for the `ProgressEvent`s of `run_ga`.
+    pub fn optimize_with_progress(&self, options: &Options, on_progress: impl FnMut(ProgressEvent)) -> Result<Layout, Error> {
+        if self.images.is_empty() {
+            return Err(Error::NoImages);
+        }
+        if options.min_images > options.max_images {
+            return Err(Error::InvalidOptions(format!("min-images ({}) must not be larger than max-images ({})", options.min_images, options.max_images)));
+        }
+        let mut all_images: Vec<(u32, DynamicImage)> = self.images.iter().map(|(id, img)| (*id, img.clone())).collect();
+        all_images.sort_by_key(|(id, _)| *id);
+        let settings = GaSettings {
+            population_size: options.pop_size.max(1),
+            generations: options.gens.max(1),
+            min_images: options.min_images.clamp(1, all_images.len()),
+            max_images: options.max_images.clamp(1, all_images.len()),
+            mutation_rate: options.mutation_rate,
+            mutation_weights: MutationWeights::default(),
+            crossover_rate: options.crossover_rate,
+            crossover: CrossoverKind::Cut,
+            per_dir_min: 0,
+            per_dir_max: None,
+            quiet: true,
+            initial: Vec::new(),
+            greedy_share: 0.1,
+            restart_after: None,
+            restart_keep: 2,
+            sharing_radius: None,
+            coarse_share: 0.0,
+            coarse_factor: 4,
+            cancel: CancellationToken::new(),
+        };
+        let packing = PackingOptions::default();
+        let mut rng = StdRng::seed_from_u64(options.seed);
+        let (population, _) = run_ga(&all_images, &self.images, &ImageInfo::default(), &packing, &settings, &mut rng, on_progress);
+        let Some((best, packed)) = population.first().and_then(|best| Some((best, best.packed_layout.as_ref()?))) else {
+            return Err(Error::Packing(settings.max_images));
+        };
+        Ok(Layout::build(packed, &best.genes, &self.images, &HashMap::<u32, PathBuf>::new(), &packing, &mut rng))
+    }
+
+    /// Renders the collage of `layout` with the default look, without the progress messages of
+    /// the command line tool.
+    pub fn render_rgba(&self, layout: &Layout) -> Result<RgbaImage, Error> {
+        if let Some(placed) = layout.images.iter().find(|placed| !self.images.contains_key(&placed.id)) {
+            return Err(Error::UnknownImage(placed.id));
+        }
+        let silent = events::silent();
+        events::set_silent(true);
+        let collage = create_collage(&self.images, layout, &HashMap::new(), &RenderOptions::default());
+        events::set_silent(silent);
+        Ok(collage.into_rgba8())
+    }
+
+    /// `render_rgba`, encoded as PNG.
+    pub fn render(&self, layout: &Layout) -> Result<Vec<u8>, String> {
+        let collage = self.render_rgba(layout).map_err(|e| e.to_string())?;
+        let mut png = Vec::new();
+        collage
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .map_err(|e| format!("Error encoding the collage: {}", e))?;
+        Ok(png)
+    }
+}
+
+/// The JavaScript side of `ImageSet`, built with the `wasm` feature. The images are passed as one
+/// buffer of their concatenated files and the length of each, options and layouts as JSON:
+///
+/// ```js
+/// const set = new ImageSet(bytes, lengths, 500);
+/// const layout = set.optimize('{"gens": 300}');
+/// const png = set.render(layout);
+/// ```
+#[cfg(feature = "wasm")]
+mod bindings {
+    use wasm_bindgen::prelude::*;
+
+    use super::Options;
+    use crate::layout::Layout;
+
+    #[wasm_bindgen(js_name = ImageSet)]
+    pub struct JsImageSet(super::ImageSet);
+
+    #[wasm_bindgen(js_class = ImageSet)]
+    impl JsImageSet {
+        #[wasm_bindgen(constructor)]
+        pub fn new(bytes: &[u8], lengths: &[u32], standard_width: Option<u32>) -> Result<JsImageSet, JsError> {
+            let mut encoded = Vec::with_capacity(lengths.len());
+            let mut rest = bytes;
+            for &length in lengths {
+                if length as usize > rest.len() {
+                    return Err(JsError::new("The image lengths exceed the buffer"));
+                }
+                let (image, tail) = rest.split_at(length as usize);
+                encoded.push(image);
+                rest = tail;
+            }
+            super::ImageSet::decode(&encoded, standard_width).map(JsImageSet).map_err(|e| JsError::new(&e))
+        }
+
+        /// Layout of the best collage as JSON, as in the `layout.json` of the command line tool.
+        pub fn optimize(&self, options: &str) -> Result<String, JsError> {
+            let options: Options = if options.trim().is_empty() { Options::default() } else { serde_json::from_str(options)? };
+            let layout = self.0.optimize(&options)?;
+            Ok(serde_json::to_string(&layout)?)
+        }
+
+        /// The collage of a layout from `optimize` as PNG bytes.
+        pub fn render(&self, layout: &str) -> Result<Vec<u8>, JsError> {
+            let layout: Layout = serde_json::from_str(layout)?;
+            self.0.render(&layout).map_err(|e| JsError::new(&e))
+        }
+    }
+}
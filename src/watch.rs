@@ -0,0 +1,83 @@
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::time::Duration;
+
+use notify::event::ModifyKind;
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+/// How long the inputs must stay unchanged before a new run starts, so that copying a batch of
+/// photos triggers a single run.
+const SETTLE_TIME: Duration = Duration::from_secs(2);
+
+/// Watches the input directories for added, removed, renamed or rewritten files.
+pub struct InputWatcher {
+    // Dropping the watcher stops the notifications
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<Event>>,
+}
+
+impl InputWatcher {
+    pub fn new(paths: &[PathBuf]) -> Result<Self, String> {
+        let (sender, events) = channel();
+        let mut watcher = notify::recommended_watcher(sender).map_err(|e| format!("Error starting file watcher: {}", e))?;
+        for path in paths {
+            watcher
+                .watch(path, RecursiveMode::NonRecursive)
+                .map_err(|e| format!("Error watching {}: {}", path.display(), e))?;
+        }
+        Ok(InputWatcher { _watcher: watcher, events })
+    }
+
+    /// Blocks until an input changes and the inputs have settled. Changes to the files written by
+    /// the run itself (`outputs` and their numbered variants) are ignored, so the output may live
+    /// in a watched directory.
+    pub fn wait_for_change(&self, outputs: &[PathBuf]) -> Result<(), String> {
+        loop {
+            let event = self.events.recv().map_err(|e| format!("Error watching inputs: {}", e))?;
+            if self.is_input_change(event, outputs) {
+                break;
+            }
+        }
+        loop {
+            match self.events.recv_timeout(SETTLE_TIME) {
+                Ok(_) => continue,
+                Err(RecvTimeoutError::Timeout) => return Ok(()),
+                Err(e) => return Err(format!("Error watching inputs: {}", e)),
+            }
+        }
+    }
+
+    fn is_input_change(&self, event: notify::Result<Event>, outputs: &[PathBuf]) -> bool {
+        let event = match event {
+            Ok(event) => event,
+            Err(e) => {
+                eprintln!("Error watching inputs: {}", e);
+                return false;
+            }
+        };
+        let relevant = matches!(
+            event.kind,
+            EventKind::Create(_) | EventKind::Remove(_) | EventKind::Modify(ModifyKind::Data(_) | ModifyKind::Name(_) | ModifyKind::Any)
+        );
+        relevant && event.paths.iter().any(|path| !is_output(path, outputs))
+    }
+}
+
+/// Whether `path` is one of the outputs, or a page, tile or temporary file derived from one
+/// (e.g. `output_02.jpg` for `output.jpg`).
+fn is_output(path: &Path, outputs: &[PathBuf]) -> bool {
+    let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    outputs.iter().any(|output| {
+        let stem = output.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+        let same_dir = match (absolute(path.parent()), absolute(output.parent())) {
+            (Some(a), Some(b)) => a == b,
+            _ => false,
+        };
+        same_dir && name.starts_with(&stem)
+    })
+}
+
+fn absolute(dir: Option<&Path>) -> Option<PathBuf> {
+    let dir = dir.filter(|d| !d.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    dir.canonicalize().ok()
+}
@@ -0,0 +1,95 @@
+//! Property tests of the packer: for any set of image sizes, `pack_images` places every image on
+//! the canvas it reports, without overlaps and with the padding between images and to reserved
+//! regions, and every other layout passes its own checks with `--validate-layout`.
+
+use std::collections::HashMap;
+
+use image::DynamicImage;
+use image_grid_optimizer::packing::{footprint, pack_images, LayoutKind, PackingOptions};
+use image_grid_optimizer::pile::{pack_pile, PileGene};
+use image_grid_optimizer::verify::check_packed;
+use proptest::prelude::*;
+use rect_packer::Rect;
+
+fn blank_images(sizes: &[(u32, u32)]) -> HashMap<u32, DynamicImage> {
+    sizes.iter().enumerate().map(|(id, &(w, h))| (id as u32, DynamicImage::new_luma8(w, h))).collect()
+}
+
+fn check_every_image_packed(sizes: &[(u32, u32)], options: &PackingOptions) -> Result<(), TestCaseError> {
+    let images = blank_images(sizes);
+    let ids: Vec<u32> = (0..sizes.len() as u32).collect();
+    let layout = pack_images(&ids, &images, options).map_err(|e| TestCaseError::fail(e.to_string()))?;
+
+    let problems = check_packed(&layout, options);
+    prop_assert!(problems.is_empty(), "{}", problems.join("\n"));
+    let (packed, _, _) = &layout;
+    prop_assert_eq!(packed.len(), ids.len());
+    for (id, rect) in packed {
+        let (w, h) = footprint(&images[id], options);
+        prop_assert_eq!((rect.width, rect.height), (w as i32, h as i32), "image {} packed at the wrong size", id);
+    }
+    Ok(())
+}
+
+fn check_layout_valid(sizes: &[(u32, u32)], options: &PackingOptions, genes: &HashMap<u32, PileGene>) -> Result<(), TestCaseError> {
+    let images = blank_images(sizes);
+    let ids: Vec<u32> = (0..sizes.len() as u32).collect();
+    let packed = match options.layout {
+        LayoutKind::Pile => pack_pile(&ids, genes, &images, options),
+        _ => pack_images(&ids, &images, options),
+    };
+    let layout = packed.map_err(|e| TestCaseError::fail(format!("{:?}: {}", options.layout, e)))?;
+
+    let problems = check_packed(&layout, options);
+    prop_assert!(problems.is_empty(), "{:?}: {}", options.layout, problems.join("\n"));
+    prop_assert_eq!(layout.0.len(), ids.len());
+    Ok(())
+}
+
+fn layout_kinds() -> impl Strategy<Value = LayoutKind> {
+    prop::sample::select(vec![LayoutKind::Packed, LayoutKind::Strip, LayoutKind::Hex, LayoutKind::Spiral, LayoutKind::Pile, LayoutKind::Golden])
+}
+
+proptest! {
+    #[test]
+    fn packs_without_overlaps(sizes in prop::collection::vec((1u32..600, 1u32..600), 1..60)) {
+        check_every_image_packed(&sizes, &PackingOptions::default())?;
+    }
+
+    #[test]
+    fn packs_rotated_and_captioned(
+        sizes in prop::collection::vec((1u32..400, 1u32..400), 1..40),
+        max_rotation in 0.0..15.0f64,
+        caption_height in 0u32..40,
+    ) {
+        let options = PackingOptions { max_rotation, caption_height, ..Default::default() };
+        check_every_image_packed(&sizes, &options)?;
+    }
+
+    #[test]
+    fn keeps_reserved_regions_free(
+        sizes in prop::collection::vec((1u32..400, 1u32..400), 1..40),
+        reserved in prop::collection::vec((0i32..800, 0i32..800, 1i32..300, 1i32..300), 1..3),
+    ) {
+        let reserved = reserved.into_iter().map(|(x, y, w, h)| Rect::new(x, y, w, h)).collect();
+        let options = PackingOptions { reserved, ..Default::default() };
+        check_every_image_packed(&sizes, &options)?;
+    }
+
+    #[test]
+    fn every_layout_validates(sizes in prop::collection::vec((1u32..600, 1u32..600), 1..40), layout in layout_kinds()) {
+        let options = PackingOptions { layout, validate: true, ..Default::default() };
+        check_layout_valid(&sizes, &options, &HashMap::new())?;
+    }
+
+    #[test]
+    fn nudged_piles_validate(
+        sizes in prop::collection::vec((1u32..400, 1u32..400), 1..30),
+        genes in prop::collection::vec((-1.0..=1.0f64, -1.0..=1.0f64, 0.0..=1.0f64), 30),
+        max_overlap in 0.0..=1.0f64,
+    ) {
+        let genes = genes.into_iter().enumerate().map(|(id, (dx, dy, z))| (id as u32, PileGene { dx, dy, z })).collect();
+        let options = PackingOptions { layout: LayoutKind::Pile, max_overlap, validate: true, ..Default::default() };
+        check_layout_valid(&sizes, &options, &genes)?;
+    }
+}